@@ -0,0 +1,51 @@
+//! Logs records through a file handler whose lines carry their time, and
+//! reports what each one costs: the time, and how many times the clock was
+//! read for it, which stamping a record once keeps at one.
+//!
+//! `cargo bench --bench emit`, `-- 1000000` for another number of records.
+
+use std::time::Instant;
+
+use soda::clock;
+use soda::handlers::file::FileOptions;
+use soda::logger::Logger;
+
+fn main() {
+    let records: u64 = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(200_000);
+
+    let dir = std::env::temp_dir().join(format!("soda-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("emit-{date}.log");
+
+    let logger = Logger::new("bench");
+    let options = FileOptions {
+        buffer_size: 64 * 1024,
+        line: Some(String::from("{time} {level} {name} {message}")),
+        ..FileOptions::default()
+    };
+    logger
+        .add_file_handler(None, path.to_str().unwrap(), options)
+        .unwrap();
+
+    let reads = clock::reads();
+    let started = Instant::now();
+    for n in 0..records {
+        logger.info(&format!("record {}", n)).unwrap();
+    }
+    logger.flush();
+    let elapsed = started.elapsed();
+    let reads = clock::reads() - reads;
+
+    println!(
+        "{} records in {:.3}s, {:.0} ns a record, {:.2} clock reads a record",
+        records,
+        elapsed.as_secs_f64(),
+        elapsed.as_nanos() as f64 / records as f64,
+        reads as f64 / records as f64
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
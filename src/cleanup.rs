@@ -0,0 +1,140 @@
+//! Deletes old log files from a directory, see `sweep`.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use regex::Regex;
+
+use crate::logger;
+
+/// What `sweep` did, or would have done on a dry run.
+#[derive(Default)]
+pub struct Sweep {
+    pub deleted: Vec<PathBuf>,
+    /// Bytes the deleted files took.
+    pub bytes: u64,
+    /// Files old enough that a live handler is still writing to.
+    pub skipped: Vec<PathBuf>,
+    /// Files that couldn't be looked at or deleted.
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+/// A regex matching the file names `pattern` does: `*` any run of
+/// characters, `?` any one, `[abc]` one of a set and `[!abc]` one outside
+/// it. Fails with why when it isn't a pattern of file names.
+pub fn glob(pattern: &str) -> Result<Regex, String> {
+    if pattern.contains('/') || pattern.contains(std::path::MAIN_SEPARATOR) {
+        return Err(format!("{:?} matches file names, not paths", pattern));
+    }
+
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                let mut set = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') if !set.is_empty() && set != "!" => break,
+                        Some(c) => set.push(c),
+                        None => return Err(format!("{:?} has an unclosed [", pattern)),
+                    }
+                }
+                let (negated, set) = match set.strip_prefix('!') {
+                    Some(set) => ("^", set),
+                    None => ("", set.as_str()),
+                };
+                // Ranges kept, anything else the regex could read as syntax
+                // escaped.
+                let set: String = set
+                    .chars()
+                    .map(|c| match c {
+                        '-' => c.to_string(),
+                        c if c.is_ascii_punctuation() => format!("\\{}", c),
+                        c => c.to_string(),
+                    })
+                    .collect();
+                regex.push_str(&format!("[{}{}]", negated, set));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex).map_err(|e| e.to_string())
+}
+
+/// Deletes the files right in `dir` whose names match `pattern` and which
+/// were last written to before `cutoff`, or only lists them on a `dry_run`.
+/// Symlinks and directories are left alone, as are the files live handlers
+/// write to. A file that can't be deleted is added to the errors and the
+/// sweep carries on, only `dir` itself failing to be read fails it.
+pub fn sweep(dir: &Path, pattern: &Regex, cutoff: SystemTime, dry_run: bool) -> io::Result<Sweep> {
+    let active: Vec<PathBuf> = logger::active_paths()
+        .iter()
+        .filter_map(|path| fs::canonicalize(path).ok())
+        .collect();
+    let mut sweep = Sweep::default();
+
+    for entry in fs::read_dir(dir)? {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                sweep.errors.push((dir.to_path_buf(), e));
+                continue;
+            }
+        };
+        let matched = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| pattern.is_match(name));
+        if !matched {
+            continue;
+        }
+
+        // A symlink's own metadata, it isn't followed.
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => continue,
+            Err(e) => {
+                sweep.errors.push((path, e));
+                continue;
+            }
+        };
+        match metadata.modified() {
+            Ok(modified) if modified < cutoff => {}
+            Ok(_) => continue,
+            Err(e) => {
+                sweep.errors.push((path, e));
+                continue;
+            }
+        }
+
+        if fs::canonicalize(&path).is_ok_and(|path| active.contains(&path)) {
+            sweep.skipped.push(path);
+            continue;
+        }
+
+        let removed = match dry_run {
+            true => Ok(()),
+            false => fs::remove_file(&path),
+        };
+        match removed {
+            Ok(()) => {
+                sweep.bytes += metadata.len();
+                sweep.deleted.push(path);
+            }
+            Err(e) => sweep.errors.push((path, e)),
+        }
+    }
+
+    sweep.deleted.sort();
+    sweep.skipped.sort();
+
+    Ok(sweep)
+}
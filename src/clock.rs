@@ -0,0 +1,55 @@
+//! The time soda goes by: the system's, unless a test sets it, see `set`.
+//!
+//! Timestamps, rotation days and retention ages all read it. Deadlines and
+//! batching intervals run on `Instant`, which a set clock leaves alone.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Duration, Local};
+
+/// Whether the clock is set, looked at first so the system's time costs no
+/// lock.
+static SET: AtomicBool = AtomicBool::new(false);
+static FIXED: Mutex<Option<DateTime<Local>>> = Mutex::new(None);
+
+/// How many times `now` was called, see `reads`.
+static READS: AtomicU64 = AtomicU64::new(0);
+
+/// The time now, the one `set` while there's one.
+pub fn now() -> DateTime<Local> {
+    READS.fetch_add(1, Ordering::Relaxed);
+    if !SET.load(Ordering::Acquire) {
+        return Local::now();
+    }
+
+    FIXED.lock().unwrap().unwrap_or_else(Local::now)
+}
+
+/// How many times the time was read in this process, for the `emit`
+/// benchmark to count what a record costs.
+pub fn reads() -> u64 {
+    READS.load(Ordering::Relaxed)
+}
+
+/// `now` as a `SystemTime`, to compare with a file's.
+pub fn system_now() -> SystemTime {
+    SystemTime::from(now())
+}
+
+/// Stops the clock at `time` for every logger in the process, until it's
+/// set again or `advance`d. `None` goes back to the system's time.
+pub fn set(time: Option<DateTime<Local>>) {
+    let mut fixed = FIXED.lock().unwrap();
+    *fixed = time;
+    SET.store(time.is_some(), Ordering::Release);
+}
+
+/// Moves the clock on by `by`, stopping it at the system's time plus `by`
+/// when it wasn't set.
+pub fn advance(by: Duration) {
+    let mut fixed = FIXED.lock().unwrap();
+    *fixed = Some(fixed.unwrap_or_else(Local::now) + by);
+    SET.store(true, Ordering::Release);
+}
@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+
+use crate::record::Record;
+use crate::template::{self, Coercion};
+
+/// The date format used until one is configured.
+pub const DEFAULT_DATEFMT: &str = "[%Y-%m-%d][%H:%M:%S]";
+
+/// How records are rendered as text, on the console and wherever else a
+/// line is wanted.
+///
+/// With an empty template a line is `[time][name][LEVEL] message`, otherwise
+/// the template is rendered, see `template::render` for its placeholders.
+#[derive(Clone)]
+pub struct Format {
+    pub template: String,
+    pub datefmt: String,
+    pub coercion: Coercion,
+    /// Whether control characters in messages are escaped, see
+    /// `template::escape_control`, so a message can't forge a line.
+    pub escape_control: bool,
+}
+
+/// A logger's format, shared with its children. It's swapped as a whole,
+/// a record holds on to the one it was checked against, see
+/// `Record::format`.
+pub type SharedFormat = Arc<RwLock<Arc<Format>>>;
+
+impl Default for Format {
+    fn default() -> Format {
+        Format {
+            template: String::new(),
+            datefmt: String::from(DEFAULT_DATEFMT),
+            coercion: Coercion::default(),
+            escape_control: false,
+        }
+    }
+}
+
+impl Format {
+    pub fn render(&self, record: &Record) -> String {
+        self.render_with(&self.template, record)
+    }
+
+    /// `render` with `template` in place of the format's own, the file
+    /// handler's `line`.
+    pub fn render_with(&self, template: &str, record: &Record) -> String {
+        let mut line = if template.is_empty() {
+            format!(
+                "[{}][{}][{}] {}",
+                record.time.format(&self.datefmt),
+                record.name,
+                record.level.to_log(),
+                self.message(&record.message)
+            )
+        } else {
+            template::render(template, record, self)
+        };
+
+        if let Some(traceback) = self.traceback(record) {
+            line.push('\n');
+            line.push_str(&traceback);
+        }
+
+        line
+    }
+
+    /// The traceback of the exception `record` was logged with, which goes
+    /// on the lines after its own the way `logging` writes it.
+    pub fn traceback<'a>(&self, record: &'a Record) -> Option<Cow<'a, str>> {
+        let stack_trace = record.exception.as_ref()?.stack_trace.trim_end();
+
+        match stack_trace.is_empty() {
+            true => None,
+            false => Some(self.message(stack_trace)),
+        }
+    }
+
+    /// `message` as it's written, escaped with `escape_control`.
+    pub fn message<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        match self.escape_control {
+            true => template::escape_control(message),
+            false => Cow::Borrowed(message),
+        }
+    }
+}
+
+/// What `basicConfig(color_scope=...)` tints on the console, in the
+/// record's level color.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorScope {
+    /// The level name alone.
+    Token,
+    /// The whole line.
+    Line,
+}
+
+impl ColorScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ColorScope::Token => "token",
+            ColorScope::Line => "line",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<ColorScope> {
+        match name {
+            "token" => Some(ColorScope::Token),
+            "line" => Some(ColorScope::Line),
+            _ => None,
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+fn color(level: log::Level) -> &'static str {
+    match level {
+        log::Level::Error => "\x1b[31m",
+        log::Level::Warn => "\x1b[33m",
+        log::Level::Info => "\x1b[32m",
+        log::Level::Debug => "\x1b[34m",
+        log::Level::Trace => "\x1b[90m",
+    }
+}
+
+/// `line` in `level`'s color, all of it or just the first `token`, the level
+/// name as the line shows it. The color is reset after, so what's printed
+/// next isn't tinted.
+pub fn colorize(line: &str, level: log::Level, token: &str, scope: ColorScope) -> String {
+    let color = color(level);
+
+    match scope {
+        ColorScope::Line => format!("{}{}{}", color, line, RESET),
+        ColorScope::Token => match line.find(token) {
+            Some(start) if !token.is_empty() => {
+                let end = start + token.len();
+                format!(
+                    "{}{}{}{}{}",
+                    &line[..start],
+                    color,
+                    token,
+                    RESET,
+                    &line[end..]
+                )
+            }
+            _ => line.to_string(),
+        },
+    }
+}
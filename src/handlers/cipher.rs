@@ -0,0 +1,201 @@
+//! Encryption at rest for the file handler.
+//!
+//! An encrypted file starts with `SODAENC1` and a 16 byte salt, then holds
+//! one frame per line written: the length of the sealed line as 4 big endian
+//! bytes, a random 12 byte nonce, and the line sealed with ChaCha20-Poly1305.
+//! Frames are appended on their own, so a crash at worst leaves the last one
+//! cut short.
+
+use std::{
+    fs::{self, File},
+    io::{self, ErrorKind, Read},
+    num::NonZeroU32,
+};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"SODAENC1";
+const SALT_LEN: usize = 16;
+/// PBKDF2-HMAC-SHA256 rounds a passphrase is stretched with.
+const ITERATIONS: u32 = 100_000;
+
+/// What a file is encrypted with, wiped from memory once dropped.
+#[derive(Clone)]
+pub enum Secret {
+    /// Used as it is, 32 bytes.
+    Key(Zeroizing<Vec<u8>>),
+    /// Stretched into a key with the file's salt.
+    Passphrase(Zeroizing<Vec<u8>>),
+}
+
+impl Secret {
+    pub const KEY_LEN: usize = 32;
+}
+
+/// The key a file's frames are sealed with, derived from a `Secret` and the
+/// salt the file starts with.
+pub struct Encryption {
+    key: LessSafeKey,
+    salt: [u8; SALT_LEN],
+}
+
+impl Encryption {
+    /// With a new random salt, for a file about to be created.
+    pub fn new(secret: &Secret) -> io::Result<Encryption> {
+        let mut salt = [0; SALT_LEN];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| random_error())?;
+
+        Encryption::with_salt(secret, salt)
+    }
+
+    /// With the salt of the encrypted file at `path`.
+    pub fn existing(secret: &Secret, path: &str) -> io::Result<Encryption> {
+        let mut start = Vec::new();
+        File::open(path)?
+            .take((MAGIC.len() + SALT_LEN) as u64)
+            .read_to_end(&mut start)?;
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(salt_of(&start, path)?);
+
+        Encryption::with_salt(secret, salt)
+    }
+
+    fn with_salt(secret: &Secret, salt: [u8; SALT_LEN]) -> io::Result<Encryption> {
+        let mut bytes = Zeroizing::new([0; Secret::KEY_LEN]);
+        match secret {
+            Secret::Key(key) if key.len() == Secret::KEY_LEN => bytes.copy_from_slice(key),
+            Secret::Key(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("an encryption key is {} bytes", Secret::KEY_LEN),
+                ))
+            }
+            Secret::Passphrase(passphrase) => pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                NonZeroU32::new(ITERATIONS).unwrap(),
+                &salt,
+                passphrase,
+                &mut *bytes,
+            ),
+        }
+
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &*bytes)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "invalid encryption key"))?;
+
+        Ok(Encryption {
+            key: LessSafeKey::new(key),
+            salt,
+        })
+    }
+
+    /// What an encrypted file starts with.
+    pub fn preamble(&self) -> Vec<u8> {
+        [&MAGIC[..], &self.salt].concat()
+    }
+
+    /// `plaintext` as a frame.
+    pub fn seal(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| random_error())?;
+
+        let mut sealed = plaintext.to_vec();
+        let nonce_used = Nonce::assume_unique_for_key(nonce);
+        self.key
+            .seal_in_place_append_tag(nonce_used, Aad::empty(), &mut sealed)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+
+        let mut frame = Vec::with_capacity(4 + NONCE_LEN + sealed.len());
+        frame.extend_from_slice(&(sealed.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&sealed);
+
+        Ok(frame)
+    }
+
+    /// Opens every frame of the encrypted file at `path`. Frames that don't
+    /// open are counted and skipped, as is a last frame cut short.
+    pub fn decrypt(&self, path: &str) -> io::Result<Decrypted> {
+        let data = fs::read(path)?;
+        salt_of(&data, path)?;
+
+        let mut decrypted = Decrypted {
+            text: Vec::new(),
+            frames: 0,
+            corrupt: 0,
+            truncated: false,
+        };
+        let mut rest = &data[MAGIC.len() + SALT_LEN..];
+
+        while !rest.is_empty() {
+            if rest.len() < 4 + NONCE_LEN {
+                decrypted.truncated = true;
+                break;
+            }
+            let mut length = [0; 4];
+            length.copy_from_slice(&rest[..4]);
+            let end = 4 + NONCE_LEN + u32::from_be_bytes(length) as usize;
+            if rest.len() < end {
+                decrypted.truncated = true;
+                break;
+            }
+
+            let mut nonce = [0; NONCE_LEN];
+            nonce.copy_from_slice(&rest[4..4 + NONCE_LEN]);
+            let mut sealed = rest[4 + NONCE_LEN..end].to_vec();
+            let opened = self.key.open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            );
+            match opened {
+                Ok(plaintext) => {
+                    decrypted.text.extend_from_slice(plaintext);
+                    decrypted.frames += 1;
+                }
+                Err(_) => decrypted.corrupt += 1,
+            }
+
+            rest = &rest[end..];
+        }
+
+        Ok(decrypted)
+    }
+}
+
+/// What `decrypt` recovered from a file.
+pub struct Decrypted {
+    pub text: Vec<u8>,
+    pub frames: usize,
+    /// Frames that failed to open, tampered with or sealed with another key.
+    pub corrupt: usize,
+    /// Whether the file ends in a frame cut short, by a crash say.
+    pub truncated: bool,
+}
+
+/// Opens every frame of an encrypted file, see `Encryption::decrypt`.
+pub fn decrypt(path: &str, secret: &Secret) -> io::Result<Decrypted> {
+    Encryption::existing(secret, path)?.decrypt(path)
+}
+
+fn salt_of<'a>(data: &'a [u8], path: &str) -> io::Result<&'a [u8]> {
+    match data.strip_prefix(&MAGIC[..]) {
+        Some(rest) if rest.len() >= SALT_LEN => Ok(&rest[..SALT_LEN]),
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is not an encrypted soda log", path),
+        )),
+    }
+}
+
+fn random_error() -> io::Error {
+    io::Error::other("no randomness available")
+}
@@ -0,0 +1,187 @@
+use std::{
+    io::{self, BufWriter, IsTerminal, Stdout, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// The console behind the fern dispatch `basicConfig` installs.
+///
+/// fern is a process wide logger, so is its console. fern flushes its output
+/// after every record, the writer it's given only forwards that flush when
+/// the console is line buffered. Otherwise the buffer is written out once it
+/// fills up, or on an explicit `flush`.
+///
+/// Instead of stdout, lines can be handed to a Python callable such as
+/// `tqdm.write`, which redraws progress bars around them, or written to
+/// Python's `sys.stdout` so a notebook shows them under the cell.
+static CONSOLE: OnceLock<Mutex<Console>> = OnceLock::new();
+
+/// Exceptions raised by the console callable, they are never propagated.
+static WRITER_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+struct Console {
+    out: BufWriter<Stdout>,
+    target: Target,
+    pending: Vec<u8>,
+}
+
+/// Where console lines end up.
+#[derive(Clone)]
+pub enum Target {
+    Stdout,
+    /// Called with each line, without its newline.
+    #[cfg(feature = "python")]
+    Callable(PyObject),
+    /// `sys.stdout.write`, looked up per line since it gets swapped around,
+    /// by `%%capture` for one.
+    #[cfg(feature = "python")]
+    PythonStdout,
+}
+
+/// Whether `sys.stdout` is an ipykernel `OutStream`, i.e. we're running in
+/// a notebook and the process stdout goes to the kernel's terminal instead.
+#[cfg(feature = "python")]
+pub fn in_notebook(py: Python) -> bool {
+    let stdout = match py.import("sys").and_then(|sys| sys.getattr("stdout")) {
+        Ok(stdout) => stdout,
+        Err(_) => return false,
+    };
+    let class = stdout.get_type();
+
+    let module: String = class
+        .getattr("__module__")
+        .and_then(|m| m.extract())
+        .unwrap_or_default();
+
+    module.starts_with("ipykernel") && class.name().is_ok_and(|name| name == "OutStream")
+}
+
+struct ConsoleWriter {
+    line_buffered: bool,
+}
+
+/// Returns the writer to chain into fern, only on the first call.
+///
+/// `line_buffered` defaults to whether stdout is an interactive terminal.
+pub fn install(line_buffered: Option<bool>, capacity: usize) -> Option<Box<dyn Write + Send>> {
+    let line_buffered = line_buffered.unwrap_or_else(|| io::stdout().is_terminal());
+
+    CONSOLE
+        .set(Mutex::new(Console {
+            out: BufWriter::with_capacity(capacity.max(1), io::stdout()),
+            target: Target::Stdout,
+            pending: Vec::new(),
+        }))
+        .ok()?;
+
+    Some(Box::new(ConsoleWriter { line_buffered }))
+}
+
+pub fn installed() -> bool {
+    CONSOLE.get().is_some()
+}
+
+pub fn set_target(target: Target) {
+    if let Some(console) = CONSOLE.get() {
+        let mut console = console.lock().unwrap();
+        let _ = console.out.flush();
+        console.pending.clear();
+        console.target = target;
+    }
+}
+
+pub fn writer_errors() -> u64 {
+    WRITER_ERRORS.load(Ordering::Relaxed)
+}
+
+pub fn flush() {
+    if let Some(console) = CONSOLE.get() {
+        let _ = console.lock().unwrap().out.flush();
+    }
+}
+
+/// Like `flush`, giving up after `timeout` on a line being written, see
+/// `lock_within`.
+pub fn flush_within(timeout: Duration) {
+    if let Some(mut console) = CONSOLE.get().and_then(|c| super::lock_within(c, timeout)) {
+        let _ = console.out.flush();
+    }
+}
+
+impl Write for ConsoleWriter {
+    #[cfg(not(feature = "python"))]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let console = match CONSOLE.get() {
+            Some(console) => console,
+            None => return Ok(buf.len()),
+        };
+
+        let mut console = console.lock().unwrap();
+        match console.target {
+            Target::Stdout => console.out.write(buf),
+        }
+    }
+
+    #[cfg(feature = "python")]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let console = match CONSOLE.get() {
+            Some(console) => console,
+            None => return Ok(buf.len()),
+        };
+
+        let (target, lines) = {
+            let mut console = console.lock().unwrap();
+
+            let target = match &console.target {
+                Target::Stdout => return console.out.write(buf),
+                target => target.clone(),
+            };
+
+            // fern writes a record in pieces, only whole lines go out.
+            console.pending.extend_from_slice(buf);
+            let mut lines = Vec::new();
+            while let Some(end) = console.pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = console.pending.drain(..=end).collect();
+                lines.push(String::from_utf8_lossy(&line[..end]).into_owned());
+            }
+
+            (target, lines)
+        };
+
+        // Called without the console locked, in case the callable logs.
+        if !lines.is_empty() {
+            let gil = Python::acquire_gil();
+            let py = gil.python();
+
+            for line in lines {
+                let written = match &target {
+                    Target::Callable(writer) => writer.call1(py, (line,)).map(drop),
+                    _ => py
+                        .import("sys")
+                        .and_then(|sys| sys.getattr("stdout"))
+                        .and_then(|stdout| stdout.call_method1("write", (line + "\n",)))
+                        .map(drop),
+                };
+
+                if written.is_err() {
+                    WRITER_ERRORS.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match (self.line_buffered, CONSOLE.get()) {
+            (true, Some(console)) => console.lock().unwrap().out.flush(),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,1967 @@
+use std::{
+    cmp::Reverse,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat};
+use flate2::{write::GzEncoder, Compression};
+use regex::Regex;
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use serde_json::{Map, Value};
+
+use super::cipher::{Encryption, Secret};
+use super::{from_hex, to_hex};
+use crate::clock;
+use crate::tail;
+use crate::template::{self, Coercion};
+
+/// Appends each record's message to a file.
+pub struct FileLogger {
+    pub enabled: bool,
+    /// The file written to, the expansion of `template` when there's one.
+    path: RwLock<String>,
+    /// The path as given, when it has date placeholders, see `expand_path`.
+    pub template: Option<String>,
+    pub reexpand: Option<Reexpand>,
+    /// The day `template` was last expanded for.
+    expanded_on: Mutex<Option<NaiveDate>>,
+    /// Bytes held back before they are written out, `0` opens the file and
+    /// writes each record as it comes in.
+    pub buffer_size: usize,
+    /// Writes the buffer out after this many records too, `0` only when it
+    /// fills up.
+    pub flush_every: usize,
+    /// Lines in the buffer, see `buffer_record`, only changed with `writer`
+    /// locked.
+    unflushed: AtomicUsize,
+    pub rotation: Option<Rotation>,
+    /// Written at the top of the file each time it's opened or rotated, see
+    /// `header`.
+    pub header: Option<String>,
+    pub audit: Option<Audit>,
+    pub signing: Option<Signing>,
+    /// Set when the file is encrypted, see `cipher`.
+    pub encryption: Option<Encryption>,
+    /// Writes the file as a compressed stream, see `Sink`.
+    pub compress: Option<StreamCompression>,
+    /// Kept pointing at the file written to, see `point_latest`.
+    pub latest: Option<PathBuf>,
+    /// The template each line is rendered from, see `Format::render_with`,
+    /// the message alone without one.
+    pub line: Option<String>,
+    /// What rotating deleted to stay within budget since `take_pruned`.
+    pruned: Mutex<Option<Pruned>>,
+    last_rotation: Mutex<Option<Rotated>>,
+    /// Rotations `Rotation::on_rotation` wasn't called for yet.
+    rotations: Mutex<Vec<Rotated>>,
+    /// The day the file was started, when it was last written to for a
+    /// file there before the handler, for `Rotation::period`.
+    started_on: Mutex<NaiveDate>,
+    writer: Mutex<Option<BufWriter<Sink>>>,
+    /// Size of the file, counted as records are written.
+    size: AtomicU64,
+    /// Hash of the last line written with `audit`, only changed with
+    /// `writer` locked.
+    chain: Mutex<String>,
+}
+
+/// Ends each line with the hex SHA-256 of the previous line's hash followed
+/// by the line, so a line changed, added or removed after the fact breaks
+/// the chain from there on, see `verify`.
+///
+/// A file starts with a `# audit genesis <hash>` line giving the hash the
+/// chain starts from. Rotating writes the last hash in a `# audit end
+/// <hash>` line and the next file starts from it.
+#[derive(Clone)]
+pub struct Audit {
+    /// What the chain of a new file starts from, the following ones start
+    /// from the end of the one before.
+    pub genesis: String,
+}
+
+/// Ends each line with its hex HMAC-SHA256, so any one line can be checked
+/// on its own, see `verify_signed`.
+#[derive(Clone)]
+pub struct Signing {
+    key: hmac::Key,
+    /// The environment variable the key was read from, if it was.
+    pub env: Option<String>,
+}
+
+impl Signing {
+    pub fn new(key: &[u8], env: Option<String>) -> Signing {
+        Signing {
+            key: hmac::Key::new(hmac::HMAC_SHA256, key),
+            env,
+        }
+    }
+
+    fn sign(&self, line: &str) -> String {
+        to_hex(hmac::sign(&self.key, line.as_bytes()).as_ref())
+    }
+}
+
+/// The genesis of an audit file unless another one is given.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+const GENESIS_PREFIX: &str = "# audit genesis ";
+const END_PREFIX: &str = "# audit end ";
+
+/// When a path with date placeholders is expanded again, switching files
+/// if it changed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Reexpand {
+    /// On the first record of each day.
+    Daily,
+}
+
+impl Reexpand {
+    pub fn parse(name: &str) -> Option<Reexpand> {
+        match name {
+            "daily" => Some(Reexpand::Daily),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reexpand::Daily => "daily",
+        }
+    }
+}
+
+/// How often the file is rotated whatever its size, see `Rotation`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// On the first record of a day, the file moved to `<path>.<date>`
+    /// after the day it was written.
+    Daily,
+}
+
+impl Period {
+    pub fn parse(name: &str) -> Option<Period> {
+        match name {
+            "daily" => Some(Period::Daily),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Period::Daily => "daily",
+        }
+    }
+}
+
+/// The strftime specifiers a path can use as placeholders, `{Y}` for `%Y`.
+const PATH_SPECIFIERS: &str = "YCymbBdejaAuwUWGgVHIMSp";
+
+/// Fills the date placeholders of a path in the local time `now`: `{date}`
+/// as `2024-05-01` and strftime's letters in braces, `{Y}`, `{m}`, `{d}`,
+/// `{H}` and so on. Others are left untouched.
+pub fn expand_path(path: &str, now: DateTime<Local>) -> String {
+    let mut fields = Map::new();
+    fields.insert(
+        String::from("date"),
+        Value::from(now.format("%Y-%m-%d").to_string()),
+    );
+    for specifier in PATH_SPECIFIERS.chars() {
+        let value = now.format(&format!("%{}", specifier)).to_string();
+        fields.insert(specifier.to_string(), Value::from(value));
+    }
+
+    template::fill(path, &fields, &Coercion::default()).message
+}
+
+/// Where the link to the latest file of `path`, as given, goes by default:
+/// next to it, named without the placeholders and with `-latest`, so
+/// `logs/app-{date}.log` has `logs/app-latest.log`.
+pub fn latest_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into());
+    let name = Regex::new(r"\{[^{}]*\}").unwrap().replace_all(&name, "");
+    let (stem, extension) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot..]),
+        _ => (&name[..], ""),
+    };
+    let stem = stem.trim_end_matches(['-', '_', '.']);
+    let latest = match stem {
+        "" => format!("latest{}", extension),
+        stem => format!("{}-latest{}", stem, extension),
+    };
+
+    path.with_file_name(latest)
+}
+
+/// Points `link` at `file` in a single rename over it, so it never dangles
+/// nor goes missing: a symlink made next to it first, to `file`'s name when
+/// they share a directory and its absolute path otherwise. Something at
+/// `link` that isn't a symlink is left alone.
+#[cfg(unix)]
+pub fn point_latest(link: &Path, file: &str) -> io::Result<()> {
+    let file = Path::new(file);
+    let target = match (link.parent(), file.parent(), file.file_name()) {
+        (Some(dir), Some(file_dir), Some(name)) if dir == file_dir => PathBuf::from(name),
+        _ => fs::canonicalize(file)?,
+    };
+
+    replace(link, |staged| std::os::unix::fs::symlink(&target, staged))
+}
+
+/// Symlinks need privileges on Windows, so `link` with a `.path` extension
+/// holds the absolute path of `file` instead, replaced in a single rename.
+#[cfg(not(unix))]
+pub fn point_latest(link: &Path, file: &str) -> io::Result<()> {
+    let target = fs::canonicalize(file)?;
+    let pointer = link.with_extension("path");
+
+    replace(&pointer, |staged| {
+        fs::write(staged, target.to_string_lossy().as_bytes())
+    })
+}
+
+/// Makes `path` anew with `make` under a name next to it and renames that
+/// over `path`, failing when what's there isn't what `point_latest` makes.
+fn replace<F>(path: &Path, make: F) -> io::Result<()>
+where
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_dir() || (cfg!(unix) && !metadata.file_type().is_symlink()) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} is there and isn't a link soda made", path.display()),
+            ));
+        }
+    }
+
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into());
+    let staged = path.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+    match fs::remove_file(&staged) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    make(&staged)?;
+
+    fs::rename(&staged, path).inspect_err(|_| {
+        let _ = fs::remove_file(&staged);
+    })
+}
+
+/// Whether `path` has placeholders `expand_path` fills.
+pub fn has_placeholders(path: &str) -> bool {
+    expand_path(path, clock::now()) != path
+}
+
+/// How `FileLogger::open` sets the handler up besides the path.
+#[derive(Default)]
+pub struct FileOptions {
+    /// Bytes held back before they are written out, see `FileLogger`.
+    pub buffer_size: usize,
+    /// Records held back at most, `0` for as many as fit in the buffer.
+    pub flush_every: usize,
+    pub rotation: Option<Rotation>,
+    pub header: Option<String>,
+    pub audit: Option<Audit>,
+    pub signing: Option<Signing>,
+    /// Encrypts the file with it, see `cipher`.
+    pub secret: Option<Secret>,
+    /// Expands a path with placeholders again as time goes by, it's
+    /// expanded once otherwise.
+    pub reexpand: Option<Reexpand>,
+    /// Writes the file as a compressed stream, see `Sink`.
+    pub compress: Option<StreamCompression>,
+    /// A symlink to keep pointing at the file written to, see `latest_path`.
+    pub latest: Option<PathBuf>,
+    /// Renders each line from this template rather than writing the message
+    /// alone.
+    pub line: Option<String>,
+}
+
+/// The longest write that goes out as it is, see `write_whole`.
+const WHOLE_WRITE: usize = 64 * 1024;
+
+/// What the file handler compresses its stream with.
+#[derive(Clone, Copy)]
+pub enum StreamCompression {
+    Gzip(Compression),
+    /// At a level of `zstd::compression_level_range`.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl StreamCompression {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StreamCompression::Gzip(_) => "gzip",
+            #[cfg(feature = "zstd")]
+            StreamCompression::Zstd(_) => "zstd",
+        }
+    }
+
+    pub fn level(self) -> i64 {
+        match self {
+            StreamCompression::Gzip(level) => level.level().into(),
+            #[cfg(feature = "zstd")]
+            StreamCompression::Zstd(level) => level.into(),
+        }
+    }
+}
+
+/// Where the handler's writes end up: the file, or a gzip or zstd stream
+/// into it.
+///
+/// The file is opened to append, so each write lands at its end in one
+/// piece, however many threads and processes write to it, and records are
+/// handed over whole: a line never ends up inside another. A compressed
+/// stream is only whole to the process writing it.
+///
+/// Flushing a stream ends a deflate block with a sync flush, so it can be
+/// decompressed up to there even if the process dies before the gzip
+/// trailer is written, `zcat` then complains of an unexpected end but gives
+/// everything it read. Rotating, switching files and dropping the handler
+/// write the trailer. A stream appended to an existing file starts another
+/// gzip member, which `zcat` reads on from the previous one. It all goes the
+/// same for zstd, a flush ending a block and the trailer ending a frame.
+enum Sink {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, File>),
+}
+
+impl Sink {
+    /// Ends the stream with its trailer.
+    fn finish(mut self) -> io::Result<()> {
+        match &mut self {
+            Sink::Plain(_) => Ok(()),
+            Sink::Gzip(encoder) => encoder.try_finish(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.do_finish(),
+        }
+    }
+}
+
+/// A gzip encoder writes its trailer when dropped, a zstd one doesn't.
+#[cfg(feature = "zstd")]
+impl Drop for Sink {
+    fn drop(&mut self) {
+        if let Sink::Zstd(encoder) = self {
+            let _ = encoder.do_finish();
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(file) if bytes.len() > WHOLE_WRITE => {
+                write_whole(file, bytes).map(|()| bytes.len())
+            }
+            Sink::Plain(file) => file.write(bytes),
+            Sink::Gzip(encoder) => encoder.write(bytes),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.write(bytes),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(file) => file.flush(),
+            Sink::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Moves the file aside to `<path>.1` once it reaches `max_bytes`, the
+/// previous backups to `<path>.2` and so on, or to a name of its `pattern`.
+/// With a `period` too it's moved to a dated name when the period is over,
+/// whichever comes first. With an `archive` the backups are named the same
+/// in its directory.
+#[derive(Clone)]
+pub struct Rotation {
+    /// `0` rotates on the `period` alone.
+    pub max_bytes: u64,
+    pub period: Option<Period>,
+    /// Backups kept at most, `0` doesn't limit their number with a
+    /// `Retention` other than `Count`, see `check`.
+    pub backup_count: usize,
+    pub retention: Retention,
+    pub pattern: Option<RotationPattern>,
+    /// Bytes the file and its backups may take up together, the oldest
+    /// backups going after a rotation until they fit, see `trim`.
+    pub budget: Option<u64>,
+    /// Called once a rotation is done, see `FileLogger::take_rotations`.
+    pub on_rotation: Option<OnRotation>,
+    pub archive: Option<Archive>,
+}
+
+/// A directory the backups are moved to rather than kept next to the file,
+/// on another file system, say.
+#[derive(Clone)]
+pub struct Archive {
+    pub dir: PathBuf,
+    /// Backups that couldn't be moved since `take_unarchived`.
+    unarchived: Arc<Mutex<Vec<Unarchived>>>,
+}
+
+/// A backup left next to the file, moving it to the archive failed.
+pub struct Unarchived {
+    pub path: PathBuf,
+    pub error: io::Error,
+}
+
+impl Archive {
+    pub fn new(dir: &str) -> Archive {
+        Archive {
+            dir: PathBuf::from(dir),
+            unarchived: Arc::default(),
+        }
+    }
+
+    pub fn take_unarchived(&self) -> Vec<Unarchived> {
+        std::mem::take(&mut *self.unarchived.lock().unwrap())
+    }
+}
+
+/// Called with the backup a rotation made, if it kept one, and the file
+/// written to from then on.
+pub type OnRotation = Arc<dyn Fn(Option<&Path>, &str) + Send + Sync>;
+
+/// A rotation of the file.
+#[derive(Clone)]
+pub struct Rotated {
+    pub time: DateTime<Local>,
+    /// What the file was moved to, `None` when the backup went to stay
+    /// within budget.
+    pub backup: Option<PathBuf>,
+    /// The file written to after.
+    pub path: String,
+}
+
+/// Backups `Rotation::trim` deleted to stay within its budget.
+pub struct Pruned {
+    pub deleted: Vec<PathBuf>,
+    pub bytes: u64,
+    pub budget: u64,
+}
+
+/// The names of a file's backups, e.g. `app-%Y%m%d-{seq:03}.log`: strftime
+/// specifiers filled in with the local time of the rotation, and a `{seq}`
+/// sequence number, `{seq:03}` zero-padded to three digits. A backup is
+/// numbered one past the latest with the same time, so rotating twice
+/// within the pattern's granularity never overwrites one. Backups live next
+/// to the file.
+#[derive(Clone)]
+pub struct RotationPattern {
+    pattern: String,
+    /// The pattern around the sequence number.
+    before: String,
+    after: String,
+    width: usize,
+    /// Matches the names of every backup, whatever its time, the sequence
+    /// number captured.
+    names: Regex,
+}
+
+impl RotationPattern {
+    /// Fails with why when `pattern` isn't one.
+    pub fn parse(pattern: &str) -> Result<RotationPattern, String> {
+        let seq = Regex::new(r"\{seq(?::0(\d+))?\}").unwrap();
+
+        let mut found = seq.captures_iter(pattern);
+        let (placeholder, width) = match (found.next(), found.next()) {
+            (Some(captures), None) => (
+                captures.get(0).unwrap(),
+                captures
+                    .get(1)
+                    .map_or(Ok(0), |width| width.as_str().parse()),
+            ),
+            (None, _) => return Err(String::from("a rotation pattern needs a {seq} placeholder")),
+            _ => {
+                return Err(String::from(
+                    "a rotation pattern takes one {seq} placeholder",
+                ))
+            }
+        };
+        let width = width.map_err(|_| String::from("{seq} has too wide a width"))?;
+
+        let before = &pattern[..placeholder.start()];
+        let after = &pattern[placeholder.end()..];
+        for part in [before, after] {
+            if StrftimeItems::new(part).any(|item| matches!(item, Item::Error)) {
+                return Err(format!(
+                    "rotation pattern {:?} isn't a valid strftime format",
+                    pattern
+                ));
+            }
+        }
+        let sample = clock::now().format(pattern).to_string();
+        if sample.contains('/') || sample.contains(std::path::MAIN_SEPARATOR) {
+            return Err(String::from(
+                "a rotation pattern is a file name, without directories",
+            ));
+        }
+
+        let names = format!("^{}(\\d+){}$", wildcards(before), wildcards(after));
+
+        Ok(RotationPattern {
+            pattern: pattern.to_string(),
+            before: before.to_string(),
+            after: after.to_string(),
+            width,
+            names: Regex::new(&names).map_err(|e| e.to_string())?,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The name of a backup made in `dir` at `now`.
+    fn next(&self, dir: &Path, now: DateTime<Local>) -> io::Result<PathBuf> {
+        let before = now.format(&self.before).to_string();
+        let after = now.format(&self.after).to_string();
+
+        let mut seq = 0;
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(before.as_str()))
+                .and_then(|name| name.strip_suffix(after.as_str()))
+                .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|n| n.parse::<u64>().ok());
+            if let Some(number) = number {
+                seq = seq.max(number);
+            }
+        }
+
+        loop {
+            seq += 1;
+            let name = format!("{}{:0width$}{}", before, seq, after, width = self.width);
+            let backup = dir.join(name);
+            if !backup.exists() {
+                return Ok(backup);
+            }
+        }
+    }
+
+    /// The backups in `dir` besides `file`, newest first, with when they
+    /// were last written to and their size.
+    fn backups(&self, dir: &Path, file: &Path) -> io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+        let mut backups = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let seq = match name.to_str().and_then(|name| self.names.captures(name)) {
+                Some(captures) => captures[1].parse::<u64>().unwrap_or(0),
+                None => continue,
+            };
+            if Some(name.as_os_str()) == file.file_name() {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            backups.push((modified, seq, entry.path(), metadata.len()));
+        }
+        backups.sort_by_key(|(modified, seq, _, _)| Reverse((*modified, *seq)));
+
+        Ok(backups
+            .into_iter()
+            .map(|(modified, _, path, size)| (path, modified, size))
+            .collect())
+    }
+}
+
+/// A regex matching what `format` renders to at any time, its specifiers
+/// matching anything.
+pub fn wildcards(format: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            regex.push_str(&regex::escape(&c.to_string()));
+            continue;
+        }
+        // Padding and width modifiers, then the specifier.
+        match chars.find(|c| !matches!(c, '-' | '_' | '0'..='9' | '.' | ':' | '#')) {
+            Some('%') => regex.push('%'),
+            _ => regex.push_str(".+?"),
+        }
+    }
+
+    regex
+}
+
+/// Which backups a rotation deletes.
+#[derive(Clone, Copy)]
+pub enum Retention {
+    /// The oldest ones past `backup_count`.
+    Count,
+    /// The oldest ones until the backups add up to at most this many bytes.
+    TotalSize(u64),
+    /// The ones last written to longer ago than this.
+    MaxAge(Duration),
+}
+
+impl Retention {
+    /// `total_size` is the cap of `"total_size"`, `max_age` the age of
+    /// `"max_age"`.
+    pub fn parse(name: &str, total_size: u64, max_age: Duration) -> Option<Retention> {
+        match name {
+            "delete_oldest" => Some(Retention::Count),
+            "total_size" if total_size > 0 => Some(Retention::TotalSize(total_size)),
+            "max_age" if !max_age.is_zero() => Some(Retention::MaxAge(max_age)),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Retention::Count => "delete_oldest",
+            Retention::TotalSize(_) => "total_size",
+            Retention::MaxAge(_) => "max_age",
+        }
+    }
+}
+
+/// When a file last written to before it is `max_age` old.
+pub fn cutoff(max_age: Duration) -> SystemTime {
+    clock::system_now()
+        .checked_sub(max_age)
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+impl Rotation {
+    /// Fails when rotating would delete the file: with `Retention::Count`
+    /// and a `backup_count` of `0` no backup would be kept of it.
+    pub fn check(&self) -> io::Result<()> {
+        let sized = self.max_bytes > 0 || self.pattern.is_some();
+        if sized && self.limit() == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "rotating with a backup_count of 0 would delete the file each time, \
+                 keep at least 1 backup",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn limit(&self) -> usize {
+        match (self.retention, self.backup_count) {
+            (Retention::TotalSize(_) | Retention::MaxAge(_), 0) => usize::MAX,
+            (_, count) => count,
+        }
+    }
+
+    /// Returns the backup the file was moved to, if any.
+    fn rotate(&self, path: &str) -> io::Result<Option<PathBuf>> {
+        if let Some(pattern) = &self.pattern {
+            return self.rotate_to(pattern, path);
+        }
+        let base = self.base(path)?;
+        let backup = |n: usize| PathBuf::from(format!("{}.{}", base, n));
+        let limit = self.limit();
+
+        let mut existing = 0;
+        while backup(existing + 1).exists() {
+            existing += 1;
+        }
+
+        for n in (1..=existing).rev() {
+            if n >= limit {
+                fs::remove_file(backup(n))?;
+            } else {
+                fs::rename(backup(n), backup(n + 1))?;
+            }
+        }
+
+        if let Some(left) = self.put(path, backup(1))? {
+            return Ok(Some(left));
+        }
+
+        if let Retention::TotalSize(cap) = self.retention {
+            let mut total = 0;
+            let mut n = 1;
+            while let Ok(metadata) = fs::metadata(backup(n)) {
+                total += metadata.len();
+                if total > cap {
+                    break;
+                }
+                n += 1;
+            }
+
+            while backup(n).exists() {
+                fs::remove_file(backup(n))?;
+                n += 1;
+            }
+        }
+
+        // The older a backup the higher its number, from the first expired
+        // one on they all go.
+        if let Retention::MaxAge(max_age) = self.retention {
+            let cutoff = cutoff(max_age);
+            let mut n = 1;
+            while fs::metadata(backup(n))
+                .and_then(|m| m.modified())
+                .is_ok_and(|m| m >= cutoff)
+            {
+                n += 1;
+            }
+
+            while backup(n).exists() {
+                fs::remove_file(backup(n))?;
+                n += 1;
+            }
+        }
+
+        Ok(Some(backup(1)).filter(|backup| backup.exists()))
+    }
+
+    /// `rotate` at the end of a period, the file written on `day` moved to
+    /// `<path>.<day>`, or `<path>.<day>.1` and so on if that's taken. These
+    /// backups go by the same `retention`, apart from the numbered ones, all
+    /// of them kept with a `backup_count` of `0`. With a `pattern` it's
+    /// `rotate` all the same.
+    fn rotate_dated(&self, path: &str, day: NaiveDate) -> io::Result<Option<PathBuf>> {
+        if let Some(pattern) = &self.pattern {
+            return self.rotate_to(pattern, path);
+        }
+
+        let base = self.base(path)?;
+        let dated = format!("{}.{}", base, day.format("%Y-%m-%d"));
+        let mut rotated = PathBuf::from(&dated);
+        let mut n = 0;
+        while rotated.exists() {
+            n += 1;
+            rotated = PathBuf::from(format!("{}.{}", dated, n));
+        }
+        if let Some(left) = self.put(path, rotated.clone())? {
+            return Ok(Some(left));
+        }
+
+        let file = Path::new(&base);
+        let limit = match self.backup_count {
+            0 => usize::MAX,
+            count => count,
+        };
+        self.retain(dated_backups(parent(file), file)?, limit)?;
+
+        Ok(Some(rotated).filter(|rotated| rotated.exists()))
+    }
+
+    /// `rotate` with the backups named after `pattern`, the oldest by when
+    /// they were last written to going first.
+    fn rotate_to(&self, pattern: &RotationPattern, path: &str) -> io::Result<Option<PathBuf>> {
+        let base = self.base(path)?;
+        let file = Path::new(&base);
+        let dir = parent(file);
+
+        let limit = self.limit();
+        let rotated = pattern.next(dir, clock::now())?;
+        if let Some(left) = self.put(path, rotated.clone())? {
+            return Ok(Some(left));
+        }
+        self.retain(pattern.backups(dir, file)?, limit)?;
+
+        Ok(Some(rotated).filter(|rotated| rotated.exists()))
+    }
+
+    /// The path the backups of `path` are named after, the same one in the
+    /// archive's directory, which is created if it's missing.
+    fn base(&self, path: &str) -> io::Result<String> {
+        let archive = match &self.archive {
+            Some(archive) => archive,
+            None => return Ok(path.to_string()),
+        };
+        fs::create_dir_all(&archive.dir)?;
+        let name = Path::new(path).file_name().unwrap_or_default();
+
+        Ok(archive.dir.join(name).to_string_lossy().into_owned())
+    }
+
+    /// Moves the file at `path` to `backup`. A backup bound for the archive
+    /// is renamed next to the file first, and left there should moving it
+    /// fail, returned along with the error kept for `take_unarchived`.
+    fn put(&self, path: &str, backup: PathBuf) -> io::Result<Option<PathBuf>> {
+        let archive = match &self.archive {
+            Some(archive) => archive,
+            None => return fs::rename(path, backup).map(|_| None),
+        };
+
+        let staged = parent(Path::new(path)).join(backup.file_name().unwrap_or_default());
+        let mut left = staged.clone();
+        let mut n = 0;
+        while left.exists() {
+            n += 1;
+            left = PathBuf::from(format!("{}.{}", staged.display(), n));
+        }
+        fs::rename(path, &left)?;
+
+        match move_file(&left, &backup) {
+            Ok(()) => Ok(None),
+            Err(error) => {
+                let unarchived = Unarchived {
+                    path: left.clone(),
+                    error,
+                };
+                archive.unarchived.lock().unwrap().push(unarchived);
+                Ok(Some(left))
+            }
+        }
+    }
+
+    /// Deletes the `backups`, newest first, past the `limit` or the
+    /// `retention`.
+    fn retain(&self, backups: Vec<(PathBuf, SystemTime, u64)>, limit: usize) -> io::Result<()> {
+        let mut total = 0;
+        for (n, (backup, modified, size)) in backups.into_iter().enumerate() {
+            total += size;
+            let over = match self.retention {
+                Retention::TotalSize(cap) => total > cap,
+                Retention::MaxAge(max_age) => modified < cutoff(max_age),
+                Retention::Count => false,
+            };
+            if n >= limit || over {
+                fs::remove_file(backup)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the oldest backups of `path` until they and the `active`
+    /// bytes of the file itself add up to at most the budget. The file is
+    /// never deleted, even when it alone is over.
+    fn trim(&self, path: &str, active: u64) -> io::Result<Option<Pruned>> {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return Ok(None),
+        };
+
+        let mut backups = self.backups(&self.base(path)?)?;
+        let mut total = active + backups.iter().map(|(_, size)| size).sum::<u64>();
+        let mut pruned = Pruned {
+            deleted: Vec::new(),
+            bytes: 0,
+            budget,
+        };
+
+        while total > budget {
+            let (backup, size) = match backups.pop() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            fs::remove_file(&backup)?;
+            total -= size;
+            pruned.bytes += size;
+            pruned.deleted.push(backup);
+        }
+
+        Ok(Some(pruned).filter(|pruned| !pruned.deleted.is_empty()))
+    }
+
+    /// The backups of `path` with their size, newest first.
+    fn backups(&self, path: &str) -> io::Result<Vec<(PathBuf, u64)>> {
+        if let Some(pattern) = &self.pattern {
+            let file = Path::new(path);
+            return Ok(pattern
+                .backups(parent(file), file)?
+                .into_iter()
+                .map(|(backup, _, size)| (backup, size))
+                .collect());
+        }
+
+        let mut backups = Vec::new();
+        while let Ok(metadata) = fs::metadata(format!("{}.{}", path, backups.len() + 1)) {
+            let backup = PathBuf::from(format!("{}.{}", path, backups.len() + 1));
+            backups.push((backup, metadata.len()));
+        }
+
+        Ok(backups)
+    }
+}
+
+/// The backups `Rotation::rotate_dated` made of `file` in `dir`, newest
+/// first, with when they were last written to and their size.
+fn dated_backups(dir: &Path, file: &Path) -> io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let names = format!(
+        r"^{}\.(\d{{4}}-\d{{2}}-\d{{2}})(?:\.(\d+))?$",
+        regex::escape(&name)
+    );
+    let names = Regex::new(&names).unwrap();
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let entry_name = entry.file_name().to_string_lossy().into_owned();
+        let captures = match names.captures(&entry_name) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let day = captures[1].to_string();
+        let seq: u64 = captures
+            .get(2)
+            .map_or(0, |seq| seq.as_str().parse().unwrap_or(0));
+
+        let metadata = entry.metadata()?;
+        backups.push((
+            (day, seq),
+            entry.path(),
+            metadata.modified()?,
+            metadata.len(),
+        ));
+    }
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+
+    Ok(backups
+        .into_iter()
+        .map(|(_, path, modified, size)| (path, modified, size))
+        .collect())
+}
+
+/// Renames `from` to `to`, or copies it over when they're on different file
+/// systems: to a temporary name next to `to`, synced to disk and renamed into
+/// place, and only then is `from` removed. A failure leaves `from` as it
+/// was.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        moved => return moved,
+    }
+
+    let name = to.file_name().unwrap_or_default().to_string_lossy();
+    let partial = to.with_file_name(format!(".{}.partial", name));
+    let copied = fs::copy(from, &partial)
+        .and_then(|_| File::open(&partial)?.sync_all())
+        .and_then(|_| fs::rename(&partial, to));
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    fs::remove_file(from)
+}
+
+/// Moves `path` to `<path>.<time>`, `<path>.<time>.1` and so on if that's
+/// taken.
+fn rename_timestamped(path: &str) -> io::Result<PathBuf> {
+    let stamped = format!("{}.{}", path, clock::now().format("%Y%m%d-%H%M%S"));
+    let mut renamed = PathBuf::from(&stamped);
+    let mut n = 0;
+    while renamed.exists() {
+        n += 1;
+        renamed = PathBuf::from(format!("{}.{}", stamped, n));
+    }
+    fs::rename(path, &renamed)?;
+
+    Ok(renamed)
+}
+
+/// The directory `file` is in, `.` for a bare name.
+fn parent(file: &Path) -> &Path {
+    match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+impl Default for FileLogger {
+    fn default() -> FileLogger {
+        FileLogger {
+            enabled: false,
+            path: RwLock::new(String::from("default.log")),
+            template: None,
+            reexpand: None,
+            expanded_on: Mutex::new(None),
+            buffer_size: 0,
+            flush_every: 0,
+            unflushed: AtomicUsize::new(0),
+            rotation: None,
+            header: None,
+            audit: None,
+            signing: None,
+            encryption: None,
+            compress: None,
+            latest: None,
+            line: None,
+            pruned: Mutex::new(None),
+            last_rotation: Mutex::new(None),
+            rotations: Mutex::new(Vec::new()),
+            started_on: Mutex::new(clock::now().date_naive()),
+            writer: Mutex::new(None),
+            size: AtomicU64::new(0),
+            chain: Mutex::new(String::new()),
+        }
+    }
+}
+
+impl FileLogger {
+    /// Points the handler at `path`, creating the file, and its directories,
+    /// if it's missing. A path with date placeholders is expanded now, see
+    /// `expand_path`. With `audit` a file that isn't empty carries on from
+    /// its last hash, it fails when there's none. With a `secret` such a file
+    /// must be one it encrypted. The `latest` link goes to it, and to each
+    /// file the handler moves on to.
+    ///
+    /// The handler only moves over once the new file is ready, writing out
+    /// what it held for the last one then. Should opening it fail, the
+    /// handler is left writing where it was, as it was.
+    pub fn open(&mut self, path: &str, options: FileOptions) -> io::Result<()> {
+        let opened = FileLogger::opened(path, options)?;
+
+        self.flush();
+        let previous = std::mem::replace(self, opened);
+        self.pruned = previous.pruned;
+        self.last_rotation = previous.last_rotation;
+        self.rotations = previous.rotations;
+
+        Ok(())
+    }
+
+    /// A handler writing to `path`, see `open`.
+    fn opened(path: &str, options: FileOptions) -> io::Result<FileLogger> {
+        if let Some(rotation) = &options.rotation {
+            rotation.check()?;
+        }
+        let mut file = FileLogger::default();
+        let now = clock::now();
+        let template = Some(path.to_string()).filter(|path| has_placeholders(path));
+        let expanded = expand_path(path, now);
+        let path = expanded.as_str();
+        create_parent(path)?;
+
+        if let Err(error) = File::open(path) {
+            match error.kind() {
+                ErrorKind::NotFound => {
+                    File::create(path)?;
+                }
+                _ => return Err(error),
+            }
+        }
+
+        // A stream is kept open, and flushed after each record when nothing
+        // is held back.
+        file.compress = options.compress;
+        let mut writer = match (options.buffer_size, options.compress) {
+            (0, None) => None,
+            (capacity, _) => Some(BufWriter::with_capacity(
+                capacity,
+                file.sink(OpenOptions::new().append(true).open(path)?)?,
+            )),
+        };
+
+        file.enabled = true;
+        *file.path.get_mut().unwrap() = path.to_string();
+        file.template = template;
+        file.reexpand = options.reexpand;
+        *file.expanded_on.get_mut().unwrap() = Some(now.date_naive());
+        file.buffer_size = options.buffer_size;
+        file.flush_every = options.flush_every;
+        file.rotation = options.rotation;
+        file.header = options.header;
+        file.latest = options.latest;
+        file.line = options.line;
+        let metadata = fs::metadata(path)?;
+        file.size = AtomicU64::new(metadata.len());
+        *file.started_on.get_mut().unwrap() = match metadata.len() {
+            0 => now.date_naive(),
+            _ => DateTime::<Local>::from(metadata.modified()?).date_naive(),
+        };
+        file.audit = options.audit;
+        file.signing = options.signing;
+
+        let fresh = metadata.len() == 0;
+        file.encryption = match &options.secret {
+            Some(secret) if fresh => Some(Encryption::new(secret)?),
+            Some(secret) => Some(Encryption::existing(secret, path)?),
+            None => None,
+        };
+        if fresh {
+            file.start_file(&mut writer)?;
+        }
+
+        match &file.audit {
+            Some(audit) if fresh => file.start_chain(&mut writer, &audit.genesis)?,
+            Some(_) => *file.chain.get_mut().unwrap() = last_hash(path, file.encryption.as_ref())?,
+            None => {}
+        }
+
+        file.write_header(&mut writer)?;
+        *file.writer.get_mut().unwrap() = writer;
+        if let Some(link) = &file.latest {
+            point_latest(link, path)?;
+        }
+
+        Ok(file)
+    }
+
+    /// The file written to.
+    pub fn path(&self) -> String {
+        self.path.read().unwrap().clone()
+    }
+
+    /// Writes the line of a record logged at `time`, which picks the day's
+    /// file too, so a record written just past midnight still goes with the
+    /// day it's stamped with. A record stamped before the day the file was
+    /// started, from a thread that was slow to get here, stays in it.
+    pub fn logger(&self, time: DateTime<Local>, message: &str) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(path) = self.reexpanded(time) {
+            self.reopen(&mut writer, path, |_| Ok(()))?;
+        }
+
+        // A record of a new period goes to a new file, only then is its size
+        // counted, so one record never sets off both.
+        let today = time.date_naive();
+        let ended = *self.started_on.lock().unwrap();
+        if let Some(rotation) = &self.rotation {
+            if rotation.period.is_some() && ended < today {
+                let path = self.path();
+                let mut backup = None;
+                self.reopen(&mut writer, path.clone(), |path| {
+                    backup = rotation.rotate_dated(path, ended)?;
+                    Ok(())
+                })?;
+                *self.started_on.lock().unwrap() = today;
+                self.rotated(rotation, path, backup, time)?;
+            }
+        }
+
+        self.write(&mut writer, message)?;
+        let flush = match self.flush_every {
+            0 => self.compress.is_some() && self.buffer_size == 0,
+            every => self.unflushed.load(Ordering::Relaxed) >= every,
+        };
+        if let Some(writer) = writer.as_mut().filter(|_| flush) {
+            writer.flush()?;
+            self.unflushed.store(0, Ordering::Relaxed);
+        }
+
+        let rotation = match &self.rotation {
+            Some(rotation) => rotation,
+            None => return Ok(()),
+        };
+
+        if rotation.max_bytes == 0 || self.size.load(Ordering::Relaxed) < rotation.max_bytes {
+            return Ok(());
+        }
+
+        let path = self.path();
+        let mut backup = None;
+        self.reopen(&mut writer, path.clone(), |path| {
+            backup = rotation.rotate(path)?;
+            Ok(())
+        })?;
+
+        self.rotated(rotation, path, backup, time)
+    }
+
+    /// Enforces the budget after a rotation of `path` to `backup` at `time`
+    /// and records it.
+    fn rotated(
+        &self,
+        rotation: &Rotation,
+        path: String,
+        backup: Option<PathBuf>,
+        time: DateTime<Local>,
+    ) -> io::Result<()> {
+        if let Some(pruned) = rotation.trim(&path, self.size.load(Ordering::Relaxed))? {
+            let mut previous = self.pruned.lock().unwrap();
+            match previous.as_mut() {
+                Some(previous) => {
+                    previous.bytes += pruned.bytes;
+                    previous.deleted.extend(pruned.deleted);
+                }
+                None => *previous = Some(pruned),
+            }
+        }
+
+        let rotated = Rotated {
+            time,
+            backup: backup.filter(|backup| backup.exists()),
+            path,
+        };
+        if rotation.on_rotation.is_some() {
+            self.rotations.lock().unwrap().push(rotated.clone());
+        }
+        *self.last_rotation.lock().unwrap() = Some(rotated);
+
+        Ok(())
+    }
+
+    /// Rotates the file now, however big and old it is: the way `rotation`
+    /// does on its size, or its period without a `max_bytes`, and to
+    /// `<path>.<time>` without a `rotation`. Returns the backup, `None` once
+    /// the rotation deleted it, nothing when the file is empty. A record
+    /// being written finishes first, in the file it went to.
+    pub fn rotate_now(&self) -> io::Result<Option<Option<PathBuf>>> {
+        let mut writer = self.writer.lock().unwrap();
+        if self.size.load(Ordering::Relaxed) == 0 {
+            return Ok(None);
+        }
+
+        let path = self.path();
+        let mut backup = None;
+        let started_on = *self.started_on.lock().unwrap();
+        self.reopen(&mut writer, path.clone(), |path| {
+            backup = match &self.rotation {
+                Some(rotation) if rotation.max_bytes == 0 => {
+                    rotation.rotate_dated(path, started_on)?
+                }
+                Some(rotation) => rotation.rotate(path)?,
+                None => Some(rename_timestamped(path)?),
+            };
+            Ok(())
+        })?;
+        let now = clock::now();
+        *self.started_on.lock().unwrap() = now.date_naive();
+
+        match &self.rotation {
+            Some(rotation) => self.rotated(rotation, path, backup.clone(), now)?,
+            None => {
+                *self.last_rotation.lock().unwrap() = Some(Rotated {
+                    time: now,
+                    backup: backup.clone(),
+                    path,
+                })
+            }
+        }
+
+        Ok(Some(backup.filter(|backup| backup.exists())))
+    }
+
+    /// The rotations since the last call, to run `Rotation::on_rotation`
+    /// for once the handlers are unlocked, oldest first.
+    pub fn take_rotations(&self) -> Vec<Rotated> {
+        std::mem::take(&mut *self.rotations.lock().unwrap())
+    }
+
+    pub fn last_rotation(&self) -> Option<Rotated> {
+        self.last_rotation.lock().unwrap().clone()
+    }
+
+    /// What rotating deleted to stay within budget since the last call.
+    pub fn take_pruned(&self) -> Option<Pruned> {
+        self.pruned.lock().unwrap().take()
+    }
+
+    /// The backups rotating couldn't move to the archive since the last
+    /// call.
+    pub fn take_unarchived(&self) -> Vec<Unarchived> {
+        match self
+            .rotation
+            .as_ref()
+            .and_then(|rotation| rotation.archive.as_ref())
+        {
+            Some(archive) => archive.take_unarchived(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The path `template` expands to at `time`, when it's a day after the
+    /// last expansion and that changed it.
+    fn reexpanded(&self, time: DateTime<Local>) -> Option<String> {
+        let template = self.template.as_ref()?;
+        match self.reexpand? {
+            Reexpand::Daily => {
+                let mut expanded_on = self.expanded_on.lock().unwrap();
+                if expanded_on.is_some_and(|day| day >= time.date_naive()) {
+                    return None;
+                }
+                *expanded_on = Some(time.date_naive());
+
+                Some(expand_path(template, time)).filter(|path| *path != self.path())
+            }
+        }
+    }
+
+    /// Ends the file being written, runs `done` on its path and carries on
+    /// in `path`: a new file's chain starts from the end of that one, a file
+    /// already there carries on from its own.
+    fn reopen<F>(
+        &self,
+        writer: &mut Option<BufWriter<Sink>>,
+        path: String,
+        done: F,
+    ) -> io::Result<()>
+    where
+        F: FnOnce(&str) -> io::Result<()>,
+    {
+        let last = self.chain.lock().unwrap().clone();
+        if self.audit.is_some() {
+            self.append(writer, &format!("{}{}", END_PREFIX, last))?;
+        }
+        let buffered = writer.is_some();
+        if let Some(writer) = writer.take() {
+            writer.into_inner().map_err(|e| e.into_error())?.finish()?;
+            self.unflushed.store(0, Ordering::Relaxed);
+        }
+        done(&self.path())?;
+
+        create_parent(&path)?;
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        if buffered {
+            *writer = Some(BufWriter::with_capacity(self.buffer_size, self.sink(file)?));
+        }
+        self.size.store(size, Ordering::Relaxed);
+        let moved = path != self.path();
+        *self.path.write().unwrap() = path;
+        if let Some(link) = self.latest.as_ref().filter(|_| moved) {
+            // The record is written all the same.
+            if let Err(e) = point_latest(link, &self.path()) {
+                eprintln!(
+                    "soda: couldn't point {} at {}: {}",
+                    link.display(),
+                    self.path(),
+                    e
+                );
+            }
+        }
+
+        if size == 0 {
+            self.start_file(writer)?;
+            if self.audit.is_some() {
+                self.start_chain(writer, &last)?;
+            }
+        } else if self.audit.is_some() {
+            *self.chain.lock().unwrap() = last_hash(&self.path(), self.encryption.as_ref())?;
+        }
+        self.write_header(writer)
+    }
+
+    /// Writes `line`, ended with its hash with `audit` or its signature with
+    /// `signing`.
+    fn write(&self, writer: &mut Option<BufWriter<Sink>>, line: &str) -> io::Result<()> {
+        if let Some(signing) = &self.signing {
+            return self.append(writer, &format!("{} {}", line, signing.sign(line)));
+        }
+        if self.audit.is_none() {
+            return self.append(writer, line);
+        }
+
+        let mut chain = self.chain.lock().unwrap();
+        let hash = link(&chain, line);
+        self.append(writer, &format!("{} {}", line, hash))?;
+        *chain = hash;
+
+        Ok(())
+    }
+
+    fn start_chain(&self, writer: &mut Option<BufWriter<Sink>>, genesis: &str) -> io::Result<()> {
+        self.append(writer, &format!("{}{}", GENESIS_PREFIX, genesis))?;
+        *self.chain.lock().unwrap() = genesis.to_string();
+
+        Ok(())
+    }
+
+    /// Writes `line`, as a frame when the file is encrypted.
+    fn append(&self, writer: &mut Option<BufWriter<Sink>>, line: &str) -> io::Result<()> {
+        let line = format!("{}\n", line);
+
+        match &self.encryption {
+            Some(encryption) => self.append_bytes(writer, &encryption.seal(line.as_bytes())?),
+            None => self.append_bytes(writer, line.as_bytes()),
+        }
+    }
+
+    /// Writes `bytes`, a whole record, through the buffer if there's one,
+    /// which only ever writes out whole records, straight to the file
+    /// otherwise, see `write_whole`.
+    fn append_bytes(&self, writer: &mut Option<BufWriter<Sink>>, bytes: &[u8]) -> io::Result<()> {
+        match writer.as_mut() {
+            Some(writer) => {
+                let mut pending = self.unflushed.load(Ordering::Relaxed);
+                super::buffer_record(writer, bytes, &mut pending)?;
+                self.unflushed.store(pending, Ordering::Relaxed);
+            }
+            None => write_whole(
+                &mut OpenOptions::new()
+                    .append(true)
+                    .open(&*self.path.read().unwrap())?,
+                bytes,
+            )?,
+        }
+        self.size.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// What a new encrypted file starts with, nothing for another one.
+    fn start_file(&self, writer: &mut Option<BufWriter<Sink>>) -> io::Result<()> {
+        match &self.encryption {
+            Some(encryption) => self.append_bytes(writer, &encryption.preamble()),
+            None => Ok(()),
+        }
+    }
+
+    fn write_header(&self, writer: &mut Option<BufWriter<Sink>>) -> io::Result<()> {
+        match &self.header {
+            Some(template) => self.write(writer, &header(template, &self.path())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn flush(&self) {
+        if let Some(writer) = self.writer.lock().unwrap().as_mut() {
+            if writer.flush().is_ok() {
+                self.unflushed.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Like `flush`, giving up after `timeout` on a record being written,
+    /// see `lock_within`. Returns the lines written out.
+    pub fn flush_within(&self, timeout: Duration) -> usize {
+        let mut writer = match super::lock_within(&self.writer, timeout) {
+            Some(writer) => writer,
+            None => return 0,
+        };
+        match writer.as_mut().map(|writer| writer.flush()) {
+            Some(Ok(())) => self.unflushed.swap(0, Ordering::Relaxed),
+            _ => 0,
+        }
+    }
+
+    /// The last `n` lines of the file, what's buffered written out first,
+    /// see `tail::tail`. Lines come back as written, a compressed or
+    /// encrypted file's aren't text.
+    pub fn tail(&self, n: usize) -> io::Result<Vec<String>> {
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(writer) = writer.as_mut() {
+            writer.flush()?;
+            self.unflushed.store(0, Ordering::Relaxed);
+        }
+
+        tail::tail(&self.path(), n)
+    }
+
+    fn sink(&self, file: File) -> io::Result<Sink> {
+        Ok(match self.compress {
+            Some(StreamCompression::Gzip(level)) => Sink::Gzip(GzEncoder::new(file, level)),
+            #[cfg(feature = "zstd")]
+            Some(StreamCompression::Zstd(level)) => {
+                Sink::Zstd(zstd::stream::write::Encoder::new(file, level)?)
+            }
+            None => Sink::Plain(file),
+        })
+    }
+}
+
+/// Appends `bytes` to `file`, opened to append, in a single write when it's
+/// up to `WHOLE_WRITE` bytes. The kernel may take a longer write in pieces,
+/// so it's written holding the file's lock, which soda's other writers of
+/// long lines to the file, in this process or another, wait for.
+fn write_whole(file: &mut File, bytes: &[u8]) -> io::Result<()> {
+    if bytes.len() <= WHOLE_WRITE {
+        return file.write_all(bytes);
+    }
+
+    file.lock()?;
+    let written = file.write_all(bytes);
+    file.unlock()?;
+
+    written
+}
+
+/// Creates the directories `path` is in, if they're missing.
+fn create_parent(path: &str) -> io::Result<()> {
+    match Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Fills a header template's `{time}` (RFC 3339, when the file was opened),
+/// `{pid}`, `{version}` (soda's) and `{path}` placeholders.
+fn header(template: &str, path: &str) -> String {
+    let mut fields = Map::new();
+    fields.insert(
+        String::from("time"),
+        Value::from(clock::now().to_rfc3339_opts(SecondsFormat::Millis, false)),
+    );
+    fields.insert(String::from("pid"), Value::from(process::id()));
+    fields.insert(
+        String::from("version"),
+        Value::from(env!("CARGO_PKG_VERSION")),
+    );
+    fields.insert(String::from("path"), Value::from(path));
+
+    template::fill(template, &fields, &Coercion::default()).message
+}
+
+/// The hash of the line following the one hashed to `previous`.
+fn link(previous: &str, line: &str) -> String {
+    let hash = digest(&SHA256, format!("{}{}", previous, line).as_bytes());
+
+    to_hex(hash.as_ref())
+}
+
+fn is_hash(text: &str) -> bool {
+    text.len() == 64 && text.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Splits a written line into the line as logged and its hash.
+fn split_hash(line: &str) -> Option<(&str, &str)> {
+    line.rsplit_once(' ').filter(|(_, hash)| is_hash(hash))
+}
+
+/// The hash an audit file's chain carries on from.
+fn last_hash(path: &str, encryption: Option<&Encryption>) -> io::Result<String> {
+    let lines: Vec<String> = match encryption {
+        Some(encryption) => String::from_utf8_lossy(&encryption.decrypt(path)?.text)
+            .lines()
+            .map(String::from)
+            .collect(),
+        None => BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<io::Result<_>>()?,
+    };
+    let last = lines.into_iter().rev().find(|line| !line.is_empty());
+
+    let hash = last
+        .as_deref()
+        .and_then(|line| match line.strip_prefix(GENESIS_PREFIX) {
+            Some(genesis) => Some(genesis),
+            None if line.starts_with(END_PREFIX) => None,
+            None => split_hash(line).map(|(_, hash)| hash),
+        });
+
+    hash.map(String::from).ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} doesn't end with an audit hash", path),
+        )
+    })
+}
+
+/// Where and how an audit file's chain breaks, see `verify`.
+pub struct Broken {
+    /// Counted from 1.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+/// Walks the chain of an audit file written by the file handler, returns
+/// the first line it breaks at, `None` when it holds. With `genesis` the
+/// chain must start from it, a rotated file's next starts from its end.
+///
+/// A message spanning lines is hashed as a whole, the hash ends its last
+/// line.
+pub fn verify(path: &str, genesis: Option<&str>) -> io::Result<Option<Broken>> {
+    let broken = |line, reason| Ok(Some(Broken { line, reason }));
+    let mut lines = BufReader::new(File::open(path)?).lines();
+
+    let first = lines.next().transpose()?;
+    let mut chain = match first
+        .as_deref()
+        .and_then(|line| line.strip_prefix(GENESIS_PREFIX))
+    {
+        Some(start) if genesis.is_none_or(|genesis| genesis == start) => start.to_string(),
+        Some(_) => return broken(1, "the chain starts from another genesis"),
+        None => return broken(1, "no genesis line"),
+    };
+
+    // The lines of a message whose hash is yet to come.
+    let mut pending = String::new();
+    let mut ended = false;
+    let mut number = 1;
+
+    for line in lines {
+        let line = line?;
+        number += 1;
+
+        if ended {
+            return broken(number, "lines after the end of the chain");
+        }
+
+        if let Some(end) = line.strip_prefix(END_PREFIX) {
+            if !pending.is_empty() || end != chain {
+                return broken(number, "the end doesn't match the last hash");
+            }
+            ended = true;
+            continue;
+        }
+
+        match split_hash(&line) {
+            Some((content, hash)) => {
+                pending.push_str(content);
+                if link(&chain, &pending) != hash {
+                    return broken(number, "the hash doesn't match");
+                }
+                chain = hash.to_string();
+                pending.clear();
+            }
+            None => {
+                pending.push_str(&line);
+                pending.push('\n');
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        return broken(number, "the last line has no hash");
+    }
+
+    Ok(None)
+}
+
+/// What `verify_signed` found in a signed file.
+pub struct Signed {
+    pub valid: usize,
+    /// The lines whose signature doesn't match, counted from 1.
+    pub invalid: Vec<usize>,
+}
+
+/// Checks the signature of every line of a file written by the file handler
+/// with `signing`. A message spanning lines is signed as a whole, the
+/// signature ends its last line.
+pub fn verify_signed(path: &str, key: &[u8]) -> io::Result<Signed> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let mut signed = Signed {
+        valid: 0,
+        invalid: Vec::new(),
+    };
+
+    // The lines of a message whose signature is yet to come.
+    let mut pending = String::new();
+    let mut number = 0;
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        number += 1;
+
+        match split_hash(&line) {
+            Some((content, tag)) => {
+                pending.push_str(content);
+                let tag = from_hex(tag).unwrap_or_default();
+                match hmac::verify(&key, pending.as_bytes(), &tag) {
+                    Ok(()) => signed.valid += 1,
+                    Err(_) => signed.invalid.push(number),
+                }
+                pending.clear();
+            }
+            None => {
+                pending.push_str(&line);
+                pending.push('\n');
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        signed.invalid.push(number);
+    }
+
+    Ok(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+    use serde_json::json;
+    use std::thread;
+
+    /// Rotates once the file reaches `max_bytes`, keeping `backup_count`.
+    fn sized(max_bytes: u64, backup_count: usize) -> Rotation {
+        Rotation {
+            max_bytes,
+            period: None,
+            backup_count,
+            retention: Retention::Count,
+            pattern: None,
+            budget: None,
+            on_rotation: None,
+            archive: None,
+        }
+    }
+
+    #[test]
+    fn a_failed_open_leaves_the_handler_as_it_was() {
+        let dir = scratch("failed-open");
+        let first = dir.join("first.log");
+        let plain = dir.join("plain.log");
+        fs::write(&plain, "not an audit log\n").unwrap();
+
+        let mut file = FileLogger::default();
+        let buffered = FileOptions {
+            buffer_size: 4096,
+            ..FileOptions::default()
+        };
+        file.open(first.to_str().unwrap(), buffered).unwrap();
+        file.logger(clock::now(), "held back").unwrap();
+
+        // An audited file that isn't empty has to end with a hash.
+        let audited = FileOptions {
+            audit: Some(Audit {
+                genesis: String::from("genesis"),
+            }),
+            ..FileOptions::default()
+        };
+        assert!(file.open(plain.to_str().unwrap(), audited).is_err());
+
+        assert_eq!(file.path(), first.to_str().unwrap());
+        assert_eq!(file.buffer_size, 4096);
+        assert!(file.audit.is_none());
+        file.logger(clock::now(), "after").unwrap();
+        file.flush();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "held back\nafter\n");
+        assert_eq!(fs::read_to_string(&plain).unwrap(), "not an audit log\n");
+    }
+
+    #[test]
+    fn a_buffer_is_written_out_every_n_lines() {
+        let path = scratch("every-n-lines").join("app.log");
+        let mut file = FileLogger::default();
+        let options = FileOptions {
+            buffer_size: 64 * 1024,
+            flush_every: 10,
+            ..FileOptions::default()
+        };
+        file.open(path.to_str().unwrap(), options).unwrap();
+        let written = || fs::read_to_string(&path).unwrap().lines().count();
+
+        let mut counts = Vec::new();
+        for n in 1..=25 {
+            file.logger(clock::now(), &format!("line {}", n)).unwrap();
+            counts.push(written());
+        }
+        let expected: Vec<usize> = (1..=25).map(|n| n / 10 * 10).collect();
+        assert_eq!(counts, expected);
+
+        file.flush();
+        assert_eq!(written(), 25);
+    }
+
+    fn rotating(path: &Path, rotation: Rotation) -> io::Result<FileLogger> {
+        let mut file = FileLogger::default();
+        let options = FileOptions {
+            rotation: Some(rotation),
+            ..FileOptions::default()
+        };
+        file.open(path.to_str().unwrap(), options)?;
+
+        Ok(file)
+    }
+
+    #[test]
+    fn rotating_without_backups_is_refused() {
+        let path = scratch("no-backups").join("app.log");
+        fs::write(&path, "kept\n").unwrap();
+
+        let error = rotating(&path, sized(10, 0)).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "kept\n");
+    }
+
+    #[test]
+    fn rotating_keeps_the_file_as_a_backup() {
+        let path = scratch("one-backup").join("app.log");
+        let file = rotating(&path, sized(10, 1)).unwrap();
+
+        file.logger(clock::now(), "first line").unwrap();
+        file.logger(clock::now(), "second line").unwrap();
+
+        let backup = fs::read_to_string(format!("{}.1", path.display())).unwrap();
+        assert_eq!(backup, "second line\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn concurrent_writers_never_split_a_line() {
+        const THREADS: usize = 8;
+        const RECORDS: usize = 200;
+
+        let path = scratch("torture").join("app.log");
+        // Two handlers on the file stand for two processes appending to it,
+        // one of them buffering.
+        let open = |buffer_size| {
+            let mut file = FileLogger::default();
+            let options = FileOptions {
+                buffer_size,
+                ..FileOptions::default()
+            };
+            file.open(path.to_str().unwrap(), options).unwrap();
+            file
+        };
+        let handlers = [open(0), open(8 * 1024)];
+
+        thread::scope(|scope| {
+            for writer in 0..THREADS {
+                let file = &handlers[writer % 2];
+                scope.spawn(move || {
+                    for n in 0..RECORDS {
+                        // Some lines are over what goes out in one write.
+                        let pad = match n % 50 {
+                            0 => WHOLE_WRITE + 1,
+                            n => n * 37,
+                        };
+                        let line = json!({ "writer": writer, "n": n, "pad": "x".repeat(pad) });
+                        file.logger(clock::now(), &line.to_string()).unwrap();
+                    }
+                });
+            }
+        });
+        for file in &handlers {
+            file.flush();
+        }
+
+        let written = fs::read_to_string(&path).unwrap();
+        let mut seen = vec![0; THREADS];
+        for line in written.lines() {
+            let record: Value = serde_json::from_str(line).expect("a whole line");
+            seen[record["writer"].as_u64().unwrap() as usize] += 1;
+        }
+        assert_eq!(seen, [RECORDS; THREADS]);
+    }
+
+    #[test]
+    fn the_budget_is_kept_to_the_byte() {
+        let path = scratch("budget").join("app.log");
+        let file = path.to_str().unwrap();
+        let backup = |n| PathBuf::from(format!("{}.{}", file, n));
+        let write = || {
+            fs::write(&path, "0123456789").unwrap();
+            fs::write(backup(1), "0123456789").unwrap();
+            fs::write(backup(2), "0123456789").unwrap();
+        };
+        let budget = |budget| Rotation {
+            budget: Some(budget),
+            ..sized(10, 5)
+        };
+
+        write();
+        assert!(budget(30).trim(file, 10).unwrap().is_none());
+        assert!(backup(2).exists());
+
+        let pruned = budget(29).trim(file, 10).unwrap().unwrap();
+        assert_eq!(pruned.deleted, [backup(2)]);
+        assert_eq!(pruned.bytes, 10);
+        assert!(backup(1).exists() && path.exists());
+
+        // The file itself stays, however far over it is alone.
+        write();
+        let pruned = budget(5).trim(file, 10).unwrap().unwrap();
+        assert_eq!(pruned.deleted, [backup(2), backup(1)]);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn daily_rotation_goes_by_the_records_time() {
+        let path = scratch("record-day").join("app.log");
+        let daily = Rotation {
+            period: Some(Period::Daily),
+            ..sized(0, 0)
+        };
+        let file = rotating(&path, daily).unwrap();
+        let today = clock::now();
+        let tomorrow = today + chrono::Duration::days(1);
+
+        file.logger(today, "today").unwrap();
+        file.logger(tomorrow, "tomorrow").unwrap();
+        // Stamped before the rotation and written after it.
+        file.logger(today, "late").unwrap();
+
+        let backup = format!("{}.{}", path.display(), today.format("%Y-%m-%d"));
+        assert_eq!(fs::read_to_string(backup).unwrap(), "today\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomorrow\nlate\n");
+        assert_eq!(file.last_rotation().unwrap().time, tomorrow);
+    }
+
+    #[test]
+    fn a_record_past_both_triggers_rotates_by_the_date_alone() {
+        let path = scratch("both-triggers").join("app.log");
+        let daily_or_sized = || Rotation {
+            period: Some(Period::Daily),
+            ..sized(20, 3)
+        };
+        let file = rotating(&path, daily_or_sized()).unwrap();
+        let today = clock::now();
+        let tomorrow = today + chrono::Duration::days(1);
+        let dated = format!("{}.{}", path.display(), today.format("%Y-%m-%d"));
+        let numbered = format!("{}.1", path.display());
+
+        // 17 bytes, and the next 19 would take the file past 20 on the day
+        // it ends: the new day's file takes it, and isn't over.
+        file.logger(today, "today 0123456789").unwrap();
+        file.logger(tomorrow, "tomorrow 012345678").unwrap();
+        assert_eq!(fs::read_to_string(&dated).unwrap(), "today 0123456789\n");
+        assert!(!Path::new(&numbered).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomorrow 012345678\n");
+    }
+
+    #[test]
+    fn a_reopened_file_rotates_on_the_size_it_had() {
+        let path = scratch("reopened-size").join("app.log");
+        let daily_or_sized = || Rotation {
+            period: Some(Period::Daily),
+            ..sized(20, 3)
+        };
+        let file = rotating(&path, daily_or_sized()).unwrap();
+        file.logger(clock::now(), "today 0123456789").unwrap();
+        drop(file);
+
+        // Within the day it's the size, to a numbered backup, stat-ed when
+        // the handler opened the file again.
+        let file = rotating(&path, daily_or_sized()).unwrap();
+        file.logger(clock::now(), "more").unwrap();
+        let numbered = format!("{}.1", path.display());
+        assert_eq!(
+            fs::read_to_string(numbered).unwrap(),
+            "today 0123456789\nmore\n"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn a_zstd_stream_reads_back_whole() {
+        use crate::parse::{Layout, Records};
+
+        let path = scratch("zstd").join("app.log");
+        let mut file = FileLogger::default();
+        let options = FileOptions {
+            compress: Some(StreamCompression::Zstd(3)),
+            ..FileOptions::default()
+        };
+        file.open(path.to_str().unwrap(), options).unwrap();
+        file.logger(clock::now(), "{\"message\":\"first\"}")
+            .unwrap();
+        file.logger(clock::now(), "{\"message\":\"second\"}")
+            .unwrap();
+        drop(file);
+
+        let read = zstd::stream::decode_all(&fs::read(&path).unwrap()[..]).unwrap();
+        assert_eq!(read, b"{\"message\":\"first\"}\n{\"message\":\"second\"}\n");
+        let records = Records::open(path.to_str().unwrap(), Layout::Json).unwrap();
+        let messages: Vec<_> = records
+            .map(|record| record.unwrap()["message"].clone())
+            .collect();
+        assert_eq!(messages, ["first", "second"]);
+    }
+}
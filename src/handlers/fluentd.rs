@@ -0,0 +1,296 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Map, Value};
+
+use crate::{record::Record, stats::Stats};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+static CHUNK_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Ships records to fluentd / fluent-bit over the forward protocol.
+///
+/// Records are pushed to a bounded buffer that a background thread drains in
+/// Forward mode, `[tag, [[time, record], ...], option]`. The thread waits up
+/// to `interval` after the oldest pending record for `batch_size` of them to
+/// come in, so a burst goes out as one message while a lone record still
+/// leaves within `interval`.
+///
+/// A batch is retried until it has been written (and acknowledged, when
+/// `ack` is enabled), so a broker restart costs nothing as long as the buffer
+/// has room. When it's full the oldest record is dropped.
+pub struct FluentdLogger {
+    host: String,
+    port: u16,
+    tag: String,
+    ack: bool,
+    interval: Duration,
+    batch_size: usize,
+    stats: Arc<Stats>,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+    capacity: usize,
+}
+
+struct State {
+    pending: VecDeque<Entry>,
+    closed: bool,
+}
+
+struct Entry {
+    queued: Instant,
+    /// `[time, record]`
+    event: Value,
+}
+
+/// What the worker needs to know about the handler.
+struct Forward {
+    address: String,
+    tag: String,
+    ack: bool,
+    interval: Duration,
+    batch_size: usize,
+    stats: Arc<Stats>,
+}
+
+impl FluentdLogger {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        host: &str,
+        port: u16,
+        tag: &str,
+        ack: bool,
+        capacity: usize,
+        interval: Duration,
+        batch_size: usize,
+        stats: Arc<Stats>,
+    ) -> FluentdLogger {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                pending: VecDeque::new(),
+                closed: false,
+            }),
+            cond: Condvar::new(),
+            capacity: capacity.max(1),
+        });
+
+        let forward = Forward {
+            address: format!("{}:{}", host, port),
+            tag: tag.to_string(),
+            ack,
+            interval,
+            batch_size: batch_size.max(1),
+            stats: Arc::clone(&stats),
+        };
+        let worker_shared = Arc::clone(&shared);
+        let worker = thread::Builder::new()
+            .name(String::from("soda-fluentd"))
+            .spawn(move || run(forward, worker_shared))
+            .ok();
+
+        FluentdLogger {
+            host: host.to_string(),
+            port,
+            tag: tag.to_string(),
+            ack,
+            interval,
+            batch_size: batch_size.max(1),
+            stats,
+            shared,
+            worker,
+        }
+    }
+
+    /// Settings in the shape `addFluentdHandler` takes them.
+    pub fn config(&self) -> Map<String, Value> {
+        let mut config = Map::new();
+        config.insert(String::from("host"), Value::from(self.host.as_str()));
+        config.insert(String::from("port"), Value::from(self.port));
+        config.insert(String::from("tag"), Value::from(self.tag.as_str()));
+        config.insert(String::from("ack"), Value::from(self.ack));
+        config.insert(
+            String::from("buffer_size"),
+            Value::from(self.shared.capacity),
+        );
+        config.insert(
+            String::from("interval"),
+            Value::from(self.interval.as_secs_f64()),
+        );
+        config.insert(String::from("batch_size"), Value::from(self.batch_size));
+        config
+    }
+
+    pub fn logger(&self, record: &Record) {
+        let event = json!([record.time.timestamp(), record.to_map()]);
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.pending.len() >= self.shared.capacity {
+            state.pending.pop_front();
+            self.stats.dropped(1);
+        }
+
+        state.pending.push_back(Entry {
+            queued: Instant::now(),
+            event,
+        });
+        self.shared.cond.notify_one();
+    }
+}
+
+impl Drop for FluentdLogger {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.cond.notify_one();
+
+        if let Some(worker) = self.worker.take() {
+            super::join_worker(worker);
+        }
+    }
+}
+
+fn run(forward: Forward, shared: Arc<Shared>) {
+    let mut stream: Option<TcpStream> = None;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let batch: Vec<Entry> = {
+            let mut state = shared.state.lock().unwrap();
+
+            while state.pending.is_empty() && !state.closed {
+                state = shared.cond.wait(state).unwrap();
+            }
+
+            let deadline = match state.pending.front() {
+                Some(entry) => entry.queued + forward.interval,
+                None => return,
+            };
+
+            while !state.closed && state.pending.len() < forward.batch_size {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                state = shared.cond.wait_timeout(state, deadline - now).unwrap().0;
+            }
+
+            let count = state.pending.len().min(forward.batch_size);
+            state.pending.drain(..count).collect()
+        };
+
+        let chunk = if forward.ack { Some(chunk_id()) } else { None };
+
+        let mut message = vec![
+            Value::from(forward.tag.as_str()),
+            Value::Array(batch.iter().map(|entry| entry.event.clone()).collect()),
+        ];
+        if let Some(chunk) = &chunk {
+            message.push(json!({ "chunk": chunk }));
+        }
+
+        let payload = match rmp_serde::to_vec(&message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                eprintln!("soda: couldn't encode records for fluentd: {}", e);
+                forward.stats.dropped(batch.len() as u64);
+                continue;
+            }
+        };
+
+        loop {
+            if stream.is_none() {
+                stream = connect(&forward.address);
+            }
+
+            let sent = match stream.as_mut() {
+                Some(conn) => send(conn, &payload, chunk.as_deref()),
+                None => false,
+            };
+
+            if sent {
+                backoff = INITIAL_BACKOFF;
+                break;
+            }
+
+            stream = None;
+
+            // Don't hold up shutdown retrying against a broker that is down.
+            let state = shared.state.lock().unwrap();
+            if state.closed {
+                forward
+                    .stats
+                    .dropped((batch.len() + state.pending.len()) as u64);
+                return;
+            }
+
+            let _ = shared.cond.wait_timeout(state, backoff).unwrap();
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+fn connect(address: &str) -> Option<TcpStream> {
+    let addrs = address.to_socket_addrs().ok()?;
+
+    for addr in addrs {
+        if let Ok(stream) = TcpStream::connect_timeout(&addr, ACK_TIMEOUT) {
+            let _ = stream.set_nodelay(true);
+            let _ = stream.set_read_timeout(Some(ACK_TIMEOUT));
+            return Some(stream);
+        }
+    }
+
+    None
+}
+
+fn send(stream: &mut TcpStream, payload: &[u8], chunk: Option<&str>) -> bool {
+    if stream
+        .write_all(payload)
+        .and_then(|_| stream.flush())
+        .is_err()
+    {
+        return false;
+    }
+
+    let chunk = match chunk {
+        Some(chunk) => chunk,
+        None => return true,
+    };
+
+    // The response is a single map, `{"ack": <chunk>}`.
+    match rmp_serde::from_read::<_, Value>(&mut *stream) {
+        Ok(response) => response.get("ack").and_then(Value::as_str) == Some(chunk),
+        Err(_) => false,
+    }
+}
+
+fn chunk_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    format!(
+        "{:x}-{:x}-{:x}",
+        nanos,
+        std::process::id(),
+        CHUNK_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
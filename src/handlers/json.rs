@@ -0,0 +1,673 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+
+use crate::record::Record;
+use crate::{template, Level};
+
+/// How the `timestamp` field is written.
+#[derive(Clone, Copy)]
+pub enum TimeFormat {
+    /// `"2021-01-02T03:04:05.678+00:00"`
+    Rfc3339,
+    /// Seconds since the epoch, as a float.
+    Epoch,
+    /// Milliseconds since the epoch, as an integer.
+    EpochMillis,
+}
+
+impl TimeFormat {
+    pub fn parse(name: &str) -> Option<TimeFormat> {
+        match name {
+            "rfc3339" => Some(TimeFormat::Rfc3339),
+            "epoch" => Some(TimeFormat::Epoch),
+            "epoch_ms" => Some(TimeFormat::EpochMillis),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimeFormat::Rfc3339 => "rfc3339",
+            TimeFormat::Epoch => "epoch",
+            TimeFormat::EpochMillis => "epoch_ms",
+        }
+    }
+
+    fn value(self, record: &Record) -> Value {
+        match self {
+            TimeFormat::Rfc3339 => Value::from(record.time.to_rfc3339()),
+            TimeFormat::Epoch => Value::from(
+                record.time.timestamp() as f64
+                    + f64::from(record.time.timestamp_subsec_nanos()) / 1e9,
+            ),
+            TimeFormat::EpochMillis => Value::from(record.time.timestamp_millis()),
+        }
+    }
+}
+
+/// Field layouts for platforms that parse JSON logs themselves.
+#[derive(Clone, Copy)]
+pub enum Preset {
+    /// `timestamp` followed by the record's own fields.
+    Default,
+    /// Google Cloud Logging: `severity`, `message`, `time` and the trace in
+    /// `logging.googleapis.com/trace`. `time` is always RFC 3339, the only
+    /// format it is parsed from.
+    Gcp,
+    /// AWS Lambda: `timestamp`, `level`, `message` and `logger`. Records are
+    /// written and flushed as they're logged, so nothing is pending when the
+    /// sandbox is frozen.
+    AwsLambda,
+    /// Elastic Common Schema, `ECS_VERSION`. Extras go under `labels` as
+    /// strings, or with `strict` off, as custom fields. Dotted keys expand
+    /// into nested objects either way.
+    Ecs { strict: bool },
+}
+
+/// The ECS version the `ecs` preset's fields follow.
+pub const ECS_VERSION: &str = "8.11.0";
+
+impl Preset {
+    pub fn parse(name: &str) -> Option<Preset> {
+        match name {
+            "default" => Some(Preset::Default),
+            "gcp" => Some(Preset::Gcp),
+            "aws_lambda" => Some(Preset::AwsLambda),
+            "ecs" => Some(Preset::Ecs { strict: true }),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::Gcp => "gcp",
+            Preset::AwsLambda => "aws_lambda",
+            Preset::Ecs { .. } => "ecs",
+        }
+    }
+}
+
+/// How documents are encoded. JSON is written one document per line, the
+/// binary codecs as a plain sequence of documents, which their decoders read
+/// back one after the other.
+#[derive(Clone, Copy)]
+pub enum Codec {
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Codec {
+    /// `None` for unknown names and for codecs soda was built without.
+    pub fn parse(name: &str) -> Option<Codec> {
+        match name {
+            "json" => Some(Codec::Json),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Some(Codec::MessagePack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Codec::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => "msgpack",
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => "cbor",
+        }
+    }
+
+    pub fn encode(self, document: &Value) -> io::Result<Vec<u8>> {
+        match self {
+            Codec::Json => {
+                let mut line = serde_json::to_vec(document)?;
+                line.push(b'\n');
+                Ok(line)
+            }
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::to_vec(document)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(document, &mut buf)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// Writes one document per record, to stdout or to an appended file.
+pub struct JsonLogger {
+    pub path: Option<String>,
+    pub time_format: TimeFormat,
+    pub preset: Preset,
+    pub codec: Codec,
+    /// Bytes held back before they are written out, `0` writes every
+    /// record as it comes in.
+    pub buffer_size: usize,
+    /// Writes the buffer out after this many records too, `0` only when it
+    /// fills up.
+    pub flush_every: usize,
+    /// Records in the buffer, see `buffer_record`, only changed with
+    /// `writer` locked.
+    unflushed: AtomicUsize,
+    /// Prefix of the GCP trace resource name, from `GOOGLE_CLOUD_PROJECT`.
+    gcp_project: Option<String>,
+    hostname: Option<String>,
+    writer: Mutex<BufWriter<Box<dyn Write + Send>>>,
+}
+
+impl JsonLogger {
+    pub fn stdout(
+        time_format: TimeFormat,
+        preset: Preset,
+        codec: Codec,
+        buffer_size: usize,
+    ) -> JsonLogger {
+        JsonLogger::new(
+            None,
+            time_format,
+            preset,
+            codec,
+            buffer_size,
+            Box::new(io::stdout()),
+        )
+    }
+
+    pub fn file(
+        path: &str,
+        time_format: TimeFormat,
+        preset: Preset,
+        codec: Codec,
+        buffer_size: usize,
+    ) -> io::Result<JsonLogger> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(JsonLogger::new(
+            Some(path.to_string()),
+            time_format,
+            preset,
+            codec,
+            buffer_size,
+            Box::new(file),
+        ))
+    }
+
+    fn new(
+        path: Option<String>,
+        time_format: TimeFormat,
+        preset: Preset,
+        codec: Codec,
+        buffer_size: usize,
+        writer: Box<dyn Write + Send>,
+    ) -> JsonLogger {
+        JsonLogger {
+            path,
+            time_format,
+            preset,
+            codec,
+            buffer_size,
+            flush_every: 0,
+            unflushed: AtomicUsize::new(0),
+            gcp_project: env::var("GOOGLE_CLOUD_PROJECT")
+                .or_else(|_| env::var("GCP_PROJECT"))
+                .ok(),
+            hostname: super::hostname(),
+            writer: Mutex::new(BufWriter::with_capacity(buffer_size, writer)),
+        }
+    }
+
+    fn document(&self, record: &Record) -> Map<String, Value> {
+        let mut map = Map::new();
+
+        match self.preset {
+            Preset::Default => {
+                map.insert(String::from("timestamp"), self.time_format.value(record));
+                map.extend(record.to_map());
+                return map;
+            }
+            Preset::Gcp => {
+                map.insert(String::from("severity"), Value::from(gcp_severity(record)));
+                map.insert(
+                    String::from("message"),
+                    Value::from(record.message.as_str()),
+                );
+                map.insert(String::from("time"), Value::from(record.time.to_rfc3339()));
+                map.insert(String::from("logger"), Value::from(record.name.as_str()));
+
+                if let Some(trace_id) = &record.trace_id {
+                    let trace = match &self.gcp_project {
+                        Some(project) => format!("projects/{}/traces/{}", project, trace_id),
+                        None => trace_id.clone(),
+                    };
+                    map.insert(
+                        String::from("logging.googleapis.com/trace"),
+                        Value::from(trace),
+                    );
+                }
+                if let Some(span_id) = &record.span_id {
+                    map.insert(
+                        String::from("logging.googleapis.com/spanId"),
+                        Value::from(span_id.as_str()),
+                    );
+                }
+            }
+            Preset::AwsLambda => {
+                map.insert(String::from("timestamp"), self.time_format.value(record));
+                map.insert(String::from("level"), Value::from(record.level.as_str()));
+                map.insert(
+                    String::from("message"),
+                    Value::from(record.message.as_str()),
+                );
+                map.insert(String::from("logger"), Value::from(record.name.as_str()));
+            }
+            Preset::Ecs { strict } => return self.ecs(record, strict),
+        }
+
+        // Whatever else the record carries, without shadowing the fields the
+        // platform reads.
+        for (key, value) in record.to_map() {
+            let mapped = match key.as_str() {
+                "level" | "name" | "message" => true,
+                "trace_id" | "span_id" => matches!(self.preset, Preset::Gcp),
+                _ => false,
+            };
+            if !mapped {
+                map.entry(key).or_insert(value);
+            }
+        }
+
+        map
+    }
+
+    fn ecs(&self, record: &Record, strict: bool) -> Map<String, Value> {
+        let mut map = Map::new();
+
+        let mut fields = record.extras.clone();
+        if let Some(event) = &record.event {
+            fields.extend(event.clone());
+        }
+
+        if strict {
+            let labels: Map<String, Value> = fields
+                .into_iter()
+                .map(|(key, value)| (key.replace('.', "_"), Value::from(template::text(&value))))
+                .collect();
+            if !labels.is_empty() {
+                map.insert(String::from("labels"), Value::Object(labels));
+            }
+        } else {
+            for (key, value) in fields {
+                dotted(&mut map, &key, value);
+            }
+        }
+
+        // The schema's own fields are written last so they always win.
+        let time = record
+            .time
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Millis, true);
+        dotted(&mut map, "@timestamp", Value::from(time));
+        dotted(
+            &mut map,
+            "log.level",
+            Value::from(record.level.as_str().to_lowercase()),
+        );
+        dotted(&mut map, "log.logger", Value::from(record.name.as_str()));
+        dotted(&mut map, "message", Value::from(record.message.as_str()));
+        dotted(&mut map, "ecs.version", Value::from(ECS_VERSION));
+        dotted(&mut map, "process.pid", Value::from(process::id()));
+
+        if let Some(hostname) = &self.hostname {
+            dotted(&mut map, "host.hostname", Value::from(hostname.as_str()));
+        }
+        if let Some(trace_id) = &record.trace_id {
+            dotted(&mut map, "trace.id", Value::from(trace_id.as_str()));
+        }
+        if let Some(span_id) = &record.span_id {
+            dotted(&mut map, "span.id", Value::from(span_id.as_str()));
+        }
+        if !record.tags.is_empty() {
+            dotted(&mut map, "tags", Value::from(record.tags.clone()));
+        }
+        if let Some(exception) = &record.exception {
+            dotted(&mut map, "error.type", Value::from(exception.kind.as_str()));
+            dotted(
+                &mut map,
+                "error.message",
+                Value::from(exception.message.as_str()),
+            );
+            dotted(
+                &mut map,
+                "error.stack_trace",
+                Value::from(exception.stack_trace.as_str()),
+            );
+        }
+
+        map
+    }
+
+    pub fn logger(&self, record: &Record) {
+        let written = self
+            .codec
+            .encode(&Value::Object(self.document(record)))
+            .and_then(|document| {
+                let mut writer = self.writer.lock().unwrap();
+                let mut pending = self.unflushed.load(Ordering::Relaxed);
+                super::buffer_record(&mut writer, &document, &mut pending)?;
+                self.unflushed.store(pending, Ordering::Relaxed);
+                // Lambda can freeze the sandbox right after the record, so
+                // its preset never holds one back.
+                let flush = match (self.buffer_size, self.flush_every) {
+                    _ if matches!(self.preset, Preset::AwsLambda) => true,
+                    (0, _) => true,
+                    (_, 0) => false,
+                    (_, every) => pending >= every,
+                };
+                match flush {
+                    true => self.flush_writer(&mut writer),
+                    false => Ok(()),
+                }
+            });
+
+        if let Err(e) = written {
+            eprintln!("soda: couldn't write {} record: {}", self.codec.as_str(), e);
+        }
+    }
+
+    pub fn flush(&self) {
+        let _ = self.flush_writer(&mut self.writer.lock().unwrap());
+    }
+
+    /// Like `flush`, giving up after `timeout` on a record being written,
+    /// see `lock_within`. Returns the records written out.
+    pub fn flush_within(&self, timeout: Duration) -> usize {
+        let mut writer = match super::lock_within(&self.writer, timeout) {
+            Some(writer) => writer,
+            None => return 0,
+        };
+        let pending = self.unflushed.load(Ordering::Relaxed);
+
+        match self.flush_writer(&mut writer) {
+            Ok(()) => pending,
+            Err(_) => 0,
+        }
+    }
+
+    /// Writes out what `writer`, locked, holds back.
+    fn flush_writer(&self, writer: &mut BufWriter<Box<dyn Write + Send>>) -> io::Result<()> {
+        writer.flush()?;
+        self.unflushed.store(0, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+/// Sets `a.b.c` as `{"a": {"b": {"c": value}}}`, merging into the objects
+/// already there. A non-object in the way is replaced.
+fn dotted(map: &mut Map<String, Value>, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, rest)) if !head.is_empty() && !rest.is_empty() => {
+            let nested = map.entry(head).or_insert_with(|| Value::Object(Map::new()));
+            if !nested.is_object() {
+                *nested = Value::Object(Map::new());
+            }
+            if let Value::Object(nested) = nested {
+                dotted(nested, rest, value);
+            }
+        }
+        _ => {
+            map.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#logseverity
+fn gcp_severity(record: &Record) -> &'static str {
+    match record.level {
+        Level::NOTSET => "DEFAULT",
+        Level::TRACE | Level::DEBUG => "DEBUG",
+        Level::INFO => "INFO",
+        Level::WARNING => "WARNING",
+        Level::ERROR => "ERROR",
+        Level::CRITICAL => "CRITICAL",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Local, TimeZone};
+    use serde_json::json;
+
+    use super::*;
+    use crate::record::Exception;
+
+    /// What a `JsonLogger` writes, readable while it's still in use.
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Written {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn logger(preset: Preset, buffer_size: usize) -> (JsonLogger, Written) {
+        let written = Written::default();
+        let mut json = JsonLogger::new(
+            None,
+            TimeFormat::EpochMillis,
+            preset,
+            Codec::Json,
+            buffer_size,
+            Box::new(written.clone()),
+        );
+        json.gcp_project = Some(String::from("demo"));
+        json.hostname = Some(String::from("web-1"));
+
+        (json, written)
+    }
+
+    /// The same record for every test, logged at a fixed time from a
+    /// traced request.
+    fn record() -> Record {
+        let mut record = Record::new(Level::ERROR, "shop.checkout", "payment failed");
+        record.time = Local.timestamp_millis_opt(1_700_000_000_123).unwrap();
+        record.trace_id = Some(String::from("4bf92f3577b34da6a3ce929d0e0e4736"));
+        record.span_id = Some(String::from("00f067aa0ba902b7"));
+        record
+            .extras
+            .insert(String::from("user"), Value::from("bob"));
+
+        record
+    }
+
+    /// `golden` with `{time}` standing for the record's local RFC 3339 time,
+    /// the one part that depends on where the test runs.
+    fn expected(golden: &str, record: &Record) -> String {
+        golden.replace("{time}", &record.time.to_rfc3339())
+    }
+
+    #[test]
+    fn the_gcp_preset_writes_its_golden_output() {
+        let (json, written) = logger(Preset::Gcp, 0);
+        let record = record();
+        json.logger(&record);
+
+        let golden = include_str!("../../tests/golden/gcp.json");
+        assert_eq!(written.text(), expected(golden, &record));
+    }
+
+    #[test]
+    fn the_aws_lambda_preset_writes_its_golden_output() {
+        let (json, written) = logger(Preset::AwsLambda, 0);
+        let record = record();
+        json.logger(&record);
+
+        let golden = include_str!("../../tests/golden/aws_lambda.json");
+        assert_eq!(written.text(), expected(golden, &record));
+    }
+
+    /// The one document `json` wrote, parsed.
+    fn document(written: &Written) -> Value {
+        serde_json::from_str(&written.text()).unwrap()
+    }
+
+    #[test]
+    fn the_ecs_preset_writes_the_pinned_version_fields() {
+        let (json, written) = logger(Preset::Ecs { strict: true }, 0);
+        let mut record = record();
+        record
+            .extras
+            .insert(String::from("http.status"), Value::from(502));
+        record.exception = Some(Exception {
+            kind: String::from("ValueError"),
+            module: String::from("builtins"),
+            message: String::from("card declined"),
+            stack_trace: String::from("Traceback (most recent call last):\n..."),
+            frames: Vec::new(),
+            cause: None,
+            context: None,
+        });
+        json.logger(&record);
+
+        assert_eq!(ECS_VERSION, "8.11.0");
+        assert_eq!(
+            document(&written),
+            json!({
+                "@timestamp": "2023-11-14T22:13:20.123Z",
+                "log": {"level": "error", "logger": "shop.checkout"},
+                "message": "payment failed",
+                "ecs": {"version": "8.11.0"},
+                "process": {"pid": process::id()},
+                "host": {"hostname": "web-1"},
+                "trace": {"id": "4bf92f3577b34da6a3ce929d0e0e4736"},
+                "span": {"id": "00f067aa0ba902b7"},
+                "labels": {"user": "bob", "http_status": "502"},
+                "error": {
+                    "type": "ValueError",
+                    "message": "card declined",
+                    "stack_trace": "Traceback (most recent call last):\n...",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn the_ecs_preset_nests_dotted_extras_unless_strict() {
+        let (json, written) = logger(Preset::Ecs { strict: false }, 0);
+        let mut record = record();
+        record
+            .extras
+            .insert(String::from("http.response.status_code"), Value::from(502));
+        record
+            .extras
+            .insert(String::from("log.origin"), Value::from("checkout.py"));
+        json.logger(&record);
+
+        let document = document(&written);
+        assert_eq!(document["user"], "bob");
+        assert_eq!(document["http"], json!({"response": {"status_code": 502}}));
+        assert_eq!(
+            document["log"],
+            json!({"origin": "checkout.py", "level": "error", "logger": "shop.checkout"})
+        );
+        assert!(document.get("labels").is_none());
+        assert!(document.get("error").is_none());
+    }
+
+    /// Encodes two records with `codec`, back to back as the handler writes
+    /// them, and checks `decode` reads both back unchanged.
+    fn round_trip(codec: Codec, decode: impl Fn(&[u8]) -> Vec<Value>) {
+        let (json, _) = logger(Preset::Default, 0);
+        let first = record();
+        let mut second = Record::new(Level::INFO, "shop", "paid");
+        second.tags = vec![String::from("billing")];
+        let documents: Vec<Value> = [first, second]
+            .iter()
+            .map(|record| Value::Object(json.document(record)))
+            .collect();
+
+        let mut encoded = Vec::new();
+        for document in &documents {
+            encoded.extend(codec.encode(document).unwrap());
+        }
+
+        assert_eq!(decode(&encoded), documents);
+    }
+
+    #[test]
+    fn records_round_trip_through_json() {
+        round_trip(Codec::Json, |bytes| {
+            serde_json::Deserializer::from_slice(bytes)
+                .into_iter()
+                .map(Result::unwrap)
+                .collect()
+        });
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn records_round_trip_through_msgpack() {
+        round_trip(Codec::MessagePack, |mut bytes| {
+            let mut documents = Vec::new();
+            while !bytes.is_empty() {
+                documents.push(rmp_serde::from_read(&mut bytes).unwrap());
+            }
+            documents
+        });
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn records_round_trip_through_cbor() {
+        round_trip(Codec::Cbor, |mut bytes| {
+            let mut documents = Vec::new();
+            while !bytes.is_empty() {
+                documents.push(ciborium::from_reader(&mut bytes).unwrap());
+            }
+            documents
+        });
+    }
+
+    #[test]
+    fn the_aws_lambda_preset_writes_each_record_out_despite_a_buffer() {
+        let (json, written) = logger(Preset::AwsLambda, 64 * 1024);
+        json.logger(&record());
+
+        assert_eq!(written.text().lines().count(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::record::Record;
+
+/// Appends each record to `<dir>/<level>.log`, e.g. `error.log`. The files
+/// are opened as records at their level come in.
+pub struct LevelSplitLogger {
+    pub dir: PathBuf,
+    /// Bytes each file holds back before they are written out, `0` writes
+    /// every record as it comes in.
+    pub buffer_size: usize,
+    /// Writes a file's buffer out after this many records too, `0` only when
+    /// it fills up.
+    pub flush_every: usize,
+    /// Each file and the records in its buffer, see `buffer_record`.
+    files: Mutex<HashMap<&'static str, (BufWriter<File>, usize)>>,
+}
+
+impl LevelSplitLogger {
+    pub fn new(dir: &str, buffer_size: usize) -> io::Result<LevelSplitLogger> {
+        fs::create_dir_all(dir)?;
+
+        Ok(LevelSplitLogger {
+            dir: PathBuf::from(dir),
+            buffer_size,
+            flush_every: 0,
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The files opened so far.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let files = self.files.lock().unwrap();
+
+        files
+            .keys()
+            .map(|level| self.dir.join(format!("{}.log", level.to_lowercase())))
+            .collect()
+    }
+
+    /// Writes `message`, the record's as the logger writes it, to the file
+    /// of the record's level.
+    pub fn logger(&self, record: &Record, message: &str) -> io::Result<()> {
+        let level = record.level.as_str();
+        let mut files = self.files.lock().unwrap();
+
+        let (file, unflushed) = match files.get_mut(level) {
+            Some(file) => file,
+            None => {
+                let path = self.dir.join(format!("{}.log", level.to_lowercase()));
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                files
+                    .entry(level)
+                    .or_insert_with(|| (BufWriter::with_capacity(self.buffer_size, file), 0))
+            }
+        };
+
+        super::buffer_record(file, format!("{}\n", message).as_bytes(), unflushed)?;
+        if self.flush_every > 0 && *unflushed >= self.flush_every {
+            file.flush()?;
+            *unflushed = 0;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&self) {
+        for (file, unflushed) in self.files.lock().unwrap().values_mut() {
+            if file.flush().is_ok() {
+                *unflushed = 0;
+            }
+        }
+    }
+
+    /// Like `flush`, giving up after `timeout` on a record being written,
+    /// see `lock_within`. Returns the records written out.
+    pub fn flush_within(&self, timeout: Duration) -> usize {
+        let mut files = match super::lock_within(&self.files, timeout) {
+            Some(files) => files,
+            None => return 0,
+        };
+
+        let mut flushed = 0;
+        for (file, unflushed) in files.values_mut() {
+            if file.flush().is_ok() {
+                flushed += std::mem::take(unflushed);
+            }
+        }
+
+        flushed
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::{Arc, Mutex};
+
+use crate::record::Record;
+
+/// What a memory handler keeps of a record.
+pub struct Entry {
+    pub record: Record,
+    pub line: String,
+}
+
+/// Keeps records in memory, for tests to look at.
+#[derive(Default)]
+pub struct MemoryLogger {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl MemoryLogger {
+    /// `line` is the record as the console would print it.
+    pub fn logger(&self, record: &Record, line: &str) {
+        self.entries.lock().unwrap().push(Entry {
+            record: record.clone(),
+            line: line.to_string(),
+        });
+    }
+
+    pub fn with_entries<R, F: FnOnce(&[Entry]) -> R>(&self, f: F) -> R {
+        f(&self.entries.lock().unwrap())
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Memory handlers every logger feeds, for capturing a whole test's records.
+static CAPTURES: Mutex<Vec<Arc<MemoryLogger>>> = Mutex::new(Vec::new());
+
+pub fn start_capture() -> Arc<MemoryLogger> {
+    let memory = Arc::new(MemoryLogger::default());
+    CAPTURES.lock().unwrap().push(Arc::clone(&memory));
+    memory
+}
+
+pub fn stop_capture(memory: &Arc<MemoryLogger>) {
+    CAPTURES
+        .lock()
+        .unwrap()
+        .retain(|capture| !Arc::ptr_eq(capture, memory));
+}
+
+pub fn capturing() -> bool {
+    !CAPTURES.lock().unwrap().is_empty()
+}
+
+pub fn capture(record: &Record, line: &str) {
+    for memory in CAPTURES.lock().unwrap().iter() {
+        memory.logger(record, line);
+    }
+}
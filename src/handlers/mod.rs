@@ -0,0 +1,94 @@
+use std::io::{self, BufWriter, Write};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub mod cipher;
+pub mod console;
+pub mod file;
+pub mod fluentd;
+pub mod json;
+pub mod level_split;
+pub mod memory;
+pub mod otlp;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod wal;
+
+/// Waits for a background worker with the GIL released, so a worker talking
+/// to something that needs the interpreter (a mock server in the same
+/// process, for one) can finish.
+///
+/// Handlers only ever get dropped along with their Python owner, so the GIL
+/// is held here. Taking a new `GILGuard` instead isn't an option, pyo3 won't
+/// allow it while a `tp_dealloc` is running during interpreter shutdown.
+#[cfg(feature = "python")]
+pub fn join_worker(worker: JoinHandle<()>) {
+    let py = unsafe { pyo3::Python::assume_gil_acquired() };
+
+    py.allow_threads(|| {
+        let _ = worker.join();
+    });
+}
+
+#[cfg(not(feature = "python"))]
+pub fn join_worker(worker: JoinHandle<()>) {
+    let _ = worker.join();
+}
+
+/// The machine's hostname, for handlers that report where a record came
+/// from.
+pub fn hostname() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|s| s.trim().to_string())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .filter(|s| !s.is_empty())
+}
+
+/// Writes `bytes`, a whole record, to `writer`, keeping `pending` to the
+/// records its buffer holds: one more, or back to this one alone, or none,
+/// when it had to write the buffer out to take it.
+pub fn buffer_record<W: Write>(
+    writer: &mut BufWriter<W>,
+    bytes: &[u8],
+    pending: &mut usize,
+) -> io::Result<()> {
+    let before = writer.buffer().len();
+    writer.write_all(bytes)?;
+
+    *pending = match writer.buffer().len() {
+        0 => 0,
+        after if after == before + bytes.len() => *pending + 1,
+        _ => 1,
+    };
+
+    Ok(())
+}
+
+/// Locks `mutex`, giving up after `timeout` rather than waiting on a holder
+/// that may be this very thread, in a panic or a signal handler. A mutex
+/// poisoned by a panic is taken all the same.
+pub fn lock_within<T>(mutex: &Mutex<T>, timeout: Duration) -> Option<MutexGuard<'_, T>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match mutex.try_lock() {
+            Ok(guard) => return Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => return Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) if Instant::now() >= deadline => return None,
+            Err(TryLockError::WouldBlock) => thread::sleep(Duration::from_millis(1)),
+        }
+    }
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,743 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Map, Value};
+
+use super::wal::Wal;
+use super::{from_hex, to_hex};
+use crate::{clock, record::Record, stats::Stats, Level};
+
+const MAX_QUEUE: usize = 2048;
+const MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Protobuf,
+    Json,
+}
+
+impl Protocol {
+    pub fn parse(name: &str) -> Option<Protocol> {
+        match name {
+            "http/protobuf" => Some(Protocol::Protobuf),
+            "http/json" => Some(Protocol::Json),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Protocol::Protobuf => "http/protobuf",
+            Protocol::Json => "http/json",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Protocol::Protobuf => "application/x-protobuf",
+            Protocol::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub protocol: Protocol,
+    pub headers: HashMap<String, String>,
+    pub resource: Map<String, Value>,
+    pub interval: Duration,
+    pub batch_size: usize,
+    pub gzip: bool,
+    /// Write-ahead log the queue is kept in until exported, see `Wal`.
+    pub wal: Option<String>,
+}
+
+/// Exports records to an OpenTelemetry collector over OTLP/HTTP.
+///
+/// Records are converted to the OTLP log data model as they are logged and
+/// queued, a background thread posts them in batches every `interval` (or
+/// as soon as `batch_size` records are waiting). Retryable responses back
+/// off exponentially, honouring `Retry-After`. Dropping the handler drains
+/// whatever is still queued.
+///
+/// With a `wal` every queued record is also appended to it, and
+/// acknowledged once exported or rejected for good. A batch that still
+/// failed after the retries stays in the log, as does whatever a killed
+/// process had queued, and is queued again when the next handler opens it,
+/// so records are delivered at least once.
+pub struct OtlpLogger {
+    config: OtlpConfig,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    stats: Arc<Stats>,
+    wal: Option<Mutex<Wal>>,
+    state: Mutex<State>,
+    cond: Condvar,
+    batch_size: usize,
+}
+
+struct State {
+    queue: Vec<LogRecord>,
+    closed: bool,
+}
+
+struct LogRecord {
+    /// The number it's in the write-ahead log under.
+    seq: Option<u64>,
+    time: u64,
+    observed: u64,
+    level: Level,
+    body: String,
+    attributes: Map<String, Value>,
+    trace_id: Option<Vec<u8>>,
+    span_id: Option<Vec<u8>>,
+}
+
+impl OtlpLogger {
+    pub fn new(mut config: OtlpConfig, stats: Arc<Stats>) -> io::Result<OtlpLogger> {
+        config
+            .resource
+            .entry("service.name")
+            .or_insert_with(|| Value::from("unknown_service"));
+        if let Some(host) = super::hostname() {
+            config
+                .resource
+                .entry("host.name")
+                .or_insert_with(|| Value::from(host));
+        }
+
+        let (wal, queue) = match &config.wal {
+            Some(path) => {
+                let (wal, pending) = Wal::open(path)?;
+                let queue = pending
+                    .iter()
+                    .filter_map(|(seq, record)| LogRecord::from_value(*seq, record))
+                    .collect();
+                (Some(Mutex::new(wal)), queue)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let shared = Arc::new(Shared {
+            stats,
+            wal,
+            state: Mutex::new(State {
+                queue,
+                closed: false,
+            }),
+            cond: Condvar::new(),
+            batch_size: config.batch_size.max(1),
+        });
+
+        let worker_config = config.clone();
+        let worker_shared = Arc::clone(&shared);
+        let worker = thread::Builder::new()
+            .name(String::from("soda-otlp"))
+            .spawn(move || run(worker_config, worker_shared))
+            .ok();
+
+        Ok(OtlpLogger {
+            config,
+            shared,
+            worker,
+        })
+    }
+
+    /// Settings in the shape `addOtlpHandler` takes them.
+    pub fn config(&self) -> Map<String, Value> {
+        let config = &self.config;
+        let headers: Map<String, Value> = config
+            .headers
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::from(v.as_str())))
+            .collect();
+
+        let mut map = Map::new();
+        map.insert(
+            String::from("endpoint"),
+            Value::from(config.endpoint.as_str()),
+        );
+        map.insert(
+            String::from("protocol"),
+            Value::from(config.protocol.as_str()),
+        );
+        map.insert(String::from("headers"), Value::Object(headers));
+        map.insert(
+            String::from("resource"),
+            Value::Object(config.resource.clone()),
+        );
+        map.insert(
+            String::from("interval"),
+            Value::from(config.interval.as_secs_f64()),
+        );
+        map.insert(String::from("batch_size"), Value::from(config.batch_size));
+        map.insert(
+            String::from("compression"),
+            Value::from(if config.gzip { "gzip" } else { "none" }),
+        );
+        if let Some(wal) = &config.wal {
+            map.insert(String::from("wal"), Value::from(wal.as_str()));
+        }
+        map
+    }
+
+    pub fn logger(&self, record: &Record) {
+        let mut attributes = record.extras.clone();
+        if let Some(event) = &record.event {
+            attributes.extend(event.clone());
+        }
+        attributes.insert(
+            String::from("logger.name"),
+            Value::from(record.name.as_str()),
+        );
+
+        let mut log = LogRecord {
+            seq: None,
+            time: nanos(record.time.timestamp_nanos_opt().unwrap_or_default()),
+            observed: unix_nanos(),
+            level: record.level,
+            body: record.message.clone(),
+            attributes,
+            trace_id: record.trace_id.as_deref().and_then(from_hex),
+            span_id: record.span_id.as_deref().and_then(from_hex),
+        };
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(wal) = &self.shared.wal {
+            let mut wal = wal.lock().unwrap();
+            match wal.append(&log.to_value()) {
+                Ok(seq) => log.seq = Some(seq),
+                Err(e) => eprintln!("soda: couldn't write to otlp wal {}: {}", wal.path(), e),
+            }
+        }
+
+        if state.queue.len() >= MAX_QUEUE {
+            let dropped = state.queue.remove(0);
+            self.shared.ack(&[dropped]);
+            self.shared.stats.dropped(1);
+            eprintln!("soda: otlp queue is full, dropping the oldest record");
+        }
+
+        state.queue.push(log);
+
+        if state.queue.len() >= self.shared.batch_size {
+            self.shared.cond.notify_one();
+        }
+    }
+}
+
+impl Shared {
+    /// Takes `batch` off the write-ahead log, if there's one.
+    fn ack(&self, batch: &[LogRecord]) {
+        if let Some(wal) = &self.wal {
+            let seqs: Vec<u64> = batch.iter().filter_map(|log| log.seq).collect();
+            let mut wal = wal.lock().unwrap();
+            if let Err(e) = wal.ack(&seqs) {
+                eprintln!("soda: couldn't write to otlp wal {}: {}", wal.path(), e);
+            }
+        }
+    }
+}
+
+impl LogRecord {
+    /// The record the way the write-ahead log keeps it.
+    fn to_value(&self) -> Value {
+        json!({
+            "time": self.time,
+            "observed": self.observed,
+            "level": self.level.as_str(),
+            "body": self.body,
+            "attributes": self.attributes,
+            "trace_id": self.trace_id.as_deref().map(to_hex),
+            "span_id": self.span_id.as_deref().map(to_hex),
+        })
+    }
+
+    fn from_value(seq: u64, value: &Value) -> Option<LogRecord> {
+        Some(LogRecord {
+            seq: Some(seq),
+            time: value["time"].as_u64()?,
+            observed: value["observed"].as_u64()?,
+            level: Level::from_name(value["level"].as_str()?)?,
+            body: value["body"].as_str()?.to_string(),
+            attributes: value["attributes"].as_object()?.clone(),
+            trace_id: value["trace_id"].as_str().and_then(from_hex),
+            span_id: value["span_id"].as_str().and_then(from_hex),
+        })
+    }
+}
+
+impl Drop for OtlpLogger {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.cond.notify_one();
+
+        if let Some(worker) = self.worker.take() {
+            super::join_worker(worker);
+        }
+    }
+}
+
+fn run(config: OtlpConfig, shared: Arc<Shared>) {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let mut deadline = Instant::now() + config.interval;
+
+    loop {
+        let (batch, closed) = {
+            let mut state = shared.state.lock().unwrap();
+
+            while !state.closed && state.queue.len() < shared.batch_size {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                state = shared.cond.wait_timeout(state, deadline - now).unwrap().0;
+            }
+
+            let take = state.queue.len().min(shared.batch_size);
+            (state.queue.drain(..take).collect::<Vec<_>>(), state.closed)
+        };
+
+        if !batch.is_empty() {
+            let export = export(&agent, &config, &batch, closed);
+            if export != Export::Delivered {
+                shared.stats.dropped(batch.len() as u64);
+            }
+            if export != Export::Failed {
+                shared.ack(&batch);
+            }
+        }
+
+        if closed {
+            if shared.state.lock().unwrap().queue.is_empty() {
+                return;
+            }
+            continue;
+        }
+
+        deadline = Instant::now() + config.interval;
+    }
+}
+
+/// How exporting a batch went.
+#[derive(PartialEq)]
+enum Export {
+    Delivered,
+    /// Not delivered, and never will be.
+    Rejected,
+    /// Not delivered after the retries, or on shutdown.
+    Failed,
+}
+
+fn export(agent: &ureq::Agent, config: &OtlpConfig, batch: &[LogRecord], closing: bool) -> Export {
+    let mut body = match config.protocol {
+        Protocol::Protobuf => encode_protobuf(&config.resource, batch),
+        Protocol::Json => encode_json(&config.resource, batch).into_bytes(),
+    };
+
+    if config.gzip {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        body = match encoder.write_all(&body).and_then(|_| encoder.finish()) {
+            Ok(compressed) => compressed,
+            Err(e) => {
+                eprintln!("soda: couldn't compress otlp batch: {}", e);
+                return Export::Rejected;
+            }
+        };
+    }
+
+    let mut backoff = INITIAL_BACKOFF;
+
+    for attempt in 0..=MAX_RETRIES {
+        let mut request = agent
+            .post(&config.endpoint)
+            .set("Content-Type", config.protocol.content_type());
+        if config.gzip {
+            request = request.set("Content-Encoding", "gzip");
+        }
+        for (name, value) in &config.headers {
+            request = request.set(name, value);
+        }
+
+        let wait = match request.send_bytes(&body) {
+            Ok(_) => return Export::Delivered,
+            Err(ureq::Error::Status(code, response)) if retryable(code) => response
+                .header("Retry-After")
+                .and_then(|s| s.trim().parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(backoff),
+            Err(ureq::Error::Status(code, _)) => {
+                eprintln!(
+                    "soda: otlp export rejected with status {}, dropping {} records",
+                    code,
+                    batch.len()
+                );
+                return Export::Rejected;
+            }
+            Err(ureq::Error::Transport(e)) => {
+                eprintln!("soda: otlp export failed: {}", e);
+                backoff
+            }
+        };
+
+        // On shutdown a single attempt is all we can afford.
+        if closing || attempt == MAX_RETRIES {
+            break;
+        }
+
+        thread::sleep(wait.min(MAX_BACKOFF));
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    eprintln!("soda: giving up on otlp batch of {} records", batch.len());
+    Export::Failed
+}
+
+fn retryable(code: u16) -> bool {
+    matches!(code, 429 | 502 | 503 | 504)
+}
+
+pub fn severity_number(level: Level) -> u64 {
+    match level {
+        Level::NOTSET => 0,
+        Level::TRACE => 1,
+        Level::DEBUG => 5,
+        Level::INFO => 9,
+        Level::WARNING => 13,
+        Level::ERROR => 17,
+        Level::CRITICAL => 21,
+    }
+}
+
+fn encode_json(resource: &Map<String, Value>, batch: &[LogRecord]) -> String {
+    let records: Vec<Value> = batch
+        .iter()
+        .map(|log| {
+            let mut record = json!({
+                "timeUnixNano": log.time.to_string(),
+                "observedTimeUnixNano": log.observed.to_string(),
+                "severityNumber": severity_number(log.level),
+                "severityText": log.level.as_str(),
+                "body": { "stringValue": log.body },
+                "attributes": json_attributes(&log.attributes),
+            });
+            if let Some(trace_id) = &log.trace_id {
+                record["traceId"] = Value::from(to_hex(trace_id));
+            }
+            if let Some(span_id) = &log.span_id {
+                record["spanId"] = Value::from(to_hex(span_id));
+            }
+            record
+        })
+        .collect();
+
+    json!({
+        "resourceLogs": [{
+            "resource": { "attributes": json_attributes(resource) },
+            "scopeLogs": [{
+                "scope": { "name": "soda", "version": env!("CARGO_PKG_VERSION") },
+                "logRecords": records,
+            }],
+        }],
+    })
+    .to_string()
+}
+
+fn json_attributes(map: &Map<String, Value>) -> Value {
+    Value::Array(
+        map.iter()
+            .map(|(key, value)| json!({ "key": key, "value": json_any(value) }))
+            .collect(),
+    )
+}
+
+fn json_any(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_i64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Array(values) => json!({
+            "arrayValue": { "values": values.iter().map(json_any).collect::<Vec<_>>() }
+        }),
+        Value::Object(map) => json!({ "kvlistValue": { "values": json_attributes(map) } }),
+        Value::Null => json!({}),
+    }
+}
+
+fn encode_protobuf(resource: &Map<String, Value>, batch: &[LogRecord]) -> Vec<u8> {
+    let mut scope = Proto::default();
+    scope.string(1, "soda");
+    scope.string(2, env!("CARGO_PKG_VERSION"));
+
+    let mut scope_logs = Proto::default();
+    scope_logs.message(1, &scope);
+    for log in batch {
+        scope_logs.message(2, &proto_record(log));
+    }
+
+    let mut resource_proto = Proto::default();
+    for (key, value) in resource {
+        resource_proto.message(1, &proto_key_value(key, value));
+    }
+
+    let mut resource_logs = Proto::default();
+    resource_logs.message(1, &resource_proto);
+    resource_logs.message(2, &scope_logs);
+
+    let mut request = Proto::default();
+    request.message(1, &resource_logs);
+    request.0
+}
+
+fn proto_record(log: &LogRecord) -> Proto {
+    let mut body = Proto::default();
+    body.string(1, &log.body);
+
+    let mut record = Proto::default();
+    record.fixed64(1, log.time);
+    record.varint(2, severity_number(log.level));
+    record.string(3, log.level.as_str());
+    record.message(5, &body);
+    for (key, value) in &log.attributes {
+        record.message(6, &proto_key_value(key, value));
+    }
+    if let Some(trace_id) = &log.trace_id {
+        record.bytes(9, trace_id);
+    }
+    if let Some(span_id) = &log.span_id {
+        record.bytes(10, span_id);
+    }
+    record.fixed64(11, log.observed);
+    record
+}
+
+fn proto_key_value(key: &str, value: &Value) -> Proto {
+    let mut kv = Proto::default();
+    kv.string(1, key);
+    kv.message(2, &proto_any(value));
+    kv
+}
+
+fn proto_any(value: &Value) -> Proto {
+    let mut any = Proto::default();
+
+    match value {
+        Value::String(s) => any.string(1, s),
+        Value::Bool(b) => any.varint(2, *b as u64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => any.varint(3, i as u64),
+            None => any.fixed64(4, n.as_f64().unwrap_or_default().to_bits()),
+        },
+        Value::Array(values) => {
+            let mut array = Proto::default();
+            for value in values {
+                array.message(1, &proto_any(value));
+            }
+            any.message(5, &array);
+        }
+        Value::Object(map) => {
+            let mut list = Proto::default();
+            for (key, value) in map {
+                list.message(1, &proto_key_value(key, value));
+            }
+            any.message(6, &list);
+        }
+        Value::Null => {}
+    }
+
+    any
+}
+
+/// Just enough of the protobuf wire format for the OTLP logs messages.
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint(u64::from(field) << 3 | u64::from(wire_type));
+    }
+
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        self.raw_varint(value);
+    }
+
+    fn fixed64(&mut self, field: u32, value: u64) {
+        self.key(field, 1);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, 2);
+        self.raw_varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, value: &Proto) {
+        self.bytes(field, &value.0);
+    }
+}
+
+fn nanos(value: i64) -> u64 {
+    value.max(0) as u64
+}
+
+fn unix_nanos() -> u64 {
+    clock::system_now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    use super::*;
+
+    /// A receiver taking one OTLP/HTTP request, its path and body come
+    /// through the channel.
+    fn receiver() -> (String, mpsc::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/logs", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+
+            let mut length = 0;
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            sender
+                .send((path, String::from_utf8(body).unwrap()))
+                .unwrap();
+        });
+
+        (endpoint, received)
+    }
+
+    /// Drops `otlp`, its worker is joined with the GIL released under
+    /// `python`.
+    fn close(otlp: OtlpLogger) {
+        #[cfg(feature = "python")]
+        pyo3::Python::with_gil(|_| drop(otlp));
+        #[cfg(not(feature = "python"))]
+        drop(otlp);
+    }
+
+    #[test]
+    fn records_are_exported_with_their_severity_and_body() {
+        let (endpoint, received) = receiver();
+        let config = OtlpConfig {
+            endpoint,
+            protocol: Protocol::Json,
+            headers: HashMap::new(),
+            resource: Map::new(),
+            interval: Duration::from_secs(60),
+            batch_size: 1,
+            gzip: false,
+            wal: None,
+        };
+        let otlp = OtlpLogger::new(config, Arc::new(Stats::default())).unwrap();
+
+        otlp.logger(&Record::new(Level::WARNING, "app", "disk full"));
+        let (path, body) = received.recv_timeout(Duration::from_secs(10)).unwrap();
+        close(otlp);
+
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let record = &body["resourceLogs"][0]["scopeLogs"][0]["logRecords"][0];
+        assert_eq!(path, "/v1/logs");
+        assert_eq!(record["severityNumber"], 13);
+        assert_eq!(record["severityText"], "WARNING");
+        assert_eq!(record["body"]["stringValue"], "disk full");
+    }
+
+    #[test]
+    fn records_queued_by_a_killed_process_are_replayed() {
+        let wal = crate::testing::scratch("otlp-wal").join("otlp.wal");
+        let config = |endpoint: &str, batch_size| OtlpConfig {
+            endpoint: endpoint.to_string(),
+            protocol: Protocol::Json,
+            headers: HashMap::new(),
+            resource: Map::new(),
+            interval: Duration::from_secs(3600),
+            batch_size,
+            gzip: false,
+            wal: Some(wal.to_str().unwrap().to_string()),
+        };
+
+        // Queued and never exported, the handler is leaked the way a killed
+        // process never gets to drain it.
+        let crashed = OtlpLogger::new(
+            config("http://127.0.0.1:9/v1/logs", 100),
+            Arc::new(Stats::default()),
+        )
+        .unwrap();
+        crashed.logger(&Record::new(Level::INFO, "app", "first"));
+        crashed.logger(&Record::new(Level::ERROR, "app", "second"));
+        std::mem::forget(crashed);
+
+        let (endpoint, received) = receiver();
+        let restarted = OtlpLogger::new(config(&endpoint, 2), Arc::new(Stats::default())).unwrap();
+        let (_, body) = received.recv_timeout(Duration::from_secs(10)).unwrap();
+        close(restarted);
+
+        let body: Value = serde_json::from_str(&body).unwrap();
+        let bodies: Vec<&Value> = body["resourceLogs"][0]["scopeLogs"][0]["logRecords"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| &record["body"]["stringValue"])
+            .collect();
+        assert_eq!(bodies, ["first", "second"]);
+        assert_eq!(std::fs::metadata(&wal).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,227 @@
+use std::{io, sync::Mutex, time::Duration};
+
+use rusqlite::{params, Connection};
+use serde_json::Value;
+
+use crate::record::Record;
+
+/// How long an insert waits on another connection writing to the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Inserts each record as a row of a SQLite table, created if it's missing:
+/// its `timestamp` (RFC 3339), `level`, `target` (the logger's name),
+/// `message` and `extra`, its extras as a JSON object.
+///
+/// Rows are held until `batch_size` of them are waiting, or until `flush`,
+/// and then inserted in one transaction. The database is in WAL mode, so it
+/// can be queried while records come in.
+pub struct SqliteLogger {
+    pub path: String,
+    pub table: String,
+    pub batch_size: usize,
+    insert: String,
+    state: Mutex<State>,
+}
+
+struct State {
+    connection: Connection,
+    pending: Vec<Row>,
+}
+
+struct Row {
+    timestamp: String,
+    level: &'static str,
+    target: String,
+    message: String,
+    extra: String,
+}
+
+impl SqliteLogger {
+    pub fn new(path: &str, table: &str, batch_size: usize) -> io::Result<SqliteLogger> {
+        let connection = Connection::open(path).map_err(error)?;
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(error)?;
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(error)?;
+
+        let quoted = quote(table);
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    id INTEGER PRIMARY KEY,
+                    timestamp TEXT NOT NULL,
+                    level TEXT NOT NULL,
+                    target TEXT NOT NULL,
+                    message TEXT NOT NULL,
+                    extra TEXT NOT NULL
+                )",
+                quoted
+            ))
+            .map_err(error)?;
+
+        Ok(SqliteLogger {
+            path: path.to_string(),
+            table: table.to_string(),
+            batch_size: batch_size.max(1),
+            insert: format!(
+                "INSERT INTO {} (timestamp, level, target, message, extra) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                quoted
+            ),
+            state: Mutex::new(State {
+                connection,
+                pending: Vec::new(),
+            }),
+        })
+    }
+
+    /// Queues `message`, the record's as the logger writes it, inserting the
+    /// batch once it's full. A batch that can't be inserted is dropped.
+    pub fn logger(&self, record: &Record, message: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.pending.push(Row {
+            timestamp: record.time.to_rfc3339(),
+            level: record.level.as_str(),
+            target: record.name.clone(),
+            message: message.to_string(),
+            extra: Value::Object(record.extras.clone()).to_string(),
+        });
+
+        match state.pending.len() >= self.batch_size {
+            true => self.insert(&mut state).map(drop),
+            false => Ok(()),
+        }
+    }
+
+    pub fn flush(&self) {
+        if let Err(e) = self.insert(&mut self.state.lock().unwrap()) {
+            eprintln!("soda: {}", e);
+        }
+    }
+
+    /// Like `flush`, giving up after `timeout` on a batch being inserted,
+    /// see `lock_within`. Returns the records inserted.
+    pub fn flush_within(&self, timeout: Duration) -> usize {
+        match super::lock_within(&self.state, timeout) {
+            Some(mut state) => self.insert(&mut state).unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    /// Inserts the pending rows in one transaction, returns how many.
+    fn insert(&self, state: &mut State) -> io::Result<usize> {
+        let rows = std::mem::take(&mut state.pending);
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let inserted = (|| {
+            let transaction = state.connection.transaction()?;
+            {
+                let mut insert = transaction.prepare_cached(&self.insert)?;
+                for row in &rows {
+                    insert.execute(params![
+                        row.timestamp,
+                        row.level,
+                        row.target,
+                        row.message,
+                        row.extra
+                    ])?;
+                }
+            }
+            transaction.commit()
+        })();
+
+        inserted.map(|()| rows.len()).map_err(|e| {
+            io::Error::other(format!(
+                "couldn't insert {} records into {}: {}",
+                rows.len(),
+                self.path,
+                e
+            ))
+        })
+    }
+}
+
+impl Drop for SqliteLogger {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// `identifier` as a quoted SQL identifier, so any table name works.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+    use crate::Level;
+
+    /// The rows of `table` as `(level, target, message, extra)`, read over a
+    /// connection of their own.
+    fn rows(path: &str, table: &str) -> Vec<(String, String, String, Value)> {
+        let connection = Connection::open(path).unwrap();
+        let sql = format!(
+            "SELECT level, target, message, extra FROM {} ORDER BY id",
+            quote(table)
+        );
+        let mut select = connection.prepare(&sql).unwrap();
+        let rows = select
+            .query_map([], |row| {
+                let extra: String = row.get(3)?;
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    serde_json::from_str(&extra).unwrap(),
+                ))
+            })
+            .unwrap();
+
+        rows.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn records_round_trip_in_batches() {
+        let path = scratch("sqlite").join("logs.db");
+        let path = path.to_str().unwrap();
+        let table = "app \"logs\"";
+        let logger = SqliteLogger::new(path, table, 2).unwrap();
+
+        let mut first = Record::new(Level::WARNING, "app.db", "slow query");
+        first.extras.insert(String::from("ms"), Value::from(120));
+        logger.logger(&first, "slow query").unwrap();
+        assert!(rows(path, table).is_empty());
+        logger
+            .logger(&Record::new(Level::INFO, "app", "ready"), "ready")
+            .unwrap();
+        logger
+            .logger(&Record::new(Level::ERROR, "app", "held"), "held")
+            .unwrap();
+        assert_eq!(rows(path, table).len(), 2);
+        logger.flush();
+
+        let rows = rows(path, table);
+        let read: Vec<_> = rows
+            .iter()
+            .map(|(level, target, message, _)| (level.as_str(), target.as_str(), message.as_str()))
+            .collect();
+        assert_eq!(
+            read,
+            [
+                ("WARNING", "app.db", "slow query"),
+                ("INFO", "app", "ready"),
+                ("ERROR", "app", "held"),
+            ]
+        );
+        assert_eq!(rows[0].3, serde_json::json!({ "ms": 120 }));
+        assert_eq!(rows[1].3, serde_json::json!({}));
+    }
+}
@@ -0,0 +1,119 @@
+//! A write-ahead log keeping queued records on disk until they're handled.
+//!
+//! The file holds a JSON line per record, `{"seq": n, "record": ...}`, and a
+//! line per batch handled, `{"ack": [first, last]}`. Opening it again gives
+//! back the records never acknowledged, the ones a killed process still had
+//! queued, and starts the file over with just those. Once every record in
+//! it is acknowledged the file is emptied, so it only grows with a backlog.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+};
+
+use serde_json::{json, Value};
+
+pub struct Wal {
+    path: String,
+    file: File,
+    next_seq: u64,
+    /// Records in the file not acknowledged yet.
+    outstanding: u64,
+}
+
+impl Wal {
+    /// Opens the log at `path`, created if needed, with the records it
+    /// holds that were never acknowledged, oldest first, numbered from 0.
+    pub fn open(path: &str) -> io::Result<(Wal, Vec<(u64, Value)>)> {
+        let pending = match File::open(path) {
+            Ok(file) => pending(file)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        // Written aside and renamed over, a crash now loses nothing.
+        let rewritten = format!("{}.tmp", path);
+        let mut file = File::create(&rewritten)?;
+        let pending: Vec<(u64, Value)> = pending
+            .into_iter()
+            .enumerate()
+            .map(|(seq, record)| (seq as u64, record))
+            .collect();
+        for (seq, record) in &pending {
+            writeln!(file, "{}", json!({ "seq": seq, "record": record }))?;
+        }
+        file.sync_all()?;
+        fs::rename(&rewritten, path)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        let wal = Wal {
+            path: path.to_string(),
+            file,
+            next_seq: pending.len() as u64,
+            outstanding: pending.len() as u64,
+        };
+
+        Ok((wal, pending))
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Appends `record`, returns the number to acknowledge it by.
+    pub fn append(&mut self, record: &Value) -> io::Result<u64> {
+        let seq = self.next_seq;
+        writeln!(self.file, "{}", json!({ "seq": seq, "record": record }))?;
+
+        self.next_seq += 1;
+        self.outstanding += 1;
+
+        Ok(seq)
+    }
+
+    /// Acknowledges the records numbered `seqs`, handled in one go and so
+    /// numbered one after the other.
+    pub fn ack(&mut self, seqs: &[u64]) -> io::Result<()> {
+        let (first, last) = match (seqs.iter().min(), seqs.iter().max()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Ok(()),
+        };
+        self.outstanding = self.outstanding.saturating_sub(seqs.len() as u64);
+
+        if self.outstanding == 0 {
+            self.file.set_len(0)?;
+            self.next_seq = 0;
+            return Ok(());
+        }
+
+        writeln!(self.file, "{}", json!({ "ack": [first, last] }))
+    }
+}
+
+/// The records of a log not acknowledged, a line cut short by a crash
+/// skipped.
+fn pending(file: File) -> io::Result<Vec<Value>> {
+    let mut records = BTreeMap::new();
+    let mut acks = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        let entry: Value = match serde_json::from_str(&line?) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        if let (Some(seq), Some(record)) = (entry["seq"].as_u64(), entry.get("record")) {
+            records.insert(seq, record.clone());
+        }
+        if let (Some(first), Some(last)) = (entry["ack"][0].as_u64(), entry["ack"][1].as_u64()) {
+            acks.push((first, last));
+        }
+    }
+
+    for (first, last) in acks {
+        records.retain(|seq, _| *seq < first || *seq > last);
+    }
+
+    Ok(records.into_values().collect())
+}
@@ -1,29 +1,42 @@
-use std::{
-    borrow::{Borrow, BorrowMut},
-    fs::File,
-    io::{ErrorKind, Write},
-};
-
-use std::fs::OpenOptions;
-use std::io::prelude::*;
-
-use fern::Dispatch;
-use log::{debug, error, info, trace, warn};
-
-use pyo3::prelude::*;
-use pyo3::types::{PyLong, PyUnicode};
-
-#[pymodule]
-fn soda(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<Soda>()?;
-    Ok(())
-}
+//! Logging with a console, file, JSON, fluentd and OpenTelemetry handlers.
+//!
+//! The core is plain Rust, see `Logger`. The Python extension module is
+//! built on top of it behind the default `python` feature, build with
+//! `default-features = false` to use soda from Rust alone.
+
+pub mod cleanup;
+pub mod clock;
+pub mod format;
+pub mod handlers;
+pub mod logger;
+pub mod mdc;
+pub mod metrics;
+pub mod parse;
+pub mod record;
+pub mod schema;
+pub mod scrub;
+pub mod stats;
+pub mod tail;
+pub mod template;
+
+#[cfg(feature = "python")]
+mod python;
+#[cfg(test)]
+mod testing;
+
+pub use format::Format;
+pub use handlers::file::FileLogger;
+pub use logger::{Handlers, Logger};
+pub use record::Record;
 
 /// Until https://github.com/PyO3/pyo3/issues/417
 /// gets merged, we cannot bind rust enums or constants
 /// as a part of module
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy)]
 pub enum Level {
     NOTSET,
+    TRACE,
     DEBUG,
     INFO,
     WARNING,
@@ -31,303 +44,66 @@ pub enum Level {
     CRITICAL,
 }
 
-static dateFormat: &'static str = "[%Y-%m-%d][%H:%M:%S]";
-
-#[pyclass(dict, subclass)]
-pub struct Soda {
-    pub level: Level,
-
-    pub format: String,
-    // pub verbosity: u64
-    pub handlers: Handlers,
-}
-
-#[pyclass(dict, subclass)]
-pub struct Handlers {
-    FileHandler: FileLogger,
-}
-
-#[pymethods]
-impl Handlers {
-    #[new]
-    #[args(json = false, file = false)]
-    fn new(json: bool, file: bool) -> Handlers {
-        Handlers {
-            FileHandler: FileLogger::new(),
-        }
-    }
-}
-
-#[pymethods]
-impl Soda {
-    #[new]
-    #[args(verbosity = "0")]
-    fn new(verbosity: u64) -> Soda {
-        // Create at Python runtime to make this logger globally accessable.
-        let mut base_config = fern::Dispatch::new();
-
-        base_config = match verbosity {
-            0 => base_config.level(log::LevelFilter::Info),
-            1 => base_config.level(log::LevelFilter::Debug),
-            2 => base_config.level(log::LevelFilter::Warn),
-            _3_or_more => base_config.level(log::LevelFilter::Trace),
-        };
-
-        Soda {
-            level: Level::NOTSET,
-            format: String::new(),
-            handlers: Handlers::new(false, false),
+impl Level {
+    /// The closest level to a `logging` level number, custom levels included.
+    pub fn from_number(levelno: i64) -> Level {
+        match levelno {
+            n if n >= 50 => Level::CRITICAL,
+            n if n >= 40 => Level::ERROR,
+            n if n >= 30 => Level::WARNING,
+            n if n >= 20 => Level::INFO,
+            n if n >= 10 => Level::DEBUG,
+            n if n > 0 => Level::TRACE,
+            _ => Level::NOTSET,
         }
     }
 
-    fn setFormat(&mut self, format: &PyUnicode) {
-        let format: Result<&str, PyErr> = format.to_str();
-
-        if let Ok(format) = format {
-            self.format = format.to_string();
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name.to_uppercase().as_str() {
+            "NOTSET" => Some(Level::NOTSET),
+            "TRACE" => Some(Level::TRACE),
+            "DEBUG" => Some(Level::DEBUG),
+            "INFO" => Some(Level::INFO),
+            "WARNING" | "WARN" => Some(Level::WARNING),
+            "ERROR" => Some(Level::ERROR),
+            "CRITICAL" => Some(Level::CRITICAL),
+            _ => None,
         }
     }
 
-    fn basicConfig(&mut self, dtFormat: &PyUnicode) {
-        let dtFormat: String = match dtFormat.to_str() {
-            Ok(fmt) => fmt.to_string(),
-            Err(e) => {
-                println!(
-                    "An error occured while reading the format {}, using the default format",
-                    e
-                );
-                String::from(dateFormat)
-            }
-        };
-
-        let mut config = fern::Dispatch::new()
-            .format(move |out, message, record| {
-                // special format for debug messages coming from our own crate.
-                if record.level() > log::LevelFilter::Info && record.target() == "soda" {
-                    out.finish(format_args!(
-                        "---\nDEBUG: {}: {}\n---",
-                        chrono::Local::now().format(dtFormat.as_str()),
-                        message
-                    ))
-                } else {
-                    out.finish(format_args!(
-                        "[{}][{}][{}] {}",
-                        chrono::Local::now().format(dtFormat.as_str()),
-                        record.target(),
-                        record.level(),
-                        message
-                    ))
-                }
-            })
-            .chain(std::io::stdout())
-            .apply();
-    }
-
-    fn addFileHandler(&mut self, path: String) {
-        let f = File::open(&path);
-
-        let _: File = match f {
-            Ok(file) => file,
-            Err(error) => match error.kind() {
-                ErrorKind::NotFound => match File::create(&path) {
-                    Ok(fc) => fc,
-                    Err(e) => panic!("Problem creating the file: {:?}", e),
-                },
-                _ => panic!("an error occured {}", error),
-            },
-        };
-
-        self.handlers.FileHandler.enabled = true;
-        self.handlers.FileHandler.path = path;
-    }
-
-    fn callback(&self, message: &str) {
-        match self.handlers.FileHandler.enabled {
-            true => self.handlers.FileHandler.logger(message),
-            false => (),
-        };
-
-        // TODO(ycd): enable json logging with extra crate.
-        // match self.handlers.JsonHandler {
-        //     // true => jsonLogger(message),
-        //     true => (),
-        //     false => (),
-        // };
-    }
-
-    fn info(&self, message: &PyUnicode) {
-        let message = match message.to_str() {
-            Ok(msg) => msg,
-            _ => return,
-        };
-
-        info!("{}", message);
-
-        self.callback(message);
-    }
-
-    fn warning(&mut self, message: &PyUnicode) {
-        let message = match message.to_str() {
-            Ok(msg) => msg,
-            _ => return,
-        };
-
-        warn!("{}", message);
-    }
-
-    fn debug(&mut self, message: &PyUnicode) {
-        let message = match message.to_str() {
-            Ok(msg) => msg,
-            _ => return,
-        };
-
-        debug!("{}", message);
-
-        self.callback(message);
-    }
-
-    fn trace(&mut self, message: &PyUnicode) {
-        let message = match message.to_str() {
-            Ok(msg) => msg,
-            _ => return,
-        };
-
-        trace!("{}", message);
-
-        self.callback(message);
-    }
-
-    fn error(&mut self, message: &PyUnicode) {
-        let message = match message.to_str() {
-            Ok(msg) => msg,
-            _ => return,
-        };
-
-        error!("{}", message);
-
-        self.callback(message);
-    }
-
-    pub fn setLevel(&mut self, verbosity: u8) {
-        match verbosity {
-            1 => self.level = Level::DEBUG,
-            2 => self.level = Level::INFO,
-            3 => self.level = Level::WARNING,
-            _ => {
-                println!("Found none, setting default value to 'DEBUG'");
-                self.level = Level::DEBUG
-            }
+    /// The `logging` level number, `5` for `TRACE`.
+    pub fn number(self) -> i64 {
+        match self {
+            Level::NOTSET => 0,
+            Level::TRACE => 5,
+            Level::DEBUG => 10,
+            Level::INFO => 20,
+            Level::WARNING => 30,
+            Level::ERROR => 40,
+            Level::CRITICAL => 50,
         }
     }
-}
-
-// fn fileLogger(message: &str) {
-//     let mut file = OpenOptions::new()
-//         .write(true)
-//         .append(true)
-//         .open(&self.path)
-//         .unwrap();
-
-//     if let Err(e) = writeln!(file, "{}", self.format(message)) {
-//         eprintln!("Couldn't write to file: {}", e);
-//     }
-
-//     let f = File::open(&self.path);
 
-//     let f: File = match f {
-//         Ok(file) => file,
-//         Err(error) => match error.kind() {
-//             ErrorKind::NotFound => match File::create(&self.path) {
-//                 Ok(fc) => fc,
-//                 Err(e) => panic!("Problem creating the file: {:?}", e),
-//             },
-//             _ => panic!("an error occured {}", error),
-//         },
-//     };
-// }
-
-// trait Logger {
-//     fn logger(message: &str);
-// }
-
-struct FileLogger {
-    enabled: bool,
-    path: String,
-}
-
-impl FileLogger {
-    fn new() -> FileLogger {
-        FileLogger {
-            enabled: false,
-            path: String::from("default.log"),
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::NOTSET => "NOTSET",
+            Level::TRACE => "TRACE",
+            Level::DEBUG => "DEBUG",
+            Level::INFO => "INFO",
+            Level::WARNING => "WARNING",
+            Level::ERROR => "ERROR",
+            Level::CRITICAL => "CRITICAL",
         }
     }
 
-    fn logger(&self, message: &str) {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(&self.path)
-            .unwrap();
-
-        if let Err(e) = writeln!(file, "{}", message) {
-            eprintln!("Couldn't write to file: {}", e);
+    /// The `log` level the console dispatch sees.
+    pub fn to_log(self) -> log::Level {
+        match self {
+            Level::NOTSET | Level::TRACE => log::Level::Trace,
+            Level::DEBUG => log::Level::Debug,
+            Level::INFO => log::Level::Info,
+            Level::WARNING => log::Level::Warn,
+            Level::ERROR | Level::CRITICAL => log::Level::Error,
         }
-
-        // let f = File::open(&self.path);
-
-        // let f: File = match f {
-        //     Ok(file) => file,
-        //     Err(error) => match error.kind() {
-        //         ErrorKind::NotFound => match File::create(&self.path) {
-        //             Ok(fc) => fc,
-        //             Err(e) => panic!("Problem creating the file: {:?}", e),
-        //         },
-        //         _ => panic!("an error occured {}", error),
-        //     },
-        // };
-    }
-
-    fn format(&self, message: &str) -> String {
-        format!("{}", message)
     }
 }
-
-// impl Soda {
-//     // fn _addConfig(&mut self, config: &fern::Dispatch) {
-
-//     // }
-// }
-
-// fn setup_logging(verbosity: u64) -> Result<(), fern::InitError> {
-//     let mut base_config = fern::Dispatch::new();
-
-//     base_config = match verbosity {
-//         0 => base_config
-//             .level(log::LevelFilter::Info)
-//             .level_for("overly-verbose-target", log::LevelFilter::Warn),
-//         1 => base_config
-//             .level(log::LevelFilter::Debug)
-//             .level_for("overly-verbose-target", log::LevelFilter::Info),
-//         2 => base_config.level(log::LevelFilter::Debug),
-//         _3_or_more => base_config.level(log::LevelFilter::Trace),
-//     };
-
-//     // Separate file config so we can include year, month and day in file logs
-
-//     let file_config = fern::Dispatch::new()
-//         .format(|out, message, record| {
-//             out.finish(format_args!(
-//                 "{}[{}][{}] {}",
-//                 chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
-//                 record.target(),
-//                 record.level(),
-//                 message
-//             ))
-//         })
-//         .chain(fern::log_file("program.log")?);
-
-//     base_config.chain(file_config);
-
-//     Ok(())
-// }
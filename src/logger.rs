@@ -0,0 +1,2267 @@
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, ErrorKind},
+    panic,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex, MutexGuard, Once, RwLock, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use log::log;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::format::{self, ColorScope, Format, SharedFormat};
+use crate::handlers::file::{FileLogger, FileOptions, Pruned, Unarchived};
+use crate::handlers::fluentd::FluentdLogger;
+use crate::handlers::json::JsonLogger;
+use crate::handlers::level_split::LevelSplitLogger;
+use crate::handlers::memory::{self, MemoryLogger};
+use crate::handlers::otlp::OtlpLogger;
+#[cfg(feature = "sqlite")]
+use crate::handlers::sqlite::SqliteLogger;
+use crate::handlers::{console, lock_within};
+use crate::mdc;
+use crate::record::{self, LazyField, Record};
+use crate::schema::{OnViolation, Schema, Violation};
+use crate::stats::{HandlerKind, Stats};
+use crate::template::{self, Coercion};
+use crate::Level;
+
+thread_local! {
+    /// Set while this thread's handlers, hooks and processors deal with a
+    /// record, so a record they log is caught rather than going round
+    /// again, see `Logger::emit_except`.
+    static HANDLING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets `HANDLING` for as long as it's held, a panic unwinding through a
+/// handler clears it too.
+struct Handling;
+
+impl Handling {
+    fn start() -> Handling {
+        HANDLING.with(|handling| handling.set(true));
+        Handling
+    }
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        HANDLING.with(|handling| handling.set(false));
+    }
+}
+
+/// Whether a record logged while handling another was reported yet, once
+/// for the process.
+static WARNED_REENTRANT: AtomicBool = AtomicBool::new(false);
+
+/// Called with every record a logger emits, see `Logger::add_callback`.
+pub type Callback = Arc<dyn Fn(&Record) + Send + Sync>;
+
+/// Transforms a record before any handler sees it, `None` drops it. See
+/// `Logger::add_processor`.
+pub type Processor = Arc<dyn Fn(Record) -> Option<Record> + Send + Sync>;
+
+/// Decides whether a record is kept, see `Logger::add_filter`.
+pub type Filter = Arc<dyn Fn(&Record) -> bool + Send + Sync>;
+
+/// Runs on a record that passed the filters, just before the handlers get
+/// it.
+pub type BeforeEmit = Arc<dyn Fn(&mut Record) + Send + Sync>;
+
+/// Runs once the handlers are done with a record, with the name of each
+/// handler that got it, see `Handlers::label`, and whether it wrote it.
+pub type AfterEmit = Arc<dyn Fn(&Record, &[(String, bool)]) + Send + Sync>;
+
+/// A regex the message of a record is matched against, see
+/// `Logger::exclude_messages` and `Logger::include_only_messages`.
+#[derive(Clone)]
+pub struct MessagePattern {
+    /// What `remove_filter` takes.
+    pub id: u64,
+    /// The handler it applies to, `None` for all of them.
+    pub handler: Option<HandlerId>,
+    /// `true` for an include-only pattern, `false` for an exclude one.
+    pub include: bool,
+    pub regex: Regex,
+}
+
+/// Keeps the records carrying any of the `require` tags, when there are
+/// some, and none of the `exclude` ones, see `Logger::add_tag_filter`.
+#[derive(Clone)]
+pub struct TagFilter {
+    /// What `remove_filter` takes.
+    pub id: u64,
+    /// The handler it keeps records from, `None` for all of them.
+    pub handler: Option<HandlerId>,
+    pub require: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn allows(&self, tags: &[String]) -> bool {
+        (self.require.is_empty() || self.require.iter().any(|tag| tags.contains(tag)))
+            && !self.exclude.iter().any(|tag| tags.contains(tag))
+    }
+}
+
+/// Lets the records it matches through or drops them, see
+/// `Logger::set_filter_rules`.
+#[derive(Clone)]
+pub struct FilterRule {
+    /// Matches records whose logger name starts with it.
+    pub target: String,
+    /// Matches records at this level or above.
+    pub min_level: Level,
+    pub allow: bool,
+}
+
+impl FilterRule {
+    pub fn matches(&self, record: &Record) -> bool {
+        record.name.starts_with(self.target.as_str()) && record.level as u8 >= self.min_level as u8
+    }
+}
+
+/// Sends the records it matches to `handlers`, see `Logger::add_route`.
+#[derive(Clone)]
+pub struct Route {
+    /// Matches records whose logger name starts with it.
+    pub name_prefix: Option<String>,
+    /// Matches records at this level or above.
+    pub min_level: Option<Level>,
+    pub handlers: Vec<HandlerId>,
+    /// A record it matches goes to its handlers alone, no later route or
+    /// default handler gets it.
+    pub exclusive: bool,
+}
+
+impl Route {
+    pub fn matches(&self, record: &Record) -> bool {
+        let name = match &self.name_prefix {
+            Some(prefix) => record.name.starts_with(prefix.as_str()),
+            None => true,
+        };
+        let level = match self.min_level {
+            Some(level) => record.level as u8 >= level as u8,
+            None => true,
+        };
+
+        name && level
+    }
+}
+
+/// One of a logger's handlers: its kind and which of that kind's, `0` for
+/// the first, see `Handlers::place`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HandlerId {
+    pub kind: HandlerKind,
+    pub index: usize,
+}
+
+impl HandlerId {
+    /// The first handler of `kind`, the one its kind's name refers to.
+    pub fn first(kind: HandlerKind) -> HandlerId {
+        HandlerId { kind, index: 0 }
+    }
+}
+
+/// A handler added under a name another kind's handler has.
+#[derive(Debug)]
+pub struct NameTaken {
+    pub name: String,
+    pub kind: HandlerKind,
+}
+
+impl fmt::Display for NameTaken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the {} handler is called {:?} already",
+            self.kind.as_str(),
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for NameTaken {}
+
+/// The handler at `index` of a kind there can be several of.
+fn at<'a, T>(first: Option<&'a T>, more: &'a [T], index: usize) -> Option<&'a T> {
+    match index {
+        0 => first,
+        index => more.get(index - 1),
+    }
+}
+
+/// Puts `handler` at `index`, returns the one it replaces.
+fn put<T>(first: &mut Option<T>, more: &mut Vec<T>, index: usize, handler: T) -> Option<T> {
+    match index {
+        0 => first.replace(handler),
+        index if index > more.len() => {
+            more.push(handler);
+            None
+        }
+        index => Some(std::mem::replace(&mut more[index - 1], handler)),
+    }
+}
+
+/// The handlers a logger fans its records out to: the console first, then
+/// the others in the order they were first added, then the callbacks.
+#[derive(Default)]
+pub struct Handlers {
+    pub file: FileLogger,
+    pub fluentd: Option<FluentdLogger>,
+    pub json: Option<JsonLogger>,
+    pub level_split: Option<LevelSplitLogger>,
+    pub memory: Option<Arc<MemoryLogger>>,
+    pub otlp: Option<OtlpLogger>,
+    #[cfg(feature = "sqlite")]
+    pub sqlite: Option<SqliteLogger>,
+    /// The file, JSON, level split and SQLite handlers after the first of
+    /// their kind, at `index - 1`.
+    pub more_files: Vec<FileLogger>,
+    pub more_json: Vec<JsonLogger>,
+    pub more_splits: Vec<LevelSplitLogger>,
+    #[cfg(feature = "sqlite")]
+    pub more_sqlite: Vec<SqliteLogger>,
+    pub callbacks: Vec<Callback>,
+    /// Names handlers were added under, besides their kind's.
+    names: HashMap<String, HandlerId>,
+    disabled: HashSet<HandlerId>,
+    /// Handlers in the order they were first added, a handler added again
+    /// keeps its place.
+    order: Vec<HandlerId>,
+}
+
+impl Handlers {
+    /// Where a handler of `kind` added under `name` goes: in place of the
+    /// one with that name or, without a name, of the first of its kind. A
+    /// new name gets a file, JSON, level split or SQLite handler of its own
+    /// next to the others of its kind, and the place of the first for the
+    /// other kinds, there's one of each.
+    pub fn place(&self, kind: HandlerKind, name: Option<&str>) -> Result<HandlerId, NameTaken> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(HandlerId::first(kind)),
+        };
+        match self.names.get(name) {
+            Some(id) if id.kind == kind => return Ok(*id),
+            Some(id) => {
+                return Err(NameTaken {
+                    name: name.to_string(),
+                    kind: id.kind,
+                })
+            }
+            None => {}
+        }
+
+        let more = match kind {
+            HandlerKind::File if self.file.enabled => self.more_files.len(),
+            HandlerKind::Json if self.json.is_some() => self.more_json.len(),
+            HandlerKind::LevelSplit if self.level_split.is_some() => self.more_splits.len(),
+            #[cfg(feature = "sqlite")]
+            HandlerKind::Sqlite if self.sqlite.is_some() => self.more_sqlite.len(),
+            _ => return Ok(HandlerId::first(kind)),
+        };
+
+        Ok(HandlerId {
+            kind,
+            index: more + 1,
+        })
+    }
+
+    /// Names the handler `id`, put there by `place`, it can always be
+    /// referred to by its kind (`"file"`, `"json"`, ...) too when it's the
+    /// first of its kind. A handler being (re)added is enabled again.
+    pub fn set_name(&mut self, id: HandlerId, name: Option<&str>) {
+        self.names.retain(|_, named| *named != id);
+        if let Some(name) = name {
+            self.names.insert(name.to_string(), id);
+        }
+        self.disabled.remove(&id);
+        if !self.order.contains(&id) {
+            self.order.push(id);
+        }
+    }
+
+    /// The name the handler `id` was added under, if it was given one.
+    pub fn name(&self, id: HandlerId) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, named)| **named == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The name to refer to the handler `id` by: the one it was added
+    /// under, or its kind's.
+    pub fn label(&self, id: HandlerId) -> String {
+        self.name(id)
+            .unwrap_or_else(|| id.kind.as_str())
+            .to_string()
+    }
+
+    /// Turns the handler called `name` on or off, returns `false` when there
+    /// is no such name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let id = match self.id(name) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        if enabled {
+            self.disabled.remove(&id);
+        } else {
+            self.disabled.insert(id);
+        }
+
+        true
+    }
+
+    /// The handler called `name`, by the name it was added under or, for
+    /// the first of a kind, by its kind's.
+    pub fn id(&self, name: &str) -> Option<HandlerId> {
+        self.names
+            .get(name)
+            .copied()
+            .or_else(|| HandlerKind::parse(name).map(HandlerId::first))
+    }
+
+    /// Every handler a record can go to, the console first.
+    fn ids(&self) -> impl Iterator<Item = HandlerId> + '_ {
+        let console = HandlerId::first(HandlerKind::Console);
+        std::iter::once(console).chain(self.order.iter().copied().filter(move |id| *id != console))
+    }
+
+    pub fn enabled(&self, id: HandlerId) -> bool {
+        !self.disabled.contains(&id)
+    }
+
+    pub fn file_at(&self, index: usize) -> Option<&FileLogger> {
+        at(Some(&self.file), &self.more_files, index)
+    }
+
+    pub fn json_at(&self, index: usize) -> Option<&JsonLogger> {
+        at(self.json.as_ref(), &self.more_json, index)
+    }
+
+    pub fn split_at(&self, index: usize) -> Option<&LevelSplitLogger> {
+        at(self.level_split.as_ref(), &self.more_splits, index)
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite_at(&self, index: usize) -> Option<&SqliteLogger> {
+        at(self.sqlite.as_ref(), &self.more_sqlite, index)
+    }
+
+    /// Puts `json` at `id`, see `place`, returns the handler it replaces.
+    pub fn put_json(&mut self, id: HandlerId, json: JsonLogger) -> Option<JsonLogger> {
+        put(&mut self.json, &mut self.more_json, id.index, json)
+    }
+
+    pub fn put_split(
+        &mut self,
+        id: HandlerId,
+        split: LevelSplitLogger,
+    ) -> Option<LevelSplitLogger> {
+        put(
+            &mut self.level_split,
+            &mut self.more_splits,
+            id.index,
+            split,
+        )
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn put_sqlite(&mut self, id: HandlerId, sqlite: SqliteLogger) -> Option<SqliteLogger> {
+        put(&mut self.sqlite, &mut self.more_sqlite, id.index, sqlite)
+    }
+
+    fn files(&self) -> impl Iterator<Item = &FileLogger> {
+        std::iter::once(&self.file).chain(&self.more_files)
+    }
+
+    fn jsons(&self) -> impl Iterator<Item = &JsonLogger> {
+        self.json.iter().chain(&self.more_json)
+    }
+
+    fn splits(&self) -> impl Iterator<Item = &LevelSplitLogger> {
+        self.level_split.iter().chain(&self.more_splits)
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn sqlites(&self) -> impl Iterator<Item = &SqliteLogger> {
+        self.sqlite.iter().chain(&self.more_sqlite)
+    }
+
+    /// Writes out what the handlers buffer, the console aside.
+    fn flush(&self) {
+        self.files().for_each(FileLogger::flush);
+        self.splits().for_each(LevelSplitLogger::flush);
+        self.jsons().for_each(JsonLogger::flush);
+        #[cfg(feature = "sqlite")]
+        self.sqlites().for_each(SqliteLogger::flush);
+    }
+
+    /// Like `flush`, giving up on a buffer after `timeout`, see
+    /// `lock_within`. Returns the records written out.
+    fn flush_within(&self, timeout: Duration) -> usize {
+        #[cfg(feature = "sqlite")]
+        let sqlite: usize = self
+            .sqlites()
+            .map(|sqlite| sqlite.flush_within(timeout))
+            .sum();
+        #[cfg(not(feature = "sqlite"))]
+        let sqlite = 0;
+
+        self.files()
+            .map(|file| file.flush_within(timeout))
+            .chain(self.splits().map(|split| split.flush_within(timeout)))
+            .chain(self.jsons().map(|json| json.flush_within(timeout)))
+            .sum::<usize>()
+            + sqlite
+    }
+
+    /// Writes out what the handler `id` buffers. The network handlers send
+    /// from their queues as they go and the memory one holds no buffer,
+    /// there's nothing to write out for them.
+    fn flush_one(&self, id: HandlerId) {
+        match id.kind {
+            HandlerKind::Console => console::flush(),
+            HandlerKind::File => self
+                .file_at(id.index)
+                .into_iter()
+                .for_each(FileLogger::flush),
+            HandlerKind::LevelSplit => {
+                if let Some(split) = self.split_at(id.index) {
+                    split.flush();
+                }
+            }
+            HandlerKind::Json => {
+                if let Some(json) = self.json_at(id.index) {
+                    json.flush();
+                }
+            }
+            HandlerKind::Sqlite =>
+            {
+                #[cfg(feature = "sqlite")]
+                if let Some(sqlite) = self.sqlite_at(id.index) {
+                    sqlite.flush();
+                }
+            }
+            HandlerKind::Fluentd | HandlerKind::Memory | HandlerKind::Otlp => {}
+        }
+    }
+
+    /// Whether there's the SQLite handler at `index`, never without the
+    /// `sqlite` feature.
+    fn has_sqlite(&self, index: usize) -> bool {
+        #[cfg(feature = "sqlite")]
+        return self.sqlite_at(index).is_some();
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let _ = index;
+            false
+        }
+    }
+
+    /// `handler` unless the handler `id` is turned off or filtered the
+    /// record out.
+    fn active<'a, T>(
+        &self,
+        handler: Option<&'a T>,
+        id: HandlerId,
+        rejected: &HashSet<HandlerId>,
+    ) -> Option<&'a T> {
+        handler.filter(|_| self.enabled(id) && !rejected.contains(&id))
+    }
+}
+
+/// Whether `message` gets past the patterns that apply to `handler`: none of
+/// the exclude ones match and, if there are include-only ones, one of them
+/// does.
+fn passes(patterns: &[MessagePattern], handler: Option<HandlerId>, message: &str) -> bool {
+    let mut included = None;
+
+    for pattern in patterns.iter().filter(|pattern| pattern.handler == handler) {
+        let matched = pattern.regex.is_match(message);
+        if !pattern.include && matched {
+            return false;
+        }
+        if pattern.include {
+            included = Some(included.unwrap_or(false) || matched);
+        }
+    }
+
+    included.unwrap_or(true)
+}
+
+/// Handler sets of every live logger, so they can be set aside as a whole.
+static REGISTRY: Mutex<Vec<Weak<Mutex<Handlers>>>> = Mutex::new(Vec::new());
+
+fn register(handlers: Handlers) -> Arc<Mutex<Handlers>> {
+    let handlers = Arc::new(Mutex::new(handlers));
+
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|set| set.strong_count() > 0);
+    registry.push(Arc::downgrade(&handlers));
+
+    handlers
+}
+
+/// Handler sets taken off the loggers by `snapshot_handlers`.
+pub struct HandlerSnapshot {
+    sets: Vec<(Weak<Mutex<Handlers>>, Handlers)>,
+}
+
+/// Leaves every live logger without handlers until `restore_handlers`.
+pub fn snapshot_handlers() -> HandlerSnapshot {
+    let registry = REGISTRY.lock().unwrap();
+
+    let sets = registry
+        .iter()
+        .filter_map(|set| {
+            let handlers = set.upgrade()?;
+            let previous = std::mem::take(&mut *handlers.lock().unwrap());
+            Some((Weak::clone(set), previous))
+        })
+        .collect();
+
+    HandlerSnapshot { sets }
+}
+
+/// Puts the handlers of a snapshot back, dropping whatever was added since.
+/// Loggers created after the snapshot keep their handlers.
+pub fn restore_handlers(snapshot: HandlerSnapshot) {
+    let mut replaced = Vec::new();
+
+    for (set, handlers) in snapshot.sets {
+        if let Some(set) = set.upgrade() {
+            replaced.push(std::mem::replace(&mut *set.lock().unwrap(), handlers));
+        }
+    }
+
+    // Replaced handlers join their workers, not while holding any lock.
+    drop(replaced);
+}
+
+/// The handler sets of every live logger.
+fn live_sets() -> Vec<Arc<Mutex<Handlers>>> {
+    REGISTRY
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Writes out what the handlers of every live logger still buffer, for a
+/// logger that may never be dropped.
+pub fn flush_all() {
+    for set in live_sets() {
+        set.lock().unwrap().flush();
+    }
+}
+
+/// Like `flush_all`, for a process about to end in a hurry: the console
+/// too, and giving up on a lock held after `timeout`, by this very thread
+/// maybe, writing out what it can. Returns the records written out.
+pub fn flush_all_within(timeout: Duration) -> usize {
+    console::flush_within(timeout);
+
+    let sets: Vec<Arc<Mutex<Handlers>>> = match lock_within(&REGISTRY, timeout) {
+        Some(registry) => registry.iter().filter_map(Weak::upgrade).collect(),
+        None => return 0,
+    };
+    sets.iter()
+        .filter_map(|set| lock_within(set, timeout))
+        .map(|handlers| handlers.flush_within(timeout))
+        .sum()
+}
+
+/// How long a panic waits on each lock to flush the handlers.
+const PANIC_FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Has a panic anywhere in the process write out what the handlers buffer,
+/// best effort, see `flush_all_within`, before the hook that was there
+/// reports it. Installing again adds nothing.
+pub fn install_panic_flush() {
+    static INSTALLED: Once = Once::new();
+
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            flush_all_within(PANIC_FLUSH_TIMEOUT);
+            previous(info);
+        }));
+    });
+}
+
+/// The files the handlers of every live logger write to.
+pub fn active_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for set in live_sets() {
+        let handlers = set.lock().unwrap();
+        for file in handlers.files().filter(|file| file.enabled) {
+            paths.push(PathBuf::from(file.path()));
+        }
+        for path in handlers.jsons().filter_map(|json| json.path.as_ref()) {
+            paths.push(PathBuf::from(path));
+        }
+        for split in handlers.splits() {
+            paths.extend(split.paths());
+        }
+        #[cfg(feature = "sqlite")]
+        for sqlite in handlers.sqlites() {
+            paths.push(PathBuf::from(&sqlite.path));
+        }
+    }
+
+    paths
+}
+
+/// A logger's level, and the one of the logger it inherits from while it
+/// has none of its own, see `Logger::effective_level`.
+pub struct LevelCell {
+    /// The `Level::number` below which records are dropped, `0` for none.
+    level: AtomicU8,
+    parent: RwLock<Option<Arc<LevelCell>>>,
+}
+
+impl LevelCell {
+    fn new(parent: Option<Arc<LevelCell>>) -> LevelCell {
+        LevelCell {
+            level: AtomicU8::new(0),
+            parent: RwLock::new(parent),
+        }
+    }
+
+    fn number(&self) -> u8 {
+        self.level.load(Ordering::Relaxed)
+    }
+
+    /// The first level set going up the parents, this one's first, `NOTSET`
+    /// when none is.
+    pub fn effective(&self) -> Level {
+        let mut number = self.number();
+        let mut parent = self.parent();
+
+        while number == 0 {
+            let cell = match parent {
+                Some(cell) => cell,
+                None => break,
+            };
+            number = cell.number();
+            parent = cell.parent();
+        }
+
+        Level::from_number(number.into())
+    }
+
+    fn parent(&self) -> Option<Arc<LevelCell>> {
+        self.parent.read().unwrap().clone()
+    }
+
+    /// Whether this one inherits the level of `cell`, or `cell` is `None`
+    /// and it inherits none.
+    pub fn inherits_from(&self, cell: Option<&Arc<LevelCell>>) -> bool {
+        match (self.parent.read().unwrap().as_ref(), cell) {
+            (Some(parent), Some(cell)) => Arc::ptr_eq(parent, cell),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Has this one inherit the level of `parent` from now on.
+    pub fn set_parent(&self, parent: Option<Arc<LevelCell>>) {
+        *self.parent.write().unwrap() = parent;
+    }
+}
+
+/// Records held back by `Logger::quiet_startup`.
+struct Startup {
+    deadline: Instant,
+    pending: Vec<(Record, Option<HandlerId>)>,
+}
+
+/// A logger: its format, handlers, counters and the fields it adds to every
+/// record. The Python `Soda` class is a thin layer over it.
+pub struct Logger {
+    name: String,
+    level: Arc<LevelCell>,
+    format: SharedFormat,
+    handlers: Arc<Mutex<Handlers>>,
+    stats: Arc<Stats>,
+    defaults: RwLock<Arc<Map<String, Value>>>,
+    lazy_defaults: RwLock<Arc<Vec<(String, LazyField)>>>,
+    processors: RwLock<Vec<(u64, Processor)>>,
+    /// Filters and the handler they apply to, `None` for all of them.
+    filters: RwLock<Vec<(u64, Option<HandlerId>, Filter)>>,
+    patterns: RwLock<Vec<MessagePattern>>,
+    tag_filters: RwLock<Vec<TagFilter>>,
+    filter_rules: RwLock<Vec<FilterRule>>,
+    /// The level a record must have at least by logger name, shared with
+    /// the children, see `set_target_level`.
+    target_levels: Arc<RwLock<HashMap<String, Level>>>,
+    schema: RwLock<Option<Arc<Schema>>>,
+    /// Shared with the children, whose records go to the same handlers.
+    routes: Arc<RwLock<Vec<(u64, Route)>>>,
+    before_emit: RwLock<Vec<(u64, BeforeEmit)>>,
+    after_emit: RwLock<Vec<(u64, AfterEmit)>>,
+    /// Shared with the children too, so the ids of the routes are unique.
+    next_id: Arc<AtomicU64>,
+    startup: Mutex<Option<Startup>>,
+}
+
+impl Logger {
+    pub fn new(name: &str) -> Logger {
+        Logger::sharing(
+            name,
+            LevelCell::new(None),
+            Arc::new(RwLock::new(Arc::new(Format::default()))),
+            register(Handlers::default()),
+            Arc::new(Stats::default()),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(Vec::new())),
+            Arc::new(AtomicU64::new(0)),
+        )
+    }
+
+    /// A logger called `name` writing through this one's handlers, by its
+    /// routes, in its format and counted in its stats, with no level of its
+    /// own: it goes by this one's until it's given one.
+    pub fn child(&self, name: &str) -> Logger {
+        Logger::sharing(
+            name,
+            LevelCell::new(Some(Arc::clone(&self.level))),
+            Arc::clone(&self.format),
+            Arc::clone(&self.handlers),
+            Arc::clone(&self.stats),
+            Arc::clone(&self.target_levels),
+            Arc::clone(&self.routes),
+            Arc::clone(&self.next_id),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn sharing(
+        name: &str,
+        level: LevelCell,
+        format: SharedFormat,
+        handlers: Arc<Mutex<Handlers>>,
+        stats: Arc<Stats>,
+        target_levels: Arc<RwLock<HashMap<String, Level>>>,
+        routes: Arc<RwLock<Vec<(u64, Route)>>>,
+        next_id: Arc<AtomicU64>,
+    ) -> Logger {
+        Logger {
+            name: name.to_string(),
+            level: Arc::new(level),
+            format,
+            handlers,
+            stats,
+            defaults: RwLock::new(Arc::new(Map::new())),
+            lazy_defaults: RwLock::new(Arc::new(Vec::new())),
+            processors: RwLock::new(Vec::new()),
+            filters: RwLock::new(Vec::new()),
+            patterns: RwLock::new(Vec::new()),
+            tag_filters: RwLock::new(Vec::new()),
+            filter_rules: RwLock::new(Vec::new()),
+            target_levels,
+            schema: RwLock::new(None),
+            routes,
+            before_emit: RwLock::new(Vec::new()),
+            after_emit: RwLock::new(Vec::new()),
+            next_id,
+            startup: Mutex::new(None),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The logger's own level, `NOTSET` when it goes by its parent's.
+    pub fn level(&self) -> Level {
+        Level::from_number(self.level.number().into())
+    }
+
+    pub fn set_level(&self, level: Level) {
+        self.level
+            .level
+            .store(level.number() as u8, Ordering::Relaxed);
+    }
+
+    /// The level records are checked against: the logger's own, or the
+    /// closest parent's with one when it has none, see `child`.
+    pub fn effective_level(&self) -> Level {
+        self.level.effective()
+    }
+
+    /// The logger's level and its parent's, shared with its children.
+    pub fn level_cell(&self) -> &Arc<LevelCell> {
+        &self.level
+    }
+
+    /// Whether a record at `level` gets past the effective level, checked
+    /// before it's built so a record that doesn't costs next to nothing.
+    pub fn is_enabled_for(&self, level: Level) -> bool {
+        level.number() >= self.effective_level().number()
+    }
+
+    /// Sets whichever of the level, template and date format are given in
+    /// one go, under the format's lock: a record checked meanwhile, by
+    /// another thread or a child logger, gets all of the old ones or all of
+    /// the new, see `snapshot`.
+    pub fn reconfigure(&self, level: Option<Level>, template: Option<&str>, datefmt: Option<&str>) {
+        let mut format = self.format.write().unwrap();
+        let changed = Arc::make_mut(&mut format);
+        if let Some(level) = level {
+            self.set_level(level);
+        }
+        if let Some(template) = template {
+            changed.template = template.to_string();
+        }
+        if let Some(datefmt) = datefmt {
+            changed.datefmt = datefmt.to_string();
+        }
+    }
+
+    /// The effective level and the format, read together under the format's
+    /// lock so they're both from before a `reconfigure` or both from after.
+    pub fn snapshot(&self) -> (Level, Arc<Format>) {
+        let format = self.format.read().unwrap();
+        (self.effective_level(), Arc::clone(&format))
+    }
+
+    /// The format as the logger keeps it, changes show up through it.
+    pub fn shared_format(&self) -> SharedFormat {
+        Arc::clone(&self.format)
+    }
+
+    pub fn format(&self) -> Arc<Format> {
+        Arc::clone(&self.format.read().unwrap())
+    }
+
+    /// The format `record` is rendered in: the one it passed the level with.
+    fn format_of(&self, record: &Record) -> Arc<Format> {
+        record.format.clone().unwrap_or_else(|| self.format())
+    }
+
+    /// Changes the format, a record already checked keeps the one it has.
+    fn change_format<F: FnOnce(&mut Format)>(&self, change: F) {
+        change(Arc::make_mut(&mut self.format.write().unwrap()));
+    }
+
+    pub fn set_template(&self, template: &str) {
+        self.change_format(|format| format.template = template.to_string());
+    }
+
+    pub fn set_datefmt(&self, datefmt: &str) {
+        self.change_format(|format| format.datefmt = datefmt.to_string());
+    }
+
+    pub fn set_coercion(&self, coercion: Coercion) {
+        self.change_format(|format| format.coercion = coercion);
+    }
+
+    pub fn set_escape_control(&self, escape: bool) {
+        self.change_format(|format| format.escape_control = escape);
+    }
+
+    /// Sets up the console, see `console::install`. Returns `false` when it
+    /// already was, the console is process wide and only the first call takes
+    /// effect, where it writes to can still be changed. `debug_blocks` prints
+    /// debug and trace records between `---` lines, `color` tints lines by
+    /// level.
+    pub fn console(
+        &self,
+        line_buffered: Option<bool>,
+        capacity: usize,
+        debug_blocks: bool,
+        color: Option<ColorScope>,
+    ) -> bool {
+        let stdout = match console::install(line_buffered, capacity) {
+            Some(stdout) => stdout,
+            None => return false,
+        };
+
+        let format = Arc::clone(&self.format);
+
+        let _ = fern::Dispatch::new()
+            .format(move |out, message, record| {
+                record::with_current(|current| {
+                    let format = match current.and_then(|current| current.format.clone()) {
+                        Some(format) => format,
+                        None => Arc::clone(&format.read().unwrap()),
+                    };
+                    let now = current.map_or_else(chrono::Local::now, |r| r.time);
+
+                    // special format for debug messages coming from our own crate.
+                    let blocked = debug_blocks
+                        && record.level() > log::LevelFilter::Info
+                        && record.target() == "soda";
+
+                    let (line, token) = match current {
+                        _ if blocked => (
+                            format!(
+                                "---\nDEBUG: {}: {}\n---",
+                                now.format(&format.datefmt),
+                                message
+                            ),
+                            String::from("DEBUG"),
+                        ),
+                        Some(current) if !format.template.is_empty() => {
+                            (format.render(current), current.level.as_str().to_string())
+                        }
+                        _ => (
+                            format!(
+                                "[{}][{}][{}] {}",
+                                now.format(&format.datefmt),
+                                record.target(),
+                                record.level(),
+                                format.message(&message.to_string())
+                            ),
+                            record.level().to_string(),
+                        ),
+                    };
+
+                    match color {
+                        Some(scope) => out.finish(format_args!(
+                            "{}",
+                            format::colorize(&line, record.level(), &token, scope)
+                        )),
+                        None => out.finish(format_args!("{}", line)),
+                    }
+                })
+            })
+            .chain(stdout)
+            .apply();
+
+        true
+    }
+
+    pub fn handlers(&self) -> MutexGuard<'_, Handlers> {
+        self.handlers.lock().unwrap()
+    }
+
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
+    }
+
+    pub fn defaults(&self) -> Arc<Map<String, Value>> {
+        Arc::clone(&self.defaults.read().unwrap())
+    }
+
+    /// Fields merged into every record, underneath the thread's `mdc` and
+    /// the record's own, `lazy` ones computed as each record is emitted (see
+    /// `Record::lazy`). They are swapped as a whole, a record gets either
+    /// the old set or the new.
+    pub fn set_defaults(&self, defaults: Map<String, Value>, lazy: Vec<(String, LazyField)>) {
+        let mut current = self.defaults.write().unwrap();
+        *self.lazy_defaults.write().unwrap() = Arc::new(lazy);
+        *current = Arc::new(defaults);
+    }
+
+    /// `set_defaults` keeping the current defaults that `defaults` and
+    /// `lazy` don't name.
+    pub fn merge_defaults(&self, defaults: Map<String, Value>, lazy: Vec<(String, LazyField)>) {
+        let mut current = self.defaults.write().unwrap();
+        let mut current_lazy = self.lazy_defaults.write().unwrap();
+        let named = |key: &String| defaults.contains_key(key) || lazy.iter().any(|(k, _)| k == key);
+
+        let mut merged = (**current).clone();
+        merged.retain(|key, _| !named(key));
+        merged.extend(defaults.clone());
+        let mut merged_lazy: Vec<_> = current_lazy
+            .iter()
+            .filter(|(key, _)| !named(key))
+            .cloned()
+            .collect();
+        merged_lazy.extend(lazy);
+
+        *current_lazy = Arc::new(merged_lazy);
+        *current = Arc::new(merged);
+    }
+
+    pub fn with_defaults(&self, record: &mut Record) {
+        let (defaults, lazy) = {
+            let defaults = self.defaults.read().unwrap();
+            (
+                Arc::clone(&defaults),
+                Arc::clone(&self.lazy_defaults.read().unwrap()),
+            )
+        };
+        let mdc = mdc::current();
+
+        if !defaults.is_empty() || !mdc.is_empty() {
+            let extras = std::mem::replace(&mut record.extras, (*defaults).clone());
+            record.extras.extend(mdc);
+            // The record's lazy fields win over these too.
+            for (key, _) in &record.lazy {
+                record.extras.remove(key);
+            }
+            record.extras.extend(extras);
+        }
+
+        for (key, field) in lazy.iter() {
+            if !record.extras.contains_key(key) && record.lazy.iter().all(|(k, _)| k != key) {
+                record.lazy.push((key.clone(), Arc::clone(field)));
+            }
+        }
+    }
+
+    /// Appends records to `path`, holding up to `options.buffer_size` bytes
+    /// back until `flush`, `0` writes each one as it comes in. With a
+    /// `rotation` the file is moved aside once it grows past its `max_bytes`,
+    /// a `header` is written atop each file the handler opens. The handler
+    /// goes where `Handlers::place` puts it.
+    pub fn add_file_handler(
+        &self,
+        name: Option<&str>,
+        path: &str,
+        options: FileOptions,
+    ) -> io::Result<HandlerId> {
+        let mut handlers = self.handlers();
+        let id = handlers
+            .place(HandlerKind::File, name)
+            .map_err(|taken| io::Error::new(ErrorKind::InvalidInput, taken))?;
+
+        match id.index {
+            0 => handlers.file.open(path, options)?,
+            index if index > handlers.more_files.len() => {
+                let mut file = FileLogger::default();
+                file.open(path, options)?;
+                handlers.more_files.push(file);
+            }
+            index => handlers.more_files[index - 1].open(path, options)?,
+        }
+        handlers.set_name(id, name);
+
+        Ok(id)
+    }
+
+    /// Calls `callback` with every record after the handlers have seen it.
+    /// It runs without the handlers locked, a record it logs dropped, see
+    /// `emit_except`.
+    pub fn add_callback<F: Fn(&Record) + Send + Sync + 'static>(&self, callback: F) {
+        self.handlers().callbacks.push(Arc::new(callback));
+    }
+
+    /// Runs `processor` on every record about to be emitted, after the ones
+    /// added before it. Returns the id `remove_processor` takes.
+    pub fn add_processor<F>(&self, processor: F) -> u64
+    where
+        F: Fn(Record) -> Option<Record> + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.processors
+            .write()
+            .unwrap()
+            .push((id, Arc::new(processor)));
+
+        id
+    }
+
+    /// Returns `false` when no processor has that id.
+    pub fn remove_processor(&self, id: u64) -> bool {
+        let mut processors = self.processors.write().unwrap();
+        let count = processors.len();
+        processors.retain(|(processor, _)| *processor != id);
+
+        processors.len() != count
+    }
+
+    /// Calls `hook` with every record that made it past the processors and
+    /// filters, right before the handlers get it. Returns the id
+    /// `remove_hook` takes.
+    pub fn on_before_emit<F>(&self, hook: F) -> u64
+    where
+        F: Fn(&mut Record) + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.before_emit.write().unwrap().push((id, Arc::new(hook)));
+
+        id
+    }
+
+    /// Calls `hook` with every record the handlers got once they are done,
+    /// along with whether each of them wrote it. Returns the id
+    /// `remove_hook` takes.
+    pub fn on_after_emit<F>(&self, hook: F) -> u64
+    where
+        F: Fn(&Record, &[(String, bool)]) + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.after_emit.write().unwrap().push((id, Arc::new(hook)));
+
+        id
+    }
+
+    /// Returns `false` when no hook has that id.
+    pub fn remove_hook(&self, id: u64) -> bool {
+        let mut before = self.before_emit.write().unwrap();
+        let mut after = self.after_emit.write().unwrap();
+        let count = before.len() + after.len();
+        before.retain(|(hook, _)| *hook != id);
+        after.retain(|(hook, _)| *hook != id);
+
+        before.len() + after.len() != count
+    }
+
+    /// Keeps only the records `filter` returns `true` for, from every
+    /// handler or, given a `handler`, from that one alone. A record has to
+    /// pass all the filters that apply. Returns the id `remove_filter` takes.
+    pub fn add_filter<F>(&self, handler: Option<HandlerId>, filter: F) -> u64
+    where
+        F: Fn(&Record) -> bool + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.filters
+            .write()
+            .unwrap()
+            .push((id, handler, Arc::new(filter)));
+
+        id
+    }
+
+    /// Returns `false` when no filter has that id, message patterns and tag
+    /// filters included.
+    pub fn remove_filter(&self, id: u64) -> bool {
+        let mut filters = self.filters.write().unwrap();
+        let count = filters.len();
+        filters.retain(|(filter, _, _)| *filter != id);
+
+        let mut patterns = self.patterns.write().unwrap();
+        let patterns_count = patterns.len();
+        patterns.retain(|pattern| pattern.id != id);
+
+        let mut tag_filters = self.tag_filters.write().unwrap();
+        let tag_filters_count = tag_filters.len();
+        tag_filters.retain(|tag_filter| tag_filter.id != id);
+
+        filters.len() != count
+            || patterns.len() != patterns_count
+            || tag_filters.len() != tag_filters_count
+    }
+
+    /// Drops the records whose message matches `pattern`, from every handler
+    /// or from `handler` alone. A record matching any of the exclude patterns
+    /// is dropped. Returns the id `remove_filter` takes.
+    pub fn exclude_messages(
+        &self,
+        handler: Option<HandlerId>,
+        pattern: &str,
+    ) -> Result<u64, regex::Error> {
+        self.add_pattern(handler, false, pattern)
+    }
+
+    /// Keeps only the records whose message matches `pattern`, or one of
+    /// the other include-only patterns for the same handlers.
+    pub fn include_only_messages(
+        &self,
+        handler: Option<HandlerId>,
+        pattern: &str,
+    ) -> Result<u64, regex::Error> {
+        self.add_pattern(handler, true, pattern)
+    }
+
+    fn add_pattern(
+        &self,
+        handler: Option<HandlerId>,
+        include: bool,
+        pattern: &str,
+    ) -> Result<u64, regex::Error> {
+        let regex = Regex::new(pattern)?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.patterns.write().unwrap().push(MessagePattern {
+            id,
+            handler,
+            include,
+            regex,
+        });
+
+        Ok(id)
+    }
+
+    /// Keeps the records that carry none of the `exclude` tags and, unless
+    /// `require` is empty, at least one of the `require` ones, for every
+    /// handler or for `handler` alone. Returns the id `remove_filter` takes.
+    pub fn add_tag_filter(
+        &self,
+        handler: Option<HandlerId>,
+        require: Vec<String>,
+        exclude: Vec<String>,
+    ) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.tag_filters.write().unwrap().push(TagFilter {
+            id,
+            handler,
+            require,
+            exclude,
+        });
+
+        id
+    }
+
+    /// Replaces the filter rules. A record is let through or dropped by the
+    /// first rule it matches, a record matching none is let through.
+    pub fn set_filter_rules(&self, rules: Vec<FilterRule>) {
+        *self.filter_rules.write().unwrap() = rules;
+    }
+
+    pub fn filter_rules(&self) -> Vec<FilterRule> {
+        self.filter_rules.read().unwrap().clone()
+    }
+
+    /// Drops the records of the logger called `target`, and the ones under
+    /// it, `"a.b"` under `"a"`, below `level`, the most specific target
+    /// winning. `None` takes the target's level away.
+    pub fn set_target_level(&self, target: &str, level: Option<Level>) {
+        let mut levels = self.target_levels.write().unwrap();
+        match level {
+            Some(level) => levels.insert(target.to_string(), level),
+            None => levels.remove(target),
+        };
+    }
+
+    /// The levels by target, sorted by it.
+    pub fn target_levels(&self) -> Vec<(String, Level)> {
+        let mut levels: Vec<(String, Level)> = self
+            .target_levels
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(target, level)| (target.clone(), *level))
+            .collect();
+        levels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        levels
+    }
+
+    /// Whether the first filter rule `record` matches denies it, checked
+    /// before the processors see it.
+    fn denied(&self, record: &Record) -> bool {
+        let rules = self.filter_rules.read().unwrap();
+        rules
+            .iter()
+            .find(|rule| rule.matches(record))
+            .is_some_and(|rule| !rule.allow)
+    }
+
+    /// Whether `record` is below the level of its target, see
+    /// `set_target_level`, checked before the processors see it.
+    fn quieted(&self, record: &Record) -> bool {
+        match self.target_level(&record.name) {
+            Some(level) => record.level.number() < level.number(),
+            None => false,
+        }
+    }
+
+    /// The level of the closest dotted parent of `name` with one, itself
+    /// included.
+    fn target_level(&self, name: &str) -> Option<Level> {
+        let levels = self.target_levels.read().unwrap();
+        if levels.is_empty() {
+            return None;
+        }
+        let mut name = name;
+
+        loop {
+            if let Some(level) = levels.get(name) {
+                return Some(*level);
+            }
+
+            match name.rfind('.') {
+                Some(dot) => name = &name[..dot],
+                None => return None,
+            }
+        }
+    }
+
+    /// Checks every record against `schema` once the processors ran, `None`
+    /// stops checking.
+    pub fn set_schema(&self, schema: Option<Schema>) {
+        *self.schema.write().unwrap() = schema.map(Arc::new);
+    }
+
+    pub fn schema(&self) -> Option<Arc<Schema>> {
+        self.schema.read().unwrap().clone()
+    }
+
+    /// Routes the records `route` matches to its handlers. Routes are tried
+    /// in the order they were added, a record goes to the handlers of every
+    /// route it matches until an exclusive one. A handler some route sends
+    /// records to only gets those, the others, the default handlers, get
+    /// every record that no exclusive route matched. The children go by the
+    /// same routes. Returns the id `remove_route` takes.
+    pub fn add_route(&self, route: Route) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.routes.write().unwrap().push((id, route));
+
+        id
+    }
+
+    /// Returns `false` when no route has that id.
+    pub fn remove_route(&self, id: u64) -> bool {
+        let mut routes = self.routes.write().unwrap();
+        let count = routes.len();
+        routes.retain(|(route, _)| *route != id);
+
+        routes.len() != count
+    }
+
+    /// The routes and their ids, in the order they are tried.
+    pub fn routes(&self) -> Vec<(u64, Route)> {
+        self.routes.read().unwrap().clone()
+    }
+
+    /// The message patterns, in the order they were added.
+    pub fn message_patterns(&self) -> Vec<MessagePattern> {
+        self.patterns.read().unwrap().clone()
+    }
+
+    /// The tag filters, in the order they were added.
+    pub fn tag_filters(&self) -> Vec<TagFilter> {
+        self.tag_filters.read().unwrap().clone()
+    }
+
+    /// Holds every record back until `mark_ready` is called or `timeout` has
+    /// passed, then emits them in one go. The timeout is checked whenever a
+    /// record is logged or the logger is flushed, or `release_due` called.
+    pub fn quiet_startup(&self, timeout: Duration) {
+        let mut startup = self.startup.lock().unwrap();
+        let pending = startup.take().map(|s| s.pending).unwrap_or_default();
+
+        *startup = Some(Startup {
+            deadline: Instant::now() + timeout,
+            pending,
+        });
+    }
+
+    /// Ends a quiet startup, emitting the records held back so far.
+    pub fn mark_ready(&self) -> io::Result<()> {
+        self.release(|_| true)
+    }
+
+    /// Ends a quiet startup whose timeout has passed, for a thread waiting
+    /// on it while nothing is logged.
+    pub fn release_due(&self) -> io::Result<()> {
+        self.release(|startup| startup.deadline <= Instant::now())
+    }
+
+    pub fn log(&self, level: Level, message: &str) -> io::Result<()> {
+        if !self.is_enabled_for(level) {
+            return Ok(());
+        }
+        let mut record = Record::new(level, &self.name, message);
+        self.with_defaults(&mut record);
+
+        self.emit(record)
+    }
+
+    pub fn trace(&self, message: &str) -> io::Result<()> {
+        self.log(Level::TRACE, message)
+    }
+
+    pub fn debug(&self, message: &str) -> io::Result<()> {
+        self.log(Level::DEBUG, message)
+    }
+
+    pub fn info(&self, message: &str) -> io::Result<()> {
+        self.log(Level::INFO, message)
+    }
+
+    pub fn warning(&self, message: &str) -> io::Result<()> {
+        self.log(Level::WARNING, message)
+    }
+
+    pub fn error(&self, message: &str) -> io::Result<()> {
+        self.log(Level::ERROR, message)
+    }
+
+    /// Sends a record to the console and the handlers, unless a quiet
+    /// startup holds it back. A record below the logger's level, or its
+    /// target's, or denied by a filter rule is dropped before anything else
+    /// sees it.
+    pub fn emit(&self, record: Record) -> io::Result<()> {
+        self.emit_except(record, None)
+    }
+
+    /// Like `emit`, keeping the record from the `skipped` handler.
+    pub fn emit_except(&self, record: Record, skipped: Option<HandlerId>) -> io::Result<()> {
+        // The level and the format are read once, together: the record is
+        // rendered in the format it passed the level with.
+        let (level, format) = self.snapshot();
+        if record.level.number() < level.number() || self.quieted(&record) || self.denied(&record) {
+            return Ok(());
+        }
+        let mut record = record;
+        record.format = Some(format);
+        // A handler logging would set itself off again with its own record,
+        // and again, so what's logged while handling a record is dropped.
+        // What `after_rotations` logs comes once the handlers are done.
+        if HANDLING.with(Cell::get) {
+            self.stats.dropped(1);
+            if !WARNED_REENTRANT.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "soda: dropping record {:?} logged while handling another, \
+                     a handler, hook or processor logs itself",
+                    record.message
+                );
+            }
+            return Ok(());
+        }
+
+        {
+            let mut startup = self.startup.lock().unwrap();
+
+            if let Some(quiet) = startup.as_mut() {
+                if quiet.deadline > Instant::now() {
+                    quiet.pending.push((record, skipped));
+                    return Ok(());
+                }
+            }
+        }
+
+        self.release(|_| true)?;
+
+        let handling = Handling::start();
+        let result = self.handle(record, skipped);
+        drop(handling);
+
+        result.and(self.after_rotations())
+    }
+
+    /// Runs `record` through the processors, filters and hooks to the
+    /// handlers, the part of `emit_except` that mustn't log.
+    fn handle(&self, record: Record, skipped: Option<HandlerId>) -> io::Result<()> {
+        let record = match self.process(record) {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+        let record = match self.conform(record)? {
+            Some(record) => record,
+            None => return Ok(()),
+        };
+
+        let mut rejected = match self.filter(&record) {
+            Some(rejected) => rejected,
+            None => return Ok(()),
+        };
+        rejected.extend(skipped);
+
+        let mut record = record;
+        self.resolve(&mut record, &rejected);
+        let before: Vec<BeforeEmit> = self.hooks(&self.before_emit);
+        for hook in before {
+            hook(&mut record);
+        }
+        record::clamp_time(&mut record);
+
+        let console = HandlerId::first(HandlerKind::Console);
+        let record = match !rejected.contains(&console) && self.handlers().enabled(console) {
+            true => record::scoped(
+                record,
+                |r| log!(target: "soda", r.level.to_log(), "{}", r.message),
+            ),
+            false => record,
+        };
+
+        let after: Vec<AfterEmit> = self.hooks(&self.after_emit);
+        let mut outcomes = match after.is_empty() {
+            true => None,
+            false => Some(Vec::new()),
+        };
+
+        let result = self.callback(&record, &rejected, &mut outcomes);
+
+        if let Some(outcomes) = outcomes {
+            for hook in after {
+                hook(&record, &outcomes);
+            }
+        }
+
+        result
+    }
+
+    /// The path the file handler writes to at the time it's called, for a
+    /// thread following the file through its rotations.
+    pub fn file_path(&self) -> impl Fn() -> String + Send + 'static {
+        let handlers = Arc::clone(&self.handlers);
+
+        move || handlers.lock().unwrap().file.path()
+    }
+
+    /// Rotates the file handler's file now, see `FileLogger::rotate_now`.
+    pub fn rotate_file(&self) -> io::Result<Option<Option<PathBuf>>> {
+        let rotated = self.handlers().file.rotate_now()?;
+        self.after_rotations()?;
+
+        Ok(rotated)
+    }
+
+    /// Reports what the file handlers' rotations deleted and runs their
+    /// `on_rotation`, with the handlers unlocked so both may log.
+    fn after_rotations(&self) -> io::Result<()> {
+        let rotated: Vec<_> = {
+            let handlers = self.handlers();
+            handlers
+                .files()
+                .enumerate()
+                .map(|(index, file)| {
+                    let on_rotation = file.rotation.as_ref();
+                    (
+                        HandlerId {
+                            kind: HandlerKind::File,
+                            index,
+                        },
+                        file.take_pruned(),
+                        file.take_unarchived(),
+                        file.take_rotations(),
+                        on_rotation.and_then(|rotation| rotation.on_rotation.clone()),
+                    )
+                })
+                .collect()
+        };
+
+        let mut result = Ok(());
+        for (id, pruned, unarchived, rotations, on_rotation) in rotated {
+            for unarchived in unarchived {
+                result = result.and(self.report_unarchived(id, unarchived));
+            }
+
+            if let Some(on_rotation) = on_rotation {
+                for rotated in rotations {
+                    on_rotation(rotated.backup.as_deref(), &rotated.path);
+                }
+            }
+
+            if let Some(pruned) = pruned {
+                result = result.and(self.report_pruned(id, pruned));
+            }
+        }
+
+        result
+    }
+
+    /// Logs a backup left next to the file `id` writes, to the other
+    /// handlers like `report_pruned`.
+    fn report_unarchived(&self, id: HandlerId, unarchived: Unarchived) -> io::Result<()> {
+        let path = unarchived.path.to_string_lossy();
+        let message = format!(
+            "couldn't move {} to the archive, left it there: {}",
+            path, unarchived.error
+        );
+
+        let mut record = Record::new(Level::ERROR, "soda", &message);
+        record
+            .extras
+            .insert(String::from("path"), Value::from(path));
+
+        self.emit_except(record, Some(id))
+    }
+
+    /// Logs what the rotation of the file handler `id` deleted to stay
+    /// within its budget, to the other handlers: written to the file, it
+    /// could set off another rotation, and another record.
+    fn report_pruned(&self, id: HandlerId, pruned: Pruned) -> io::Result<()> {
+        let message = format!(
+            "deleted {} log backups, {} bytes, to stay within {} bytes",
+            pruned.deleted.len(),
+            pruned.bytes,
+            pruned.budget
+        );
+        let deleted: Vec<Value> = pruned
+            .deleted
+            .iter()
+            .map(|path| Value::from(path.to_string_lossy()))
+            .collect();
+
+        let mut record = Record::new(Level::INFO, "soda", &message);
+        record
+            .extras
+            .insert(String::from("deleted"), Value::from(deleted));
+        record
+            .extras
+            .insert(String::from("bytes"), Value::from(pruned.bytes));
+
+        self.emit_except(record, Some(id))
+    }
+
+    /// The hooks, cloned so they run unlocked.
+    fn hooks<T: ?Sized>(&self, hooks: &RwLock<Vec<(u64, Arc<T>)>>) -> Vec<Arc<T>> {
+        hooks
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, hook)| Arc::clone(hook))
+            .collect()
+    }
+
+    /// Runs the processors, unlocked.
+    fn process(&self, record: Record) -> Option<Record> {
+        let processors: Vec<Processor> = self
+            .processors
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, processor)| Arc::clone(processor))
+            .collect();
+
+        processors
+            .iter()
+            .try_fold(record, |record, processor| processor(record))
+    }
+
+    /// Checks the record against the schema, if there's one. A record that
+    /// doesn't conform is dropped, flagged or makes this fail with a
+    /// `Violation`, as the schema says.
+    fn conform(&self, mut record: Record) -> io::Result<Option<Record>> {
+        let schema = match self.schema() {
+            Some(schema) => schema,
+            None => return Ok(Some(record)),
+        };
+        let violations = schema.violations(&record);
+        if violations.is_empty() {
+            return Ok(Some(record));
+        }
+        self.stats.schema_violation();
+
+        match schema.on_violation {
+            OnViolation::Drop => {
+                eprintln!("soda: dropping record: {}", Violation { violations });
+                Ok(None)
+            }
+            OnViolation::Flag => {
+                record
+                    .extras
+                    .insert(String::from("schema_violations"), Value::from(violations));
+                Ok(Some(record))
+            }
+            OnViolation::Raise => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                Violation { violations },
+            )),
+        }
+    }
+
+    /// Computes the record's lazy fields a handler it goes to writes: all of
+    /// them for a structured, memory or callback one, those its template
+    /// shows for the console. A field that fails is written as
+    /// `"<error: ...>"` and counted. They're computed with no lock held.
+    fn resolve(&self, record: &mut Record, rejected: &HashSet<HandlerId>) {
+        let lazy = std::mem::take(&mut record.lazy);
+        if lazy.is_empty() {
+            return;
+        }
+
+        let handlers = self.handlers();
+        let gets = |id: HandlerId| handlers.enabled(id) && !rejected.contains(&id);
+        let structured = handlers.ids().any(|id| {
+            gets(id)
+                && match id.kind {
+                    HandlerKind::Fluentd => handlers.fluentd.is_some(),
+                    HandlerKind::Json => handlers.json_at(id.index).is_some(),
+                    HandlerKind::Otlp => handlers.otlp.is_some(),
+                    HandlerKind::Memory => handlers.memory.is_some(),
+                    HandlerKind::Sqlite => handlers.has_sqlite(id.index),
+                    HandlerKind::Console | HandlerKind::File | HandlerKind::LevelSplit => false,
+                }
+        }) || memory::capturing()
+            || !handlers.callbacks.is_empty();
+        let console = console::installed() && gets(HandlerId::first(HandlerKind::Console));
+        drop(handlers);
+
+        let template = match console && !structured {
+            true => self.format_of(record).template.clone(),
+            false => String::new(),
+        };
+
+        for (key, field) in lazy {
+            if record.extras.contains_key(&key)
+                || (!structured && !template::shows_field(&template, &key))
+            {
+                continue;
+            }
+
+            let value = field().unwrap_or_else(|e| {
+                self.stats.field_error();
+                Value::from(format!("<error: {}>", e))
+            });
+            record.extras.insert(key, value);
+        }
+    }
+
+    /// Runs the filters, unlocked. Returns the handlers that filtered the
+    /// record out, `None` when one that applies to all of them did.
+    fn filter(&self, record: &Record) -> Option<HashSet<HandlerId>> {
+        let filters: Vec<(Option<HandlerId>, Filter)> = self
+            .filters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, handler, filter)| (*handler, Arc::clone(filter)))
+            .collect();
+
+        let mut rejected = self.route(record);
+        if let Some(only) = &record.handlers {
+            rejected.extend(self.handlers().ids().filter(|id| !only.contains(id)));
+        }
+
+        for tag_filter in self.tag_filters.read().unwrap().iter() {
+            if !tag_filter.allows(&record.tags) {
+                match tag_filter.handler {
+                    Some(id) => {
+                        rejected.insert(id);
+                    }
+                    None => return None,
+                }
+            }
+        }
+
+        {
+            let patterns = self.patterns.read().unwrap();
+            if !passes(&patterns, None, &record.message) {
+                return None;
+            }
+            for id in patterns.iter().filter_map(|pattern| pattern.handler) {
+                if !rejected.contains(&id) && !passes(&patterns, Some(id), &record.message) {
+                    rejected.insert(id);
+                }
+            }
+        }
+
+        for (handler, filter) in filters {
+            match handler {
+                Some(id) if !rejected.contains(&id) => {
+                    if !filter(record) {
+                        rejected.insert(id);
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    if !filter(record) {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some(rejected)
+    }
+
+    /// The handlers the routes keep `record` from.
+    fn route(&self, record: &Record) -> HashSet<HandlerId> {
+        let routes = self.routes.read().unwrap();
+        if routes.is_empty() {
+            return HashSet::new();
+        }
+
+        let mut routed = HashSet::new();
+        let mut defaults = true;
+        for (_, route) in routes.iter().filter(|(_, route)| route.matches(record)) {
+            routed.extend(route.handlers.iter().copied());
+            if route.exclusive {
+                defaults = false;
+                break;
+            }
+        }
+
+        let targets: HashSet<HandlerId> = routes
+            .iter()
+            .flat_map(|(_, route)| route.handlers.iter().copied())
+            .collect();
+        drop(routes);
+
+        self.handlers()
+            .ids()
+            .filter(|id| !routed.contains(id) && (!defaults || targets.contains(id)))
+            .collect()
+    }
+
+    /// Ends the quiet startup if `ready` says so, emitting its records.
+    fn release<F: FnOnce(&Startup) -> bool>(&self, ready: F) -> io::Result<()> {
+        let pending = {
+            let mut startup = self.startup.lock().unwrap();
+
+            match startup.as_ref() {
+                Some(quiet) if ready(quiet) => startup.take().unwrap().pending,
+                _ => return Ok(()),
+            }
+        };
+
+        let mut result = Ok(());
+        for (record, skipped) in pending {
+            let emitted = self.emit_except(record, skipped);
+            result = result.and(emitted);
+        }
+
+        result
+    }
+
+    /// The record the way the console prints it.
+    pub fn line(&self, record: &Record) -> String {
+        self.format_of(record).render(record)
+    }
+
+    /// Writes out anything still buffered, console included.
+    pub fn flush(&self) {
+        let _ = self.release_due();
+
+        console::flush();
+        self.handlers().flush();
+    }
+
+    /// Writes out what the handler called `name` buffers and nothing else,
+    /// `false` when there is no such name. Records held back at startup
+    /// stay held.
+    pub fn flush_handler(&self, name: &str) -> bool {
+        let handlers = self.handlers();
+        match handlers.id(name) {
+            Some(id) => {
+                handlers.flush_one(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Hands the record to the handlers, in the order of `Handlers`. With
+    /// `outcomes` each handler that got it is added along with whether it
+    /// wrote it, in the order it got it.
+    fn callback(
+        &self,
+        record: &Record,
+        rejected: &HashSet<HandlerId>,
+        outcomes: &mut Option<Vec<(String, bool)>>,
+    ) -> io::Result<()> {
+        let handlers = self.handlers();
+        let mut outcome = |id: HandlerId, written: bool| {
+            if written {
+                self.stats.record(id.kind, record.level);
+            }
+            if let Some(outcomes) = outcomes.as_mut() {
+                outcomes.push((handlers.label(id), written));
+            }
+        };
+
+        let console = HandlerId::first(HandlerKind::Console);
+        if console::installed() && handlers.enabled(console) && !rejected.contains(&console) {
+            outcome(console, true);
+        }
+
+        // A failing file is reported once every other handler has seen
+        // the record, the first failure if both fail.
+        let mut failure = None;
+        let mut line = None;
+        let format = self.format_of(record);
+        let mut message = format.message(&record.message);
+        if let Some(traceback) = format.traceback(record) {
+            message = Cow::Owned(format!("{}\n{}", message, traceback));
+        }
+
+        for &id in &handlers.order {
+            let written = match id.kind {
+                HandlerKind::Console => continue,
+                HandlerKind::File => {
+                    let file = match handlers.active(handlers.file_at(id.index), id, rejected) {
+                        Some(file) if file.enabled => file,
+                        _ => continue,
+                    };
+                    let line = file
+                        .line
+                        .as_ref()
+                        .map(|template| format.render_with(template, record));
+                    file.logger(record.time, line.as_deref().unwrap_or(&message))
+                }
+                HandlerKind::LevelSplit => {
+                    match handlers.active(handlers.split_at(id.index), id, rejected) {
+                        Some(split) => split.logger(record, &message),
+                        None => continue,
+                    }
+                }
+                HandlerKind::Fluentd => {
+                    match handlers.active(handlers.fluentd.as_ref(), id, rejected) {
+                        Some(fluentd) => {
+                            fluentd.logger(record);
+                            Ok(())
+                        }
+                        None => continue,
+                    }
+                }
+                HandlerKind::Json => {
+                    match handlers.active(handlers.json_at(id.index), id, rejected) {
+                        Some(json) => {
+                            json.logger(record);
+                            Ok(())
+                        }
+                        None => continue,
+                    }
+                }
+                HandlerKind::Otlp => match handlers.active(handlers.otlp.as_ref(), id, rejected) {
+                    Some(otlp) => {
+                        otlp.logger(record);
+                        Ok(())
+                    }
+                    None => continue,
+                },
+                HandlerKind::Memory => {
+                    match handlers.active(handlers.memory.as_ref(), id, rejected) {
+                        Some(memory) => {
+                            memory.logger(record, line.get_or_insert_with(|| self.line(record)));
+                            Ok(())
+                        }
+                        None => continue,
+                    }
+                }
+                #[cfg(feature = "sqlite")]
+                HandlerKind::Sqlite => {
+                    match handlers.active(handlers.sqlite_at(id.index), id, rejected) {
+                        Some(sqlite) => sqlite.logger(record, &message),
+                        None => continue,
+                    }
+                }
+                #[cfg(not(feature = "sqlite"))]
+                HandlerKind::Sqlite => continue,
+            };
+
+            match written {
+                Ok(()) => outcome(id, true),
+                Err(e) => {
+                    outcome(id, false);
+                    failure = failure.or(Some(e));
+                }
+            }
+        }
+
+        if memory::capturing() {
+            let line = line.unwrap_or_else(|| self.line(record));
+            memory::capture(record, &line);
+        }
+
+        let callbacks = handlers.callbacks.clone();
+        drop(handlers);
+
+        for callback in callbacks {
+            callback(record);
+        }
+
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        let _ = self.release(|_| true);
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The messages of the records that reach `logger`'s handlers.
+    fn seen(logger: &Logger) -> Arc<Mutex<Vec<String>>> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::clone(&seen);
+        logger.add_callback(move |record| messages.lock().unwrap().push(record.message.clone()));
+
+        seen
+    }
+
+    #[test]
+    fn records_below_the_level_reach_no_handler() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+
+        logger.set_level(Level::WARNING);
+        logger.info("dropped").unwrap();
+        logger
+            .emit(Record::new(Level::DEBUG, "app", "dropped too"))
+            .unwrap();
+        logger.warning("kept").unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), ["kept"]);
+        assert!(!logger.is_enabled_for(Level::INFO));
+        assert!(logger.is_enabled_for(Level::ERROR));
+    }
+
+    #[test]
+    fn processors_only_see_records_at_the_level() {
+        let logger = Logger::new("app");
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::clone(&processed);
+        logger.add_processor(move |record| {
+            messages.lock().unwrap().push(record.message.clone());
+            Some(record)
+        });
+
+        logger.set_level(Level::WARNING);
+        logger.info("dropped").unwrap();
+        logger
+            .emit(Record::new(Level::DEBUG, "app", "dropped too"))
+            .unwrap();
+        logger.error("kept").unwrap();
+
+        assert_eq!(*processed.lock().unwrap(), ["kept"]);
+    }
+
+    #[test]
+    fn quieted_targets_never_reach_the_processors() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::clone(&processed);
+        logger.add_processor(move |record| {
+            messages.lock().unwrap().push(record.message.clone());
+            Some(record)
+        });
+
+        logger.set_target_level("app.noisy", Some(Level::WARNING));
+        for (name, level, message) in [
+            ("app.noisy", Level::INFO, "dropped"),
+            ("app.noisy.db", Level::DEBUG, "dropped too"),
+            ("app.noisy", Level::WARNING, "kept"),
+            ("app.quiet", Level::INFO, "kept too"),
+        ] {
+            logger.emit(Record::new(level, name, message)).unwrap();
+        }
+
+        assert_eq!(*processed.lock().unwrap(), ["kept", "kept too"]);
+        assert_eq!(*seen.lock().unwrap(), ["kept", "kept too"]);
+    }
+
+    #[test]
+    fn denied_records_never_reach_the_processors() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let messages = Arc::clone(&processed);
+        logger.add_processor(move |record| {
+            messages.lock().unwrap().push(record.message.clone());
+            Some(record)
+        });
+
+        logger.set_filter_rules(vec![FilterRule {
+            target: String::from("app.noisy"),
+            min_level: Level::ERROR,
+            allow: false,
+        }]);
+        logger
+            .emit(Record::new(Level::CRITICAL, "app.noisy", "denied"))
+            .unwrap();
+        logger
+            .emit(Record::new(Level::WARNING, "app.noisy", "unmatched"))
+            .unwrap();
+        logger.info("kept").unwrap();
+
+        assert_eq!(*processed.lock().unwrap(), ["unmatched", "kept"]);
+        assert_eq!(*seen.lock().unwrap(), ["unmatched", "kept"]);
+    }
+
+    #[test]
+    fn hooks_and_the_startup_buffer_only_see_records_at_the_level() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+        let hooked = Arc::new(Mutex::new(Vec::new()));
+        let before = Arc::clone(&hooked);
+        logger.on_before_emit(move |record| before.lock().unwrap().push(record.message.clone()));
+        let after = Arc::clone(&hooked);
+        logger.on_after_emit(move |record, _| after.lock().unwrap().push(record.message.clone()));
+
+        logger.set_level(Level::WARNING);
+        logger.quiet_startup(Duration::from_secs(60));
+        logger.info("dropped").unwrap();
+        logger.warning("held").unwrap();
+        assert!(seen.lock().unwrap().is_empty());
+        logger.mark_ready().unwrap();
+        logger.debug("dropped too").unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), ["held"]);
+        assert_eq!(*hooked.lock().unwrap(), ["held", "held"]);
+    }
+
+    #[test]
+    fn lazy_fields_are_only_computed_for_records_at_the_level() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+        let computed = Arc::new(AtomicU64::new(0));
+        let count = Arc::clone(&computed);
+        let depth: LazyField =
+            Arc::new(move || Ok(Value::from(count.fetch_add(1, Ordering::Relaxed))));
+        logger.set_defaults(Map::new(), vec![(String::from("depth"), depth)]);
+
+        logger.set_level(Level::WARNING);
+        logger.info("dropped").unwrap();
+        logger.debug("dropped too").unwrap();
+        logger.warning("kept").unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), ["kept"]);
+        assert_eq!(computed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn a_record_has_one_time_for_its_line_and_its_file() {
+        use crate::handlers::file::Reexpand;
+        use chrono::{Local, TimeZone};
+
+        let dir = crate::testing::scratch("one-time");
+        let logger = Logger::new("app");
+        logger.reconfigure(None, None, Some("%Y-%m-%d %H:%M:%S"));
+        let options = FileOptions {
+            reexpand: Some(Reexpand::Daily),
+            line: Some(String::from("{time} {level} {message}")),
+            ..FileOptions::default()
+        };
+        let path = dir.join("run-{date}.log");
+        logger
+            .add_file_handler(None, path.to_str().unwrap(), options)
+            .unwrap();
+
+        // Stamped a moment before midnight, written after.
+        for (time, message) in [
+            (Local.with_ymd_and_hms(2099, 12, 31, 23, 59, 59), "last"),
+            (Local.with_ymd_and_hms(2100, 1, 1, 0, 0, 0), "first"),
+        ] {
+            let mut record = Record::new(Level::INFO, "app", message);
+            record.time = time.unwrap();
+            logger.emit(record).unwrap();
+        }
+
+        let read = |day| std::fs::read_to_string(dir.join(format!("run-{}.log", day))).unwrap();
+        assert_eq!(read("2099-12-31"), "2099-12-31 23:59:59 INFO last\n");
+        assert_eq!(read("2100-01-01"), "2100-01-01 00:00:00 INFO first\n");
+    }
+
+    #[test]
+    fn children_go_by_the_parents_level_until_given_one() {
+        let parent = Logger::new("app");
+        let child = parent.child("app.db");
+        let grandchild = child.child("app.db.pool");
+        let seen = seen(&parent);
+
+        parent.set_level(Level::WARNING);
+        assert_eq!(child.level().as_str(), "NOTSET");
+        assert_eq!(child.effective_level().as_str(), "WARNING");
+        assert_eq!(grandchild.effective_level().as_str(), "WARNING");
+        child.info("dropped").unwrap();
+        grandchild.info("dropped too").unwrap();
+
+        child.set_level(Level::DEBUG);
+        assert_eq!(grandchild.effective_level().as_str(), "DEBUG");
+        child.info("kept").unwrap();
+        parent.info("dropped as well").unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), ["kept"]);
+    }
+
+    #[test]
+    fn records_logged_while_handling_one_are_dropped() {
+        let logger = Arc::new(Logger::new("app"));
+        let seen = seen(&logger);
+        let inner = Arc::downgrade(&logger);
+        logger.add_callback(move |record| {
+            if let Some(logger) = inner.upgrade() {
+                logger.info(&format!("about {}", record.message)).unwrap();
+            }
+        });
+
+        logger.info("first").unwrap();
+        logger.info("second").unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), ["first", "second"]);
+        assert_eq!(logger.stats().dropped_total(), 2);
+    }
+
+    #[test]
+    fn a_panicking_handler_leaves_logging_on() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+        logger.add_callback(|record| {
+            if record.message == "boom" {
+                panic!("the handler failed");
+            }
+        });
+
+        let panicked = panic::catch_unwind(panic::AssertUnwindSafe(|| logger.info("boom")));
+        assert!(panicked.is_err());
+        logger.info("after").unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), ["boom", "after"]);
+    }
+
+    #[test]
+    fn reconfigure_changes_level_and_format_together() {
+        let logger = Logger::new("app");
+        let seen = seen(&logger);
+
+        logger.reconfigure(Some(Level::ERROR), Some("{level} {message}"), Some("%H"));
+        logger.warning("dropped").unwrap();
+        logger.error("kept").unwrap();
+
+        let format = logger.format();
+        assert_eq!(format.template, "{level} {message}");
+        assert_eq!(format.datefmt, "%H");
+        assert_eq!(logger.level().as_str(), "ERROR");
+        assert_eq!(*seen.lock().unwrap(), ["kept"]);
+    }
+
+    #[test]
+    fn records_render_in_the_format_they_passed_the_level_with() {
+        let logger = Arc::new(Logger::new("app"));
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let rendered = Arc::clone(&lines);
+        logger.add_callback(move |record| {
+            let line = record.format.as_ref().unwrap().render(record);
+            rendered.lock().unwrap().push(line);
+        });
+        logger.reconfigure(Some(Level::INFO), Some("old {message}"), None);
+
+        let flipping = Arc::clone(&logger);
+        let flipper = std::thread::spawn(move || {
+            for i in 0..2000 {
+                match i % 2 {
+                    0 => flipping.reconfigure(Some(Level::ERROR), Some("new {message}"), None),
+                    _ => flipping.reconfigure(Some(Level::INFO), Some("old {message}"), None),
+                }
+            }
+        });
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let logger = Arc::clone(&logger);
+                std::thread::spawn(move || {
+                    for _ in 0..2000 {
+                        logger.warning("warning").unwrap();
+                    }
+                })
+            })
+            .collect();
+        flipper.join().unwrap();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        logger.warning("warning").unwrap();
+
+        // Under the new format the level is ERROR, a warning that got through
+        // went by the old one.
+        let lines = lines.lock().unwrap();
+        assert!(!lines.is_empty());
+        assert!(
+            lines.iter().all(|line| line == "old warning"),
+            "{:?}",
+            lines
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! Mapped diagnostic context: fields put on the current thread that every
+//! record logged from it carries, underneath the fields bound to a logger.
+
+use std::cell::RefCell;
+
+use serde_json::{Map, Value};
+
+thread_local! {
+    static MDC: RefCell<Map<String, Value>> = RefCell::new(Map::new());
+}
+
+/// Puts `key` on this thread, returning what it held before.
+pub fn put(key: &str, value: Value) -> Option<Value> {
+    MDC.with(|mdc| mdc.borrow_mut().insert(key.to_string(), value))
+}
+
+pub fn get(key: &str) -> Option<Value> {
+    MDC.with(|mdc| mdc.borrow().get(key).cloned())
+}
+
+pub fn remove(key: &str) -> Option<Value> {
+    MDC.with(|mdc| mdc.borrow_mut().remove(key))
+}
+
+pub fn clear() {
+    MDC.with(|mdc| mdc.borrow_mut().clear());
+}
+
+/// Everything put on this thread.
+pub fn current() -> Map<String, Value> {
+    MDC.with(|mdc| mdc.borrow().clone())
+}
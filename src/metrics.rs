@@ -0,0 +1,107 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::stats::Stats;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A tiny HTTP responder serving the counters on `/metrics`.
+///
+/// Requests are answered one at a time on a background thread, logging only
+/// ever touches the atomic counters so a slow scrape can't hold it up.
+pub struct MetricsServer {
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(host: &str, port: u16, stats: Arc<Stats>) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind((host, port))?;
+        listener.set_nonblocking(true)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker_stop = Arc::clone(&stop);
+
+        let worker = thread::Builder::new()
+            .name(String::from("soda-metrics"))
+            .spawn(move || serve(listener, stats, worker_stop))?;
+
+        Ok(MetricsServer {
+            stop,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(worker) = self.worker.take() {
+            crate::handlers::join_worker(worker);
+        }
+    }
+}
+
+fn serve(listener: TcpListener, stats: Arc<Stats>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = respond(stream, &stats);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(_) => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+fn respond(stream: TcpStream, stats: &Stats) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the headers, the body of a scrape request is never interesting.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = match path {
+        "/metrics" => ("200 OK", stats.exposition()),
+        _ => ("404 Not Found", String::from("not found\n")),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Writes the counters for node_exporter's textfile collector, through a
+/// temporary file so the collector never reads a half written file.
+pub fn write_textfile(path: &str, stats: &Stats) -> io::Result<()> {
+    let tmp = format!("{}.{}.tmp", path, std::process::id());
+
+    fs::write(&tmp, stats.exposition())?;
+    fs::rename(&tmp, path)
+}
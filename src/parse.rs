@@ -0,0 +1,374 @@
+//! Reads soda's output back into records, see `Records`.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use flate2::read::MultiGzDecoder;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::handlers::file::wildcards;
+use crate::template::{self, Piece};
+
+/// Lines that go on a traceback besides its indented frames, between the
+/// exceptions of a chain.
+const CHAINED: [&str; 2] = [
+    "During handling of the above exception, another exception occurred:",
+    "The above exception was the direct cause of the following exception:",
+];
+
+/// How the lines of a log were written.
+pub enum Layout {
+    /// A JSON document per line, as the JSON handler writes them.
+    Json,
+    /// Lines rendered from a `Format`'s template.
+    Text(Text),
+    /// Told from the first line, see `detect`, with this date format.
+    Detect(String),
+}
+
+impl Layout {
+    /// The layout of lines rendered with `template` and `datefmt`, the
+    /// default line for an empty template. Fails with why when `template`
+    /// can't be read back.
+    pub fn text(template: &str, datefmt: &str) -> Result<Layout, String> {
+        Text::new(template, datefmt).map(Layout::Text)
+    }
+
+    /// The layout of a log whose first line is `line`: JSON when it's a JSON
+    /// object, the default line when it reads as one, otherwise a message a
+    /// line, as the file handler writes them.
+    pub fn detect(line: &str, datefmt: &str) -> Layout {
+        if let Ok(Value::Object(_)) = serde_json::from_str::<Value>(line) {
+            return Layout::Json;
+        }
+
+        let text = Text::new("", datefmt).unwrap();
+        match text.line.is_match(line) {
+            true => Layout::Text(text),
+            false => Layout::Text(Text::new("{message}", datefmt).unwrap()),
+        }
+    }
+}
+
+/// What a placeholder of the template reads back into.
+enum Field {
+    Time,
+    Name,
+    Level,
+    Message,
+    TraceId,
+    SpanId,
+    Tags,
+    Extras,
+    Extra(String),
+}
+
+/// A template turned around: a regex matching the lines it renders, with a
+/// group per placeholder.
+pub struct Text {
+    line: Regex,
+    /// The field of each group, `p0`, `p1` and so on.
+    fields: Vec<Field>,
+    datefmt: String,
+}
+
+impl Text {
+    fn new(template: &str, datefmt: &str) -> Result<Text, String> {
+        let mut regex = String::from("^");
+        let mut fields = Vec::new();
+        let time = wildcards(datefmt);
+
+        if template.is_empty() {
+            // `Format::render` without a template, the level as `log` names it.
+            regex.push_str(&format!(
+                r"\[(?P<p0>{})\]\[(?P<p1>.*?)\]\[(?P<p2>[A-Z]+)\] ",
+                time
+            ));
+            regex.push_str("(?P<p3>.*)");
+            fields.extend([Field::Time, Field::Name, Field::Level, Field::Message]);
+        }
+        for piece in template::pieces(template) {
+            let (field, pattern) = match piece {
+                Piece::Literal(literal) => {
+                    regex.push_str(&regex::escape(literal));
+                    continue;
+                }
+                Piece::Placeholder(key) => match key {
+                    "time" => (Field::Time, time.as_str()),
+                    "name" => (Field::Name, ".*?"),
+                    "level" => (Field::Level, "[A-Z]+"),
+                    "message" => (Field::Message, ".*"),
+                    "trace_id" => (Field::TraceId, "[0-9a-f]*"),
+                    "span_id" => (Field::SpanId, "[0-9a-f]*"),
+                    "tags" => (Field::Tags, r"(?:#\S+(?: #\S+)*)?"),
+                    "extras" => (Field::Extras, ".*?"),
+                    _ => match key.strip_prefix("extra[").and_then(|k| k.strip_suffix(']')) {
+                        Some(name) => (Field::Extra(name.to_string()), ".*?"),
+                        None => {
+                            regex.push_str(&regex::escape(&format!("{{{}}}", key)));
+                            continue;
+                        }
+                    },
+                },
+            };
+            regex.push_str(&format!("(?P<p{}>{})", fields.len(), pattern));
+            fields.push(field);
+        }
+        regex.push('$');
+
+        if !fields.iter().any(|field| matches!(field, Field::Message)) {
+            return Err(String::from(
+                "a template without {message} can't be read back",
+            ));
+        }
+        let line = Regex::new(&regex).map_err(|e| e.to_string())?;
+
+        Ok(Text {
+            line,
+            fields,
+            datefmt: datefmt.to_string(),
+        })
+    }
+
+    /// The record `line` was rendered from, as far as its template shows
+    /// it, `None` when it doesn't match the template.
+    fn parse(&self, line: &str) -> Option<Map<String, Value>> {
+        let captures = self.line.captures(line)?;
+        let mut record = Map::new();
+
+        for (i, field) in self.fields.iter().enumerate() {
+            let text = match captures.name(&format!("p{}", i)) {
+                Some(text) => text.as_str(),
+                None => continue,
+            };
+            let (key, value) = match field {
+                Field::Time => ("timestamp", self.time(text)),
+                Field::Name => ("name", Value::from(text)),
+                Field::Level => ("level", Value::from(level(text))),
+                Field::Message => ("message", Value::from(text)),
+                Field::TraceId if !text.is_empty() => ("trace_id", Value::from(text)),
+                Field::SpanId if !text.is_empty() => ("span_id", Value::from(text)),
+                Field::Tags if !text.is_empty() => {
+                    let tags = text.split(' ').map(|tag| tag.trim_start_matches('#'));
+                    ("tags", Value::from(tags.collect::<Vec<_>>()))
+                }
+                Field::Extras => {
+                    for (key, value) in pairs(text) {
+                        record.entry(key).or_insert(value);
+                    }
+                    continue;
+                }
+                Field::Extra(name) => {
+                    record.entry(name.as_str()).or_insert_with(|| scalar(text));
+                    continue;
+                }
+                _ => continue,
+            };
+            record.entry(key).or_insert(value);
+        }
+
+        Some(record)
+    }
+
+    /// `text`, written with `datefmt`, as an RFC 3339 time, local when it
+    /// has no offset. It stays as written when there's no date in it.
+    fn time(&self, text: &str) -> Value {
+        if let Ok(time) = DateTime::parse_from_str(text, &self.datefmt) {
+            return Value::from(time.to_rfc3339());
+        }
+        match NaiveDateTime::parse_from_str(text, &self.datefmt) {
+            Ok(time) => match Local.from_local_datetime(&time).earliest() {
+                Some(time) => Value::from(time.to_rfc3339()),
+                None => Value::from(text),
+            },
+            Err(_) => Value::from(text),
+        }
+    }
+}
+
+/// A level name as `Level::as_str` has it, from `log`'s too.
+fn level(name: &str) -> &str {
+    match name {
+        "WARN" => "WARNING",
+        name => name,
+    }
+}
+
+/// The `key=value` pairs of `{extras}`. A value runs up to the next word
+/// with a `=` in it, so it may hold spaces.
+fn pairs(text: &str) -> Vec<(String, Value)> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+
+    for word in text.split(' ') {
+        match (word.split_once('='), pairs.last_mut()) {
+            (Some((key, value)), _) if !key.is_empty() => {
+                pairs.push((key.to_string(), value.to_string()))
+            }
+            (_, Some((_, value))) => {
+                value.push(' ');
+                value.push_str(word);
+            }
+            (_, None) => {}
+        }
+    }
+
+    pairs
+        .into_iter()
+        .map(|(key, value)| (key, scalar(&value)))
+        .collect()
+}
+
+/// A field's text back as the number, boolean or null it reads as,
+/// otherwise the string.
+fn scalar(text: &str) -> Value {
+    match serde_json::from_str::<Value>(text) {
+        Ok(value) if !value.is_object() && !value.is_array() && !value.is_string() => value,
+        _ => Value::from(text),
+    }
+}
+
+/// The records of a log, read a line at a time. A text record carries on
+/// over the lines of a traceback after it, added to its message. A line
+/// that can't be read back comes as `{"raw": line}`.
+pub struct Records {
+    lines: io::Lines<Box<dyn BufRead + Send>>,
+    layout: Layout,
+    /// The text record read last, held until it's clear no more of its
+    /// lines follow.
+    last: Option<Map<String, Value>>,
+    /// Whether the last record's lines are in a traceback.
+    traceback: bool,
+    ready: VecDeque<Value>,
+}
+
+#[cfg(feature = "zstd")]
+fn zstd(file: BufReader<File>) -> io::Result<Box<dyn BufRead + Send>> {
+    Ok(Box::new(BufReader::new(
+        zstd::stream::read::Decoder::with_buffer(file)?,
+    )))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd(_: BufReader<File>) -> io::Result<Box<dyn BufRead + Send>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "a zstd file, and this build of soda has no zstd support",
+    ))
+}
+
+impl Records {
+    /// The records of the file at `path`, written in `layout`. A gzip or
+    /// zstd file is read decompressed.
+    pub fn open(path: &str, layout: Layout) -> io::Result<Records> {
+        let mut file = BufReader::new(File::open(path)?);
+        let head = file.fill_buf()?;
+        let reader: Box<dyn BufRead + Send> = if head.starts_with(&[0x1f, 0x8b]) {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            zstd(file)?
+        } else {
+            Box::new(file)
+        };
+
+        Ok(Records::new(reader, layout))
+    }
+
+    pub fn new(reader: Box<dyn BufRead + Send>, layout: Layout) -> Records {
+        Records {
+            lines: reader.lines(),
+            layout,
+            last: None,
+            traceback: false,
+            ready: VecDeque::new(),
+        }
+    }
+
+    fn read(&mut self, line: String) {
+        if let Layout::Detect(datefmt) = &self.layout {
+            self.layout = Layout::detect(&line, datefmt);
+        }
+        let text = match &self.layout {
+            Layout::Json => {
+                if !line.trim().is_empty() {
+                    self.ready.push_back(match serde_json::from_str(&line) {
+                        Ok(Value::Object(record)) => Value::Object(record),
+                        _ => raw(line),
+                    });
+                }
+                return;
+            }
+            Layout::Text(text) => text,
+            Layout::Detect(_) => unreachable!(),
+        };
+
+        if let Some(record) = text.parse(&line) {
+            self.traceback = false;
+            if let Some(last) = self.last.replace(record) {
+                self.ready.push_back(Value::Object(last));
+            }
+            return;
+        }
+
+        match self.last.as_mut() {
+            Some(last) if continues(&line, &mut self.traceback) => {
+                if let Some(Value::String(message)) = last.get_mut("message") {
+                    message.push('\n');
+                    message.push_str(&line);
+                }
+            }
+            _ => {
+                if let Some(last) = self.last.take() {
+                    self.ready.push_back(Value::Object(last));
+                }
+                self.ready.push_back(raw(line));
+            }
+        }
+    }
+}
+
+impl Iterator for Records {
+    type Item = io::Result<Value>;
+
+    fn next(&mut self) -> Option<io::Result<Value>> {
+        loop {
+            if let Some(record) = self.ready.pop_front() {
+                return Some(Ok(record));
+            }
+            match self.lines.next() {
+                Some(Ok(line)) => self.read(line),
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.last.take().map(|last| Ok(Value::Object(last))),
+            }
+        }
+    }
+}
+
+/// Whether `line`, matching no record, goes on the one before it: as a line
+/// of a traceback, an indented or blank line, or the exception line ending
+/// a traceback.
+fn continues(line: &str, traceback: &mut bool) -> bool {
+    if line.starts_with("Traceback (most recent call last):") {
+        *traceback = true;
+        return true;
+    }
+    if line.trim().is_empty() || line.starts_with(char::is_whitespace) {
+        return true;
+    }
+    if CHAINED.contains(&line) {
+        return true;
+    }
+
+    std::mem::replace(traceback, false)
+}
+
+fn raw(line: String) -> Value {
+    let mut raw = Map::new();
+    raw.insert(String::from("raw"), Value::from(line));
+
+    Value::Object(raw)
+}
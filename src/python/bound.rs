@@ -0,0 +1,168 @@
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use pyo3::PyNativeType;
+use serde_json::{Map, Value};
+
+use super::timer::Timer;
+use super::{level_name, value, Soda};
+use crate::record::LazyField;
+use crate::Level;
+
+/// Fields bound to a logger. A callable value is a lazy field, called with
+/// no arguments for its value each time a record that will write it is
+/// emitted.
+#[derive(Clone, Default)]
+pub struct Bound {
+    fields: Map<String, Value>,
+    lazy: Vec<(String, PyObject)>,
+}
+
+impl Bound {
+    pub fn from_dict(dict: &PyDict) -> Bound {
+        let (fields, lazy) = value::fields_from_dict(dict);
+
+        Bound { fields, lazy }
+    }
+
+    pub fn fields(&self) -> &Map<String, Value> {
+        &self.fields
+    }
+
+    pub fn lazy(&self, py: Python) -> Vec<(String, LazyField)> {
+        self.lazy
+            .iter()
+            .map(|(key, function)| (key.clone(), value::lazy_field(function.clone_ref(py))))
+            .collect()
+    }
+
+    /// These with `other` added, its fields replace the ones with the same
+    /// key whether they're lazy or not.
+    fn with(&self, other: Bound) -> Bound {
+        let mut bound = self.clone();
+        for key in other
+            .fields
+            .keys()
+            .chain(other.lazy.iter().map(|(key, _)| key))
+        {
+            bound.remove(key);
+        }
+        bound.fields.extend(other.fields);
+        bound.lazy.extend(other.lazy);
+
+        bound
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.fields.remove(key);
+        self.lazy.retain(|(lazy, _)| lazy != key);
+    }
+}
+
+/// What `Soda.bind` returns: the parent logger plus fields merged into every
+/// record it logs. Creating one copies the fields and nothing else.
+#[pyclass]
+pub struct BoundLogger {
+    soda: Py<Soda>,
+    bound: Bound,
+}
+
+impl BoundLogger {
+    pub fn new(soda: Py<Soda>, bound: Bound) -> BoundLogger {
+        BoundLogger { soda, bound }
+    }
+
+    fn log(
+        &self,
+        level: Level,
+        message: &PyAny,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<()> {
+        let soda = self.soda.borrow(message.py());
+        if !soda.wanted(level, kwargs)? {
+            return Ok(());
+        }
+        let record = soda.record(level, message, args, kwargs, Some(&self.bound))?;
+
+        soda.emit(record)
+    }
+}
+
+#[pymethods]
+impl BoundLogger {
+    /// A logger with `fields` added to these, the new values win. Like
+    /// `Soda.bind`, a callable value is a lazy field.
+    #[args(fields = "**")]
+    fn bind(&self, py: Python, fields: Option<&PyDict>) -> BoundLogger {
+        let bound = match fields {
+            Some(fields) => self.bound.with(Bound::from_dict(fields)),
+            None => self.bound.clone(),
+        };
+
+        BoundLogger::new(self.soda.clone_ref(py), bound)
+    }
+
+    /// A logger without the fields named in `keys`.
+    #[args(keys = "*")]
+    fn unbind(&self, py: Python, keys: Vec<String>) -> BoundLogger {
+        let mut bound = self.bound.clone();
+        for key in keys {
+            bound.remove(&key);
+        }
+
+        BoundLogger::new(self.soda.clone_ref(py), bound)
+    }
+
+    /// `Soda.timeit`, with the bound fields on both records.
+    #[args(level = "\"INFO\"")]
+    fn timeit(&self, py: Python, label: &str, level: &str) -> PyResult<Timer> {
+        Ok(Timer::new(
+            self.soda.clone_ref(py),
+            Some(self.bound.clone()),
+            label,
+            level_name(level)?,
+        ))
+    }
+
+    /// The bound fields, lazy ones as the callables they were bound as.
+    #[getter]
+    fn fields(&self, py: Python) -> PyResult<PyObject> {
+        let fields = value::to_py(py, &Value::Object(self.bound.fields.clone()));
+        let fields = fields.as_ref(py).downcast::<PyDict>()?;
+        for (key, function) in &self.bound.lazy {
+            fields.set_item(key, function)?;
+        }
+
+        Ok(fields.into())
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn info(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        self.log(Level::INFO, message, args, kwargs)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn warning(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        self.log(Level::WARNING, message, args, kwargs)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn debug(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        self.log(Level::DEBUG, message, args, kwargs)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn trace(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        self.log(Level::TRACE, message, args, kwargs)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn error(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        self.log(Level::ERROR, message, args, kwargs)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn critical(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        self.log(Level::CRITICAL, message, args, kwargs)
+    }
+}
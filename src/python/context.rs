@@ -0,0 +1,134 @@
+use pyo3::once_cell::GILOnceCell;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use serde_json::{Map, Value};
+
+use super::value;
+
+/// `soda.context`, a `ContextVar` holding the fields bound for the current
+/// task or thread. It's only ever replaced, never mutated in place, so tasks
+/// that inherited a dict can't see each other's changes.
+static CONTEXT: GILOnceCell<PyObject> = GILOnceCell::new();
+
+pub fn var(py: Python<'_>) -> &'_ PyAny {
+    let var = CONTEXT.get_or_init(py, || {
+        py.import("contextvars")
+            .and_then(|contextvars| contextvars.call_method1("ContextVar", ("soda_context",)))
+            .map(|var| var.into())
+            .unwrap_or_else(|_| py.None())
+    });
+
+    var.as_ref(py)
+}
+
+/// Binds `fields` on top of whatever the current context holds, returning
+/// the `Token` that `soda.context.reset` takes to undo it.
+pub fn bind(py: Python, fields: Option<&PyDict>) -> PyResult<PyObject> {
+    let var = var(py);
+
+    let bound = match var.call_method1("get", (py.None(),))?.downcast::<PyDict>() {
+        Ok(current) => current.copy()?,
+        Err(_) => PyDict::new(py),
+    };
+    if let Some(fields) = fields {
+        for (key, value) in fields.iter() {
+            bound.set_item(key, value)?;
+        }
+    }
+
+    Ok(var.call_method1("set", (bound,))?.into())
+}
+
+/// Undoes the `bind` that returned `token`, restoring what was bound before.
+pub fn reset(py: Python, token: &PyAny) -> PyResult<()> {
+    var(py).call_method1("reset", (token,))?;
+
+    Ok(())
+}
+
+/// Rebinds the current context without `keys`.
+pub fn unbind(py: Python, keys: &[&str]) -> PyResult<()> {
+    let var = var(py);
+
+    if let Ok(current) = var.call_method1("get", (py.None(),))?.downcast::<PyDict>() {
+        let bound = current.copy()?;
+        for key in keys {
+            if bound.contains(*key)? {
+                bound.del_item(*key)?;
+            }
+        }
+        var.call_method1("set", (bound,))?;
+    }
+
+    Ok(())
+}
+
+/// A single bound field, `None` when it isn't bound.
+pub fn get(py: Python, key: &str) -> PyResult<PyObject> {
+    let current = var(py).call_method1("get", (py.None(),))?;
+
+    Ok(match current.downcast::<PyDict>() {
+        Ok(current) => current
+            .get_item(key)
+            .map_or_else(|| py.None(), |v| v.into()),
+        Err(_) => py.None(),
+    })
+}
+
+/// The fields bound in the current context, empty when nothing is bound.
+pub fn current(py: Python) -> Map<String, Value> {
+    let var = var(py);
+    if var.is_none() {
+        return Map::new();
+    }
+
+    match var.call_method1("get", (py.None(),)) {
+        Ok(current) => match current.downcast::<PyDict>() {
+            Ok(current) => value::map_from_dict(current),
+            Err(_) => Map::new(),
+        },
+        Err(_) => Map::new(),
+    }
+}
+
+/// What `Soda.contextualize` returns: binds its fields for the `with` block
+/// and puts back what was bound before on the way out, exception or not.
+#[pyclass]
+pub struct Contextualized {
+    fields: Py<PyDict>,
+    tokens: Vec<PyObject>,
+}
+
+impl Contextualized {
+    pub fn new(fields: Py<PyDict>) -> Contextualized {
+        Contextualized {
+            fields,
+            tokens: Vec::new(),
+        }
+    }
+}
+
+#[pymethods]
+impl Contextualized {
+    fn __enter__(&mut self, py: Python) -> PyResult<()> {
+        let token = bind(py, Some(self.fields.as_ref(py)))?;
+        self.tokens.push(token);
+
+        Ok(())
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python,
+        _kind: &PyAny,
+        _error: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        if let Some(token) = self.tokens.pop() {
+            reset(py, token.as_ref(py))?;
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::Mutex;
+
+use pyo3::exceptions::PyKeyboardInterrupt;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyType, PyUnicode};
+
+use super::Soda;
+use crate::Level;
+
+/// `sys.excepthook` as it was before `install`.
+static ORIGINAL: Mutex<Option<PyObject>> = Mutex::new(None);
+
+/// Stands in for `sys.excepthook`, logging the exception that ended the
+/// program.
+#[pyclass]
+pub struct Excepthook {
+    soda: Py<Soda>,
+    /// Whether the original hook runs after, printing the traceback.
+    chain: bool,
+}
+
+#[pymethods]
+impl Excepthook {
+    #[call]
+    fn __call__(
+        &self,
+        py: Python,
+        kind: &PyType,
+        error: &PyAny,
+        traceback: &PyAny,
+    ) -> PyResult<()> {
+        let interrupted = kind.is_subclass::<PyKeyboardInterrupt>()?;
+
+        if !interrupted {
+            if let Ok(soda) = self.soda.try_borrow(py) {
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("exc_info", (kind, error, traceback))?;
+
+                let message = format!("uncaught {}: {}", kind.name()?, error.str()?);
+                let record = soda.record(
+                    Level::CRITICAL,
+                    PyUnicode::new(py, &message),
+                    PyTuple::empty(py),
+                    Some(kwargs),
+                    None,
+                )?;
+                let emitted = soda.emit(record);
+                soda.flush();
+                emitted?;
+            }
+        }
+
+        if self.chain || interrupted {
+            let original = ORIGINAL.lock().unwrap().as_ref().map(|o| o.clone_ref(py));
+            if let Some(original) = original {
+                original.call1(py, (kind, error, traceback))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Swaps `sys.excepthook` for one logging through `soda`. Installing again
+/// replaces the hook, still in front of the original one.
+pub fn install(py: Python, soda: Py<Soda>, chain: bool) -> PyResult<()> {
+    let sys = py.import("sys")?;
+    let mut original = ORIGINAL.lock().unwrap();
+
+    if original.is_none() {
+        *original = Some(sys.getattr("excepthook")?.into());
+    }
+
+    sys.setattr("excepthook", Py::new(py, Excepthook { soda, chain })?)
+}
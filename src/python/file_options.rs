@@ -0,0 +1,217 @@
+//! The option groups `addFileHandler` takes, checked as they're made.
+
+use std::env;
+
+use flate2::Compression;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use super::{days, hmac_key_bytes, secret};
+use crate::handlers::cipher::Secret;
+use crate::handlers::file::{
+    self, Archive, Audit, Period, Retention, Rotation, RotationPattern, Signing, StreamCompression,
+};
+
+/// When a file handler rotates its file and which backups it keeps.
+///
+/// The file is rotated once it reaches `max_bytes`, moved to `<path>.1` and
+/// the older backups to `<path>.2` and so on, and with `period="daily"` on
+/// the first record of a day too, to `<path>.<date>`. A `pattern` like
+/// `"app-%Y%m%d-{seq:03}.log"` names the backups instead and an
+/// `archive_dir` takes them, created if it's missing.
+///
+/// The `deletion_policy` keeps `backup_count` backups, `"delete_oldest"`, at
+/// most `max_total_size` bytes of them, `"total_size"`, or those written to
+/// within `max_age_days`, `"max_age"`, as `cleanupLogs` does. The oldest go
+/// too until the file and its backups take up `retention_total_bytes`.
+/// `on_rotation` is called with the backup's path and the new file's after
+/// each rotation.
+#[pyclass]
+pub struct FileRotation {
+    /// Without its `on_rotation`, which counts errors in the logger's stats.
+    pub(super) rotation: Rotation,
+    pub(super) on_rotation: Option<PyObject>,
+}
+
+#[pymethods]
+impl FileRotation {
+    #[new]
+    #[args(
+        max_bytes = "0",
+        period = "None",
+        backup_count = "0",
+        deletion_policy = "\"delete_oldest\"",
+        max_total_size = "0",
+        max_age_days = "0.0",
+        retention_total_bytes = "None",
+        pattern = "None",
+        archive_dir = "None",
+        on_rotation = "None"
+    )]
+    fn new(
+        max_bytes: u64,
+        period: Option<&str>,
+        backup_count: usize,
+        deletion_policy: &str,
+        max_total_size: u64,
+        max_age_days: f64,
+        retention_total_bytes: Option<u64>,
+        pattern: Option<&str>,
+        archive_dir: Option<&str>,
+        on_rotation: Option<PyObject>,
+    ) -> PyResult<FileRotation> {
+        let max_age = days(max_age_days)?;
+        let retention =
+            Retention::parse(deletion_policy, max_total_size, max_age).ok_or_else(|| {
+                PyValueError::new_err(match deletion_policy {
+                    "total_size" => String::from("\"total_size\" needs a max_total_size"),
+                    "max_age" => String::from("\"max_age\" needs a max_age_days"),
+                    other => format!("unknown deletion policy {:?}", other),
+                })
+            })?;
+        let period = match period {
+            Some(name) => Some(Period::parse(name).ok_or_else(|| {
+                PyValueError::new_err(format!("unknown period {:?}, only \"daily\"", name))
+            })?),
+            None => None,
+        };
+        if max_bytes == 0 && period.is_none() {
+            return Err(PyValueError::new_err(
+                "a FileRotation needs a max_bytes or a period",
+            ));
+        }
+
+        let rotation = Rotation {
+            max_bytes,
+            period,
+            backup_count,
+            retention,
+            pattern: pattern
+                .map(RotationPattern::parse)
+                .transpose()
+                .map_err(PyValueError::new_err)?,
+            budget: retention_total_bytes,
+            on_rotation: None,
+            archive: archive_dir.map(Archive::new),
+        };
+        rotation
+            .check()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        Ok(FileRotation {
+            rotation,
+            on_rotation,
+        })
+    }
+}
+
+/// What makes a file handler's lines tamper-evident or unreadable.
+///
+/// With `audit=True` each line ends with the SHA-256 chaining it to the
+/// previous one, from `audit_genesis`, which `verifyAuditLog` checks. A
+/// `hmac_key`, or the variable `hmac_key_env` names, ends each with its
+/// HMAC-SHA256 instead, for `verifySignedLog`. An `encryption_key`, 32
+/// `bytes` or a `str` passphrase, encrypts each line for `decryptLog`.
+/// `exportConfig` never gives the keys.
+#[pyclass]
+pub struct FileSecurity {
+    pub(super) audit: Option<Audit>,
+    pub(super) signing: Option<Signing>,
+    pub(super) secret: Option<Secret>,
+}
+
+#[pymethods]
+impl FileSecurity {
+    #[new]
+    #[args(
+        audit = "false",
+        audit_genesis = "None",
+        hmac_key = "None",
+        hmac_key_env = "None",
+        encryption_key = "None"
+    )]
+    fn new(
+        audit: bool,
+        audit_genesis: Option<String>,
+        hmac_key: Option<&PyAny>,
+        hmac_key_env: Option<String>,
+        encryption_key: Option<&PyAny>,
+    ) -> PyResult<FileSecurity> {
+        let genesis = audit_genesis.unwrap_or_else(|| String::from(file::GENESIS));
+        if genesis.is_empty() || genesis.contains(char::is_whitespace) {
+            return Err(PyValueError::new_err(
+                "audit_genesis must be a word without whitespace",
+            ));
+        }
+        let audit = Some(Audit { genesis }).filter(|_| audit);
+
+        let key = match (hmac_key, &hmac_key_env) {
+            (Some(key), _) => Some(hmac_key_bytes(key)?),
+            (None, Some(name)) => Some(
+                env::var(name)
+                    .map_err(|_| PyValueError::new_err(format!("{} is not set", name)))?
+                    .into_bytes(),
+            ),
+            (None, None) => None,
+        };
+        let signing = key.map(|key| Signing::new(&key, hmac_key_env));
+        if audit.is_some() && signing.is_some() {
+            return Err(PyValueError::new_err(
+                "a file is either audited or signed, not both",
+            ));
+        }
+
+        Ok(FileSecurity {
+            audit,
+            signing,
+            secret: encryption_key.map(secret).transpose()?,
+        })
+    }
+}
+
+/// Has a file handler write a compressed stream, `"gzip"` at a `level` from
+/// 0 to 9, 6 by default, or `"zstd"` from 1 to 22, 3 by default, in a build
+/// with the `zstd` feature. Each flush ends with a sync flush, so a process
+/// dying loses no more than what was buffered.
+#[pyclass]
+pub struct FileCompression {
+    pub(super) compress: StreamCompression,
+}
+
+#[pymethods]
+impl FileCompression {
+    #[new]
+    #[args(algorithm = "\"gzip\"", level = "None")]
+    fn new(algorithm: &str, level: Option<i32>) -> PyResult<FileCompression> {
+        let compress = match algorithm {
+            "gzip" => match level {
+                Some(level) if !(0..=9).contains(&level) => {
+                    return Err(PyValueError::new_err("a gzip level is 0 to 9"))
+                }
+                Some(level) => StreamCompression::Gzip(Compression::new(level as u32)),
+                None => StreamCompression::Gzip(Compression::default()),
+            },
+            #[cfg(feature = "zstd")]
+            "zstd" => match level {
+                Some(level) if !(1..=22).contains(&level) => {
+                    return Err(PyValueError::new_err("a zstd level is 1 to 22"))
+                }
+                level => StreamCompression::Zstd(level.unwrap_or(3)),
+            },
+            #[cfg(not(feature = "zstd"))]
+            "zstd" => {
+                return Err(PyValueError::new_err(
+                    "this build of soda has no zstd support, build it with the zstd feature",
+                ))
+            }
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown compression {:?}",
+                    other
+                )))
+            }
+        };
+
+        Ok(FileCompression { compress })
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use pyo3::prelude::*;
+
+use super::heartbeat::{self, Stop};
+use crate::tail::Follow;
+
+/// The thread `Soda.follow` runs, stopped and joined on drop.
+pub struct Follower {
+    stop: Arc<Stop>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Follower {
+    /// Calls `callback` with each line written to the file at `path()`,
+    /// looking for more every `poll_interval` seconds.
+    pub fn start<P>(path: P, callback: PyObject, poll_interval: f64) -> PyResult<Follower>
+    where
+        P: Fn() -> String + Send + 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let worker_stop = Arc::clone(&stop);
+        let follow = Follow::new(&path());
+
+        let worker = thread::Builder::new()
+            .name(String::from("soda-follow"))
+            .spawn(move || run(follow, path, callback, poll_interval, worker_stop))?;
+        heartbeat::running(&stop);
+
+        Ok(Follower {
+            stop,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        heartbeat::stop_worker(&self.stop, self.worker.take());
+    }
+}
+
+fn run<P>(mut follow: Follow, path: P, callback: PyObject, poll_interval: f64, stop: Arc<Stop>)
+where
+    P: Fn() -> String,
+{
+    let interval = Duration::from_secs_f64(poll_interval);
+    while !heartbeat::wait(&stop, interval) {
+        let lines = match follow.read(&path()) {
+            Ok(lines) if lines.is_empty() => continue,
+            lines => lines,
+        };
+
+        Python::with_gil(|py| {
+            let lines = match lines {
+                Ok(lines) => lines,
+                Err(e) => return PyErr::from(e).print(py),
+            };
+            for line in lines {
+                if *stop.0.lock().unwrap() {
+                    return;
+                }
+                if let Err(e) = callback.call1(py, (line,)) {
+                    e.print(py);
+                }
+            }
+        });
+    }
+}
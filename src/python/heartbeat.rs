@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyDict, PyTuple, PyUnicode};
+use pyo3::wrap_pyfunction;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::Soda;
+use crate::Level;
+
+/// When the module was imported, what the uptime is counted from.
+static LOADED: OnceLock<Instant> = OnceLock::new();
+
+/// Stop flags of the running heartbeats and follows, all raised at exit.
+static RUNNING: Mutex<Vec<Arc<Stop>>> = Mutex::new(Vec::new());
+
+/// How far an interval may stray either way, so processes started together
+/// don't beat together.
+const JITTER: f64 = 0.1;
+
+pub type Stop = (Mutex<bool>, Condvar);
+
+/// The thread `Soda.startHeartbeat` runs, stopped and joined on drop.
+pub struct Heartbeat {
+    stop: Arc<Stop>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    pub fn start(
+        soda: Py<Soda>,
+        interval: f64,
+        level: Level,
+        extra_fn: Option<PyObject>,
+    ) -> PyResult<Heartbeat> {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let worker_stop = Arc::clone(&stop);
+
+        let worker = thread::Builder::new()
+            .name(String::from("soda-heartbeat"))
+            .spawn(move || run(soda, interval, level, extra_fn, worker_stop))?;
+        running(&stop);
+
+        Ok(Heartbeat {
+            stop,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        stop_worker(&self.stop, self.worker.take());
+    }
+}
+
+/// Starts counting the uptime and has the heartbeats stop at exit.
+pub fn init(py: Python, m: &PyModule) -> PyResult<()> {
+    LOADED.get_or_init(Instant::now);
+
+    py.import("atexit")?
+        .call_method1("register", (wrap_pyfunction!(stop_heartbeats, m)?,))?;
+
+    Ok(())
+}
+
+/// Stops the heartbeats without waiting for them, a thread waiting for the
+/// GIL finds the flag raised once it gets it.
+#[pyfunction]
+fn stop_heartbeats() {
+    for stop in RUNNING.lock().unwrap().drain(..) {
+        raise(&stop);
+    }
+}
+
+fn raise(stop: &Stop) {
+    *stop.0.lock().unwrap() = true;
+    stop.1.notify_all();
+}
+
+/// Has `stop` raised at exit.
+pub fn running(stop: &Arc<Stop>) {
+    RUNNING.lock().unwrap().push(Arc::clone(stop));
+}
+
+/// Raises `stop` and waits for the `worker` it stops.
+pub fn stop_worker(stop: &Arc<Stop>, worker: Option<JoinHandle<()>>) {
+    raise(stop);
+    RUNNING
+        .lock()
+        .unwrap()
+        .retain(|running| !Arc::ptr_eq(running, stop));
+
+    if let Some(worker) = worker {
+        crate::handlers::join_worker(worker);
+    }
+}
+
+/// Waits `timeout` for `stop` to be raised, returns whether it was.
+pub fn wait(stop: &Stop, timeout: Duration) -> bool {
+    let stopped = stop.0.lock().unwrap();
+    let (stopped, _) = stop
+        .1
+        .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+        .unwrap();
+
+    *stopped
+}
+
+fn run(soda: Py<Soda>, interval: f64, level: Level, extra_fn: Option<PyObject>, stop: Arc<Stop>) {
+    loop {
+        if wait(&stop, jittered(interval)) {
+            return;
+        }
+
+        Python::with_gil(|py| {
+            if *stop.0.lock().unwrap() {
+                return;
+            }
+            if let Err(e) = beat(py, &soda, level, extra_fn.as_ref()) {
+                e.print(py);
+            }
+        });
+    }
+}
+
+/// Logs a heartbeat record. `extra_fn` failing is printed and the record
+/// goes out without its gauges.
+fn beat(py: Python, soda: &Py<Soda>, level: Level, extra_fn: Option<&PyObject>) -> PyResult<()> {
+    // Skipped while the logger is being changed.
+    let soda = match soda.try_borrow(py) {
+        Ok(soda) => soda,
+        Err(_) => return Ok(()),
+    };
+    let stats = soda.logger.stats();
+
+    let mut records: BTreeMap<&str, u64> = BTreeMap::new();
+    for (handler, _, count) in stats.records() {
+        *records.entry(handler.as_str()).or_default() += count;
+    }
+
+    let kwargs = PyDict::new(py);
+    if let Some(extra_fn) = extra_fn {
+        let gauges = extra_fn.call0(py).and_then(|gauges| {
+            for (key, value) in gauges.as_ref(py).downcast::<PyDict>()? {
+                kwargs.set_item(key, value)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = gauges {
+            e.print(py);
+        }
+    }
+    let uptime = LOADED.get_or_init(Instant::now).elapsed().as_secs_f64();
+    kwargs.set_item("uptime_s", (uptime * 1e3).round() / 1e3)?;
+    kwargs.set_item("records", records.into_py_dict(py))?;
+    kwargs.set_item("dropped", stats.dropped_total())?;
+
+    let record = soda.record(
+        level,
+        PyUnicode::new(py, "heartbeat"),
+        PyTuple::empty(py),
+        Some(kwargs),
+        None,
+    )?;
+
+    soda.emit(record)
+}
+
+/// `interval` seconds give or take `JITTER` of it.
+fn jittered(interval: f64) -> Duration {
+    let mut bytes = [0; 4];
+    let spread = match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => f64::from(u32::from_be_bytes(bytes)) / f64::from(u32::MAX) * 2.0 - 1.0,
+        Err(_) => 0.0,
+    };
+
+    Duration::from_secs_f64(interval * (1.0 + JITTER * spread))
+}
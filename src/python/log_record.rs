@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use chrono::{Local, TimeZone};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::{PyMappingProtocol, PyNativeType};
+use serde_json::Value;
+
+use super::value;
+use crate::format::{Format, SharedFormat};
+use crate::record::{Caller, Record};
+use crate::{template, Level};
+
+/// A record the way callbacks, processors and filters get it.
+///
+/// Besides the attributes it can be indexed like the dict they used to get,
+/// `record["message"]`, `record["extras"]` and so on, an unknown key being
+/// looked up in the extras.
+#[pyclass]
+pub struct LogRecord {
+    record: Record,
+    extras: Py<PyDict>,
+    format: Arc<Format>,
+}
+
+impl LogRecord {
+    /// `format` is the logger's, what `rendered_message` renders with unless
+    /// the record holds on to the one it passed the level with.
+    pub fn new(py: Python, record: Record, format: &SharedFormat) -> LogRecord {
+        let extras = PyDict::new(py);
+        for (key, value) in &record.extras {
+            let _ = extras.set_item(key, value::to_py(py, value));
+        }
+
+        let format = match &record.format {
+            Some(format) => Arc::clone(format),
+            None => Arc::clone(&format.read().unwrap()),
+        };
+        LogRecord {
+            record,
+            extras: extras.into(),
+            format,
+        }
+    }
+
+    /// The record with whatever was changed on it from Python.
+    pub fn to_record(&self, py: Python) -> Record {
+        let mut record = self.record.clone();
+        record.extras = value::map_from_dict(self.extras.as_ref(py));
+
+        record
+    }
+
+    fn time_seconds(&self) -> f64 {
+        self.record.time.timestamp() as f64
+            + f64::from(self.record.time.timestamp_subsec_nanos()) / 1e9
+    }
+
+    /// Looks `key` up the way indexing does, `None` when there's no such key.
+    fn item(&self, py: Python, key: &str) -> PyResult<Option<PyObject>> {
+        Ok(Some(match key {
+            "message" => self.record.message.to_object(py),
+            "level" => self.record.level.as_str().to_object(py),
+            "name" => self.record.name.to_object(py),
+            "time" => self.time_seconds().to_object(py),
+            "extras" => self.extras.clone_ref(py).into(),
+            "exception" => self.exception(py),
+            "trace_id" => self.record.trace_id.to_object(py),
+            "span_id" => self.record.span_id.to_object(py),
+            "tags" => self.record.tags.to_object(py),
+            _ => match self.extras.as_ref(py).get_item(key) {
+                Some(value) => value.into(),
+                None => return Ok(None),
+            },
+        }))
+    }
+}
+
+#[pymethods]
+impl LogRecord {
+    /// The message as logged, placeholders and `%` arguments filled in.
+    #[getter]
+    fn message(&self) -> &str {
+        &self.record.message
+    }
+
+    #[setter]
+    fn set_message(&mut self, message: &PyAny) {
+        self.record.message = value::to_text(message);
+    }
+
+    /// The record the way the console prints it.
+    #[getter]
+    fn rendered_message(&self, py: Python) -> String {
+        self.format.render(&self.to_record(py))
+    }
+
+    #[getter]
+    fn levelname(&self) -> &'static str {
+        self.record.level.as_str()
+    }
+
+    #[setter]
+    fn set_levelname(&mut self, name: &str) -> PyResult<()> {
+        self.record.level = Level::from_name(name)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown level {:?}", name)))?;
+
+        Ok(())
+    }
+
+    #[getter]
+    fn levelno(&self) -> i64 {
+        self.record.level.number()
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.record.name
+    }
+
+    #[setter]
+    fn set_name(&mut self, name: String) {
+        self.record.name = name;
+    }
+
+    /// When the record was logged, a local `datetime`.
+    #[getter]
+    fn timestamp(&self, py: Python) -> PyResult<PyObject> {
+        let datetime = py.import("datetime")?.getattr("datetime")?;
+
+        Ok(datetime
+            .call_method1("fromtimestamp", (self.time_seconds(),))?
+            .into())
+    }
+
+    /// The same in seconds since the epoch.
+    #[getter]
+    fn time(&self) -> f64 {
+        self.time_seconds()
+    }
+
+    #[setter]
+    fn set_time(&mut self, time: f64) {
+        if let Some(time) = Local
+            .timestamp_opt(time.floor() as i64, (time.fract() * 1e9) as u32)
+            .single()
+        {
+            self.record.time = time;
+        }
+    }
+
+    /// The record's fields, changes made to the dict are kept.
+    #[getter]
+    fn extras(&self, py: Python) -> Py<PyDict> {
+        self.extras.clone_ref(py)
+    }
+
+    #[setter]
+    fn set_extras(&mut self, extras: &PyDict) {
+        self.extras = extras.into();
+    }
+
+    /// The exception it was logged with as the JSON handler writes it,
+    /// `{"type", "module", "message", "stack_trace", "frames", ...}`, `None`
+    /// without one.
+    #[getter]
+    fn exception(&self, py: Python) -> PyObject {
+        match &self.record.exception {
+            Some(exception) => value::to_py(py, &exception.to_value()),
+            None => py.None(),
+        }
+    }
+
+    #[getter]
+    fn filename(&self) -> Option<&str> {
+        self.record
+            .caller
+            .as_ref()
+            .map(|caller| caller.file.as_str())
+    }
+
+    #[getter]
+    fn lineno(&self) -> Option<u32> {
+        self.record.caller.as_ref().map(|caller| caller.line)
+    }
+
+    #[getter]
+    fn funcName(&self) -> Option<&str> {
+        self.record
+            .caller
+            .as_ref()
+            .map(|caller| caller.function.as_str())
+    }
+
+    #[getter]
+    fn process(&self) -> u32 {
+        self.record.process
+    }
+
+    #[getter]
+    fn thread(&self) -> Option<u64> {
+        self.record.thread
+    }
+
+    #[getter]
+    fn tags(&self) -> Vec<String> {
+        self.record.tags.clone()
+    }
+
+    #[setter]
+    fn set_tags(&mut self, tags: Vec<String>) {
+        self.record.tags = tags;
+    }
+
+    #[getter]
+    fn trace_id(&self) -> Option<&str> {
+        self.record.trace_id.as_deref()
+    }
+
+    #[getter]
+    fn span_id(&self) -> Option<&str> {
+        self.record.span_id.as_deref()
+    }
+
+    /// Renders the record with a `setFormat` template, the same way the
+    /// console does.
+    fn format(&self, py: Python, template: &str) -> String {
+        template::render(template, &self.to_record(py), &self.format)
+    }
+
+    #[args(default = "None")]
+    fn get(&self, py: Python, key: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        Ok(self
+            .item(py, key)?
+            .unwrap_or_else(|| default.unwrap_or_else(|| py.None())))
+    }
+
+    /// The fields the JSON handler writes plus `time` in seconds since the
+    /// epoch, as a dict.
+    fn to_dict(&self, py: Python) -> PyObject {
+        let mut fields = self.to_record(py).to_map();
+        fields.insert(String::from("time"), Value::from(self.time_seconds()));
+
+        value::to_py(py, &Value::Object(fields))
+    }
+}
+
+#[pyproto]
+impl PyMappingProtocol for LogRecord {
+    fn __getitem__(&self, key: &PyAny) -> PyResult<PyObject> {
+        let name: &str = key.extract()?;
+
+        self.item(key.py(), name)?
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+
+    fn __setitem__(&mut self, key: &str, value: &PyAny) -> PyResult<()> {
+        match key {
+            "message" => self.set_message(value),
+            "level" => self.set_levelname(value.extract()?)?,
+            "name" => self.set_name(value.extract()?),
+            "time" => self.set_time(value.extract()?),
+            "extras" => self.set_extras(value.downcast()?),
+            "tags" => self.set_tags(value.extract()?),
+            _ => self.extras.as_ref(value.py()).set_item(key, value)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the Python code currently running called in from.
+pub fn caller(py: Python) -> Option<Caller> {
+    let frame = py
+        .import("sys")
+        .ok()?
+        .call_method1("_getframe", (0,))
+        .ok()?;
+    let code = frame.getattr("f_code").ok()?;
+
+    Some(Caller {
+        file: code.getattr("co_filename").ok()?.extract().ok()?,
+        line: frame.getattr("f_lineno").ok()?.extract().ok()?,
+        function: code.getattr("co_name").ok()?.extract().ok()?,
+    })
+}
+
+/// `threading.get_ident()` of the current thread.
+pub fn thread(py: Python) -> Option<u64> {
+    py.import("threading")
+        .ok()?
+        .call_method0("get_ident")
+        .ok()?
+        .extract()
+        .ok()
+}
@@ -0,0 +1,185 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::wrap_pyfunction;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::Soda;
+use crate::logger::LevelCell;
+
+/// A logger `getLogger` or `getChild` has handed out, with its level to get
+/// at without borrowing it.
+struct Registered {
+    soda: Py<Soda>,
+    level: Arc<LevelCell>,
+}
+
+/// The loggers handed out, by name.
+static LOGGERS: Mutex<BTreeMap<String, Registered>> = Mutex::new(BTreeMap::new());
+
+/// The `setSampling` rates by logger name, `""` for the root.
+static SAMPLING: RwLock<BTreeMap<String, f64>> = RwLock::new(BTreeMap::new());
+
+thread_local! {
+    /// State of the xorshift generator sampling draws from, seeded on first
+    /// use.
+    static DRAW: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Adds `getLogger`, `listLoggers` and `setSampling` to the `soda` module.
+pub fn add_functions(m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(getLogger, m)?)?;
+    m.add_function(wrap_pyfunction!(listLoggers, m)?)?;
+    m.add_function(wrap_pyfunction!(setSampling, m)?)?;
+
+    Ok(())
+}
+
+/// The logger called `name`, created on first use and the same one on every
+/// call after that, the way `logging.getLogger` works. Leaving `name` out
+/// gives the `"soda"` logger. Until it's given a level it goes by the one of
+/// its closest dotted parent handed out, `"a.b"` by `"a"`'s, one created
+/// later included.
+#[pyfunction(name = "None")]
+fn getLogger(py: Python, name: Option<&str>) -> PyResult<Py<Soda>> {
+    let name = name.unwrap_or("soda");
+
+    let mut loggers = LOGGERS.lock().unwrap();
+    if let Some(registered) = loggers.get(name) {
+        return Ok(registered.soda.clone_ref(py));
+    }
+
+    let soda = Py::new(py, Soda::named(py, name, false, None))?;
+    let level = Arc::clone(soda.borrow(py).logger.level_cell());
+
+    let parent = parent(&loggers, name);
+    level.set_parent(parent.clone());
+    // The ones under it that went by the same parent go by it now.
+    let prefix = format!("{}.", name);
+    let under = loggers
+        .range(prefix.clone()..)
+        .take_while(|(under, _)| under.starts_with(&prefix));
+    for (_, under) in under {
+        if under.level.inherits_from(parent.as_ref()) {
+            under.level.set_parent(Some(Arc::clone(&level)));
+        }
+    }
+    loggers.insert(
+        name.to_string(),
+        Registered {
+            soda: soda.clone_ref(py),
+            level,
+        },
+    );
+
+    Ok(soda)
+}
+
+/// The level of the closest dotted parent of `name` handed out.
+fn parent(loggers: &BTreeMap<String, Registered>, name: &str) -> Option<Arc<LevelCell>> {
+    let mut name = name;
+
+    while let Some(dot) = name.rfind('.') {
+        name = &name[..dot];
+        if let Some(registered) = loggers.get(name) {
+            return Some(Arc::clone(&registered.level));
+        }
+    }
+
+    None
+}
+
+/// The child of `parent` called `<parent>.<suffix>`, see `Soda::getChild`.
+pub fn child(py: Python, parent: &Soda, suffix: &str) -> PyResult<Py<Soda>> {
+    let name = format!("{}.{}", parent.logger.name(), suffix);
+
+    let mut loggers = LOGGERS.lock().unwrap();
+    if let Some(registered) = loggers.get(&name) {
+        return Ok(registered.soda.clone_ref(py));
+    }
+
+    let logger = parent.logger.child(&name);
+    let level = Arc::clone(logger.level_cell());
+    let soda = Py::new(py, Soda::with_logger(py, logger, parent.otel_context))?;
+    loggers.insert(
+        name,
+        Registered {
+            soda: soda.clone_ref(py),
+            level,
+        },
+    );
+
+    Ok(soda)
+}
+
+/// `(name, level)` of every logger created through `getLogger` or
+/// `getChild`, sorted by name, the level its records are checked against,
+/// see `Soda.getEffectiveLevel`.
+#[pyfunction]
+fn listLoggers() -> Vec<(String, &'static str)> {
+    LOGGERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, registered)| (name.clone(), registered.level.effective().as_str()))
+        .collect()
+}
+
+/// Keeps about `rate` of the records below `ERROR` logged through the
+/// logger called `name` and the ones under it, `"a.b"` under `"a"`, the
+/// most specific name set winning. Leaving `name` out sets the rate of
+/// every logger without one of its own, 1.0 until set. A `sample=` rate
+/// passed with a call wins over both. `stats()` counts the records left out
+/// by name.
+#[pyfunction(name = "None")]
+fn setSampling(rate: f64, name: Option<&str>) -> PyResult<()> {
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(PyValueError::new_err("rate must be between 0 and 1"));
+    }
+    SAMPLING
+        .write()
+        .unwrap()
+        .insert(name.unwrap_or("").to_string(), rate);
+
+    Ok(())
+}
+
+/// The sampling rate of the closest dotted parent of `name` with one set,
+/// itself included, falling back on the root's.
+pub fn sampling_rate(name: &str) -> f64 {
+    let rates = SAMPLING.read().unwrap();
+    let mut name = name;
+
+    loop {
+        if let Some(rate) = rates.get(name) {
+            return *rate;
+        }
+
+        match name.rfind('.') {
+            Some(dot) => name = &name[..dot],
+            None => return rates.get("").copied().unwrap_or(1.0),
+        }
+    }
+}
+
+/// A number in `[0, 1)` to sample with, not one to rely on for anything
+/// secret.
+pub fn chance() -> f64 {
+    DRAW.with(|draw| {
+        let mut x = draw.get();
+        if x == 0 {
+            let mut seed = [0; 8];
+            let _ = SystemRandom::new().fill(&mut seed);
+            x = u64::from_le_bytes(seed) | 1;
+        }
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        draw.set(x);
+
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
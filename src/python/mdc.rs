@@ -0,0 +1,88 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use pyo3::wrap_pyfunction;
+use serde_json::Value;
+
+use super::value;
+use crate::mdc;
+
+/// The `soda.mdc` module, fields for the current thread. Threads in a pool
+/// get reused, `scope` takes its fields off again however the block ends.
+pub fn module(py: Python<'_>) -> PyResult<&'_ PyModule> {
+    let m = PyModule::new(py, "soda.mdc")?;
+    m.add_class::<Scope>()?;
+    m.add_function(wrap_pyfunction!(put, m)?)?;
+    m.add_function(wrap_pyfunction!(get, m)?)?;
+    m.add_function(wrap_pyfunction!(remove, m)?)?;
+    m.add_function(wrap_pyfunction!(clear, m)?)?;
+    m.add_function(wrap_pyfunction!(scope, m)?)?;
+
+    // Lets `import soda.mdc` find the submodule.
+    let modules: &PyDict = py.import("sys")?.getattr("modules")?.downcast()?;
+    modules.set_item("soda.mdc", m)?;
+
+    Ok(m)
+}
+
+#[pyfunction]
+fn put(key: &str, value: &PyAny) {
+    mdc::put(key, value::from_py(value));
+}
+
+/// The value put under `key`, `None` when there is none.
+#[pyfunction]
+fn get(py: Python, key: &str) -> PyObject {
+    mdc::get(key).map_or_else(|| py.None(), |value| value::to_py(py, &value))
+}
+
+#[pyfunction]
+fn remove(key: &str) {
+    mdc::remove(key);
+}
+
+#[pyfunction]
+fn clear() {
+    mdc::clear();
+}
+
+/// Puts `fields` for the duration of a `with` block.
+#[pyfunction(fields = "**")]
+fn scope(fields: Option<&PyDict>) -> Scope {
+    Scope {
+        fields: fields
+            .map(|fields| value::map_from_dict(fields).into_iter().collect())
+            .unwrap_or_default(),
+        previous: Vec::new(),
+    }
+}
+
+/// What `soda.mdc.scope` returns. Leaving the block puts back whatever the
+/// keys held before it, or removes them when they held nothing.
+#[pyclass]
+pub struct Scope {
+    fields: Vec<(String, Value)>,
+    previous: Vec<Vec<(String, Option<Value>)>>,
+}
+
+#[pymethods]
+impl Scope {
+    fn __enter__(&mut self) {
+        let previous = self
+            .fields
+            .iter()
+            .map(|(key, value)| (key.clone(), mdc::put(key, value.clone())))
+            .collect();
+        self.previous.push(previous);
+    }
+
+    fn __exit__(&mut self, _kind: &PyAny, _error: &PyAny, _traceback: &PyAny) -> bool {
+        for (key, previous) in self.previous.pop().unwrap_or_default().into_iter().rev() {
+            match previous {
+                Some(value) => mdc::put(&key, value),
+                None => mdc::remove(&key),
+            };
+        }
+
+        false
+    }
+}
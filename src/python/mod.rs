@@ -0,0 +1,4047 @@
+// The Python API is camelCase and its keyword arguments map one to one onto
+// parameters.
+#![allow(non_snake_case, clippy::too_many_arguments)]
+//! The `soda` Python extension module, a thin layer over `Logger`.
+
+use std::{collections::HashMap, io, path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::{Local, TimeZone};
+use pyo3::exceptions::{PySystemExit, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple, PyUnicode};
+use pyo3::wrap_pyfunction;
+use pyo3::{AsPyPointer, PyClass, PyNativeType, PyTypeInfo};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+mod bound;
+mod context;
+mod excepthook;
+mod file_options;
+mod follow;
+mod heartbeat;
+mod log_record;
+mod loggers;
+mod mdc;
+mod otel;
+mod print;
+mod processor;
+mod pytest_plugin;
+mod records;
+mod signals;
+mod span;
+mod startup;
+mod stdlib;
+mod timer;
+mod value;
+
+use crate::cleanup;
+use crate::clock;
+use crate::format::{ColorScope, SharedFormat, DEFAULT_DATEFMT};
+use crate::handlers::cipher::{self, Secret};
+use crate::handlers::console;
+use crate::handlers::file::{self, FileOptions, Reexpand, Retention, Rotation};
+use crate::handlers::fluentd::FluentdLogger;
+use crate::handlers::json::{Codec, JsonLogger, Preset, TimeFormat};
+use crate::handlers::level_split::LevelSplitLogger;
+use crate::handlers::memory::MemoryLogger;
+use crate::handlers::otlp::{OtlpConfig, OtlpLogger, Protocol};
+#[cfg(feature = "sqlite")]
+use crate::handlers::sqlite::SqliteLogger;
+use crate::logger::{self, FilterRule, HandlerId, Logger, NameTaken, Route};
+use crate::metrics::{self, MetricsServer};
+use crate::parse::{Layout, Records};
+use crate::record::{self, Caller, DecodeErrors, Record};
+use crate::schema::{OnViolation, Schema, Violation};
+use crate::scrub::{PiiKind, Scrubber, PII_KINDS};
+use crate::stats::HandlerKind;
+use crate::template::{self, Coercion};
+use crate::Level;
+use bound::{Bound, BoundLogger};
+use context::Contextualized;
+use file_options::{FileCompression, FileRotation, FileSecurity};
+use follow::Follower;
+use heartbeat::Heartbeat;
+use log_record::LogRecord;
+use otel::OtelContext;
+use records::LogRecords;
+use span::Span;
+use startup::StartupTimer;
+use timer::Timer;
+use value::{Capture, Unserializable};
+
+#[pymodule]
+fn soda(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Soda>()?;
+    m.add_class::<MemoryHandler>()?;
+    m.add_class::<BoundLogger>()?;
+    m.add_class::<Timer>()?;
+    m.add_class::<Span>()?;
+    m.add_class::<Contextualized>()?;
+    m.add_class::<LogRecord>()?;
+    m.add_class::<LogRecords>()?;
+    m.add_class::<FileRotation>()?;
+    m.add_class::<FileSecurity>()?;
+    m.add_class::<FileCompression>()?;
+    m.add_class::<stdlib::LoggingHandler>()?;
+    m.add_class::<print::PrintWriter>()?;
+    m.add_class::<excepthook::Excepthook>()?;
+    m.add_class::<signals::SignalFlush>()?;
+    m.add("pytest_plugin", pytest_plugin::module(py)?)?;
+    m.add("mdc", mdc::module(py)?)?;
+    m.add("context", context::var(py))?;
+    m.add_function(wrap_pyfunction!(dictConfig, m)?)?;
+    loggers::add_functions(m)?;
+    m.add_function(wrap_pyfunction!(setMonotonicTimestamps, m)?)?;
+    m.add_function(wrap_pyfunction!(setClock, m)?)?;
+    m.add_function(wrap_pyfunction!(installPanicFlush, m)?)?;
+    m.add_function(wrap_pyfunction!(advanceClock, m)?)?;
+    m.add_function(wrap_pyfunction!(verifyAuditLog, m)?)?;
+    m.add_function(wrap_pyfunction!(findAuditBreak, m)?)?;
+    m.add_function(wrap_pyfunction!(verifySignedLog, m)?)?;
+    m.add_function(wrap_pyfunction!(decryptLog, m)?)?;
+    m.add_function(wrap_pyfunction!(cleanupLogs, m)?)?;
+    m.add_function(wrap_pyfunction!(parseLog, m)?)?;
+
+    // A logger kept alive by a reference cycle, say through a processor
+    // defined next to it, or by a module global is never dropped, so nothing
+    // else flushes the console and the buffering handlers on the way out.
+    // Registered once, with the module.
+    py.import("atexit")?
+        .call_method1("register", (wrap_pyfunction!(flush_at_exit, m)?,))?;
+    // Exit functions run last in first out, spans end before the flush.
+    span::end_at_exit(py, m)?;
+    heartbeat::init(py, m)?;
+
+    Ok(())
+}
+
+#[pyfunction]
+fn flush_at_exit() {
+    console::flush();
+    logger::flush_all();
+}
+
+/// Walks the hash chain of a file written with `FileSecurity(audit=True)`,
+/// `True` when it holds. `genesis` is the hash the chain must
+/// start from, for a rotated file's next the one its last line ends with.
+/// `findAuditBreak` tells where it doesn't hold.
+#[pyfunction(genesis = "None")]
+fn verifyAuditLog(path: &str, genesis: Option<&str>) -> PyResult<bool> {
+    Ok(file::verify(path, genesis)?.is_none())
+}
+
+/// The first broken link of the chain `verifyAuditLog` walks, as `{"line":
+/// ..., "reason": ...}` with lines counted from 1, `None` when it holds.
+#[pyfunction(genesis = "None")]
+fn findAuditBreak(py: Python, path: &str, genesis: Option<&str>) -> PyResult<PyObject> {
+    let broken = match file::verify(path, genesis)? {
+        Some(broken) => json!({ "line": broken.line, "reason": broken.reason }),
+        None => Value::Null,
+    };
+
+    Ok(value::to_py(py, &broken))
+}
+
+/// Checks the signature of each line of a file written with
+/// `FileSecurity(hmac_key=key)`, returns `{"valid": ..., "invalid":
+/// [...]}`, the count of lines that check out and the numbers, from 1, of
+/// those that don't.
+#[pyfunction]
+fn verifySignedLog(py: Python, path: &str, key: &PyAny) -> PyResult<PyObject> {
+    let signed = file::verify_signed(path, &hmac_key_bytes(key)?)?;
+
+    Ok(value::to_py(
+        py,
+        &json!({ "valid": signed.valid, "invalid": signed.invalid }),
+    ))
+}
+
+/// Decrypts a file written with `FileSecurity(encryption_key=key)` to
+/// `out_path`. A frame that fails to decrypt, tampered with or sealed with
+/// another key, is skipped, as is one cut short at the end of the file by a
+/// crash. Returns `{"frames": ..., "corrupt": ..., "truncated": ...}`, the
+/// frames decrypted, those skipped, and whether the last was cut short.
+#[pyfunction]
+fn decryptLog(py: Python, path: &str, key: &PyAny, out_path: &str) -> PyResult<PyObject> {
+    let decrypted = cipher::decrypt(path, &secret(key)?)?;
+    std::fs::write(out_path, &decrypted.text)?;
+
+    Ok(value::to_py(
+        py,
+        &json!({
+            "frames": decrypted.frames,
+            "corrupt": decrypted.corrupt,
+            "truncated": decrypted.truncated,
+        }),
+    ))
+}
+
+/// Reads a log soda wrote back into records, a dict each, a line at a time
+/// so a log of any size can be gone through. `format` is `"json"` for the
+/// JSON handler's lines, otherwise the `setFormat` template they were
+/// rendered with, `""` for the default `[time][name][LEVEL] message` line,
+/// and `datefmt` the date format. Without one it's told from the first
+/// line: JSON, the default line, or else a message a line as the file
+/// handler writes them. A gzip file, a compressed rotation say, is read
+/// decompressed.
+///
+/// A JSON line gives back its document. A text line gives back the
+/// `timestamp`, in RFC 3339 when `datefmt` has a date, `level`, `name`,
+/// `message` and the `trace_id`, `span_id`, `tags` and extras the template
+/// shows, extras that read as numbers, booleans or null as those. The
+/// lines of a traceback after a record, and its blank and indented lines,
+/// are added to its message, which takes the message being the last of
+/// the template. Any other line that doesn't match comes back as `{"raw":
+/// line}`.
+#[pyfunction(format = "None", datefmt = "None")]
+fn parseLog(path: &str, format: Option<&str>, datefmt: Option<&str>) -> PyResult<LogRecords> {
+    let datefmt = datefmt.unwrap_or(DEFAULT_DATEFMT);
+    let layout = match format {
+        Some("json") => Layout::Json,
+        Some(template) => Layout::text(template, datefmt).map_err(PyValueError::new_err)?,
+        None => Layout::Detect(datefmt.to_string()),
+    };
+
+    Ok(LogRecords::new(Records::open(path, layout)?))
+}
+
+/// Deletes the files in `directory` whose names match the glob `pattern`,
+/// `*`, `?` and `[...]`, and that were last written to more than
+/// `older_than_days` ago. Subdirectories aren't looked into, symlinks are
+/// left alone and the file any live handler is writing to is skipped. A
+/// file that can't be deleted doesn't stop the others. Returns `{"deleted":
+/// [...], "bytes": ..., "skipped": [...], "errors": [{"path": ...,
+/// "error": ...}], "dry_run": ...}`, with `dry_run=True` what would have
+/// been deleted without deleting anything.
+#[pyfunction(dry_run = "false")]
+fn cleanupLogs(
+    py: Python,
+    directory: &str,
+    pattern: &str,
+    older_than_days: f64,
+    dry_run: bool,
+) -> PyResult<PyObject> {
+    let pattern = cleanup::glob(pattern).map_err(PyValueError::new_err)?;
+    let cutoff = file::cutoff(days(older_than_days)?);
+    let sweep =
+        py.allow_threads(|| cleanup::sweep(directory.as_ref(), &pattern, cutoff, dry_run))?;
+
+    let paths = |paths: &[std::path::PathBuf]| -> Vec<Value> {
+        paths
+            .iter()
+            .map(|path| Value::from(path.to_string_lossy()))
+            .collect()
+    };
+    let errors: Vec<Value> = sweep
+        .errors
+        .iter()
+        .map(|(path, e)| json!({ "path": path.to_string_lossy(), "error": e.to_string() }))
+        .collect();
+
+    Ok(value::to_py(
+        py,
+        &json!({
+            "deleted": paths(&sweep.deleted),
+            "bytes": sweep.bytes,
+            "skipped": paths(&sweep.skipped),
+            "errors": errors,
+            "dry_run": dry_run,
+        }),
+    ))
+}
+
+/// An encryption key given as 32 `bytes`, or a passphrase as a `str`.
+fn secret(key: &PyAny) -> PyResult<Secret> {
+    if let Ok(bytes) = key.downcast::<PyBytes>() {
+        if bytes.as_bytes().len() != Secret::KEY_LEN {
+            return Err(PyValueError::new_err(format!(
+                "an encryption key is {} bytes, or a str passphrase",
+                Secret::KEY_LEN
+            )));
+        }
+        return Ok(Secret::Key(Zeroizing::new(bytes.as_bytes().to_vec())));
+    }
+
+    Ok(Secret::Passphrase(Zeroizing::new(
+        key.extract::<String>()?.into_bytes(),
+    )))
+}
+
+/// A handler's `flush_every_n_lines`, which only holds for a buffer.
+fn buffered_lines(buffer_size: usize, lines: usize) -> PyResult<usize> {
+    match (buffer_size, lines) {
+        (0, 1..) => Err(PyValueError::new_err(
+            "flush_every_n_lines needs a buffer_size",
+        )),
+        (_, lines) => Ok(lines),
+    }
+}
+
+/// The bytes of a key given as a `str` or `bytes`.
+fn hmac_key_bytes(key: &PyAny) -> PyResult<Vec<u8>> {
+    match key.downcast::<PyBytes>() {
+        Ok(bytes) => Ok(bytes.as_bytes().to_vec()),
+        Err(_) => Ok(key.extract::<String>()?.into_bytes()),
+    }
+}
+
+/// Where a file handler's `latest_symlink` goes: `True` for next to the
+/// file, see `file::latest_path`, or the path it names.
+fn latest_link(path: &str, latest: Option<&PyAny>) -> PyResult<Option<PathBuf>> {
+    let latest = match latest {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+    if let Ok(enabled) = latest.extract::<bool>() {
+        return Ok(Some(file::latest_path(path)).filter(|_| enabled));
+    }
+
+    match latest.extract::<String>() {
+        Ok(link) => Ok(Some(PathBuf::from(link))),
+        Err(_) => Err(PyTypeError::new_err("latest_symlink is a bool or a path")),
+    }
+}
+
+/// Has a panic in soda's Rust code write out what the handlers of every
+/// logger buffer, as far as it can within a moment, before it's raised as
+/// usual. Installing again does nothing more.
+#[pyfunction]
+fn installPanicFlush() {
+    logger::install_panic_flush();
+}
+
+/// With `enabled`, a record is never timestamped before the one logged
+/// before it, by whichever logger, even if the wall clock jumps back. It
+/// holds for every logger in the process.
+#[pyfunction]
+fn setMonotonicTimestamps(enabled: bool) {
+    record::set_monotonic(enabled);
+}
+
+/// For tests: stops the clock soda reads at `time`, seconds since the
+/// epoch, for every logger in the process. Records are timestamped with
+/// it, and daily rotations and reexpansions and the `max_age` policies go
+/// by it, so they can be set off at will. `None` goes back to the system
+/// clock. Batching intervals and timeouts keep running on their own.
+#[pyfunction(time = "None")]
+fn setClock(time: Option<f64>) -> PyResult<()> {
+    let time = match time {
+        Some(time) => Some(
+            Local
+                .timestamp_opt(time.floor() as i64, (time.fract() * 1e9) as u32)
+                .single()
+                .ok_or_else(|| PyValueError::new_err(format!("{} isn't a time", time)))?,
+        ),
+        None => None,
+    };
+    clock::set(time);
+
+    Ok(())
+}
+
+/// For tests: moves the clock on by `seconds`, back when negative, from now
+/// when `setClock` didn't stop it, which this does.
+#[pyfunction]
+fn advanceClock(seconds: f64) -> PyResult<()> {
+    if !seconds.is_finite() {
+        return Err(PyValueError::new_err(format!(
+            "{} isn't a number of seconds",
+            seconds
+        )));
+    }
+    clock::advance(chrono::Duration::nanoseconds((seconds * 1e9) as i64));
+
+    Ok(())
+}
+
+#[pyclass(dict, subclass)]
+pub struct Soda {
+    logger: Logger,
+
+    otel: Option<OtelContext>,
+    otel_context: bool,
+
+    console: Option<ConsoleConfig>,
+
+    correlation_field: String,
+
+    metrics: Option<MetricsServer>,
+    heartbeat: Option<Heartbeat>,
+    follower: Option<Follower>,
+    startup_timer: Option<StartupTimer>,
+
+    decode_errors: DecodeErrors,
+
+    /// What a call's fields that can't be serialized turn into.
+    json_default: Unserializable,
+
+    /// Whether the fields a message's placeholders took are kept as extras.
+    template_extras: bool,
+
+    /// How deep a record describes its exception's chain and whether with
+    /// the frames' locals.
+    exception_capture: Capture,
+
+    /// `addProcessor` callables and their ids on the logger.
+    processors: Vec<(u64, PyObject)>,
+
+    /// Ids of the filters handlers were added with through `filter=`.
+    handler_filters: HashMap<HandlerId, u64>,
+
+    /// Ids of the tag filters handlers were added with.
+    handler_tags: HashMap<HandlerId, u64>,
+
+    /// The level from which a record ends the program, with what exit code.
+    exit_on: Option<(Level, i32)>,
+
+    /// The id of the `enablePiiScrubbing` processor and its settings.
+    pii_scrubbing: Option<(u64, Vec<PiiKind>, Vec<String>)>,
+}
+
+#[pymethods]
+impl Soda {
+    /// A `verbosity` other than `0` sets the level as `setLevel` does.
+    /// `fields` are the `setDefaultFields` ones, `template_extras` the
+    /// `setTemplateExtras` setting.
+    #[new]
+    #[args(
+        verbosity = "0",
+        otel_context = "false",
+        fields = "None",
+        template_extras = "true"
+    )]
+    fn new(
+        py: Python,
+        verbosity: u64,
+        otel_context: bool,
+        fields: Option<&PyDict>,
+        template_extras: bool,
+    ) -> Soda {
+        let mut soda = Soda::named(py, "soda", otel_context, fields);
+        soda.template_extras = template_extras;
+        if verbosity != 0 {
+            soda.setLevel(verbosity.min(u8::MAX.into()) as u8);
+        }
+
+        soda
+    }
+
+    /// Changes the level, the `setFormat` template and the date format
+    /// together, rather than one after the other with records logged from
+    /// another thread in between getting the new format at the old level or
+    /// the other way round. What isn't given stays as it is, and nothing
+    /// changes when the level isn't one.
+    #[args(level = "None", format = "None", datefmt = "None")]
+    fn reconfigure(
+        &mut self,
+        level: Option<&str>,
+        format: Option<&str>,
+        datefmt: Option<&str>,
+    ) -> PyResult<()> {
+        let level = level.map(level_name).transpose()?;
+        self.logger.reconfigure(level, format, datefmt);
+
+        Ok(())
+    }
+
+    fn setFormat(&mut self, format: &PyUnicode) {
+        let format: Result<&str, PyErr> = format.to_str();
+
+        if let Ok(format) = format {
+            self.logger.set_template(format);
+        }
+    }
+
+    /// `buffered` picks between flushing the console per line (`False`) and
+    /// once `buffer_size` bytes are pending (`True`), by default it is line
+    /// buffered only when stdout is a terminal.
+    ///
+    /// With `console_writer` every console line is passed to that callable
+    /// instead of being written to stdout, `tqdm_compat=True` is a shortcut
+    /// for `console_writer=tqdm.write` so progress bars survive logging.
+    ///
+    /// `notebook=True` writes lines to Python's `sys.stdout`, so Jupyter
+    /// shows them under the cell rather than in the kernel's terminal. It is
+    /// detected by default, pass `False` to keep the process stdout.
+    ///
+    /// `filter`, `require_tags` and `exclude_tags` apply to the console
+    /// alone, see `addFilter` and `addTagFilter`.
+    ///
+    /// `debug_blocks=True` sets soda's debug and trace records apart between
+    /// `---` lines instead of printing them like the others.
+    ///
+    /// `color_scope` tints lines by level on the console alone, `"token"`
+    /// just the level name and `"line"` the whole record, errors in red and
+    /// so on. Left out there's no color.
+    #[args(
+        buffered = "None",
+        buffer_size = "8192",
+        console_writer = "None",
+        tqdm_compat = "false",
+        notebook = "None",
+        filter = "None",
+        require_tags = "None",
+        exclude_tags = "None",
+        debug_blocks = "false",
+        color_scope = "None"
+    )]
+    fn basicConfig(
+        &mut self,
+        py: Python,
+        dtFormat: &PyUnicode,
+        buffered: Option<bool>,
+        buffer_size: usize,
+        console_writer: Option<PyObject>,
+        tqdm_compat: bool,
+        notebook: Option<bool>,
+        filter: Option<PyObject>,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+        debug_blocks: bool,
+        color_scope: Option<&str>,
+    ) -> PyResult<()> {
+        let color = match color_scope {
+            Some(scope) => Some(ColorScope::parse(scope).ok_or_else(|| {
+                PyValueError::new_err(format!("unknown color_scope {:?}", scope))
+            })?),
+            None => None,
+        };
+
+        let target = match (console_writer, tqdm_compat) {
+            (Some(writer), _) => console::Target::Callable(writer),
+            (None, true) => console::Target::Callable(
+                py.import("tqdm")
+                    .and_then(|tqdm| tqdm.getattr("tqdm"))
+                    .and_then(|tqdm| tqdm.getattr("write"))
+                    .map_err(|e| self.raise(e))?
+                    .into(),
+            ),
+            _ if notebook.unwrap_or_else(|| console::in_notebook(py)) => {
+                console::Target::PythonStdout
+            }
+            _ => console::Target::Stdout,
+        };
+
+        let dtFormat: String = match dtFormat.to_str() {
+            Ok(fmt) => fmt.to_string(),
+            Err(e) => {
+                eprintln!(
+                    "soda: couldn't read the date format ({}), using the default",
+                    e
+                );
+                String::from(DEFAULT_DATEFMT)
+            }
+        };
+
+        self.logger.set_datefmt(&dtFormat);
+        self.console = Some(ConsoleConfig {
+            datefmt: dtFormat,
+            buffered,
+            buffer_size,
+            tqdm_compat,
+            notebook,
+            debug_blocks,
+            color,
+        });
+
+        // The dispatch is global, only the first configuration takes effect,
+        // where the console writes to can still be changed.
+        self.logger
+            .console(buffered.map(|b| !b), buffer_size, debug_blocks, color);
+        console::set_target(target);
+        let console = HandlerId::first(HandlerKind::Console);
+        self.set_handler_filter(console, filter, require_tags, exclude_tags);
+
+        Ok(())
+    }
+
+    /// Counters of emitted records per handler and level, plus the records
+    /// dropped by full buffers, failed exports or logging while handling
+    /// another record.
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        let records = PyDict::new(py);
+
+        for (handler, level, count) in self.logger.stats().records() {
+            let levels = match records.get_item(handler.as_str()) {
+                Some(levels) => levels.downcast::<PyDict>()?,
+                None => {
+                    let levels = PyDict::new(py);
+                    records.set_item(handler.as_str(), levels)?;
+                    levels
+                }
+            };
+            levels.set_item(level.as_str(), count)?;
+        }
+
+        let stats = PyDict::new(py);
+        stats.set_item("records", records)?;
+        stats.set_item("dropped", self.logger.stats().dropped_total())?;
+        stats.set_item(
+            "processor_errors",
+            self.logger.stats().processor_errors_total(),
+        )?;
+        stats.set_item("filter_errors", self.logger.stats().filter_errors_total())?;
+        stats.set_item("format_errors", self.logger.stats().format_errors_total())?;
+        stats.set_item("hook_errors", self.logger.stats().hook_errors_total())?;
+        stats.set_item("field_errors", self.logger.stats().field_errors_total())?;
+        stats.set_item(
+            "rotation_errors",
+            self.logger.stats().rotation_errors_total(),
+        )?;
+        stats.set_item(
+            "schema_violations",
+            self.logger.stats().schema_violations_total(),
+        )?;
+        stats.set_item("console_errors", console::writer_errors())?;
+
+        let scrubbed = PyDict::new(py);
+        for (kind, count) in self.logger.stats().scrubbed_totals() {
+            scrubbed.set_item(kind.as_str(), count)?;
+        }
+        stats.set_item("scrubbed", scrubbed)?;
+        stats.set_item("sampled_out", self.logger.stats().sampled_out_totals())?;
+
+        Ok(stats.into())
+    }
+
+    /// Serves the counters in the Prometheus text format on
+    /// `http://host:port/metrics` until the logger goes away.
+    #[args(port = "9464", host = "\"0.0.0.0\"")]
+    fn startMetricsServer(&mut self, port: u16, host: &str) -> PyResult<()> {
+        self.metrics = None;
+        self.metrics = Some(
+            MetricsServer::start(host, port, Arc::clone(self.logger.stats()))
+                .map_err(|e| self.raise(e))?,
+        );
+
+        Ok(())
+    }
+
+    /// Logs a `heartbeat` record every `interval` seconds, give or take a
+    /// tenth so processes started together don't beat together, from a
+    /// background thread and through the usual filters and routes. It carries
+    /// `uptime_s` since soda was imported, `records` written per handler,
+    /// `dropped` and whatever fields the dict `extra_fn()` returns, gauges
+    /// say. Should `extra_fn` raise, the error is printed and the record goes
+    /// out without them. Runs until `stopHeartbeat` or exit, keeping the
+    /// logger alive until then; starting one again replaces the last.
+    #[args(interval = "60.0", level = "\"INFO\"", extra_fn = "None")]
+    fn startHeartbeat(
+        slf: &PyCell<Soda>,
+        interval: f64,
+        level: &str,
+        extra_fn: Option<PyObject>,
+    ) -> PyResult<()> {
+        if !(interval > 0.0 && interval.is_finite()) {
+            return Err(PyValueError::new_err("interval must be a positive number"));
+        }
+        let level = level_name(level)?;
+
+        slf.borrow_mut().stopHeartbeat();
+        let heartbeat = Heartbeat::start(slf.into(), interval, level, extra_fn)?;
+        slf.borrow_mut().heartbeat = Some(heartbeat);
+
+        Ok(())
+    }
+
+    /// Stops the `startHeartbeat` thread, returns whether one was running.
+    fn stopHeartbeat(&mut self) -> bool {
+        self.heartbeat.take().is_some()
+    }
+
+    /// Writes the counters to `path` for node_exporter's textfile collector.
+    fn writeMetricsTextfile(&self, path: &str) -> PyResult<()> {
+        metrics::write_textfile(path, self.logger.stats()).map_err(|e| self.raise(e))?;
+
+        Ok(())
+    }
+
+    /// Current configuration, in the shape `dictConfig` accepts, with the
+    /// first handler of each kind.
+    fn exportConfig(&self, py: Python) -> PyObject {
+        let mut handlers = Map::new();
+        let tag_filters = self.logger.tag_filters();
+        let set = self.logger.handlers();
+
+        // The tags a handler was added with, the other tag filters are
+        // listed on their own.
+        let handler_tags = |handler: HandlerId, settings: &mut Value| {
+            let id = self.handler_tags.get(&handler);
+            if let Some(tag_filter) = tag_filters.iter().find(|f| Some(&f.id) == id) {
+                settings["require_tags"] = Value::from(tag_filter.require.clone());
+                settings["exclude_tags"] = Value::from(tag_filter.exclude.clone());
+            }
+        };
+
+        let mut insert = |kind: HandlerKind, mut settings: Value| {
+            let id = HandlerId::first(kind);
+            if let Some(name) = set.name(id) {
+                settings["name"] = Value::from(name);
+            }
+            if !set.enabled(id) {
+                settings["enabled"] = Value::from(false);
+            }
+            handler_tags(id, &mut settings);
+            handlers.insert(kind.as_str().to_string(), settings);
+        };
+
+        if set.file.enabled {
+            let path = set.file.template.clone().unwrap_or_else(|| set.file.path());
+            let mut settings = json!({ "path": path, "buffer_size": set.file.buffer_size });
+            if set.file.flush_every > 0 {
+                settings["flush_every_n_lines"] = Value::from(set.file.flush_every);
+            }
+            if set.file.template.is_some() {
+                settings["current_path"] = Value::from(set.file.path());
+            }
+            if let Some(reexpand) = set.file.reexpand {
+                settings["reexpand"] = Value::from(reexpand.as_str());
+            }
+            if let Some(rotation) = &set.file.rotation {
+                let mut group = json!({
+                    "max_bytes": rotation.max_bytes,
+                    "backup_count": rotation.backup_count,
+                    "deletion_policy": rotation.retention.as_str(),
+                });
+                if let Some(period) = rotation.period {
+                    group["period"] = Value::from(period.as_str());
+                }
+                match rotation.retention {
+                    Retention::TotalSize(cap) => group["max_total_size"] = Value::from(cap),
+                    Retention::MaxAge(age) => {
+                        group["max_age_days"] = Value::from(age.as_secs_f64() / DAY)
+                    }
+                    Retention::Count => {}
+                }
+                if let Some(pattern) = &rotation.pattern {
+                    group["pattern"] = Value::from(pattern.as_str());
+                }
+                if let Some(budget) = rotation.budget {
+                    group["retention_total_bytes"] = Value::from(budget);
+                }
+                if let Some(archive) = &rotation.archive {
+                    group["archive_dir"] = Value::from(archive.dir.to_string_lossy());
+                }
+                settings["rotation"] = group;
+            }
+            if let Some(latest) = &set.file.latest {
+                settings["latest_symlink"] = Value::from(latest.to_string_lossy());
+            }
+            if let Some(header) = &set.file.header {
+                settings["header"] = Value::from(header.as_str());
+            }
+            if let Some(line) = &set.file.line {
+                settings["line_format"] = Value::from(line.as_str());
+            }
+            let mut security = Map::new();
+            if let Some(audit) = &set.file.audit {
+                security.insert(String::from("audit"), Value::from(true));
+                security.insert(
+                    String::from("audit_genesis"),
+                    Value::from(audit.genesis.as_str()),
+                );
+            }
+            if let Some(signing) = &set.file.signing {
+                security.insert(String::from("signed"), Value::from(true));
+                if let Some(env) = &signing.env {
+                    security.insert(String::from("hmac_key_env"), Value::from(env.as_str()));
+                }
+            }
+            if set.file.encryption.is_some() {
+                security.insert(String::from("encrypted"), Value::from(true));
+            }
+            if !security.is_empty() {
+                settings["security"] = Value::Object(security);
+            }
+            if let Some(compress) = set.file.compress {
+                settings["compression"] =
+                    json!({ "algorithm": compress.as_str(), "level": compress.level() });
+            }
+            insert(HandlerKind::File, settings);
+        }
+        if let Some(json) = &set.json {
+            let mut settings = json!({
+                "path": json.path,
+                "time_format": json.time_format.as_str(),
+                "preset": json.preset.as_str(),
+                "format": json.codec.as_str(),
+                "buffer_size": json.buffer_size,
+            });
+            if let Preset::Ecs { strict } = json.preset {
+                settings["ecs_strict"] = Value::from(strict);
+            }
+            if json.flush_every > 0 {
+                settings["flush_every_n_lines"] = Value::from(json.flush_every);
+            }
+            insert(HandlerKind::Json, settings);
+        }
+        if let Some(split) = &set.level_split {
+            let mut settings = json!({ "dir": split.dir, "buffer_size": split.buffer_size });
+            if split.flush_every > 0 {
+                settings["flush_every_n_lines"] = Value::from(split.flush_every);
+            }
+            insert(HandlerKind::LevelSplit, settings);
+        }
+        if let Some(fluentd) = &set.fluentd {
+            insert(HandlerKind::Fluentd, Value::Object(fluentd.config()));
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(sqlite) = &set.sqlite {
+            insert(
+                HandlerKind::Sqlite,
+                json!({
+                    "db_path": sqlite.path,
+                    "table": sqlite.table,
+                    "batch_size": sqlite.batch_size,
+                }),
+            );
+        }
+        if let Some(otlp) = &set.otlp {
+            insert(HandlerKind::Otlp, Value::Object(otlp.config()));
+        }
+
+        let console = match &self.console {
+            Some(console) => {
+                let mut settings = json!({
+                    "datefmt": console.datefmt,
+                    "buffered": console.buffered,
+                    "buffer_size": console.buffer_size,
+                    "tqdm_compat": console.tqdm_compat,
+                    "notebook": console.notebook,
+                    "debug_blocks": console.debug_blocks,
+                });
+                if let Some(color) = console.color {
+                    settings["color_scope"] = Value::from(color.as_str());
+                }
+                handler_tags(HandlerId::first(HandlerKind::Console), &mut settings);
+                settings
+            }
+            None => Value::Null,
+        };
+
+        let tag_filters: Vec<Value> = tag_filters
+            .iter()
+            .filter(|tag_filter| !self.handler_tags.values().any(|id| *id == tag_filter.id))
+            .map(|tag_filter| {
+                json!({
+                    "id": tag_filter.id,
+                    "require": tag_filter.require,
+                    "exclude": tag_filter.exclude,
+                    "handler": tag_filter.handler.map(|id| set.label(id)),
+                })
+            })
+            .collect();
+
+        let message_patterns: Vec<Value> = self
+            .logger
+            .message_patterns()
+            .iter()
+            .map(|pattern| {
+                json!({
+                    "id": pattern.id,
+                    "pattern": pattern.regex.as_str(),
+                    "mode": if pattern.include { "include_only" } else { "exclude" },
+                    "handler": pattern.handler.map(|id| set.label(id)),
+                })
+            })
+            .collect();
+        drop(set);
+
+        let filter_rules: Vec<Value> = self
+            .logger
+            .filter_rules()
+            .iter()
+            .map(|rule| {
+                json!({
+                    "target": rule.target,
+                    "level": rule.min_level.as_str(),
+                    "action": if rule.allow { "allow" } else { "deny" },
+                })
+            })
+            .collect();
+
+        let target_levels: Map<String, Value> = self
+            .logger
+            .target_levels()
+            .into_iter()
+            .map(|(target, level)| (target, Value::from(level.as_str())))
+            .collect();
+
+        let mut config = json!({
+            "version": 1,
+            "level": self.logger.level().as_str(),
+            "format": self.logger.format().template.clone(),
+            "otel_context": self.otel_context,
+            "decode_errors": self.decode_errors.as_str(),
+            "json_default": self.json_default.as_str(),
+            "template_extras": self.template_extras,
+            "text_coercion": coercion(&self.logger.format().coercion),
+            "escape_control": self.logger.format().escape_control,
+            "exception_depth": self.exception_capture.depth,
+            "exception_locals": self.exception_capture.locals.is_some(),
+            "defaults": *self.logger.defaults(),
+            "console": console,
+            "handlers": handlers,
+            "message_patterns": message_patterns,
+            "tag_filters": tag_filters,
+            "routes": self.routes(),
+            "filter_rules": filter_rules,
+            "target_levels": target_levels,
+        });
+        if let Some(length) = self.exception_capture.locals {
+            config["locals_length"] = Value::from(length);
+        }
+        if let Some((_, kinds, no_scrub)) = &self.pii_scrubbing {
+            let kinds: Vec<&str> = kinds.iter().map(|kind| kind.as_str()).collect();
+            config["pii_scrubbing"] = json!({ "kinds": kinds, "no_scrub": no_scrub });
+        }
+        if let Some(schema) = self.logger.schema() {
+            config["schema"] = json!({
+                "schema": schema.schema(),
+                "on_violation": schema.on_violation.as_str(),
+            });
+        }
+        if let Some((level, code)) = self.exit_on {
+            config["exit_on_level"] = Value::from(level.as_str());
+            config["exit_code"] = Value::from(code);
+        }
+
+        value::to_py(py, &config)
+    }
+
+    /// A logger whose records all carry `fields`, sharing this one's
+    /// handlers and level, e.g. `log = soda.bind(request_id=rid)`.
+    ///
+    /// A callable value is a lazy field, `bind(queue_depth=queue.qsize)`:
+    /// it's called with no arguments for each record that passes the level
+    /// and filters and goes to a handler writing that field, the console only
+    /// when its template shows it. Processors, filters and the schema see
+    /// the record without it. What it raises is logged as
+    /// `"<error: ...>"` and counted in `stats()["field_errors"]`.
+    #[args(fields = "**")]
+    fn bind(slf: &PyCell<Soda>, fields: Option<&PyDict>) -> BoundLogger {
+        BoundLogger::new(slf.into(), fields.map(Bound::from_dict).unwrap_or_default())
+    }
+
+    /// The logger called `<name>.<suffix>`, the way `logging.getLogger`'s
+    /// `getChild` works. Created on first use, it writes through this
+    /// logger's handlers and inherits its level until given one, and
+    /// `getLogger` hands the same one out by its full name.
+    fn getChild(slf: &PyCell<Soda>, suffix: &str) -> PyResult<Py<Soda>> {
+        loggers::child(slf.py(), &slf.borrow(), suffix)
+    }
+
+    /// The name of the level records are checked against: this logger's,
+    /// or when it has none the closest parent's with one, `"NOTSET"` when
+    /// none has.
+    fn getEffectiveLevel(&self) -> &'static str {
+        self.logger.effective_level().as_str()
+    }
+
+    /// Times a block, `with soda.timeit("load"):` logs `load started` and
+    /// then `load finished in 0.123s` at `level`, with the seconds in an
+    /// `elapsed` field.
+    #[args(level = "\"INFO\"")]
+    fn timeit(slf: &PyCell<Soda>, label: &str, level: &str) -> PyResult<Timer> {
+        Ok(Timer::new(slf.into(), None, label, level_name(level)?))
+    }
+
+    /// Starts a span, logging a begin record right away. The span's `end()`,
+    /// or leaving it as a `with` block, logs the end record with the time it
+    /// was open, and `event(message)` records in between. Record fields are
+    /// described on `Span`.
+    #[args(level = "\"INFO\"")]
+    fn start_span(slf: &PyCell<Soda>, name: &str, level: &str) -> PyResult<Py<Span>> {
+        Span::start(slf.py(), slf.into(), name, level_name(level)?)
+    }
+
+    /// Logs what differs between the dicts `before` and `after`, rather than
+    /// the whole of either: `logChange("config", old, new)` logs `config
+    /// changed: timeout=30->60, +retries=3, -debug=true` with a `label`
+    /// field and a `changes` one mapping each key to its `old` and `new`
+    /// value, one of them missing for a key added or removed. Only the top
+    /// level keys are compared. Nothing is logged when they're equal,
+    /// returns whether something was.
+    #[args(level = "\"DEBUG\"")]
+    fn logChange(
+        &self,
+        py: Python,
+        label: &str,
+        before: &PyDict,
+        after: &PyDict,
+        level: &str,
+    ) -> PyResult<bool> {
+        let level = level_name(level)?;
+        let before = value::convert_dict(before, self.json_default).map_err(|e| self.raise(e))?;
+        let after = value::convert_dict(after, self.json_default).map_err(|e| self.raise(e))?;
+
+        let mut changes = Map::new();
+        let mut summary = Vec::new();
+        for (key, old) in &before {
+            match after.get(key) {
+                Some(new) if new == old => {}
+                Some(new) => {
+                    changes.insert(key.clone(), json!({ "old": old, "new": new }));
+                    summary.push(format!("{}={}->{}", key, old, new));
+                }
+                None => {
+                    changes.insert(key.clone(), json!({ "old": old }));
+                    summary.push(format!("-{}={}", key, old));
+                }
+            }
+        }
+        for (key, new) in after.iter().filter(|(key, _)| !before.contains_key(*key)) {
+            changes.insert(key.clone(), json!({ "new": new }));
+            summary.push(format!("+{}={}", key, new));
+        }
+
+        if changes.is_empty() || !self.wanted(level, None)? {
+            return Ok(false);
+        }
+
+        let message = format!("{} changed: {}", label, summary.join(", "));
+        let mut record = self.record(
+            level,
+            PyUnicode::new(py, &message),
+            PyTuple::empty(py),
+            None,
+            None,
+        )?;
+        record
+            .extras
+            .insert(String::from("label"), Value::from(label));
+        record
+            .extras
+            .insert(String::from("changes"), Value::Object(changes));
+        self.emit(record)?;
+
+        Ok(true)
+    }
+
+    /// Replaces `sys.stdout` and `sys.stderr` so what's `print()`ed is
+    /// logged, a record per line at INFO and ERROR with a `stream` field of
+    /// `"stdout"` or `"stderr"`. With `prefix_with_timestamp` the console
+    /// prints those records like any other, in the `setFormat` format,
+    /// otherwise lines are printed as they were and the records only go to
+    /// the other handlers. `restorePrint` puts the streams back.
+    #[args(prefix_with_timestamp = "true")]
+    fn redirectPrint(slf: &PyCell<Soda>, prefix_with_timestamp: bool) -> PyResult<()> {
+        print::redirect(slf.py(), slf.into(), prefix_with_timestamp)
+    }
+
+    /// Undoes `redirectPrint`, logging any unfinished line first. Returns
+    /// whether print was redirected.
+    fn restorePrint(&self, py: Python) -> PyResult<bool> {
+        print::restore(py)
+    }
+
+    /// Replaces `sys.excepthook` so an uncaught exception is logged at
+    /// CRITICAL with its traceback, and the handlers flushed, before the
+    /// program ends. With `chain` the previous hook runs after and prints
+    /// the traceback as usual, without it the log is the only trace. A
+    /// `KeyboardInterrupt` isn't logged and always goes to the previous
+    /// hook.
+    #[args(chain = "true")]
+    fn installExcepthook(slf: &PyCell<Soda>, chain: bool) -> PyResult<()> {
+        excepthook::install(slf.py(), slf.into(), chain)
+    }
+
+    /// Has each of the `signals`, `["SIGTERM", "SIGINT"]` by default, write
+    /// out what every logger's handlers buffer and log a WARNING record
+    /// saying so, `"received SIGTERM, flushed 12 records"`, flushed as well,
+    /// before going on as it would have: to the handler that was there, or
+    /// ending the process by the signal for the default one. A signal coming
+    /// in while a record is written flushes what it can within a moment
+    /// rather than wait on it, and the record saying so is dropped.
+    #[args(signals = "None")]
+    fn installSignalFlush(slf: &PyCell<Soda>, signals: Option<Vec<String>>) -> PyResult<()> {
+        let signals =
+            signals.unwrap_or_else(|| vec![String::from("SIGTERM"), String::from("SIGINT")]);
+        signals::install(slf.py(), slf.into(), signals)
+    }
+
+    /// Binds `fields` for the duration of a `with` block, records logged in
+    /// it carry them whichever logger they go through. Nested blocks shadow
+    /// the outer values until they exit.
+    #[args(fields = "**")]
+    fn contextualize(&self, py: Python, fields: Option<&PyDict>) -> Contextualized {
+        Contextualized::new(fields.map_or_else(|| PyDict::new(py).into(), |f| f.into()))
+    }
+
+    /// Binds fields to the current `contextvars` context, every record logged
+    /// from it (and from tasks it spawns) carries them. Returns the token
+    /// `soda.context.reset` takes.
+    #[args(fields = "**")]
+    fn bind_contextvar(&self, py: Python, fields: Option<&PyDict>) -> PyResult<PyObject> {
+        context::bind(py, fields)
+    }
+
+    /// Binds a correlation id (a new UUIDv4 unless `value` is given) to the
+    /// current context under `field` and returns it, e.g. to echo it back in
+    /// a response header.
+    #[args(field = "\"request_id\"", value = "None")]
+    fn new_correlation_id(
+        &mut self,
+        py: Python,
+        field: &str,
+        value: Option<String>,
+    ) -> PyResult<String> {
+        let id = value.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let fields = PyDict::new(py);
+        fields.set_item(field, &id)?;
+        context::bind(py, Some(fields))?;
+
+        self.correlation_field = field.to_string();
+
+        Ok(id)
+    }
+
+    /// The correlation id bound in the current context, if any.
+    fn correlation_id(&self, py: Python) -> PyResult<PyObject> {
+        context::get(py, &self.correlation_field)
+    }
+
+    fn clear_correlation_id(&self, py: Python) -> PyResult<()> {
+        context::unbind(py, &[&self.correlation_field])
+    }
+
+    /// Writes out anything the handlers are still buffering.
+    fn flush(&self) {
+        self.logger.flush();
+    }
+
+    /// Writes out what the handler called `name` is buffering and leaves
+    /// the others' buffers be, e.g. to have the audit file on disk without
+    /// waiting on a slow handler. Takes the names `setHandlerEnabled` does.
+    fn flushHandler(&self, name: &str) -> PyResult<()> {
+        if !self.logger.flush_handler(name) {
+            return Err(PyValueError::new_err(format!("unknown handler {:?}", name)));
+        }
+
+        Ok(())
+    }
+
+    /// Every `add*Handler` method takes a `name`, which `setHandlerEnabled`
+    /// accepts besides the handler's kind (`"file"`, `"json"`, ...), and a
+    /// `filter` for that handler alone, see `addFilter`, as well as the
+    /// `require_tags` and `exclude_tags` of a tag filter, see `addTagFilter`.
+    /// Adding the handler again replaces its filters.
+    ///
+    /// A file, JSON, level split or SQLite handler added under a new name
+    /// is one more, next to the others of its kind, the kind's name
+    /// referring to the first. Added again under a name it has, or with no
+    /// name in place of the first, it replaces that one. A name another
+    /// kind's handler has raises `ValueError`.
+    ///
+    /// The file handlers also take a `buffer_size`, the bytes they hold back
+    /// until `flush` or the buffer fills up, `0` writing each record as it
+    /// comes in, and with it a `flush_every_n_lines` to write the buffer out
+    /// after that many records too.
+    ///
+    /// Writes each record's message, or the `line_format` rendering of it,
+    /// a template as `setFormat` takes, to `path` in a single append, so other
+    /// processes' lines are never split. Date placeholders in the path, as in
+    /// `"logs/{date}.log"`, are filled in once, or daily with
+    /// `reexpand="daily"`. A `header` line, which may use `{time}`, `{pid}`,
+    /// `{version}` and `{path}`, starts each file opened, and
+    /// `latest_symlink=True` keeps a link to the one written.
+    /// `rotation`, `security` and `compression` take a `FileRotation`,
+    /// `FileSecurity` and `FileCompression`.
+    #[args(
+        name = "None",
+        filter = "None",
+        buffer_size = "0",
+        require_tags = "None",
+        exclude_tags = "None",
+        flush_every_n_lines = "0",
+        line_format = "None",
+        header = "None",
+        reexpand = "None",
+        latest_symlink = "None",
+        rotation = "None",
+        security = "None",
+        compression = "None"
+    )]
+    fn addFileHandler(
+        &mut self,
+        py: Python,
+        path: String,
+        name: Option<&str>,
+        filter: Option<PyObject>,
+        buffer_size: usize,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+        flush_every_n_lines: usize,
+        line_format: Option<String>,
+        header: Option<String>,
+        reexpand: Option<&str>,
+        latest_symlink: Option<&PyAny>,
+        rotation: Option<PyRef<FileRotation>>,
+        security: Option<PyRef<FileSecurity>>,
+        compression: Option<PyRef<FileCompression>>,
+    ) -> PyResult<()> {
+        let flush_every = buffered_lines(buffer_size, flush_every_n_lines)?;
+        let reexpand = match reexpand {
+            Some(name) => Some(Reexpand::parse(name).ok_or_else(|| {
+                PyValueError::new_err(format!("unknown reexpand {:?}, only \"daily\"", name))
+            })?),
+            None => None,
+        };
+        if reexpand.is_some() && !file::has_placeholders(&path) {
+            return Err(PyValueError::new_err(
+                "reexpand needs a path with date placeholders",
+            ));
+        }
+
+        let rotation = rotation.map(|rotation| Rotation {
+            on_rotation: rotation.on_rotation.as_ref().map(|func| {
+                processor::on_rotation(func.clone_ref(py), Arc::clone(self.logger.stats()))
+            }),
+            ..rotation.rotation.clone()
+        });
+        if reexpand.is_some() && rotation.as_ref().is_some_and(|r| r.period.is_some()) {
+            return Err(PyValueError::new_err(
+                "a file is either rotated or reexpanded daily, not both",
+            ));
+        }
+        if let Some(archive) = rotation.as_ref().and_then(|r| r.archive.as_ref()) {
+            std::fs::create_dir_all(&archive.dir)?;
+        }
+
+        let (audit, signing, secret) = match security {
+            Some(security) => (
+                security.audit.clone(),
+                security.signing.clone(),
+                security.secret.clone(),
+            ),
+            None => (None, None, None),
+        };
+        if reexpand.is_some() && secret.is_some() {
+            return Err(PyValueError::new_err(
+                "an encrypted file can't be reexpanded",
+            ));
+        }
+        let compress = compression.map(|compression| compression.compress);
+        if compress.is_some() && (audit.is_some() || signing.is_some() || secret.is_some()) {
+            return Err(PyValueError::new_err(
+                "a compressed file can't be audited, signed or encrypted",
+            ));
+        }
+
+        let options = FileOptions {
+            buffer_size,
+            flush_every,
+            rotation,
+            header,
+            audit,
+            signing,
+            secret,
+            reexpand,
+            compress,
+            latest: latest_link(&path, latest_symlink)?,
+            line: line_format,
+        };
+        let id = self
+            .logger
+            .add_file_handler(name, &path, options)
+            .map_err(|e| self.raise(handler_error(e)))?;
+        self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+        Ok(())
+    }
+
+    /// When the file handler last rotated, `{"time": ..., "backup": ...,
+    /// "path": ...}` with the time in seconds since the epoch, the backup
+    /// kept, if any, and the file written to after, `None` until it does.
+    fn lastRotation(&self, py: Python) -> PyObject {
+        let rotated = match self.logger.handlers().file.last_rotation() {
+            Some(rotated) => rotated,
+            None => return py.None(),
+        };
+        let time = rotated.time.timestamp() as f64
+            + f64::from(rotated.time.timestamp_subsec_nanos()) / 1e9;
+        let backup = rotated
+            .backup
+            .map(|backup| backup.to_string_lossy().into_owned());
+
+        value::to_py(
+            py,
+            &json!({ "time": time, "backup": backup, "path": rotated.path }),
+        )
+    }
+
+    /// Rotates the file handler's file now, the way its `FileRotation` would,
+    /// with the same `deletion_policy` and `on_rotation`,
+    /// and returns the backup's path, `None` when the policy deleted it right
+    /// away. A file without rotation goes to `<path>.<YYYYmmdd-HHMMSS>` and
+    /// is kept. Records logged meanwhile go to the file before or after,
+    /// none is lost or split. An empty file isn't rotated, with a
+    /// `UserWarning`, and `None` returned.
+    fn rotate(&self, py: Python) -> PyResult<Option<String>> {
+        if !self.logger.handlers().file.enabled {
+            return Err(PyValueError::new_err("there's no file handler to rotate"));
+        }
+
+        match self.logger.rotate_file().map_err(|e| self.raise(e))? {
+            Some(backup) => Ok(backup.map(|backup| backup.to_string_lossy().into_owned())),
+            None => {
+                let path = self.logger.handlers().file.path();
+                let message = format!("{} is empty, not rotated", path);
+                let category = py.import("builtins")?.getattr("UserWarning")?;
+                PyErr::warn(py, category, &message, 1)?;
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// The last `n` lines of the file handler's file, read back from its
+    /// end, with what's buffered written out first. A compressed or
+    /// encrypted file can't be read back as lines and raises `ValueError`.
+    #[args(n = "10")]
+    fn tail(&self, n: usize) -> PyResult<Vec<String>> {
+        self.readable_file()?;
+
+        self.logger
+            .handlers()
+            .file
+            .tail(n)
+            .map_err(|e| self.raise(e))
+    }
+
+    /// Calls `callback` with each line written to the file handler's file
+    /// from now on, looking for more every `poll_interval` seconds from a
+    /// background thread. A rotation is noticed, the lines left in the old
+    /// file passed first, and so does the move to the next day's file of a
+    /// path with date placeholders. Should `callback` raise, the error is
+    /// printed and the next line goes to it all the same. Runs until
+    /// `stopFollow` or exit, following again replaces the last.
+    #[args(poll_interval = "1.0")]
+    fn follow(&mut self, callback: PyObject, poll_interval: f64) -> PyResult<()> {
+        if !(poll_interval > 0.0 && poll_interval.is_finite()) {
+            return Err(PyValueError::new_err(
+                "poll_interval must be a positive number",
+            ));
+        }
+        self.readable_file()?;
+
+        self.stopFollow();
+        self.follower = Some(Follower::start(
+            self.logger.file_path(),
+            callback,
+            poll_interval,
+        )?);
+
+        Ok(())
+    }
+
+    /// Stops the `follow` thread, returns whether one was running.
+    fn stopFollow(&mut self) -> bool {
+        self.follower.take().is_some()
+    }
+
+    /// Calls `func` with each record as a `LogRecord`, indexing it reads
+    /// the fields the JSON handler writes plus `time` in seconds since the
+    /// epoch, `to_dict()` gives them all as a dict. An exception it raises is
+    /// printed to stderr and doesn't stop the record.
+    ///
+    /// A record goes to the handlers in the same order every time: the
+    /// console, then the other handlers in the order they were first added,
+    /// one added again keeping its place, then the callbacks in theirs.
+    ///
+    /// A record logged while one is being handled, by `func` or a processor,
+    /// filter or hook, would be handled and logged again without end, so
+    /// it's dropped instead, with a warning on stderr the first time.
+    fn addCallback(&self, func: PyObject) {
+        let format = self.logger.shared_format();
+
+        self.logger.add_callback(move |record| {
+            Python::with_gil(|py| {
+                let called = Py::new(py, LogRecord::new(py, record.clone(), &format))
+                    .and_then(|log_record| func.call1(py, (log_record,)));
+
+                if let Err(e) = called {
+                    e.print(py);
+                }
+            })
+        });
+    }
+
+    /// Adds `func` to the processors records go through before any handler
+    /// sees them, in the order they were added. It is called with the
+    /// `LogRecord` and returns it, changed as it likes, a dict of the
+    /// `message`, `level`, `name`, `extras` or `time` (seconds since the
+    /// epoch) to change, or `None` to drop the record. Exceptions are printed
+    /// and counted in `stats()`, the record carries on unchanged. A record
+    /// below the logger's level never gets to them.
+    fn addProcessor(&mut self, py: Python, func: PyObject) {
+        let id = self.logger.add_processor(processor::processor(
+            func.clone_ref(py),
+            self.logger.shared_format(),
+            Arc::clone(self.logger.stats()),
+        ));
+        self.processors.push((id, func));
+    }
+
+    /// Removes a processor added with `addProcessor`, returns whether it was
+    /// there.
+    fn removeProcessor(&mut self, func: PyObject) -> bool {
+        let position = self
+            .processors
+            .iter()
+            .position(|(_, added)| added.as_ptr() == func.as_ptr());
+
+        match position {
+            Some(position) => {
+                let (id, _) = self.processors.remove(position);
+                self.logger.remove_processor(id)
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a processor replacing the personal data of the given `kinds`
+    /// found in the message, fields (nested ones too), event and exception
+    /// with `<kind:hash>`, `hash` being 8 hex characters of the value's
+    /// SHA-256, so the same value can be followed across records without
+    /// being shown. The kinds are `"email"`, `"phone"`, `"credit_card"`,
+    /// Luhn checked, and `"ipv4"`, all of them by default. Fields named in
+    /// `no_scrub` are left alone. `stats()` counts the values replaced.
+    /// Enabling it again replaces the settings.
+    #[args(kinds = "None", no_scrub = "None")]
+    fn enablePiiScrubbing(
+        &mut self,
+        kinds: Option<Vec<&str>>,
+        no_scrub: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        let kinds = match kinds {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    PiiKind::parse(name).ok_or_else(|| {
+                        PyValueError::new_err(format!("unknown kind of personal data {:?}", name))
+                    })
+                })
+                .collect::<PyResult<Vec<_>>>()?,
+            None => PII_KINDS.to_vec(),
+        };
+        let no_scrub = no_scrub.unwrap_or_default();
+
+        self.disablePiiScrubbing();
+        let scrubber = Scrubber::new(&kinds, no_scrub.clone(), Arc::clone(self.logger.stats()));
+        let id = self.logger.add_processor(move |mut record| {
+            scrubber.scrub(&mut record);
+            Some(record)
+        });
+        self.pii_scrubbing = Some((id, kinds, no_scrub));
+
+        Ok(())
+    }
+
+    /// Removes the `enablePiiScrubbing` processor, returns whether there was
+    /// one.
+    fn disablePiiScrubbing(&mut self) -> bool {
+        match self.pii_scrubbing.take() {
+            Some((id, _, _)) => self.logger.remove_processor(id),
+            None => false,
+        }
+    }
+
+    /// Checks every record, once the processors ran, against the JSON Schema
+    /// `schema` (a dict) as the JSON handler would write it, fields,
+    /// `message`, `level` and `name`. A record that doesn't conform is, by
+    /// `on_violation`, dropped with a warning on stderr (`"drop"`), emitted
+    /// with a `schema_violations` field listing what's wrong (`"flag"`), or
+    /// not emitted and the call that logged it raises `ValueError`
+    /// (`"raise"`). `stats()` counts them. Setting one replaces the last.
+    #[args(on_violation = "\"drop\"")]
+    fn setSchema(&self, schema: &PyAny, on_violation: &str) -> PyResult<()> {
+        let on_violation = OnViolation::parse(on_violation).ok_or_else(|| {
+            PyValueError::new_err(format!("unknown on_violation {:?}", on_violation))
+        })?;
+        let schema = Schema::new(value::from_py(schema), on_violation)
+            .map_err(|e| PyValueError::new_err(format!("invalid schema: {}", e)))?;
+
+        self.logger.set_schema(Some(schema));
+
+        Ok(())
+    }
+
+    /// Stops checking records against the `setSchema` schema, returns
+    /// whether there was one.
+    fn clearSchema(&self) -> bool {
+        let schema = self.logger.schema().is_some();
+        self.logger.set_schema(None);
+
+        schema
+    }
+
+    /// The processors, in the order they run.
+    fn getProcessors(&self, py: Python) -> Vec<PyObject> {
+        self.processors
+            .iter()
+            .map(|(_, func)| func.clone_ref(py))
+            .collect()
+    }
+
+    /// Adds `func` to the filters records have to pass, called like a
+    /// processor with the `LogRecord` and keeping it when it returns a true
+    /// value. With `handler`, a name `setHandlerEnabled` accepts, it only
+    /// applies to that handler, e.g. to keep healthchecks out of the console
+    /// but not the file. Filters run after the processors, an exception is
+    /// printed and counted in `stats()` and keeps the record. Returns the id
+    /// `removeFilter` takes.
+    #[args(handler = "None")]
+    fn addFilter(&self, func: PyObject, handler: Option<&str>) -> PyResult<u64> {
+        let kind = self.filter_handler(handler)?;
+
+        Ok(self.logger.add_filter(
+            kind,
+            processor::filter(
+                func,
+                self.logger.shared_format(),
+                Arc::clone(self.logger.stats()),
+            ),
+        ))
+    }
+
+    /// Removes a filter by the id `addFilter` returned, returns whether
+    /// there was one.
+    fn removeFilter(&mut self, id: u64) -> bool {
+        self.handler_filters.retain(|_, filter| *filter != id);
+        self.handler_tags.retain(|_, filter| *filter != id);
+        self.logger.remove_filter(id)
+    }
+
+    /// Calls `func` with the `LogRecord` of every record that passed the
+    /// level, processors and filters, right before the handlers write it, the last
+    /// chance to change it. Hooks run in the order they were added, an
+    /// exception is printed and counted in `stats()` and leaves the record as
+    /// it was. Returns the id `removeHook` takes.
+    fn onBeforeEmit(&self, func: PyObject) -> u64 {
+        self.logger.on_before_emit(processor::before_emit(
+            func,
+            self.logger.shared_format(),
+            Arc::clone(self.logger.stats()),
+        ))
+    }
+
+    /// Calls `func` with the `LogRecord` once the handlers are done with it
+    /// and a dict of every handler that got it, `{"file": True, ...}`, to
+    /// whether it wrote the record, in the order they got it. Exceptions are printed and counted in
+    /// `stats()`. Returns the id `removeHook` takes.
+    fn onAfterEmit(&self, func: PyObject) -> u64 {
+        self.logger.on_after_emit(processor::after_emit(
+            func,
+            self.logger.shared_format(),
+            Arc::clone(self.logger.stats()),
+        ))
+    }
+
+    /// Removes a hook by the id `onBeforeEmit` or `onAfterEmit` returned,
+    /// returns whether there was one.
+    fn removeHook(&self, id: u64) -> bool {
+        self.logger.remove_hook(id)
+    }
+
+    /// Keeps the records tagged with none of `exclude` and, given `require`,
+    /// with at least one of those, from every handler or, with `handler`,
+    /// from that one alone. Records are tagged with `tags=["cache", "perf"]`
+    /// on the level methods, `bind()` and `contextualize()`. Returns the id
+    /// `removeFilter` takes.
+    #[args(require = "None", exclude = "None", handler = "None")]
+    fn addTagFilter(
+        &self,
+        require: Option<Vec<String>>,
+        exclude: Option<Vec<String>>,
+        handler: Option<&str>,
+    ) -> PyResult<u64> {
+        let kind = self.filter_handler(handler)?;
+
+        Ok(self.logger.add_tag_filter(
+            kind,
+            require.unwrap_or_default(),
+            exclude.unwrap_or_default(),
+        ))
+    }
+
+    /// Replaces the filter rules, dicts of a logger name `target` prefix, a
+    /// `level` and an `action`, `"allow"` or `"deny"`. A record is let
+    /// through or dropped by the first rule whose `target` starts its
+    /// logger's name and whose `level` it has at least, a record no rule
+    /// matches is let through.
+    fn setFilterRules(&self, rules: Vec<&PyDict>) -> PyResult<()> {
+        let mut parsed = Vec::new();
+
+        for settings in rules {
+            let mut rule = FilterRule {
+                target: String::new(),
+                min_level: Level::NOTSET,
+                allow: true,
+            };
+            let mut action = None;
+
+            for (key, value) in settings.iter() {
+                match key.extract::<&str>()? {
+                    "target" => rule.target = value.extract()?,
+                    "level" => rule.min_level = level_name(value.extract()?)?,
+                    "action" => action = Some(value.extract::<&str>()?),
+                    other => {
+                        return Err(PyValueError::new_err(format!(
+                            "unknown filter rule key {:?}",
+                            other
+                        )))
+                    }
+                }
+            }
+            rule.allow = match action {
+                Some("allow") => true,
+                Some("deny") => false,
+                Some(other) => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown filter rule action {:?}",
+                        other
+                    )))
+                }
+                None => return Err(PyValueError::new_err("a filter rule needs an action")),
+            };
+
+            parsed.push(rule);
+        }
+
+        self.logger.set_filter_rules(parsed);
+
+        Ok(())
+    }
+
+    /// `setFilterRules` with the `[[rule]]` tables of a TOML file, e.g.
+    ///
+    /// ```toml
+    /// [[rule]]
+    /// target = "urllib3"
+    /// level = "WARNING"
+    /// action = "allow"
+    ///
+    /// [[rule]]
+    /// target = "urllib3"
+    /// action = "deny"
+    /// ```
+    ///
+    /// keeps urllib3's warnings and errors only. It is read with `tomllib`,
+    /// or `tomli` before Python 3.11.
+    fn loadFilterRules(&self, py: Python, path: &str) -> PyResult<()> {
+        let text = std::fs::read_to_string(path)?;
+        let toml = py.import("tomllib").or_else(|_| py.import("tomli"))?;
+        let document: &PyDict = toml.call_method1("loads", (text,))?.downcast()?;
+
+        self.setFilterRules(item(document, "rule")?.unwrap_or_default())
+    }
+
+    /// Drops the records of the logger called `target`, and the loggers
+    /// under it, `"a.b"` under `"a"`, below `level`, the most specific
+    /// target set winning, say `setTargetLevel("urllib3", "WARNING")` to
+    /// quiet a chatty library. It holds for this logger and those
+    /// `getChild` gives, and `None` takes the target's level away again.
+    fn setTargetLevel(&self, target: &str, level: Option<&str>) -> PyResult<()> {
+        let level = level.map(level_name).transpose()?;
+        self.logger.set_target_level(target, level);
+
+        Ok(())
+    }
+
+    /// Drops the records whose message, once formatted, matches the regex
+    /// `pattern`, from every handler or, with `handler`, from that one alone.
+    /// A record matching any exclude pattern is dropped. Returns the id
+    /// `removeFilter` takes, an invalid pattern raises a `ValueError`.
+    #[args(handler = "None")]
+    fn excludeMessages(&self, pattern: &str, handler: Option<&str>) -> PyResult<u64> {
+        let kind = self.filter_handler(handler)?;
+
+        self.logger
+            .exclude_messages(kind, pattern)
+            .map_err(|e| PyValueError::new_err(format!("invalid pattern {:?}: {}", pattern, e)))
+    }
+
+    /// Keeps only the records whose message matches the regex `pattern`, or
+    /// another include-only pattern given for the same handlers. Otherwise
+    /// the same as `excludeMessages`.
+    #[args(handler = "None")]
+    fn includeOnlyMessages(&self, pattern: &str, handler: Option<&str>) -> PyResult<u64> {
+        let kind = self.filter_handler(handler)?;
+
+        self.logger
+            .include_only_messages(kind, pattern)
+            .map_err(|e| PyValueError::new_err(format!("invalid pattern {:?}: {}", pattern, e)))
+    }
+
+    /// Sends records to specific handlers, e.g.
+    /// `addRoute(match={"name_prefix": "audit."}, handlers=["audit_file"],
+    /// exclusive=True)`. `match` takes a `name_prefix` for the logger name
+    /// and a `min_level`, a record has to satisfy both, and `handlers` names
+    /// the handlers the way `setHandlerEnabled` does.
+    ///
+    /// Routes are tried in the order they were added and a record goes to
+    /// the handlers of each one it matches, stopping at the first exclusive
+    /// one. Handlers routes send records to only get those, the remaining
+    /// ones get what no exclusive route matched. Returns the id
+    /// `removeRoute` takes.
+    ///
+    /// A single record can be sent to some handlers alone with `handlers=`
+    /// on the level methods, `info("access granted", handlers=["audit"])`,
+    /// the console only getting it when it's listed.
+    #[args(r#match = "None", handlers = "None", exclusive = "false")]
+    fn addRoute(
+        &self,
+        r#match: Option<&PyDict>,
+        handlers: Option<Vec<&str>>,
+        exclusive: bool,
+    ) -> PyResult<u64> {
+        let mut route = Route {
+            name_prefix: None,
+            min_level: None,
+            handlers: Vec::new(),
+            exclusive,
+        };
+
+        if let Some(conditions) = r#match {
+            for (key, value) in conditions.iter().filter(|(_, value)| !value.is_none()) {
+                match key.extract::<&str>()? {
+                    "name_prefix" => route.name_prefix = Some(value.extract()?),
+                    "min_level" => route.min_level = Some(level_name(value.extract()?)?),
+                    other => {
+                        return Err(PyValueError::new_err(format!(
+                            "unknown route condition {:?}",
+                            other
+                        )))
+                    }
+                }
+            }
+        }
+
+        let set = self.logger.handlers();
+        for name in handlers.unwrap_or_default() {
+            let id = set
+                .id(name)
+                .ok_or_else(|| PyValueError::new_err(format!("unknown handler {:?}", name)))?;
+            route.handlers.push(id);
+        }
+        drop(set);
+
+        Ok(self.logger.add_route(route))
+    }
+
+    /// The routes in the order they are tried, as `addRoute` takes them
+    /// plus their `id`.
+    fn getRoutes(&self, py: Python) -> PyObject {
+        value::to_py(py, &Value::Array(self.routes()))
+    }
+
+    /// Removes a route by the id `addRoute` returned, returns whether there
+    /// was one.
+    fn removeRoute(&self, id: u64) -> bool {
+        self.logger.remove_route(id)
+    }
+
+    /// Turns a handler off, or back on, without reconfiguring the rest, e.g.
+    /// `setHandlerEnabled("debug_file", True)` while looking into an issue.
+    /// `"console"` refers to this logger's console output.
+    fn setHandlerEnabled(&mut self, name: &str, enabled: bool) -> PyResult<()> {
+        if !self.logger.handlers().set_enabled(name, enabled) {
+            return Err(PyValueError::new_err(format!("unknown handler {:?}", name)));
+        }
+
+        Ok(())
+    }
+
+    /// Writes records as JSON lines to `path`, or to stdout when no path is
+    /// given. `time_format` is one of `"rfc3339"`, `"epoch"` (float seconds)
+    /// or `"epoch_ms"` (integer milliseconds).
+    ///
+    /// `json_preset` lays the fields out for a platform that parses the
+    /// output, `"gcp"` for Google Cloud Logging, `"aws_lambda"` or `"ecs"`
+    /// for the Elastic Common Schema. With `ecs_strict=False` the ECS preset
+    /// writes extras as custom fields rather than string `labels`.
+    ///
+    /// `format` picks the encoding, `"json"` lines, or `"msgpack"` and
+    /// `"cbor"` documents back to back.
+    #[args(
+        path = "None",
+        time_format = "\"rfc3339\"",
+        json_preset = "\"default\"",
+        ecs_strict = "true",
+        name = "None",
+        format = "\"json\"",
+        filter = "None",
+        buffer_size = "0",
+        require_tags = "None",
+        exclude_tags = "None",
+        flush_every_n_lines = "0"
+    )]
+    fn addJsonHandler(
+        &mut self,
+        path: Option<String>,
+        time_format: &str,
+        json_preset: &str,
+        ecs_strict: bool,
+        name: Option<&str>,
+        format: &str,
+        filter: Option<PyObject>,
+        buffer_size: usize,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+        flush_every_n_lines: usize,
+    ) -> PyResult<()> {
+        let flush_every = buffered_lines(buffer_size, flush_every_n_lines)?;
+        let codec = Codec::parse(format).ok_or_else(|| {
+            self.raise(PyValueError::new_err(format!(
+                "unsupported format {:?}",
+                format
+            )))
+        })?;
+        let time_format = TimeFormat::parse(time_format).ok_or_else(|| {
+            self.raise(PyValueError::new_err(format!(
+                "unknown time_format {:?}",
+                time_format
+            )))
+        })?;
+        let preset = match Preset::parse(json_preset) {
+            Some(Preset::Ecs { .. }) => Preset::Ecs { strict: ecs_strict },
+            Some(preset) => preset,
+            None => {
+                return Err(self.raise(PyValueError::new_err(format!(
+                    "unknown json_preset {:?}",
+                    json_preset
+                ))))
+            }
+        };
+
+        let mut json = match path {
+            Some(path) => JsonLogger::file(&path, time_format, preset, codec, buffer_size)
+                .map_err(|e| self.raise(e))?,
+            None => JsonLogger::stdout(time_format, preset, codec, buffer_size),
+        };
+        json.flush_every = flush_every;
+        let id = self.place(HandlerKind::Json, name)?;
+        // The handler replaced writes out what it holds.
+        let previous = {
+            let mut handlers = self.logger.handlers();
+            handlers.set_name(id, name);
+            handlers.put_json(id, json)
+        };
+        drop(previous);
+        self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+        Ok(())
+    }
+
+    /// Writes each record to `dir/<level>.log`, `error.log`, `info.log` and
+    /// so on, creating `dir` if needed.
+    #[args(
+        name = "None",
+        filter = "None",
+        buffer_size = "0",
+        require_tags = "None",
+        exclude_tags = "None",
+        flush_every_n_lines = "0"
+    )]
+    fn addLevelSplitFileHandler(
+        &mut self,
+        dir: &str,
+        name: Option<&str>,
+        filter: Option<PyObject>,
+        buffer_size: usize,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+        flush_every_n_lines: usize,
+    ) -> PyResult<()> {
+        let flush_every = buffered_lines(buffer_size, flush_every_n_lines)?;
+        let mut split = LevelSplitLogger::new(dir, buffer_size).map_err(|e| self.raise(e))?;
+        split.flush_every = flush_every;
+        let id = self.place(HandlerKind::LevelSplit, name)?;
+        let previous = {
+            let mut handlers = self.logger.handlers();
+            handlers.set_name(id, name);
+            handlers.put_split(id, split)
+        };
+        drop(previous);
+        self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+        Ok(())
+    }
+
+    /// Inserts each record as a row of the SQLite database at `db_path`, in
+    /// `table`, created if it's missing with the columns `id`, `timestamp`
+    /// (RFC 3339), `level`, `target` (the logger's name), `message` and
+    /// `extra`, the record's extras as a JSON object for `json_extract`.
+    ///
+    /// With a `batch_size`, rows are held until that many are waiting, or
+    /// until `flush`, then inserted in one transaction, much faster than
+    /// one each. The database is put in WAL mode, so it can be queried while
+    /// records come in, and another process writing to it is waited on for
+    /// up to 5 seconds.
+    #[args(
+        table = "\"logs\"",
+        name = "None",
+        filter = "None",
+        batch_size = "1",
+        require_tags = "None",
+        exclude_tags = "None"
+    )]
+    fn addSqliteHandler(
+        &mut self,
+        db_path: &str,
+        table: &str,
+        name: Option<&str>,
+        filter: Option<PyObject>,
+        batch_size: usize,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        #[cfg(feature = "sqlite")]
+        {
+            let id = self.place(HandlerKind::Sqlite, name)?;
+            let sqlite =
+                SqliteLogger::new(db_path, table, batch_size).map_err(|e| self.raise(e))?;
+            // The handler replaced inserts what it holds.
+            let previous = {
+                let mut handlers = self.logger.handlers();
+                handlers.set_name(id, name);
+                handlers.put_sqlite(id, sqlite)
+            };
+            drop(previous);
+            self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+            Ok(())
+        }
+        #[cfg(not(feature = "sqlite"))]
+        {
+            let _ = (
+                db_path,
+                table,
+                name,
+                filter,
+                batch_size,
+                require_tags,
+                exclude_tags,
+            );
+            Err(PyValueError::new_err(
+                "this build of soda has no sqlite support",
+            ))
+        }
+    }
+
+    /// Records are sent in batches of up to `batch_size`, waiting at most
+    /// `interval` seconds after the first one for the rest to come in.
+    #[args(
+        tag = "\"app.soda\"",
+        ack = "false",
+        buffer_size = "1024",
+        interval = "0.1",
+        batch_size = "256",
+        name = "None",
+        filter = "None",
+        require_tags = "None",
+        exclude_tags = "None"
+    )]
+    fn addFluentdHandler(
+        &mut self,
+        host: String,
+        port: u16,
+        tag: &str,
+        ack: bool,
+        buffer_size: usize,
+        interval: f64,
+        batch_size: usize,
+        name: Option<&str>,
+        filter: Option<PyObject>,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        let id = self.place(HandlerKind::Fluentd, name)?;
+        let fluentd = FluentdLogger::new(
+            &host,
+            port,
+            tag,
+            ack,
+            buffer_size,
+            Duration::from_secs_f64(interval.max(0.0)),
+            batch_size,
+            Arc::clone(self.logger.stats()),
+        );
+        // The replaced handler joins its worker, not while holding the lock.
+        let previous = {
+            let mut handlers = self.logger.handlers();
+            handlers.set_name(id, name);
+            handlers.fluentd.replace(fluentd)
+        };
+        drop(previous);
+        self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+        Ok(())
+    }
+
+    /// Exports records to an OpenTelemetry collector, `endpoint` is the full
+    /// logs url, e.g. `http://localhost:4318/v1/logs`. With `wal`, a path,
+    /// queued records are also kept in that file until exported, those a
+    /// killed process or a collector down for good left behind are sent
+    /// again by the next handler opened on it.
+    #[args(
+        protocol = "\"http/protobuf\"",
+        headers = "None",
+        resource = "None",
+        interval = "1.0",
+        batch_size = "512",
+        compression = "\"gzip\"",
+        name = "None",
+        filter = "None",
+        require_tags = "None",
+        exclude_tags = "None",
+        wal = "None"
+    )]
+    fn addOtlpHandler(
+        &mut self,
+        endpoint: String,
+        protocol: &str,
+        headers: Option<HashMap<String, String>>,
+        resource: Option<&PyDict>,
+        interval: f64,
+        batch_size: usize,
+        compression: Option<&str>,
+        name: Option<&str>,
+        filter: Option<PyObject>,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+        wal: Option<String>,
+    ) -> PyResult<()> {
+        let id = self.place(HandlerKind::Otlp, name)?;
+        let protocol = Protocol::parse(protocol).ok_or_else(|| {
+            self.raise(PyValueError::new_err(format!(
+                "unsupported otlp protocol {:?}",
+                protocol
+            )))
+        })?;
+
+        let gzip = match compression {
+            Some("gzip") => true,
+            None | Some("none") => false,
+            Some(other) => {
+                return Err(self.raise(PyValueError::new_err(format!(
+                    "unsupported otlp compression {:?}",
+                    other
+                ))))
+            }
+        };
+
+        if wal.is_some() {
+            // The handler being replaced is done with the log before this one
+            // reads it.
+            let previous = self.logger.handlers().otlp.take();
+            drop(previous);
+        }
+        let otlp = OtlpLogger::new(
+            OtlpConfig {
+                endpoint,
+                protocol,
+                headers: headers.unwrap_or_default(),
+                resource: resource.map(value::map_from_dict).unwrap_or_default(),
+                interval: Duration::from_secs_f64(interval.max(0.0)),
+                batch_size,
+                gzip,
+                wal,
+            },
+            Arc::clone(self.logger.stats()),
+        )
+        .map_err(|e| self.raise(e))?;
+        // The replaced handler joins its worker, not while holding the lock.
+        let previous = {
+            let mut handlers = self.logger.handlers();
+            handlers.set_name(id, name);
+            handlers.otlp.replace(otlp)
+        };
+        drop(previous);
+        self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+        Ok(())
+    }
+
+    /// Holds every record back until `markReady` is called or `timeout`
+    /// seconds have passed, then emits them in one go, from a background
+    /// thread should nothing be logged by then.
+    #[args(timeout = "5.0")]
+    fn quietStartup(slf: &PyCell<Soda>, timeout: f64) -> PyResult<()> {
+        let timeout = Duration::from_secs_f64(timeout.max(0.0));
+
+        slf.borrow_mut().startup_timer = None;
+        slf.borrow().logger.quiet_startup(timeout);
+        let timer = StartupTimer::start(slf.into(), timeout)?;
+        slf.borrow_mut().startup_timer = Some(timer);
+
+        Ok(())
+    }
+
+    /// Ends a quiet startup, emitting the records held back so far.
+    fn markReady(&mut self) -> PyResult<()> {
+        self.startup_timer = None;
+        self.logger.mark_ready().map_err(|e| self.raise(e))
+    }
+
+    /// Fields merged into every record, e.g. `{"service": "billing"}`, at the
+    /// lowest precedence: `soda.mdc` fields, then `bind()` ones, then
+    /// `contextualize()` ones and then a call's own keyword arguments win
+    /// over them. They're merged into the previous defaults, or replace them
+    /// all with `replace=True`. A callable value is a lazy field, as with
+    /// `bind`.
+    #[args(replace = "false")]
+    fn setDefaultFields(&self, fields: &PyDict, replace: bool) {
+        set_defaults(&self.logger, fields, replace);
+    }
+
+    /// Same as `setDefaultFields`, merging `fields` into the defaults.
+    fn setDefaults(&self, fields: &PyDict) {
+        self.setDefaultFields(fields, false);
+    }
+
+    /// How bytes messages that aren't valid UTF-8 are handled, `"replace"`
+    /// (the default) logs them with U+FFFD in place of the invalid
+    /// sequences, `"strict"` raises a `ValueError` instead.
+    fn setDecodeErrors(&mut self, policy: &str) -> PyResult<()> {
+        self.decode_errors = DecodeErrors::parse(policy).ok_or_else(|| {
+            PyValueError::new_err(format!("unknown decode_errors policy {:?}", policy))
+        })?;
+
+        Ok(())
+    }
+
+    /// What a value passed to a log call that has no JSON equivalent, a raw
+    /// object say, is logged as: `"str"` (the default) and `"repr"` log its
+    /// `str()` or `repr()`, `"skip"` leaves the field out and `"error"`
+    /// raises a `TypeError` instead of logging the record.
+    fn setJsonDefault(&mut self, policy: &str) -> PyResult<()> {
+        self.json_default = Unserializable::parse(policy).ok_or_else(|| {
+            PyValueError::new_err(format!("unknown json_default policy {:?}", policy))
+        })?;
+
+        Ok(())
+    }
+
+    /// Keyword arguments fill `{key}` placeholders in the message, e.g.
+    /// `info("user {user} logged in", user="bob")`, and are logged as fields
+    /// too. With `False` the ones a placeholder took are left out of the
+    /// fields. A call's own `template_extras=` keyword wins over this.
+    fn setTemplateExtras(&mut self, enabled: bool) {
+        self.template_extras = enabled;
+    }
+
+    /// How fields that aren't strings read in text, in the message's
+    /// `{key}` placeholders and the template's `{extras}` and
+    /// `{extra[key]}`: floats with `float_precision` digits after the point
+    /// when it's set, `True`, `False` and `None` as the texts given. The
+    /// structured handlers keep the values as they are.
+    #[args(
+        float_precision = "None",
+        true_text = "\"true\"",
+        false_text = "\"false\"",
+        none_text = "\"null\""
+    )]
+    fn setTextCoercion(
+        &self,
+        float_precision: Option<usize>,
+        true_text: &str,
+        false_text: &str,
+        none_text: &str,
+    ) {
+        self.logger.set_coercion(Coercion {
+            float_precision,
+            true_text: true_text.to_string(),
+            false_text: false_text.to_string(),
+            none_text: none_text.to_string(),
+        });
+    }
+
+    /// Escapes the control characters of messages in the text handlers'
+    /// lines, the console's, the file's and the level split ones', so a
+    /// message with user input like `"bad login\n[INFO] admin logged in"`
+    /// can't forge a line of its own: newlines read `\n`, carriage returns
+    /// `\r`, tabs `\t`, other C0 controls and DEL `\x1b` and the like, C1
+    /// controls and the Unicode line and paragraph separators `\u{2028}`.
+    /// Off by default, for messages meant to span lines. The structured
+    /// handlers already escape them as their formats do.
+    fn setControlEscaping(&self, enabled: bool) {
+        self.logger.set_escape_control(enabled);
+    }
+
+    /// A record logged with `exc_info` describes the exception's `__cause__`
+    /// and `__context__`, and theirs, as nested `exception` fields the JSON
+    /// handler writes. This caps how deep that goes, `0` leaves the chain
+    /// out. Defaults to 8.
+    fn setExceptionDepth(&mut self, depth: usize) {
+        self.exception_capture.depth = depth;
+    }
+
+    /// Lists each traceback frame's local variables under it, their `repr`s
+    /// cut to `max_length` characters. The JSON handler writes them as a
+    /// `locals` map on the exception's `frames`. A frame with a local that
+    /// fails to `repr` is listed without them. Off by default, locals are
+    /// costly to collect and may hold secrets. `exception_locals=` on a
+    /// single call overrides it.
+    #[args(max_length = "200")]
+    fn setExceptionLocals(&mut self, enabled: bool, max_length: usize) {
+        self.exception_capture.locals = Some(max_length).filter(|_| enabled);
+    }
+
+    /// Once a record at `level` or above is logged, every handler is flushed
+    /// and `SystemExit(code)` raised, for command line tools that give up on
+    /// a `critical` say. `None` turns it off, it is by default.
+    #[args(code = "1")]
+    fn exitOnLevel(&mut self, level: Option<&str>, code: i32) -> PyResult<()> {
+        self.exit_on = match level {
+            Some(level) => Some((level_name(level)?, code)),
+            None => None,
+        };
+
+        Ok(())
+    }
+
+    /// Keeps every record in memory, the returned handler reads them back.
+    #[args(
+        name = "None",
+        filter = "None",
+        require_tags = "None",
+        exclude_tags = "None"
+    )]
+    fn addMemoryHandler(
+        &mut self,
+        name: Option<&str>,
+        filter: Option<PyObject>,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+    ) -> PyResult<MemoryHandler> {
+        let id = self.place(HandlerKind::Memory, name)?;
+        let memory = Arc::new(MemoryLogger::default());
+        {
+            let mut handlers = self.logger.handlers();
+            handlers.memory = Some(Arc::clone(&memory));
+            handlers.set_name(id, name);
+        }
+        self.set_handler_filter(id, filter, require_tags, exclude_tags);
+
+        Ok(MemoryHandler {
+            memory,
+            format: self.logger.shared_format(),
+        })
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn info(&self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        if !self.wanted(Level::INFO, kwargs)? {
+            return Ok(());
+        }
+        let record = self.record(Level::INFO, message, args, kwargs, None)?;
+
+        self.emit(record)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn warning(
+        &mut self,
+        message: &PyAny,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<()> {
+        if !self.wanted(Level::WARNING, kwargs)? {
+            return Ok(());
+        }
+        let record = self.record(Level::WARNING, message, args, kwargs, None)?;
+
+        self.emit(record)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn debug(&mut self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        if !self.wanted(Level::DEBUG, kwargs)? {
+            return Ok(());
+        }
+        let record = self.record(Level::DEBUG, message, args, kwargs, None)?;
+
+        self.emit(record)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn trace(&mut self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        if !self.wanted(Level::TRACE, kwargs)? {
+            return Ok(());
+        }
+        let record = self.record(Level::TRACE, message, args, kwargs, None)?;
+
+        self.emit(record)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn error(&mut self, message: &PyAny, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<()> {
+        if !self.wanted(Level::ERROR, kwargs)? {
+            return Ok(());
+        }
+        let record = self.record(Level::ERROR, message, args, kwargs, None)?;
+
+        self.emit(record)
+    }
+
+    #[args(args = "*", kwargs = "**")]
+    fn critical(
+        &mut self,
+        message: &PyAny,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<()> {
+        if !self.wanted(Level::CRITICAL, kwargs)? {
+            return Ok(());
+        }
+        let record = self.record(Level::CRITICAL, message, args, kwargs, None)?;
+
+        self.emit(record)
+    }
+
+    /// Logs many records in a single call, to import historical logs say,
+    /// instead of calling into soda once per record. Each is a `(level,
+    /// message, extra, timestamp)` tuple, `extra` a dict of fields logged
+    /// like keyword arguments and `timestamp` the time in seconds since the
+    /// epoch, both of which can be `None` or left off. `level` is a name or
+    /// a `logging` number. Records below the level are skipped like the
+    /// level methods' are. An invalid record raises, the ones before it are
+    /// logged. Returns how many were.
+    fn logBatch(&self, py: Python, records: &PyAny) -> PyResult<usize> {
+        let args = PyTuple::empty(py);
+        let caller = log_record::caller(py);
+        let thread = log_record::thread(py);
+        let context = context::current(py);
+        let mut logged = 0;
+
+        for item in records.iter()? {
+            let item: &PyTuple = item?.downcast()?;
+            if !(2..=4).contains(&item.len()) {
+                return Err(PyValueError::new_err(format!(
+                    "expected (level, message, extra, timestamp), got {} items",
+                    item.len()
+                )));
+            }
+            let optional = |index| {
+                Some(index)
+                    .filter(|index| *index < item.len())
+                    .map(|i| item.get_item(i))
+            };
+
+            let level = item.get_item(0);
+            let level = match level.extract::<&str>() {
+                Ok(name) => level_name(name)?,
+                Err(_) => Level::from_number(level.extract()?),
+            };
+            if !self.wanted(level, None)? {
+                continue;
+            }
+            let extra = match optional(2) {
+                Some(extra) if !extra.is_none() => Some(extra.downcast::<PyDict>()?),
+                _ => None,
+            };
+
+            let message = item.get_item(1);
+            let mut record = self.record_from(
+                &caller,
+                thread,
+                context.clone(),
+                level,
+                message,
+                args,
+                extra,
+                None,
+            )?;
+            if let Some(timestamp) = optional(3).filter(|timestamp| !timestamp.is_none()) {
+                let timestamp: f64 = timestamp.extract()?;
+                if let Some(time) = Local
+                    .timestamp_opt(timestamp.floor() as i64, (timestamp.fract() * 1e9) as u32)
+                    .single()
+                {
+                    record.time = time;
+                }
+            }
+
+            self.emit(record)?;
+            logged += 1;
+        }
+
+        Ok(logged)
+    }
+
+    /// Logs a measurement at INFO, which the structured handlers write as
+    /// `{"metric": name, "value": value, "tags": tags}` so soda can feed a
+    /// metrics pipeline too. The other handlers get a `name value key=value`
+    /// line.
+    #[args(tags = "None")]
+    fn metric(&self, py: Python, name: &str, value: &PyAny, tags: Option<&PyDict>) -> PyResult<()> {
+        let number = match value::convert(value, self.json_default)? {
+            Some(number @ Value::Number(_)) => number,
+            _ => {
+                return Err(PyTypeError::new_err(format!(
+                    "metric value must be a number, got {}",
+                    value.get_type().name()?
+                )))
+            }
+        };
+        let tags = match tags {
+            Some(tags) => value::convert_dict(tags, self.json_default)?,
+            None => Map::new(),
+        };
+
+        let coercion = self.logger.format().coercion.clone();
+        let mut message = format!("{} {}", name, number);
+        for (key, value) in &tags {
+            message.push_str(&format!(" {}={}", key, coercion.text(value)));
+        }
+
+        let event = PyDict::new(py);
+        event.set_item("metric", name)?;
+        event.set_item("value", value)?;
+        let mut record = self.record(Level::INFO, event, PyTuple::empty(py), None, None)?;
+        record.message = message;
+        if let Some(event) = record.event.as_mut() {
+            event.insert(String::from("tags"), Value::Object(tags));
+        }
+        // The metric's tags take the field record tags would be written to.
+        record.tags.clear();
+
+        self.emit(record)
+    }
+
+    pub fn setLevel(&mut self, verbosity: u8) {
+        match verbosity {
+            1 => self.logger.set_level(Level::DEBUG),
+            2 => self.logger.set_level(Level::INFO),
+            3 => self.logger.set_level(Level::WARNING),
+            _ => {
+                eprintln!("soda: unknown verbosity {}, logging from DEBUG", verbosity);
+                self.logger.set_level(Level::DEBUG)
+            }
+        }
+    }
+}
+
+impl Soda {
+    /// A logger called `name`, what `Soda()` and `getLogger` create.
+    fn named(py: Python, name: &str, otel_context: bool, fields: Option<&PyDict>) -> Soda {
+        let logger = Logger::new(name);
+        if let Some(fields) = fields {
+            set_defaults(&logger, fields, true);
+        }
+
+        Soda::with_logger(py, logger, otel_context)
+    }
+
+    fn with_logger(py: Python, logger: Logger, otel_context: bool) -> Soda {
+        Soda {
+            logger,
+            otel: if otel_context {
+                OtelContext::load(py)
+            } else {
+                None
+            },
+            otel_context,
+            console: None,
+            correlation_field: String::from("request_id"),
+            metrics: None,
+            heartbeat: None,
+            follower: None,
+            startup_timer: None,
+            decode_errors: DecodeErrors::Replace,
+            json_default: Unserializable::Str,
+            template_extras: true,
+            exception_capture: Capture::default(),
+            processors: Vec::new(),
+            handler_filters: HashMap::new(),
+            handler_tags: HashMap::new(),
+            exit_on: None,
+            pii_scrubbing: None,
+        }
+    }
+
+    /// Applies a configuration produced by `exportConfig`, each section goes
+    /// through the same method a user would call to set it up.
+    fn configure(&mut self, py: Python, config: &PyDict) -> PyResult<()> {
+        if let Some(level) = item::<String>(config, "level")? {
+            self.logger.set_level(level_name(&level)?);
+        }
+
+        if let Some(format) = item::<&PyUnicode>(config, "format")? {
+            self.setFormat(format);
+        }
+
+        if let Some(defaults) = item::<&PyDict>(config, "defaults")? {
+            self.setDefaultFields(defaults, true);
+        }
+        if let Some(fields) = item::<&PyDict>(config, "fields")? {
+            self.setDefaultFields(fields, false);
+        }
+
+        if let Some(policy) = item::<&str>(config, "decode_errors")? {
+            self.setDecodeErrors(policy)?;
+        }
+        if let Some(policy) = item::<&str>(config, "json_default")? {
+            self.setJsonDefault(policy)?;
+        }
+        if let Some(enabled) = item(config, "template_extras")? {
+            self.setTemplateExtras(enabled);
+        }
+        if let Some(enabled) = item(config, "escape_control")? {
+            self.setControlEscaping(enabled);
+        }
+        if let Some(coercion) = item::<&PyDict>(config, "text_coercion")? {
+            self.setTextCoercion(
+                item(coercion, "float_precision")?,
+                item(coercion, "true_text")?.unwrap_or("true"),
+                item(coercion, "false_text")?.unwrap_or("false"),
+                item(coercion, "none_text")?.unwrap_or("null"),
+            );
+        }
+        if let Some(depth) = item(config, "exception_depth")? {
+            self.setExceptionDepth(depth);
+        }
+        if let Some(enabled) = item(config, "exception_locals")? {
+            let length = item(config, "locals_length")?.unwrap_or(value::LOCALS_LENGTH);
+            self.setExceptionLocals(enabled, length);
+        }
+        if let Some(scrubbing) = item::<&PyDict>(config, "pii_scrubbing")? {
+            self.enablePiiScrubbing(item(scrubbing, "kinds")?, item(scrubbing, "no_scrub")?)?;
+        }
+        if let Some(schema) = item::<&PyDict>(config, "schema")? {
+            let on_violation = item(schema, "on_violation")?.unwrap_or("drop");
+            self.setSchema(required(schema, "schema")?, on_violation)?;
+        }
+        if let Some(level) = item(config, "exit_on_level")? {
+            self.exitOnLevel(Some(level), item(config, "exit_code")?.unwrap_or(1))?;
+        }
+
+        if let Some(patterns) = item::<Vec<&PyDict>>(config, "message_patterns")? {
+            for settings in patterns {
+                let pattern: &str = required(settings, "pattern")?;
+                let handler = item(settings, "handler")?;
+                match item(settings, "mode")?.unwrap_or("exclude") {
+                    "exclude" => self.excludeMessages(pattern, handler)?,
+                    "include_only" => self.includeOnlyMessages(pattern, handler)?,
+                    other => {
+                        return Err(PyValueError::new_err(format!(
+                            "unknown message pattern mode {:?}",
+                            other
+                        )))
+                    }
+                };
+            }
+        }
+
+        if let Some(rules) = item(config, "filter_rules")? {
+            self.setFilterRules(rules)?;
+        }
+        if let Some(levels) = item::<&PyDict>(config, "target_levels")? {
+            for (target, level) in levels {
+                self.setTargetLevel(target.extract()?, level.extract()?)?;
+            }
+        }
+
+        if let Some(tag_filters) = item::<Vec<&PyDict>>(config, "tag_filters")? {
+            for settings in tag_filters {
+                self.addTagFilter(
+                    item(settings, "require")?,
+                    item(settings, "exclude")?,
+                    item(settings, "handler")?,
+                )?;
+            }
+        }
+
+        if let Some(console) = item::<&PyDict>(config, "console")? {
+            let datefmt = item::<&PyUnicode>(console, "datefmt")?
+                .unwrap_or_else(|| PyUnicode::new(py, DEFAULT_DATEFMT));
+            self.basicConfig(
+                py,
+                datefmt,
+                item(console, "buffered")?,
+                item(console, "buffer_size")?.unwrap_or(8192),
+                None,
+                item(console, "tqdm_compat")?.unwrap_or(false),
+                item(console, "notebook")?,
+                item(console, "filter")?,
+                item(console, "require_tags")?,
+                item(console, "exclude_tags")?,
+                item(console, "debug_blocks")?.unwrap_or(false),
+                item(console, "color_scope")?,
+            )?;
+        }
+
+        let handlers = item::<&PyDict>(config, "handlers")?.unwrap_or_else(|| PyDict::new(py));
+
+        for (kind, settings) in handlers.iter() {
+            let kind: &str = kind.extract()?;
+            let settings: &PyDict = settings.downcast()?;
+
+            let name = item(settings, "name")?;
+            let filter = item(settings, "filter")?;
+            let require_tags = item(settings, "require_tags")?;
+            let exclude_tags = item(settings, "exclude_tags")?;
+
+            match kind {
+                "file" => self.addFileHandler(
+                    py,
+                    required(settings, "path")?,
+                    name,
+                    filter,
+                    item(settings, "buffer_size")?.unwrap_or(0),
+                    require_tags,
+                    exclude_tags,
+                    item(settings, "flush_every_n_lines")?.unwrap_or(0),
+                    item(settings, "line_format")?,
+                    item(settings, "header")?,
+                    item(settings, "reexpand")?,
+                    item(settings, "latest_symlink")?,
+                    option_group(settings, "rotation")?,
+                    file_security(settings)?,
+                    option_group(settings, "compression")?,
+                )?,
+                "json" => self.addJsonHandler(
+                    item(settings, "path")?,
+                    item(settings, "time_format")?.unwrap_or("rfc3339"),
+                    item(settings, "preset")?.unwrap_or("default"),
+                    item(settings, "ecs_strict")?.unwrap_or(true),
+                    name,
+                    item(settings, "format")?.unwrap_or("json"),
+                    filter,
+                    item(settings, "buffer_size")?.unwrap_or(0),
+                    require_tags,
+                    exclude_tags,
+                    item(settings, "flush_every_n_lines")?.unwrap_or(0),
+                )?,
+                "level_split" => self.addLevelSplitFileHandler(
+                    required(settings, "dir")?,
+                    name,
+                    filter,
+                    item(settings, "buffer_size")?.unwrap_or(0),
+                    require_tags,
+                    exclude_tags,
+                    item(settings, "flush_every_n_lines")?.unwrap_or(0),
+                )?,
+                "sqlite" => self.addSqliteHandler(
+                    required(settings, "db_path")?,
+                    item(settings, "table")?.unwrap_or("logs"),
+                    name,
+                    filter,
+                    item(settings, "batch_size")?.unwrap_or(1),
+                    require_tags,
+                    exclude_tags,
+                )?,
+                "fluentd" => self.addFluentdHandler(
+                    required(settings, "host")?,
+                    required(settings, "port")?,
+                    item(settings, "tag")?.unwrap_or("app.soda"),
+                    item(settings, "ack")?.unwrap_or(false),
+                    item(settings, "buffer_size")?.unwrap_or(1024),
+                    item(settings, "interval")?.unwrap_or(0.1),
+                    item(settings, "batch_size")?.unwrap_or(256),
+                    name,
+                    filter,
+                    require_tags,
+                    exclude_tags,
+                )?,
+                "otlp" => self.addOtlpHandler(
+                    required(settings, "endpoint")?,
+                    item(settings, "protocol")?.unwrap_or("http/protobuf"),
+                    item(settings, "headers")?,
+                    item(settings, "resource")?,
+                    item(settings, "interval")?.unwrap_or(1.0),
+                    item(settings, "batch_size")?.unwrap_or(512),
+                    Some(item(settings, "compression")?.unwrap_or("gzip")),
+                    name,
+                    filter,
+                    require_tags,
+                    exclude_tags,
+                    item(settings, "wal")?,
+                )?,
+                other => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown handler {:?}",
+                        other
+                    )))
+                }
+            }
+
+            if item(settings, "enabled")? == Some(false) {
+                self.setHandlerEnabled(kind, false)?;
+            }
+        }
+
+        // Routes name their handlers, so they come once those are added.
+        if let Some(routes) = item::<Vec<&PyDict>>(config, "routes")? {
+            for route in routes {
+                self.addRoute(
+                    item(route, "match")?,
+                    item(route, "handlers")?,
+                    item(route, "exclusive")?.unwrap_or(false),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The routes as `getRoutes` and `exportConfig` list them, handlers by
+    /// the name they were added under when they have one.
+    fn routes(&self) -> Vec<Value> {
+        let set = self.logger.handlers();
+
+        self.logger
+            .routes()
+            .iter()
+            .map(|(id, route)| {
+                let handlers: Vec<String> =
+                    route.handlers.iter().map(|id| set.label(*id)).collect();
+
+                json!({
+                    "id": id,
+                    "match": {
+                        "name_prefix": route.name_prefix,
+                        "min_level": route.min_level.map(|level| level.as_str()),
+                    },
+                    "handlers": handlers,
+                    "exclusive": route.exclusive,
+                })
+            })
+            .collect()
+    }
+
+    /// The handlers a call's `handlers=`, a name or a list of them, sends
+    /// its record to.
+    fn handler_ids(&self, names: &PyAny) -> PyResult<Vec<HandlerId>> {
+        let names: Vec<String> = match names.extract::<String>() {
+            Ok(name) => vec![name],
+            Err(_) => names.extract()?,
+        };
+
+        let set = self.logger.handlers();
+        let ids: Result<Vec<HandlerId>, &String> =
+            names.iter().map(|name| set.id(name).ok_or(name)).collect();
+        drop(set);
+
+        ids.map_err(|name| self.raise(PyValueError::new_err(format!("unknown handler {:?}", name))))
+    }
+
+    /// Where a handler of `kind` added under `name` goes, see
+    /// `Handlers::place`.
+    fn place(&self, kind: HandlerKind, name: Option<&str>) -> PyResult<HandlerId> {
+        let placed = self.logger.handlers().place(kind, name);
+        placed.map_err(|taken| self.raise(PyValueError::new_err(taken.to_string())))
+    }
+
+    /// The handler a filter given `handler` applies to, `None` for all.
+    fn filter_handler(&self, handler: Option<&str>) -> PyResult<Option<HandlerId>> {
+        match handler {
+            Some(name) => match self.logger.handlers().id(name) {
+                Some(id) => Ok(Some(id)),
+                None => Err(PyValueError::new_err(format!("unknown handler {:?}", name))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the `filter=`, `require_tags=` and `exclude_tags=` a handler
+    /// was added with.
+    fn set_handler_filter(
+        &mut self,
+        handler: HandlerId,
+        filter: Option<PyObject>,
+        require_tags: Option<Vec<String>>,
+        exclude_tags: Option<Vec<String>>,
+    ) {
+        if let Some(id) = self.handler_filters.remove(&handler) {
+            self.logger.remove_filter(id);
+        }
+        if let Some(id) = self.handler_tags.remove(&handler) {
+            self.logger.remove_filter(id);
+        }
+
+        if require_tags.is_some() || exclude_tags.is_some() {
+            let id = self.logger.add_tag_filter(
+                Some(handler),
+                require_tags.unwrap_or_default(),
+                exclude_tags.unwrap_or_default(),
+            );
+            self.handler_tags.insert(handler, id);
+        }
+
+        if let Some(func) = filter {
+            let id = self.logger.add_filter(
+                Some(handler),
+                processor::filter(
+                    func,
+                    self.logger.shared_format(),
+                    Arc::clone(self.logger.stats()),
+                ),
+            );
+            self.handler_filters.insert(handler, id);
+        }
+    }
+
+    /// Whether a level method call is logged, decided before its record is
+    /// built: it's not below the logger's level and sampling keeps it. A
+    /// `sample=` rate passed with the call, taken out of `kwargs`, wins over
+    /// the `setSampling` one for the logger's name. `ERROR` and above are
+    /// always sampled.
+    pub(crate) fn wanted(&self, level: Level, kwargs: Option<&PyDict>) -> PyResult<bool> {
+        if !self.logger.is_enabled_for(level) {
+            return Ok(false);
+        }
+        let mut rate = None;
+        if let Some(kwargs) = kwargs {
+            if let Some(sample) = kwargs.get_item("sample") {
+                rate = Some(sample.extract::<f64>()?);
+                kwargs.del_item("sample")?;
+            }
+        }
+        if level.number() >= Level::ERROR.number() {
+            return Ok(true);
+        }
+
+        let name = self.logger.name();
+        let rate = rate.unwrap_or_else(|| loggers::sampling_rate(name));
+        if rate >= 1.0 || loggers::chance() < rate {
+            return Ok(true);
+        }
+        self.logger.stats().sampled_out(name);
+
+        Ok(false)
+    }
+
+    /// Builds the record for a level method call, `bound` are the fields of
+    /// the `bind()` logger it was made on.
+    pub(crate) fn record(
+        &self,
+        level: Level,
+        message: &PyAny,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+        bound: Option<&Bound>,
+    ) -> PyResult<Record> {
+        let py = message.py();
+        let caller = log_record::caller(py);
+
+        self.record_from(
+            &caller,
+            log_record::thread(py),
+            context::current(py),
+            level,
+            message,
+            args,
+            kwargs,
+            bound,
+        )
+    }
+
+    /// `record` for a call made from `caller` on `thread` with the fields
+    /// bound in `context`, which a batch looks up once for all of its
+    /// records.
+    fn record_from(
+        &self,
+        caller: &Option<Caller>,
+        thread: Option<u64>,
+        mut context: Map<String, Value>,
+        level: Level,
+        message: &PyAny,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+        bound: Option<&Bound>,
+    ) -> PyResult<Record> {
+        let py = message.py();
+
+        let message: &PyAny = match message.downcast::<PyBytes>() {
+            Ok(bytes) => match self.decode_errors.decode(bytes.as_bytes()) {
+                Ok(text) => PyUnicode::new(py, &text),
+                Err(e) => {
+                    return Err(self.raise(PyValueError::new_err(format!(
+                        "message is not valid UTF-8: {}",
+                        e
+                    ))))
+                }
+            },
+            Err(_) => message,
+        };
+
+        let mut record = Record::new(level, self.logger.name(), &interpolate(message, args));
+        record.caller = caller.clone();
+        record.thread = thread;
+
+        // A dict message is an event object, structured handlers merge its
+        // keys into the record instead of logging it as a string.
+        if let Ok(event) = message.downcast::<PyDict>() {
+            record.event =
+                Some(value::convert_dict(event, self.json_default).map_err(|e| self.raise(e))?);
+        }
+
+        // Per call fields win over the ones bound in the current context,
+        // which win over the ones bound to the logger. Tags are gathered
+        // from all three instead.
+        record.extras = bound
+            .map(|bound| bound.fields().clone())
+            .unwrap_or_default();
+        record.lazy = bound.map(|bound| bound.lazy(py)).unwrap_or_default();
+        take_tags(&mut record.extras, &mut record.tags);
+        take_tags(&mut context, &mut record.tags);
+        record.extras.extend(context);
+        if let Some(kwargs) = kwargs {
+            let exc_info = kwargs.get_item("exc_info");
+            let locals = kwargs.get_item("exception_locals");
+            let targets = kwargs.get_item("handlers");
+            let template_extras = kwargs.get_item("template_extras");
+
+            // exc_info is never a field, so it's left out before an "error"
+            // `json_default` could reject the exception in it.
+            let fields = kwargs.copy()?;
+            if exc_info.is_some() {
+                fields.del_item("exc_info")?;
+            }
+            if locals.is_some() {
+                fields.del_item("exception_locals")?;
+            }
+            let template_extras = match template_extras {
+                Some(enabled) => {
+                    fields.del_item("template_extras")?;
+                    enabled.is_true()?
+                }
+                None => self.template_extras,
+            };
+            if let Some(targets) = targets {
+                fields.del_item("handlers")?;
+                record.handlers = Some(self.handler_ids(targets)?);
+            }
+            let mut fields =
+                value::convert_dict(fields, self.json_default).map_err(|e| self.raise(e))?;
+            take_tags(&mut fields, &mut record.tags);
+
+            // Without `%` arguments the same fields fill `{key}` placeholders
+            // in a string message.
+            if args.is_empty() && !fields.is_empty() && message.downcast::<PyUnicode>().is_ok() {
+                let coercion = self.logger.format().coercion.clone();
+                let filled = template::fill(&record.message, &fields, &coercion);
+                if filled.missing > 0 {
+                    self.logger.stats().format_error();
+                }
+                if !template_extras {
+                    for key in &filled.used {
+                        fields.remove(key);
+                    }
+                }
+                record.message = filled.message;
+            }
+
+            record.extras.extend(fields);
+
+            if let Some(exc_info) = exc_info {
+                let mut capture = self.exception_capture;
+                if let Some(locals) = locals {
+                    capture.locals = match locals.is_true()? {
+                        true => capture.locals.or(Some(value::LOCALS_LENGTH)),
+                        false => None,
+                    };
+                }
+                record.exception = value::exception(exc_info, capture);
+            }
+        }
+
+        self.annotate(py, &mut record);
+
+        Ok(record)
+    }
+
+    /// Adds what the logger contributes to every record: the `setDefaults`
+    /// fields, underneath the record's own, and the current OpenTelemetry
+    /// span with `otel_context` on.
+    pub(crate) fn annotate(&self, py: Python, record: &mut Record) {
+        self.logger.with_defaults(record);
+
+        if let Some((trace_id, span_id)) = self.otel.as_ref().and_then(|otel| otel.ids(py)) {
+            record.trace_id = Some(trace_id);
+            record.span_id = Some(span_id);
+        }
+    }
+
+    /// Sends a record to the console and the handlers, unless a quiet
+    /// startup holds it back. Raises `SystemExit` past the `exitOnLevel`.
+    pub(crate) fn emit(&self, record: Record) -> PyResult<()> {
+        let level = record.level;
+        self.logger
+            .emit(record)
+            .map_err(|e| self.raise(emit_error(e)))?;
+
+        match self.exit_on {
+            Some((exit_level, code)) if level.number() >= exit_level.number() => {
+                Err(self.raise(PySystemExit::new_err(code)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails unless there's a file handler writing lines that read back as
+    /// text, for `tail` and `follow`.
+    fn readable_file(&self) -> PyResult<()> {
+        let handlers = self.logger.handlers();
+        let file = &handlers.file;
+        if !file.enabled {
+            return Err(PyValueError::new_err("there's no file handler to read"));
+        }
+        if file.compress.is_some() {
+            return Err(PyValueError::new_err(
+                "a compressed file can't be read back as lines, decompress it first",
+            ));
+        }
+        if file.encryption.is_some() {
+            return Err(PyValueError::new_err(
+                "an encrypted file can't be read back as lines, see decryptLog",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Flushes every handler before an error is raised to the caller, so
+    /// whatever was logged up to a failure isn't lost with it.
+    fn raise<E: Into<PyErr>>(&self, err: E) -> PyErr {
+        self.flush();
+        err.into()
+    }
+}
+
+/// A handler added under a name that's taken raises `ValueError`, any
+/// other error the `OSError` it is.
+fn handler_error(e: io::Error) -> PyErr {
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<NameTaken>())
+    {
+        Some(taken) => PyValueError::new_err(taken.to_string()),
+        None => e.into(),
+    }
+}
+
+/// A schema `Violation` raises `ValueError`, any other error the `OSError`
+/// it is.
+fn emit_error(e: io::Error) -> PyErr {
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<Violation>())
+    {
+        Some(violation) => PyValueError::new_err(violation.to_string()),
+        None => e.into(),
+    }
+}
+
+/// What `basicConfig` was called with, kept for `exportConfig`.
+struct ConsoleConfig {
+    datefmt: String,
+    buffered: Option<bool>,
+    buffer_size: usize,
+    tqdm_compat: bool,
+    notebook: Option<bool>,
+    debug_blocks: bool,
+    color: Option<ColorScope>,
+}
+
+/// Builds a new logger from a configuration dict, see `Soda.exportConfig`.
+#[pyfunction]
+fn dictConfig(py: Python, config: &PyDict) -> PyResult<Py<Soda>> {
+    let otel_context = item(config, "otel_context")?.unwrap_or(false);
+
+    let mut soda = Soda::new(py, 0, otel_context, None, true);
+    soda.configure(py, config)?;
+
+    Py::new(py, soda)
+}
+
+/// `message % args` the way the `logging` module does it, a single mapping
+/// argument fills `%(name)s` style placeholders. Should the formatting fail
+/// the message is logged as is rather than lost.
+fn interpolate(message: &PyAny, args: &PyTuple) -> String {
+    if args.is_empty() || message.downcast::<PyUnicode>().is_err() {
+        return value::to_text(message);
+    }
+
+    let args: &PyAny = match args.get_item(0).downcast::<PyDict>() {
+        Ok(mapping) if args.len() == 1 => mapping,
+        _ => args,
+    };
+
+    match message.call_method1("__mod__", (args,)) {
+        Ok(formatted) => value::to_text(formatted),
+        Err(_) => value::to_text(message),
+    }
+}
+
+fn level_name(name: &str) -> PyResult<Level> {
+    Level::from_name(name).ok_or_else(|| PyValueError::new_err(format!("unknown level {:?}", name)))
+}
+
+/// Has `logger` default to `fields`, their callable values lazy fields, in
+/// place of its defaults or on top of them.
+fn set_defaults(logger: &Logger, fields: &PyDict, replace: bool) {
+    let (fields, lazy) = value::fields_from_dict(fields);
+    let lazy = lazy
+        .into_iter()
+        .map(|(key, function)| (key, value::lazy_field(function)))
+        .collect();
+
+    match replace {
+        true => logger.set_defaults(fields, lazy),
+        false => logger.merge_defaults(fields, lazy),
+    }
+}
+
+/// Moves the `tags` field, a list of strings or a single one, over to
+/// `tags`, leaving out the ones already there.
+fn take_tags(fields: &mut Map<String, Value>, tags: &mut Vec<String>) {
+    let added = match fields.remove("tags") {
+        Some(Value::Array(added)) => added,
+        Some(tag) => vec![tag],
+        None => return,
+    };
+
+    for tag in added {
+        let tag = template::text(&tag);
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+}
+
+/// A `Coercion` as `exportConfig` writes it.
+fn coercion(coercion: &Coercion) -> Value {
+    json!({
+        "float_precision": coercion.float_precision,
+        "true_text": coercion.true_text,
+        "false_text": coercion.false_text,
+        "none_text": coercion.none_text,
+    })
+}
+
+/// `config[key]`, treating a missing key and `None` alike.
+fn item<'a, T: FromPyObject<'a>>(config: &'a PyDict, key: &str) -> PyResult<Option<T>> {
+    match config.get_item(key) {
+        Some(value) if !value.is_none() => value.extract().map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Seconds in a day.
+const DAY: f64 = 86400.0;
+
+/// `days` as a duration, which mustn't be negative.
+fn days(days: f64) -> PyResult<Duration> {
+    if !days.is_finite() || days < 0.0 {
+        return Err(PyValueError::new_err(format!(
+            "{} isn't a number of days",
+            days
+        )));
+    }
+
+    Ok(Duration::from_secs_f64(days * DAY))
+}
+
+fn required<'a, T: FromPyObject<'a>>(config: &'a PyDict, key: &str) -> PyResult<T> {
+    item(config, key)?.ok_or_else(|| PyValueError::new_err(format!("missing {:?}", key)))
+}
+
+/// A `FileRotation` or `FileCompression` in a file handler's settings, or
+/// the dict of keyword arguments to make one with.
+fn option_group<'a, T>(settings: &'a PyDict, key: &str) -> PyResult<Option<PyRef<'a, T>>>
+where
+    T: PyClass + PyTypeInfo,
+{
+    match item::<&PyAny>(settings, key)? {
+        Some(group) => match group.downcast::<PyDict>() {
+            Ok(kwargs) => settings
+                .py()
+                .get_type::<T>()
+                .call((), Some(kwargs))?
+                .extract()
+                .map(Some),
+            Err(_) => group.extract().map(Some),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The `FileSecurity` of a file handler's settings. `exportConfig` leaves
+/// the keys out, a `"signed"` one needs its `hmac_key` put back unless it's
+/// read from `hmac_key_env`, an `"encrypted"` one its `encryption_key`.
+fn file_security(settings: &PyDict) -> PyResult<Option<PyRef<'_, FileSecurity>>> {
+    let security = match item::<&PyAny>(settings, "security")? {
+        Some(group) => match group.downcast::<PyDict>() {
+            Ok(kwargs) => kwargs.copy()?,
+            Err(_) => return group.extract().map(Some),
+        },
+        None => return Ok(None),
+    };
+
+    let signed = item(security, "signed")?.unwrap_or(false);
+    if signed
+        && item::<&PyAny>(security, "hmac_key")?.is_none()
+        && item::<&PyAny>(security, "hmac_key_env")?.is_none()
+    {
+        return Err(PyValueError::new_err(
+            "the signed file handler needs its hmac_key or hmac_key_env",
+        ));
+    }
+    let encrypted = item(security, "encrypted")?.unwrap_or(false);
+    if encrypted && item::<&PyAny>(security, "encryption_key")?.is_none() {
+        return Err(PyValueError::new_err(
+            "the encrypted file handler needs its encryption_key",
+        ));
+    }
+    for key in &["signed", "encrypted"] {
+        if security.contains(key)? {
+            security.del_item(key)?;
+        }
+    }
+
+    settings
+        .py()
+        .get_type::<FileSecurity>()
+        .call((), Some(security))?
+        .extract()
+        .map(Some)
+}
+
+#[pyclass]
+pub struct MemoryHandler {
+    memory: Arc<MemoryLogger>,
+    format: SharedFormat,
+}
+
+#[pymethods]
+impl MemoryHandler {
+    /// The captured records, formatted.
+    fn getRecords(&self) -> Vec<String> {
+        self.memory
+            .with_entries(|entries| entries.iter().map(|e| e.line.clone()).collect())
+    }
+
+    /// The captured records as dicts of `level`, `name`, `message`, `extra`
+    /// (the record's fields) and `time` in seconds since the epoch, for tests
+    /// to check fields without parsing lines.
+    fn getStructuredRecords(&self, py: Python) -> PyResult<Vec<PyObject>> {
+        self.memory.with_entries(|entries| {
+            entries
+                .iter()
+                .map(|e| {
+                    let extra = value::to_py(py, &Value::Object(e.record.extras.clone()));
+                    let time = e.record.time.timestamp() as f64
+                        + f64::from(e.record.time.timestamp_subsec_nanos()) / 1e9;
+
+                    let record = PyDict::new(py);
+                    record.set_item("level", e.record.level.as_str())?;
+                    record.set_item("name", &e.record.name)?;
+                    record.set_item("message", &e.record.message)?;
+                    record.set_item("extra", extra)?;
+                    record.set_item("time", time)?;
+
+                    Ok(record.into())
+                })
+                .collect()
+        })
+    }
+
+    /// The captured records as `LogRecord`s.
+    fn getLogRecords(&self, py: Python) -> PyResult<Vec<Py<LogRecord>>> {
+        self.memory.with_entries(|entries| {
+            entries
+                .iter()
+                .map(|e| Py::new(py, LogRecord::new(py, e.record.clone(), &self.format)))
+                .collect()
+        })
+    }
+
+    fn clear(&self) {
+        self.memory.clear();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// Globals with the `soda` module imported, made importable the first
+    /// time.
+    fn globals(py: Python<'_>) -> &PyDict {
+        let modules: &PyDict = py
+            .import("sys")
+            .unwrap()
+            .get("modules")
+            .unwrap()
+            .downcast()
+            .unwrap();
+        if modules.get_item("soda").is_none() {
+            let module = PyModule::new(py, "soda").unwrap();
+            soda(py, module).unwrap();
+            modules.set_item("soda", module).unwrap();
+        }
+        let globals = PyDict::new(py);
+        globals
+            .set_item("soda", py.import("soda").unwrap())
+            .unwrap();
+        // What `import` and the exceptions' tracebacks look up.
+        globals
+            .set_item("__builtins__", py.import("builtins").unwrap())
+            .unwrap();
+
+        globals
+    }
+
+    /// Runs `code` with the `soda` module imported, the test failing with
+    /// what it raises.
+    pub fn run(code: &str) {
+        Python::with_gil(|py| {
+            if let Err(e) = py.run(code, Some(globals(py)), None) {
+                e.print(py);
+                panic!("the Python code raised");
+            }
+        });
+    }
+
+    /// Runs `code` as a program in a process of its own, this test binary
+    /// started again for `subprocess_main`, and waits for it to end.
+    fn subprocess(code: &str) -> std::process::Output {
+        std::process::Command::new(env::current_exe().unwrap())
+            .args(["python::tests::subprocess_main", "--exact", "--nocapture"])
+            .args(["--test-threads", "1"])
+            .env("SODA_SUBPROCESS", code)
+            .output()
+            .unwrap()
+    }
+
+    /// What `subprocess` runs, a no-op anywhere else. Like the interpreter
+    /// does for a program, an uncaught exception goes to `sys.excepthook`
+    /// and exit status 1, and the interpreter is finalized after, running
+    /// the `atexit` functions.
+    #[test]
+    fn subprocess_main() {
+        let code = match env::var("SODA_SUBPROCESS") {
+            Ok(code) => code,
+            Err(_) => return,
+        };
+
+        let status = Python::with_gil(|py| {
+            let globals = globals(py);
+            globals.set_item("__name__", "__main__").unwrap();
+
+            match py.run(&code, Some(globals), None) {
+                Ok(()) => 0,
+                Err(e) => {
+                    e.print(py);
+                    1
+                }
+            }
+        });
+        unsafe {
+            pyo3::ffi::PyGILState_Ensure();
+            pyo3::ffi::Py_FinalizeEx();
+        }
+        std::process::exit(status);
+    }
+
+    #[test]
+    fn children_inherit_the_level_until_given_one() {
+        run(r#"
+parent = soda.Soda()
+memory = parent.addMemoryHandler()
+parent.setLevel(3)
+child = parent.getChild("inherits")
+assert child.getEffectiveLevel() == "WARNING"
+child.info("dropped")
+child.warning("kept")
+child.setLevel(2)
+child.info("kept too")
+parent.info("dropped too")
+records = [(r["name"], r["message"]) for r in memory.getStructuredRecords()]
+assert records == [("soda.inherits", "kept"), ("soda.inherits", "kept too")], records
+"#);
+    }
+
+    #[test]
+    fn loggers_are_listed_with_the_level_they_go_by() {
+        run(r#"
+app = soda.getLogger("listed")
+pool = soda.getLogger("listed.db.pool")
+app.reconfigure(level="WARNING")
+db = soda.getLogger("listed.db")
+db.reconfigure(level="ERROR")
+soda.getLogger("listed-other")
+parent = soda.Soda()
+parent.setLevel(3)
+parent.getChild("listed")
+
+levels = dict(soda.listLoggers())
+assert levels["listed"] == "WARNING", levels
+assert levels["listed.db"] == "ERROR", levels
+assert levels["listed.db.pool"] == "ERROR", levels
+assert levels["listed-other"] == "NOTSET", levels
+assert levels["soda.listed"] == "WARNING", levels
+assert pool.getEffectiveLevel() == "ERROR"
+"#);
+    }
+
+    #[test]
+    fn audit_log_verification_is_a_bool() {
+        let path = crate::testing::scratch("audit-bool").join("audit.log");
+        run(&format!(
+            r#"
+path = {:?}
+s = soda.getLogger("audited")
+s.addFileHandler(path, security=soda.FileSecurity(audit=True))
+for n in range(4):
+    s.info("record %d", n)
+s.flush()
+assert soda.verifyAuditLog(path) is True
+assert soda.findAuditBreak(path) is None
+
+lines = open(path).read().splitlines()
+lines[2] = lines[2].replace("record 1", "record 9")
+open(path, "w").write("\n".join(lines) + "\n")
+assert soda.verifyAuditLog(path) is False
+assert soda.findAuditBreak(path)["line"] == 3
+"#,
+            path.to_str().unwrap()
+        ));
+    }
+
+    #[test]
+    fn file_option_groups_round_trip_through_the_config() {
+        let path = crate::testing::scratch("option-groups").join("app.log.gz");
+        run(&format!(
+            r#"
+config = {{"handlers": {{"file": {{
+    "path": {:?},
+    "rotation": {{"max_bytes": 1024, "backup_count": 2}},
+    "security": {{}},
+    "compression": {{"level": 9}},
+}}}}}}
+exported = soda.dictConfig(config).exportConfig()["handlers"]["file"]
+assert exported["rotation"]["max_bytes"] == 1024, exported
+assert exported["rotation"]["backup_count"] == 2, exported
+assert exported["compression"] == {{"algorithm": "gzip", "level": 9}}, exported
+assert "security" not in exported, exported
+again = soda.dictConfig({{"handlers": {{"file": exported}}}})
+assert again.exportConfig()["handlers"]["file"] == exported
+
+try:
+    soda.FileRotation(backup_count=2)
+except ValueError as e:
+    assert "max_bytes" in str(e), e
+else:
+    raise AssertionError("a FileRotation without a trigger")
+"#,
+            path.to_str().unwrap()
+        ));
+    }
+
+    #[test]
+    fn context_fields_follow_the_task_that_bound_them() {
+        run(r#"
+import asyncio
+
+s = soda.getLogger("contextvars")
+memory = s.addMemoryHandler()
+
+async def nested():
+    s.info("nested")
+
+async def bound(started, other_logged):
+    s.bind_contextvar(trace_id="abc")
+    started.set()
+    await nested()
+    await other_logged.wait()
+
+async def unbound(started, other_logged):
+    await started.wait()
+    s.info("unbound")
+    other_logged.set()
+
+async def main():
+    started, other_logged = asyncio.Event(), asyncio.Event()
+    await asyncio.gather(bound(started, other_logged), unbound(started, other_logged))
+
+asyncio.run(main())
+s.info("outside")
+fields = {r["message"]: r["extra"].get("trace_id") for r in memory.getStructuredRecords()}
+assert fields == {"nested": "abc", "unbound": None, "outside": None}, fields
+"#);
+    }
+
+    #[test]
+    fn level_methods_below_the_level_are_dropped() {
+        run(r#"
+s = soda.Soda()
+memory = s.addMemoryHandler()
+s.setLevel(3)
+s.info("dropped")
+s.bind(user="x").debug("dropped too")
+s.warning("kept")
+s.reconfigure(level="ERROR")
+s.warning("dropped as well")
+s.error("kept too")
+assert [r["message"] for r in memory.getStructuredRecords()] == ["kept", "kept too"]
+"#);
+    }
+
+    #[test]
+    fn default_fields_are_merged_unless_replaced() {
+        run(r#"
+s = soda.Soda()
+memory = s.addMemoryHandler()
+s.setDefaults({"service": "api", "env": "prod"})
+s.setDefaultFields({"version": "1.4.2", "env": "staging"})
+s.info("merged")
+s.info("overridden", env="dev")
+s.setDefaultFields({"version": "1.5.0"}, replace=True)
+s.info("replaced")
+assert [r["extra"] for r in memory.getStructuredRecords()] == [
+    {"service": "api", "env": "staging", "version": "1.4.2"},
+    {"service": "api", "env": "dev", "version": "1.4.2"},
+    {"version": "1.5.0"},
+]
+"#);
+    }
+
+    #[test]
+    fn template_extras_can_be_given_per_call() {
+        run(r#"
+s = soda.Soda()
+memory = s.addMemoryHandler()
+s.info("user {user} logged in", user="bob", template_extras=False)
+s.info("user {user} logged in", user="eve")
+s.setTemplateExtras(False)
+s.info("user {user} logged in", user="sam", template_extras=True)
+records = memory.getStructuredRecords()
+assert [r["message"] for r in records] == [
+    "user bob logged in",
+    "user eve logged in",
+    "user sam logged in",
+]
+assert [r["extra"] for r in records] == [{}, {"user": "eve"}, {"user": "sam"}]
+"#);
+    }
+
+    #[test]
+    fn verbosity_sets_the_level() {
+        run(r#"
+s = soda.Soda(verbosity=3)
+memory = s.addMemoryHandler()
+s.info("dropped")
+s.warning("kept")
+assert s.getEffectiveLevel() == "WARNING"
+assert [r["message"] for r in memory.getStructuredRecords()] == ["kept"]
+assert soda.Soda().getEffectiveLevel() == "NOTSET"
+"#);
+    }
+
+    #[test]
+    fn records_below_the_level_cost_no_processing() {
+        run(r#"
+import logging
+
+class Costly:
+    converted = 0
+    def __str__(self):
+        Costly.converted += 1
+        return "costly"
+
+s = soda.Soda()
+processed = []
+s.addProcessor(lambda record: processed.append(record.message) or record)
+s.reconfigure(level="WARNING")
+s.info("dropped", sample=0.0)
+with s.timeit("dropped too", level="DEBUG"):
+    pass
+
+stdlib = logging.getLogger("below_the_level_cost_no_processing")
+stdlib.propagate = False
+stdlib.setLevel(logging.DEBUG)
+stdlib.addHandler(soda.LoggingHandler(s))
+stdlib.info("%s", Costly())
+stdlib.warning("kept")
+
+assert processed == ["kept"]
+assert Costly.converted == 0
+assert s.stats()["sampled_out"] == {}
+"#);
+    }
+
+    #[test]
+    fn hooks_skip_records_below_the_level() {
+        run(r#"
+s = soda.Soda()
+s.addMemoryHandler()
+hooked = []
+s.onBeforeEmit(lambda record: hooked.append(("before", record.message)))
+s.onAfterEmit(lambda record, handlers: hooked.append(("after", record.message)))
+s.reconfigure(level="ERROR")
+s.warning("dropped")
+s.error("kept")
+assert hooked == [("before", "kept"), ("after", "kept")]
+"#);
+    }
+
+    #[test]
+    fn lazy_fields_skip_records_below_the_level() {
+        run(r#"
+calls = []
+def depth():
+    calls.append(1)
+    return len(calls)
+
+s = soda.Soda()
+memory = s.addMemoryHandler()
+s.reconfigure(level="WARNING")
+log = s.bind(depth=depth)
+log.info("dropped")
+log.debug("dropped too")
+log.warning("kept")
+assert [r["extra"]["depth"] for r in memory.getStructuredRecords()] == [1]
+assert len(calls) == 1
+"#);
+    }
+
+    #[test]
+    fn a_quiet_startup_ends_on_time_with_nothing_logged() {
+        run(r#"
+import time
+
+s = soda.Soda()
+memory = s.addMemoryHandler()
+s.quietStartup(timeout=0.1)
+s.info("held back")
+assert memory.getRecords() == []
+time.sleep(0.5)
+assert [r["message"] for r in memory.getStructuredRecords()] == ["held back"]
+
+s.quietStartup(timeout=0.1)
+s.info("ready early")
+s.markReady()
+time.sleep(0.2)
+assert [r["message"] for r in memory.getStructuredRecords()] == ["held back", "ready early"]
+"#);
+    }
+
+    /// Runs `code` with `a` and `b`, the paths of two files in a scratch
+    /// directory called `name`, and `read(path)` giving a file's lines.
+    fn with_two_files(name: &str, code: &str) {
+        let dir = crate::testing::scratch(name);
+        run(&format!(
+            r#"
+a = {:?}
+b = {:?}
+def read(path):
+    return open(path).read().splitlines()
+{}"#,
+            dir.join("a.log").to_str().unwrap(),
+            dir.join("b.log").to_str().unwrap(),
+            code
+        ));
+    }
+
+    #[test]
+    fn each_name_gets_a_file_handler_of_its_own() {
+        with_two_files(
+            "two-files",
+            r#"
+s = soda.Soda()
+s.addFileHandler(a, name="a", line_format="{message}")
+s.addFileHandler(b, name="b", line_format="{message}")
+s.info("both")
+s.flush()
+assert read(a) == ["both"] and read(b) == ["both"]
+
+s.addFileHandler(a, name="a", line_format="again {message}")
+s.info("replaced")
+s.flush()
+assert read(a) == ["both", "again replaced"] and read(b) == ["both", "replaced"]
+
+s.addMemoryHandler(name="audit")
+try:
+    s.addJsonHandler(name="audit")
+except ValueError as e:
+    assert "memory" in str(e)
+else:
+    raise AssertionError("a name taken by another kind was accepted")
+"#,
+        );
+    }
+
+    #[test]
+    fn overlapping_routes_send_to_every_handler_they_name() {
+        with_two_files(
+            "overlapping-routes",
+            r#"
+s = soda.getLogger("overlapping")
+s.addFileHandler(a, name="a", line_format="{message}")
+s.addFileHandler(b, name="b", line_format="{message}")
+memory = s.addMemoryHandler()
+s.addRoute(match={"name_prefix": "overlapping.app"}, handlers=["a"])
+s.addRoute(match={"min_level": "ERROR"}, handlers=["b"])
+app = s.getChild("app")
+app.info("app info")
+app.error("app error")
+s.getChild("db").error("db error")
+s.getChild("db").info("db info")
+s.flush()
+assert read(a) == ["app info", "app error"]
+assert read(b) == ["app error", "db error"]
+assert [r["message"] for r in memory.getStructuredRecords()] == [
+    "app info", "app error", "db error", "db info"
+]
+"#,
+        );
+    }
+
+    #[test]
+    fn an_exclusive_route_keeps_its_records_to_its_handlers() {
+        with_two_files(
+            "exclusive-route",
+            r#"
+s = soda.getLogger("exclusive")
+s.addFileHandler(a, name="a", line_format="{message}")
+s.addFileHandler(b, name="b", line_format="{message}")
+memory = s.addMemoryHandler()
+s.addRoute(match={"name_prefix": "exclusive.audit"}, handlers=["a"], exclusive=True)
+s.addRoute(match={"min_level": "ERROR"}, handlers=["b"])
+s.getChild("audit").error("audit error")
+s.getChild("app").error("app error")
+s.flush()
+assert read(a) == ["audit error"]
+assert read(b) == ["app error"]
+assert [r["message"] for r in memory.getStructuredRecords()] == ["app error"]
+"#,
+        );
+    }
+
+    #[test]
+    fn calls_and_flushes_go_to_the_named_handler() {
+        with_two_files(
+            "named-calls",
+            r#"
+s = soda.Soda()
+s.addFileHandler(a, name="a", line_format="{message}", buffer_size=65536)
+s.addFileHandler(b, name="b", line_format="{message}", buffer_size=65536)
+s.info("to b alone", handlers=["b"])
+s.info("to both")
+s.flushHandler("b")
+assert read(a) == [] and read(b) == ["to b alone", "to both"]
+s.flushHandler("a")
+assert read(a) == ["to both"]
+
+s.setHandlerEnabled("b", False)
+s.info("to a alone")
+s.flush()
+assert read(a) == ["to both", "to a alone"] and read(b) == ["to b alone", "to both"]
+"#,
+        );
+    }
+
+    #[test]
+    fn an_uncaught_exception_is_logged_before_the_process_ends() {
+        let path = crate::testing::scratch("excepthook").join("crash.log");
+        let ended = subprocess(&format!(
+            r#"
+s = soda.getLogger("crashing")
+s.addFileHandler({:?}, line_format="{{level}} {{message}}")
+s.installExcepthook()
+
+def handle():
+    raise ValueError("boom")
+
+handle()
+"#,
+            path.to_str().unwrap()
+        ));
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let stderr = String::from_utf8_lossy(&ended.stderr);
+        assert_eq!(ended.status.code(), Some(1), "{}", stderr);
+        assert!(
+            log.starts_with("CRITICAL uncaught ValueError: boom\n"),
+            "{}",
+            log
+        );
+        assert!(
+            log.contains("Traceback (most recent call last):"),
+            "{}",
+            log
+        );
+        assert!(log.contains("in handle"), "{}", log);
+        // Chained by default, the original hook still prints it.
+        assert!(stderr.contains("ValueError: boom"), "{}", stderr);
+    }
+
+    #[test]
+    fn buffered_records_are_written_out_at_a_normal_exit() {
+        let path = crate::testing::scratch("atexit").join("exit.log");
+        let ended = subprocess(&format!(
+            r#"
+path = {:?}
+s = soda.getLogger("exiting")
+s.addFileHandler(path, buffer_size=64 * 1024)
+for n in range(3):
+    s.info("buffered %d", n)
+assert open(path).read() == ""
+"#,
+            path.to_str().unwrap()
+        ));
+
+        let stderr = String::from_utf8_lossy(&ended.stderr);
+        assert!(ended.status.success(), "{}", stderr);
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines, ["buffered 0", "buffered 1", "buffered 2"]);
+    }
+
+    #[test]
+    fn a_killed_writer_leaves_a_stream_zcat_reads_up_to_the_last_flush() {
+        let path = crate::testing::scratch("killed-stream").join("trace.log.gz");
+        let ended = subprocess(&format!(
+            r#"
+import os, signal
+
+s = soda.getLogger("killed-stream")
+s.addFileHandler({:?}, compression=soda.FileCompression(), buffer_size=64 * 1024)
+for n in range(100):
+    s.info("flushed %d", n)
+s.flush()
+for n in range(100):
+    s.info("lost %d", n)
+os.kill(os.getpid(), signal.SIGKILL)
+"#,
+            path.to_str().unwrap()
+        ));
+        assert_eq!(ended.status.code(), None, "{:?}", ended);
+
+        let recovered = std::process::Command::new("zcat")
+            .arg(&path)
+            .output()
+            .unwrap();
+        // Without the trailer zcat reports the stream cut short, and gives
+        // what it read anyway.
+        assert!(!recovered.status.success());
+        let text = String::from_utf8(recovered.stdout).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let flushed: Vec<String> = (0..100).map(|n| format!("flushed {}", n)).collect();
+        assert_eq!(lines, flushed);
+    }
+
+    #[test]
+    fn parsed_records_have_the_fields_they_were_logged_with() {
+        let dir = crate::testing::scratch("parse-round-trip");
+        run(&format!(
+            r#"
+text, json = {:?}, {:?}
+template = "{{time}} {{level}} {{name}} user={{extra[user]}} {{message}}"
+s = soda.getLogger("parsed")
+s.reconfigure(level="INFO")
+s.addFileHandler(text, line_format=template)
+s.addJsonHandler(json)
+
+s.info("signed in", user="bob")
+s.warning("retries: %d", 3, user="eve")
+try:
+    raise ValueError("card declined")
+except ValueError:
+    s.error("payment failed", exc_info=True, user="bob")
+s.flush()
+with open(text, "a") as f:
+    f.write("not a record\n")
+
+logged = [
+    ("INFO", "signed in", "bob"),
+    ("WARNING", "retries: 3", "eve"),
+    ("ERROR", "payment failed", "bob"),
+]
+records = list(soda.parseLog(text, format=template))
+assert len(records) == 4, records
+for record, (level, message, user) in zip(records, logged):
+    assert record["level"] == level, record
+    assert record["name"] == "parsed", record
+    assert record["user"] == user, record
+    assert record["message"].startswith(message), record
+    assert "timestamp" in record, record
+traceback = records[2]["message"].splitlines()
+assert traceback[1] == "Traceback (most recent call last):", traceback
+assert traceback[-1] == "ValueError: card declined", traceback
+assert records[3] == {{"raw": "not a record"}}, records[3]
+
+records = list(soda.parseLog(json, format="json"))
+assert [(r["level"], r["message"], r["user"]) for r in records] == logged, records
+assert records[2]["exception"]["type"] == "ValueError", records[2]
+assert [r["timestamp"] for r in records] == [
+    r["timestamp"] for r in soda.parseLog(json)
+]
+"#,
+            dir.join("parsed.log").to_str().unwrap(),
+            dir.join("parsed.json").to_str().unwrap()
+        ));
+    }
+
+    #[test]
+    fn the_asgi_example_gives_each_request_its_correlation_id() {
+        let dir = crate::testing::scratch("asgi");
+        let paths = format!(
+            "text, json = {:?}, {:?}\n",
+            dir.join("asgi.log").to_str().unwrap(),
+            dir.join("asgi.json").to_str().unwrap()
+        );
+        let driver = r#"
+import asyncio, json as json_module, uuid
+
+s = soda.getLogger("asgi")
+s.reconfigure(level="INFO")
+s.addFileHandler(text, line_format="{extra[request_id]} {message}")
+s.addJsonHandler(json)
+
+async def app(scope, receive, send):
+    s.info("handling %s", scope["path"])
+    # The other request runs meanwhile, with an id of its own.
+    await asyncio.sleep(0)
+    s.info("handled %s", scope["path"])
+    await send({"type": "http.response.start", "status": 200, "headers": []})
+    await send({"type": "http.response.body", "body": b"ok"})
+
+middleware = CorrelationId(app, s)
+
+async def request(path, headers=()):
+    sent = []
+
+    async def receive():
+        return {"type": "http.request", "body": b""}
+
+    async def send(message):
+        sent.append(message)
+
+    scope = {"type": "http", "path": path, "headers": list(headers)}
+    await middleware(scope, receive, send)
+    assert s.correlation_id() is None
+    return dict(sent[0]["headers"])[b"x-request-id"].decode()
+
+async def main():
+    return await asyncio.gather(
+        request("/new"), request("/given", [(b"x-request-id", b"given-id")])
+    )
+
+new, given = asyncio.run(main())
+assert given == "given-id"
+assert uuid.UUID(new).version == 4
+s.info("outside")
+s.flush()
+
+ids = {"/new": new, "/given": given}
+lines = open(text).read().splitlines()
+for path, request_id in ids.items():
+    assert "%s handling %s" % (request_id, path) in lines, lines
+    assert "%s handled %s" % (request_id, path) in lines, lines
+
+records = [json_module.loads(line) for line in open(json)]
+for record in records[:-1]:
+    assert record["request_id"] == ids[record["message"].split()[1]], record
+assert records[-1]["message"] == "outside" and "request_id" not in records[-1]
+"#;
+
+        run(&[
+            include_str!("../../tests/asgi_middleware.py"),
+            &paths,
+            driver,
+        ]
+        .concat());
+    }
+
+    /// The clock is the whole process's, it's moved in one of its own.
+    #[test]
+    fn a_daily_rotation_fires_as_the_clock_passes_midnight() {
+        let path = crate::testing::scratch("clock-rollover").join("app.log");
+        let ended = subprocess(&format!(
+            r#"
+import datetime, os
+
+path = {:?}
+dated = path + ".2024-02-29"
+read = lambda path: open(path).read().splitlines()
+
+soda.setClock(datetime.datetime(2024, 3, 1).timestamp() - 1)
+s = soda.getLogger("rollover")
+s.reconfigure(level="INFO")
+s.addFileHandler(path, rotation=soda.FileRotation(period="daily"))
+
+s.info("a second to go")
+soda.advanceClock(0.999)
+s.info("the last millisecond")
+assert not os.path.exists(dated)
+assert read(path) == ["a second to go", "the last millisecond"], read(path)
+
+soda.advanceClock(0.001)
+s.info("midnight")
+assert read(dated) == ["a second to go", "the last millisecond"], read(dated)
+assert read(path) == ["midnight"], read(path)
+"#,
+            path.to_str().unwrap()
+        ));
+
+        assert!(ended.status.success(), "{:?}", ended);
+    }
+
+    #[test]
+    fn a_batch_logs_every_record_faster_than_a_loop() {
+        run(r#"
+import time
+
+s = soda.getLogger("batched")
+s.reconfigure(level="INFO")
+memory = s.addMemoryHandler()
+levels = ["INFO", "WARNING", 40, "DEBUG"]
+batch = [
+    (levels[n % 4], "imported %d" % n, {"n": n}, 1_600_000_000 + n)
+    for n in range(10000)
+]
+
+assert s.logBatch(batch) == 7500
+records = memory.getStructuredRecords()
+assert len(records) == 7500, len(records)
+for record in records:
+    n = record["extra"]["n"]
+    assert n % 4 != 3, n
+    assert record["level"] == ["INFO", "WARNING", "ERROR"][n % 4], record
+    assert record["message"] == "imported %d" % n, record
+    assert record["time"] == 1_600_000_000 + n, record
+
+# The best of a few runs each, the same records logged without handlers so
+# only what the call costs is timed.
+def best(log):
+    runs = []
+    for _ in range(3):
+        started = time.perf_counter()
+        log()
+        runs.append(time.perf_counter() - started)
+    return min(runs)
+
+timed = soda.getLogger("batched-timed")
+timed.reconfigure(level="INFO")
+methods = {"INFO": timed.info, "WARNING": timed.warning, 40: timed.error, "DEBUG": timed.debug}
+
+def loop():
+    for level, message, extra, timestamp in batch:
+        methods[level](message, **extra)
+
+batched, looped = best(lambda: timed.logBatch(batch)), best(loop)
+assert batched < looped, (batched, looped)
+"#);
+    }
+}
@@ -0,0 +1,50 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Reads the active span from the Python `opentelemetry.trace` API.
+///
+/// The lookup is compiled once, into a single callable, so a record only
+/// pays for one Python call.
+pub struct OtelContext {
+    current: PyObject,
+}
+
+impl OtelContext {
+    /// Returns `None` when `opentelemetry` isn't importable.
+    pub fn load(py: Python) -> Option<OtelContext> {
+        let trace = py.import("opentelemetry.trace").ok()?;
+
+        let globals = PyDict::new(py);
+        globals
+            .set_item("get_current_span", trace.getattr("get_current_span").ok()?)
+            .ok()?;
+
+        let current = py
+            .eval(
+                "lambda: get_current_span().get_span_context()",
+                Some(globals),
+                None,
+            )
+            .ok()?;
+
+        Some(OtelContext {
+            current: current.into(),
+        })
+    }
+
+    /// Hex encoded `(trace_id, span_id)` of the active span, if there is one.
+    pub fn ids(&self, py: Python) -> Option<(String, String)> {
+        let context = self.current.call0(py).ok()?;
+        let context = context.as_ref(py);
+
+        let trace_id: u128 = context.getattr("trace_id").ok()?.extract().ok()?;
+        let span_id: u64 = context.getattr("span_id").ok()?.extract().ok()?;
+
+        // The invalid span context uses all zero ids.
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some((format!("{:032x}", trace_id), format!("{:016x}", span_id)))
+    }
+}
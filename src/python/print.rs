@@ -0,0 +1,187 @@
+use std::cell::Cell;
+use std::sync::Mutex;
+
+use pyo3::prelude::*;
+use serde_json::Value;
+
+use super::{log_record, Soda};
+use crate::logger::HandlerId;
+use crate::record::Record;
+use crate::stats::HandlerKind;
+use crate::Level;
+
+/// `sys.stdout` and `sys.stderr` as they were before `redirect`.
+static ORIGINAL: Mutex<Option<(PyObject, PyObject)>> = Mutex::new(None);
+
+thread_local! {
+    /// Set while a writer logs a line, so whatever the handlers print on the
+    /// way (the notebook console writes to `sys.stdout`) goes to the original
+    /// stream rather than round again.
+    static WRITING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Stands in for `sys.stdout` or `sys.stderr`, logging each line printed to
+/// it.
+#[pyclass]
+pub struct PrintWriter {
+    soda: Py<Soda>,
+    stream: &'static str,
+    level: Level,
+    original: PyObject,
+    /// Whether the console prints lines as records, formatted and
+    /// timestamped, rather than `original` getting them as they were.
+    timestamp: bool,
+    /// What was written since the last newline.
+    pending: Mutex<String>,
+}
+
+impl PrintWriter {
+    fn log(&self, py: Python, line: String) -> PyResult<()> {
+        let soda = match self.soda.try_borrow(py) {
+            Ok(soda) => soda,
+            // Printed from within a soda call, by a console writer say.
+            Err(_) => return self.forward(py, format!("{}\n", line)),
+        };
+
+        if !self.timestamp {
+            self.forward(py, format!("{}\n", line))?;
+        }
+
+        let mut record = Record::new(self.level, soda.logger.name(), &line);
+        record.caller = log_record::caller(py);
+        record.thread = log_record::thread(py);
+        record
+            .extras
+            .insert(String::from("stream"), Value::from(self.stream));
+        soda.annotate(py, &mut record);
+
+        let skipped = Some(HandlerId::first(HandlerKind::Console)).filter(|_| !self.timestamp);
+        WRITING.with(|writing| writing.set(true));
+        let emitted = soda.logger.emit_except(record, skipped);
+        WRITING.with(|writing| writing.set(false));
+
+        emitted.map_err(|e| soda.raise(e))
+    }
+
+    fn forward(&self, py: Python, text: String) -> PyResult<()> {
+        self.original.call_method1(py, "write", (text,))?;
+
+        Ok(())
+    }
+
+    /// Logs what's left of an unfinished line.
+    fn finish(&self, py: Python) -> PyResult<()> {
+        let rest = std::mem::take(&mut *self.pending.lock().unwrap());
+
+        match rest.is_empty() {
+            true => Ok(()),
+            false => self.log(py, rest),
+        }
+    }
+}
+
+#[pymethods]
+impl PrintWriter {
+    fn write(&self, py: Python, text: &str) -> PyResult<usize> {
+        if WRITING.with(Cell::get) {
+            self.forward(py, text.to_string())?;
+            return Ok(text.chars().count());
+        }
+
+        let lines: Vec<String> = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push_str(text);
+
+            match pending.rfind('\n') {
+                Some(end) => {
+                    let complete: String = pending.drain(..=end).collect();
+                    complete.lines().map(String::from).collect()
+                }
+                None => Vec::new(),
+            }
+        };
+
+        for line in lines {
+            self.log(py, line)?;
+        }
+
+        Ok(text.chars().count())
+    }
+
+    /// An unfinished line stays pending, so `print(..., end="", flush=True)`
+    /// doesn't split it into several records.
+    fn flush(&self, py: Python) -> PyResult<()> {
+        if let Ok(soda) = self.soda.try_borrow(py) {
+            soda.flush();
+        }
+        self.original.call_method0(py, "flush")?;
+
+        Ok(())
+    }
+
+    fn isatty(&self, py: Python) -> PyResult<PyObject> {
+        self.original.call_method0(py, "isatty")
+    }
+
+    fn fileno(&self, py: Python) -> PyResult<PyObject> {
+        self.original.call_method0(py, "fileno")
+    }
+
+    #[getter]
+    fn encoding(&self, py: Python) -> PyResult<PyObject> {
+        self.original.getattr(py, "encoding")
+    }
+}
+
+/// Swaps `sys.stdout` and `sys.stderr` for writers logging through `soda`,
+/// at INFO and ERROR. Redirecting again replaces the writers, still in front
+/// of the original streams.
+pub fn redirect(py: Python, soda: Py<Soda>, timestamp: bool) -> PyResult<()> {
+    let sys = py.import("sys")?;
+    let mut original = ORIGINAL.lock().unwrap();
+
+    let (stdout, stderr) = match original.as_ref() {
+        Some((stdout, stderr)) => (stdout.clone_ref(py), stderr.clone_ref(py)),
+        None => (sys.getattr("stdout")?.into(), sys.getattr("stderr")?.into()),
+    };
+
+    let writer = |stream, level, original: &PyObject| {
+        Py::new(
+            py,
+            PrintWriter {
+                soda: soda.clone_ref(py),
+                stream,
+                level,
+                original: original.clone_ref(py),
+                timestamp,
+                pending: Mutex::new(String::new()),
+            },
+        )
+    };
+    let stdout_writer = writer("stdout", Level::INFO, &stdout)?;
+    let stderr_writer = writer("stderr", Level::ERROR, &stderr)?;
+
+    sys.setattr("stdout", stdout_writer)?;
+    sys.setattr("stderr", stderr_writer)?;
+    *original = Some((stdout, stderr));
+
+    Ok(())
+}
+
+/// Puts back the streams `redirect` replaced, returns whether they were.
+pub fn restore(py: Python) -> PyResult<bool> {
+    let (stdout, stderr) = match ORIGINAL.lock().unwrap().take() {
+        Some(streams) => streams,
+        None => return Ok(false),
+    };
+
+    let sys = py.import("sys")?;
+    for (name, stream) in [("stdout", stdout), ("stderr", stderr)].iter() {
+        if let Ok(writer) = sys.getattr(*name)?.extract::<PyRef<PrintWriter>>() {
+            writer.finish(py)?;
+        }
+        sys.setattr(*name, stream)?;
+    }
+
+    Ok(true)
+}
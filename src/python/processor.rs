@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use chrono::{Local, TimeZone};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use super::log_record::LogRecord;
+use super::value;
+use crate::format::SharedFormat;
+use crate::record::Record;
+use crate::stats::Stats;
+use crate::Level;
+
+/// Wraps a Python callable as a record processor. It gets the record as a
+/// `LogRecord` and returns it, changed or not, a dict of the fields to
+/// change, or `None` to drop the record. Should it raise or return something
+/// else, the error is printed and counted and the record goes on as it was.
+pub fn processor(
+    func: PyObject,
+    format: SharedFormat,
+    stats: Arc<Stats>,
+) -> impl Fn(Record) -> Option<Record> {
+    move |record| {
+        Python::with_gil(|py| {
+            let processed = Py::new(py, LogRecord::new(py, record.clone(), &format))
+                .and_then(|log_record| func.call1(py, (log_record,)))
+                .and_then(|result| {
+                    let result = result.as_ref(py);
+                    if result.is_none() {
+                        return Ok(None);
+                    }
+
+                    match result.extract::<PyRef<LogRecord>>() {
+                        Ok(log_record) => Ok(Some(log_record.to_record(py))),
+                        Err(_) => {
+                            let mut changed = record.clone();
+                            apply(result.downcast()?, &mut changed)?;
+                            Ok(Some(changed))
+                        }
+                    }
+                });
+
+            match processed {
+                Ok(processed) => processed,
+                Err(e) => {
+                    e.print(py);
+                    stats.processor_error();
+                    Some(record)
+                }
+            }
+        })
+    }
+}
+
+/// Wraps a Python callable as a record filter, it gets the same
+/// `LogRecord` as a processor and keeps the record when it returns a true
+/// value. Should it raise, the error is printed and counted and the record
+/// is kept.
+pub fn filter(func: PyObject, format: SharedFormat, stats: Arc<Stats>) -> impl Fn(&Record) -> bool {
+    move |record| {
+        Python::with_gil(|py| {
+            let kept = Py::new(py, LogRecord::new(py, record.clone(), &format))
+                .and_then(|log_record| func.call1(py, (log_record,)))
+                .and_then(|result| result.as_ref(py).is_true());
+
+            kept.unwrap_or_else(|e| {
+                e.print(py);
+                stats.filter_error();
+                true
+            })
+        })
+    }
+}
+
+/// Wraps a Python callable as a before emit hook. It gets the same
+/// `LogRecord` as a processor, what it changes on it is what the handlers
+/// write. Should it raise, the error is printed and counted and the record
+/// goes on as it was.
+pub fn before_emit(
+    func: PyObject,
+    format: SharedFormat,
+    stats: Arc<Stats>,
+) -> impl Fn(&mut Record) {
+    move |record| {
+        Python::with_gil(|py| {
+            let changed =
+                Py::new(py, LogRecord::new(py, record.clone(), &format)).and_then(|log_record| {
+                    func.call1(py, (log_record.clone_ref(py),))?;
+                    Ok(log_record.borrow(py).to_record(py))
+                });
+
+            match changed {
+                Ok(changed) => *record = changed,
+                Err(e) => {
+                    e.print(py);
+                    stats.hook_error();
+                }
+            }
+        })
+    }
+}
+
+/// Wraps a Python callable as an after emit hook, called with the
+/// `LogRecord` and a dict of each handler that got it, by name, to whether
+/// it wrote it. Should it raise, the error is printed and counted.
+pub fn after_emit(
+    func: PyObject,
+    format: SharedFormat,
+    stats: Arc<Stats>,
+) -> impl Fn(&Record, &[(String, bool)]) {
+    move |record, outcomes| {
+        Python::with_gil(|py| {
+            let written = PyDict::new(py);
+            let called = outcomes
+                .iter()
+                .try_for_each(|(name, ok)| written.set_item(name, ok))
+                .and_then(|()| Py::new(py, LogRecord::new(py, record.clone(), &format)))
+                .and_then(|log_record| func.call1(py, (log_record, written)));
+
+            if let Err(e) = called {
+                e.print(py);
+                stats.hook_error();
+            }
+        })
+    }
+}
+
+/// Wraps a Python callable as a file handler's `on_rotation`, called with
+/// the backup's path, or `None`, and the new file's. Should it raise, the
+/// error is printed and counted.
+pub fn on_rotation(func: PyObject, stats: Arc<Stats>) -> crate::handlers::file::OnRotation {
+    Arc::new(move |backup, path| {
+        Python::with_gil(|py| {
+            let backup = backup.map(|backup| backup.to_string_lossy().into_owned());
+            if let Err(e) = func.call1(py, (backup, path)) {
+                e.print(py);
+                stats.rotation_error();
+            }
+        })
+    })
+}
+
+/// Copies what a processor returned back onto the record, leaving it
+/// untouched if any of it is invalid.
+fn apply(fields: &PyDict, record: &mut Record) -> PyResult<()> {
+    let message = fields.get_item("message").map(value::to_text);
+    let level = match fields.get_item("level") {
+        Some(level) => {
+            let name: &str = level.extract()?;
+            Some(
+                Level::from_name(name)
+                    .ok_or_else(|| PyValueError::new_err(format!("unknown level {:?}", name)))?,
+            )
+        }
+        None => None,
+    };
+    let name = match fields.get_item("name") {
+        Some(name) => Some(name.extract::<String>()?),
+        None => None,
+    };
+    let extras = match fields.get_item("extras") {
+        Some(extras) => Some(value::map_from_dict(extras.downcast()?)),
+        None => None,
+    };
+    let time = match fields.get_item("time") {
+        Some(time) => {
+            let time: f64 = time.extract()?;
+            Local
+                .timestamp_opt(time.floor() as i64, (time.fract() * 1e9) as u32)
+                .single()
+        }
+        None => None,
+    };
+
+    if let Some(message) = message {
+        record.message = message;
+    }
+    if let Some(level) = level {
+        record.level = level;
+    }
+    if let Some(name) = name {
+        record.name = name;
+    }
+    if let Some(extras) = extras {
+        record.extras = extras;
+    }
+    if let Some(time) = time {
+        record.time = time;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyAssertionError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::handlers::memory::{self, MemoryLogger};
+use crate::logger::{self, HandlerSnapshot};
+use crate::Level;
+
+/// Only compiled once pytest is loaded, so importing soda never pulls it in.
+const FIXTURE: &str = r#"
+import pytest
+
+@pytest.fixture
+def soda_caplog():
+    """Captures every soda record logged during the test."""
+    caplog = SodaCaplog()
+    try:
+        yield caplog
+    finally:
+        caplog.close()
+"#;
+
+/// The `soda.pytest_plugin` module, registered as a `pytest11` entry point
+/// by the `pytest` extra or listed in a conftest's `pytest_plugins`.
+pub fn module(py: Python<'_>) -> PyResult<&'_ PyModule> {
+    let m = PyModule::new(py, "soda.pytest_plugin")?;
+    m.add_class::<SodaCaplog>()?;
+
+    let modules: &PyDict = py.import("sys")?.getattr("modules")?.downcast()?;
+    if modules.contains("pytest")? {
+        py.run(FIXTURE, Some(m.dict()), None)?;
+    }
+
+    // Lets `import soda.pytest_plugin` find the submodule.
+    modules.set_item("soda.pytest_plugin", m)?;
+
+    Ok(m)
+}
+
+/// Capture installed by the `soda_caplog` fixture.
+///
+/// Creating one sets the handlers of every live logger aside, so a test
+/// neither writes to the application's files and collectors nor keeps the
+/// handlers it adds itself, `close` puts the original ones back.
+#[pyclass]
+pub struct SodaCaplog {
+    memory: Arc<MemoryLogger>,
+    snapshot: Option<HandlerSnapshot>,
+}
+
+#[pymethods]
+impl SodaCaplog {
+    #[new]
+    fn new() -> SodaCaplog {
+        SodaCaplog {
+            snapshot: Some(logger::snapshot_handlers()),
+            memory: memory::start_capture(),
+        }
+    }
+
+    /// `(level, message)` of every captured record.
+    #[getter]
+    fn records(&self) -> Vec<(&'static str, String)> {
+        self.memory.with_entries(|entries| {
+            entries
+                .iter()
+                .map(|e| (e.record.level.as_str(), e.record.message.clone()))
+                .collect()
+        })
+    }
+
+    #[getter]
+    fn messages(&self) -> Vec<String> {
+        self.memory
+            .with_entries(|entries| entries.iter().map(|e| e.record.message.clone()).collect())
+    }
+
+    /// The captured records as they would have been printed.
+    #[getter]
+    fn text(&self) -> String {
+        self.memory
+            .with_entries(|entries| entries.iter().map(|e| format!("{}\n", e.line)).collect())
+    }
+
+    /// Fails unless a record at `level` contains `substring`.
+    fn assert_logged(&self, level: &str, substring: &str) -> PyResult<()> {
+        let level = Level::from_name(level)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown level {:?}", level)))?;
+
+        self.memory.with_entries(|entries| {
+            if entries.iter().any(|e| {
+                e.record.level as u8 == level as u8 && e.record.message.contains(substring)
+            }) {
+                return Ok(());
+            }
+
+            let logged: Vec<String> = entries
+                .iter()
+                .map(|e| {
+                    let record = &e.record;
+                    format!(
+                        "  {} {}: {}",
+                        record.level.as_str(),
+                        record.name,
+                        record.message
+                    )
+                })
+                .collect();
+
+            Err(PyAssertionError::new_err(format!(
+                "no {} record containing {:?}, captured:\n{}",
+                level.as_str(),
+                substring,
+                if logged.is_empty() {
+                    String::from("  (nothing)")
+                } else {
+                    logged.join("\n")
+                }
+            )))
+        })
+    }
+
+    fn clear(&self) {
+        self.memory.clear();
+    }
+
+    /// Stops capturing and restores the handlers, called at fixture teardown.
+    fn close(&mut self) {
+        memory::stop_capture(&self.memory);
+
+        if let Some(snapshot) = self.snapshot.take() {
+            logger::restore_handlers(snapshot);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use pyo3::prelude::*;
+use pyo3::PyIterProtocol;
+
+use super::value;
+use crate::parse::Records;
+
+/// The records of a log as `parseLog` reads them, one dict at a time.
+#[pyclass]
+pub struct LogRecords {
+    records: Records,
+}
+
+impl LogRecords {
+    pub fn new(records: Records) -> LogRecords {
+        LogRecords { records }
+    }
+}
+
+#[pyproto]
+impl PyIterProtocol for LogRecords {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<Option<PyObject>> {
+        match slf.records.next() {
+            Some(record) => Ok(Some(value::to_py(slf.py(), &record?))),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use pyo3::prelude::*;
+use pyo3::AsPyPointer;
+
+use super::Soda;
+use crate::logger;
+use crate::record::Record;
+use crate::Level;
+
+/// How long a signal waits on each lock to flush the handlers.
+const TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Stands in for a signal's handler, flushing the handlers before the
+/// signal goes on to the handler that was there.
+#[pyclass]
+pub struct SignalFlush {
+    soda: Py<Soda>,
+    name: String,
+    /// `signal.SIG_DFL`, `signal.SIG_IGN`, a callable or `None` for one set
+    /// outside of Python.
+    previous: PyObject,
+}
+
+#[pymethods]
+impl SignalFlush {
+    #[call]
+    fn __call__(&self, py: Python, signum: i32, frame: &PyAny) -> PyResult<()> {
+        // Taking the locks with a timeout, a signal may come in while this
+        // thread holds one, and what can't be flushed then is left.
+        let flushed = logger::flush_all_within(TIMEOUT);
+        if let Ok(soda) = self.soda.try_borrow(py) {
+            let message = format!("received {}, flushed {} records", self.name, flushed);
+            let mut record = Record::new(Level::WARNING, "soda", &message);
+            soda.annotate(py, &mut record);
+
+            // Dropped when the signal came in while a record was handled.
+            let _ = soda.logger.emit(record);
+            logger::flush_all_within(TIMEOUT);
+        }
+
+        let signal = py.import("signal")?;
+        let previous = self.previous.as_ref(py);
+        if previous.as_ptr() == signal.getattr("SIG_IGN")?.as_ptr() {
+            return Ok(());
+        }
+        if previous.is_callable() {
+            previous.call1((signum, frame))?;
+            return Ok(());
+        }
+
+        // The default, ending the process for the signals worth flushing
+        // on, from the signal itself so its exit status says so.
+        signal.call_method1("signal", (signum, signal.getattr("SIG_DFL")?))?;
+        let os = py.import("os")?;
+        os.call_method1("kill", (os.call_method0("getpid")?, signum))?;
+
+        Ok(())
+    }
+}
+
+/// Sets the handler of each of the `signals`, `"SIGTERM"` and the like, to
+/// one flushing before the handler that was there. Installing again keeps
+/// that one rather than chaining to the flush.
+pub fn install(py: Python, soda: Py<Soda>, signals: Vec<String>) -> PyResult<()> {
+    let signal = py.import("signal")?;
+
+    for name in signals {
+        let signum = signal.getattr(name.as_str())?;
+        let current = signal.call_method1("getsignal", (signum,))?;
+        let previous = match current.extract::<PyRef<SignalFlush>>() {
+            Ok(installed) => installed.previous.clone_ref(py),
+            Err(_) => current.into(),
+        };
+
+        let handler = SignalFlush {
+            soda: soda.clone_ref(py),
+            name,
+            previous,
+        };
+        signal.call_method1("signal", (signum, Py::new(py, handler)?))?;
+    }
+
+    Ok(())
+}
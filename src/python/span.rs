@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyUnicode};
+use pyo3::wrap_pyfunction;
+use uuid::Uuid;
+
+use super::Soda;
+use crate::Level;
+
+/// Spans started and not ended yet, by id, ended as abandoned on the way out.
+static OPEN: Mutex<Vec<(String, Py<Span>)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// Ids of the spans open on this thread, innermost last, what a span
+    /// started on it takes its parent from.
+    static ACTIVE: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// What `Soda.start_span` returns. Its records all carry `span` and
+/// `span_id` fields, and `parent_span_id` when it was started inside another
+/// span, with `span_event` telling `"begin"`, `"event"` and `"end"` apart.
+/// The end record adds `elapsed_ms`, and `abandoned: true` for a span still
+/// open when the interpreter exits.
+#[pyclass]
+pub struct Span {
+    soda: Py<Soda>,
+    name: String,
+    id: String,
+    parent: Option<String>,
+    level: Level,
+    start: Instant,
+    elapsed_ms: Option<f64>,
+}
+
+impl Span {
+    /// Starts a span, logging its begin record.
+    pub fn start(py: Python, soda: Py<Soda>, name: &str, level: Level) -> PyResult<Py<Span>> {
+        let id = Uuid::new_v4().simple().to_string()[..16].to_string();
+        let parent = ACTIVE.with(|active| active.borrow().last().cloned());
+
+        let span = Span {
+            soda,
+            name: name.to_string(),
+            id: id.clone(),
+            parent,
+            level,
+            start: Instant::now(),
+            elapsed_ms: None,
+        };
+        span.log(
+            py,
+            level,
+            &format!("{} began", name),
+            "begin",
+            PyDict::new(py),
+        )?;
+
+        let span = Py::new(py, span)?;
+        ACTIVE.with(|active| active.borrow_mut().push(id.clone()));
+        OPEN.lock().unwrap().push((id, span.clone_ref(py)));
+
+        Ok(span)
+    }
+
+    fn log(
+        &self,
+        py: Python,
+        level: Level,
+        message: &str,
+        event: &str,
+        kwargs: &PyDict,
+    ) -> PyResult<()> {
+        kwargs.set_item("span", &self.name)?;
+        kwargs.set_item("span_id", &self.id)?;
+        if let Some(parent) = &self.parent {
+            kwargs.set_item("parent_span_id", parent)?;
+        }
+        kwargs.set_item("span_event", event)?;
+
+        let soda = self.soda.borrow(py);
+        if !soda.logger.is_enabled_for(level) {
+            return Ok(());
+        }
+        let record = soda.record(
+            level,
+            PyUnicode::new(py, message),
+            PyTuple::empty(py),
+            Some(kwargs),
+            None,
+        )?;
+
+        soda.emit(record)
+    }
+
+    /// Logs the end record, at `level` with `error` as its exception when
+    /// given. Does nothing for a span already ended.
+    fn finish(&mut self, py: Python, error: Option<&PyAny>, abandoned: bool) -> PyResult<()> {
+        if self.elapsed_ms.is_some() {
+            return Ok(());
+        }
+        let elapsed_ms = self.start.elapsed().as_secs_f64() * 1e3;
+        self.elapsed_ms = Some(elapsed_ms);
+
+        let id = &self.id;
+        ACTIVE.with(|active| active.borrow_mut().retain(|active| active != id));
+        OPEN.lock().unwrap().retain(|(open, _)| open != id);
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("elapsed_ms", elapsed_ms)?;
+
+        let (level, outcome) = match error {
+            Some(error) => {
+                kwargs.set_item("exc_info", error)?;
+                (Level::ERROR, "failed")
+            }
+            None if abandoned => {
+                kwargs.set_item("abandoned", true)?;
+                (Level::WARNING, "abandoned")
+            }
+            None => (self.level, "ended"),
+        };
+        let message = format!("{} {} after {:.1}ms", self.name, outcome, elapsed_ms);
+
+        self.log(py, level, &message, "end", kwargs)
+    }
+}
+
+#[pymethods]
+impl Span {
+    /// Logs `message` with the span's fields, plus `fields`.
+    #[args(fields = "**")]
+    fn event(&self, py: Python, message: &str, fields: Option<&PyDict>) -> PyResult<()> {
+        let kwargs = match fields {
+            Some(fields) => fields.copy()?,
+            None => PyDict::new(py),
+        };
+
+        self.log(py, self.level, message, "event", kwargs)
+    }
+
+    /// Logs the end record. Ending a span twice logs it once.
+    fn end(&mut self, py: Python) -> PyResult<()> {
+        self.finish(py, None, false)
+    }
+
+    fn __enter__(slf: &PyCell<Span>) -> &PyCell<Span> {
+        slf
+    }
+
+    /// Ends the span, at `ERROR` with the exception when one ended the
+    /// block. Never swallows the exception.
+    fn __exit__(
+        &mut self,
+        py: Python,
+        _kind: &PyAny,
+        error: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        let error = if error.is_none() { None } else { Some(error) };
+        self.finish(py, error, false)?;
+
+        Ok(false)
+    }
+
+    #[getter]
+    fn span_id(&self) -> &str {
+        &self.id
+    }
+
+    #[getter]
+    fn parent_span_id(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+
+    /// Milliseconds the span has been open for, or was once it ended.
+    #[getter]
+    fn elapsed_ms(&self) -> f64 {
+        self.elapsed_ms
+            .unwrap_or_else(|| self.start.elapsed().as_secs_f64() * 1e3)
+    }
+}
+
+/// Has the spans still open at exit ended as abandoned.
+pub fn end_at_exit(py: Python, m: &PyModule) -> PyResult<()> {
+    py.import("atexit")?
+        .call_method1("register", (wrap_pyfunction!(end_abandoned, m)?,))?;
+
+    Ok(())
+}
+
+/// Ends the spans still open as abandoned and flushes their loggers.
+#[pyfunction]
+fn end_abandoned(py: Python) -> PyResult<()> {
+    let open = std::mem::take(&mut *OPEN.lock().unwrap());
+
+    for (_, span) in open {
+        let mut span = match span.try_borrow_mut(py) {
+            Ok(span) => span,
+            Err(_) => continue,
+        };
+        span.finish(py, None, true)?;
+        span.soda.borrow(py).logger.flush();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use pyo3::prelude::*;
+
+use super::heartbeat::{self, Stop};
+use super::Soda;
+
+/// How long the thread waits to try again while the logger is being
+/// changed.
+const RETRY: Duration = Duration::from_millis(10);
+
+/// The thread `Soda.quietStartup` runs, emitting the records held back once
+/// the timeout passes even with nothing logged. Stopped and joined on drop.
+pub struct StartupTimer {
+    stop: Arc<Stop>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl StartupTimer {
+    pub fn start(soda: Py<Soda>, timeout: Duration) -> PyResult<StartupTimer> {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let worker_stop = Arc::clone(&stop);
+
+        let worker = thread::Builder::new()
+            .name(String::from("soda-quiet-startup"))
+            .spawn(move || run(soda, timeout, worker_stop))?;
+        heartbeat::running(&stop);
+
+        Ok(StartupTimer {
+            stop,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for StartupTimer {
+    fn drop(&mut self) {
+        heartbeat::stop_worker(&self.stop, self.worker.take());
+    }
+}
+
+fn run(soda: Py<Soda>, timeout: Duration, stop: Arc<Stop>) {
+    let mut wait = timeout;
+    while !heartbeat::wait(&stop, wait) {
+        let released = Python::with_gil(|py| {
+            if *stop.0.lock().unwrap() {
+                return true;
+            }
+            let soda = match soda.try_borrow(py) {
+                Ok(soda) => soda,
+                Err(_) => return false,
+            };
+            if let Err(e) = soda.logger.release_due() {
+                PyErr::from(e).print(py);
+            }
+            true
+        });
+        if released {
+            return;
+        }
+        wait = RETRY;
+    }
+}
@@ -0,0 +1,279 @@
+use chrono::{Local, TimeZone};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use pyo3::PyNativeType;
+
+use super::{context, value, Soda};
+use crate::record::{Caller, Record};
+use crate::Level;
+
+/// `LogRecord` attributes that aren't `extra=` fields.
+const RECORD_ATTRIBUTES: &[&str] = &[
+    "name",
+    "msg",
+    "args",
+    "levelname",
+    "levelno",
+    "pathname",
+    "filename",
+    "module",
+    "exc_info",
+    "exc_text",
+    "stack_info",
+    "lineno",
+    "funcName",
+    "created",
+    "msecs",
+    "relativeCreated",
+    "thread",
+    "threadName",
+    "processName",
+    "process",
+    "taskName",
+    "message",
+    "asctime",
+];
+
+/// A `logging` handler that emits records through a soda logger, so apps
+/// configured through the stdlib (`logging.config.dictConfig` and the like)
+/// still get soda's handlers.
+///
+/// It has the attributes and methods `logging` uses on a `Handler` rather
+/// than subclassing it. The first record it handles registers it with
+/// `logging`, like `Handler.__init__` would, so `logging.shutdown` flushes
+/// and closes it.
+#[pyclass(dict, weakref)]
+pub struct LoggingHandler {
+    soda: Py<Soda>,
+    #[pyo3(get, set)]
+    level: i64,
+    #[pyo3(get, set)]
+    formatter: Option<PyObject>,
+    #[pyo3(get)]
+    filters: Py<PyList>,
+    registered: bool,
+    closed: bool,
+}
+
+#[pymethods]
+impl LoggingHandler {
+    #[new]
+    #[args(level = "None")]
+    fn new(py: Python, soda: Py<Soda>, level: Option<&PyAny>) -> PyResult<LoggingHandler> {
+        Ok(LoggingHandler {
+            soda,
+            level: match level {
+                Some(level) => level_number(py, level)?,
+                None => 0,
+            },
+            formatter: None,
+            filters: PyList::empty(py).into(),
+            registered: false,
+            closed: false,
+        })
+    }
+
+    fn setLevel(&mut self, py: Python, level: &PyAny) -> PyResult<()> {
+        self.level = level_number(py, level)?;
+        Ok(())
+    }
+
+    fn setFormatter(&mut self, formatter: Option<PyObject>) {
+        self.formatter = formatter;
+    }
+
+    fn addFilter(&self, py: Python, filter: PyObject) -> PyResult<()> {
+        let filters: &PyAny = self.filters.as_ref(py);
+        if !filters
+            .call_method1("__contains__", (&filter,))?
+            .is_true()?
+        {
+            filters.call_method1("append", (filter,))?;
+        }
+        Ok(())
+    }
+
+    fn removeFilter(&self, py: Python, filter: PyObject) -> PyResult<()> {
+        let filters: &PyAny = self.filters.as_ref(py);
+        if filters
+            .call_method1("__contains__", (&filter,))?
+            .is_true()?
+        {
+            filters.call_method1("remove", (filter,))?;
+        }
+        Ok(())
+    }
+
+    /// `Filterer.filter`: false if any filter rejects the record, a filter
+    /// may also hand back a replacement record.
+    fn filter(&self, py: Python, record: PyObject) -> PyResult<PyObject> {
+        let mut record = record;
+
+        for filter in self.filters.as_ref(py).iter() {
+            let result = match filter.hasattr("filter")? {
+                true => filter.call_method1("filter", (record.clone_ref(py),))?,
+                false => filter.call1((record.clone_ref(py),))?,
+            };
+
+            if !result.is_true()? {
+                return Ok(false.into_py(py));
+            }
+            if result.hasattr("getMessage")? {
+                record = result.into();
+            }
+        }
+
+        Ok(record)
+    }
+
+    /// The record's message, or what the formatter set with `setFormatter`
+    /// makes of it.
+    fn format(&self, py: Python, record: &PyAny) -> PyResult<String> {
+        match &self.formatter {
+            Some(formatter) => formatter.call_method1(py, "format", (record,))?.extract(py),
+            None => record.call_method0("getMessage")?.extract(),
+        }
+    }
+
+    fn handle(slf: &PyCell<Self>, record: PyObject) -> PyResult<PyObject> {
+        let py = slf.py();
+
+        if !slf.borrow().registered {
+            py.import("logging")?
+                .call_method1("_addHandlerRef", (slf,))?;
+            slf.borrow_mut().registered = true;
+        }
+
+        let handler = slf.borrow();
+        let filtered = handler.filter(py, record)?;
+
+        if filtered.as_ref(py).is_true()? {
+            handler.emit(py, filtered.as_ref(py))?;
+        }
+
+        Ok(filtered)
+    }
+
+    /// Errors are reported the way `Handler.handleError` does, never raised.
+    fn emit(&self, py: Python, record: &PyAny) -> PyResult<()> {
+        if self.closed {
+            return Ok(());
+        }
+
+        // A record logged from within a soda call (by a console writer, say)
+        // is dropped rather than borrowing the logger twice, and one below
+        // the soda logger's level isn't converted at all.
+        let emitted = match self.soda.try_borrow(py) {
+            Ok(soda) => level(record).and_then(|level| match soda.logger.is_enabled_for(level) {
+                true => convert(py, record).and_then(|mut converted| {
+                    soda.annotate(py, &mut converted);
+                    soda.emit(converted)
+                }),
+                false => Ok(()),
+            }),
+            Err(_) => Ok(()),
+        };
+
+        if let Err(e) = emitted {
+            let raise = py
+                .import("logging")
+                .and_then(|logging| logging.getattr("raiseExceptions"))
+                .and_then(|raise| raise.is_true())
+                .unwrap_or(true);
+            if raise {
+                e.print(py);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self, py: Python) {
+        if let Ok(soda) = self.soda.try_borrow(py) {
+            soda.flush();
+        }
+    }
+
+    fn close(&mut self, py: Python) {
+        self.flush(py);
+        self.closed = true;
+    }
+
+    /// Emitting is serialized by soda itself, there is no handler lock.
+    fn acquire(&self) {}
+
+    fn release(&self) {}
+
+    fn createLock(&self) {}
+
+    fn handleError(&self, py: Python, _record: &PyAny) {
+        if let Ok(traceback) = py.import("traceback") {
+            let _ = traceback.call_method0("print_exc");
+        }
+    }
+}
+
+/// `setLevel` accepts level numbers and names alike.
+fn level_number(py: Python, level: &PyAny) -> PyResult<i64> {
+    if let Ok(level) = level.extract::<i64>() {
+        return Ok(level);
+    }
+
+    let logging = py.import("logging")?;
+    logging.call_method1("_checkLevel", (level,))?.extract()
+}
+
+/// Where a `LogRecord` was logged from.
+fn caller(record: &PyAny) -> Option<Caller> {
+    Some(Caller {
+        file: record.getattr("pathname").ok()?.extract().ok()?,
+        line: record.getattr("lineno").ok()?.extract().ok()?,
+        function: record.getattr("funcName").ok()?.extract().ok()?,
+    })
+}
+
+fn level(record: &PyAny) -> PyResult<Level> {
+    Ok(Level::from_number(record.getattr("levelno")?.extract()?))
+}
+
+/// Turns a `LogRecord` into a soda record.
+fn convert(py: Python, record: &PyAny) -> PyResult<Record> {
+    let message: String = record.call_method0("getMessage")?.extract()?;
+    let name: String = record.getattr("name")?.extract()?;
+
+    let mut converted = Record::new(level(record)?, &name, &message);
+    converted.caller = caller(record);
+    converted.thread = record.getattr("thread")?.extract().unwrap_or(None);
+
+    let created: f64 = record.getattr("created")?.extract()?;
+    if let Some(time) = Local
+        .timestamp_opt(created.floor() as i64, (created.fract() * 1e9) as u32)
+        .single()
+    {
+        converted.time = time;
+    }
+
+    // Fields passed with `extra=` end up as attributes on the record.
+    converted.extras = context::current(py);
+    let attributes: &PyDict = record.getattr("__dict__")?.downcast()?;
+    for (key, value) in attributes.iter() {
+        let key: String = key.extract()?;
+        if !RECORD_ATTRIBUTES.contains(&key.as_str()) {
+            converted.extras.insert(key, value::from_py(value));
+        }
+    }
+
+    let exc_info = record.getattr("exc_info")?;
+    if !exc_info.is_none() {
+        converted.exception = value::exception(exc_info, value::Capture::default());
+    }
+
+    let stack_info = record.getattr("stack_info")?;
+    if !stack_info.is_none() {
+        converted
+            .extras
+            .insert(String::from("stack_info"), value::from_py(stack_info));
+    }
+
+    Ok(converted)
+}
@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+use super::bound::Bound;
+use super::Soda;
+use crate::Level;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyUnicode};
+use pyo3::PyNativeType;
+
+/// The context manager `Soda.timeit` returns. Logs when the block starts and
+/// how long it took when it ends, failures at `ERROR` along with the
+/// exception that ended it.
+#[pyclass]
+pub struct Timer {
+    soda: Py<Soda>,
+    fields: Option<Bound>,
+    label: String,
+    level: Level,
+    start: Option<Instant>,
+    elapsed: Option<f64>,
+}
+
+impl Timer {
+    pub fn new(soda: Py<Soda>, fields: Option<Bound>, label: &str, level: Level) -> Timer {
+        Timer {
+            soda,
+            fields,
+            label: label.to_string(),
+            level,
+            start: None,
+            elapsed: None,
+        }
+    }
+
+    fn log(&self, py: Python, level: Level, message: &str, kwargs: &PyDict) -> PyResult<()> {
+        kwargs.set_item("label", &self.label)?;
+
+        let soda = self.soda.borrow(py);
+        if !soda.logger.is_enabled_for(level) {
+            return Ok(());
+        }
+        let record = soda.record(
+            level,
+            PyUnicode::new(py, message),
+            PyTuple::empty(py),
+            Some(kwargs),
+            self.fields.as_ref(),
+        )?;
+
+        soda.emit(record)
+    }
+}
+
+#[pymethods]
+impl Timer {
+    fn __enter__(slf: &PyCell<Timer>) -> PyResult<&PyCell<Timer>> {
+        let py = slf.py();
+        let mut timer = slf.borrow_mut();
+
+        timer.log(
+            py,
+            timer.level,
+            &format!("{} started", timer.label),
+            PyDict::new(py),
+        )?;
+        timer.start = Some(Instant::now());
+
+        Ok(slf)
+    }
+
+    /// Never swallows the exception.
+    fn __exit__(
+        &mut self,
+        py: Python,
+        _kind: &PyAny,
+        error: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        let elapsed = self
+            .start
+            .map_or(0.0, |start| start.elapsed().as_secs_f64());
+        self.elapsed = Some(elapsed);
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("elapsed", elapsed)?;
+
+        if error.is_none() {
+            let message = format!("{} finished in {:.3}s", self.label, elapsed);
+            self.log(py, self.level, &message, kwargs)?;
+        } else {
+            kwargs.set_item("exc_info", error)?;
+            let message = format!("{} failed after {:.3}s", self.label, elapsed);
+            self.log(py, Level::ERROR, &message, kwargs)?;
+        }
+
+        Ok(false)
+    }
+
+    /// Seconds the block has been running for, or ran for once it's done.
+    #[getter]
+    fn elapsed(&self) -> Option<f64> {
+        self.elapsed
+            .or_else(|| self.start.map(|start| start.elapsed().as_secs_f64()))
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use pyo3::exceptions::{PyBaseException, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyTuple, PyUnicode};
+use pyo3::{AsPyPointer, PyNativeType, ToPyObject};
+use serde_json::{Map, Number, Value};
+
+use crate::record::{Exception, Frame, LazyField};
+
+/// What happens to a value that has no structured equivalent, the
+/// `default=` of `json.dumps`.
+#[derive(Clone, Copy)]
+pub enum Unserializable {
+    /// Logged as its `str()`, like `default=str`.
+    Str,
+    /// Logged as its `repr()`.
+    Repr,
+    /// Raise a `TypeError`, the record isn't logged.
+    Error,
+    /// Leave the key out, or the item when it's inside a list.
+    Skip,
+}
+
+impl Unserializable {
+    pub fn parse(name: &str) -> Option<Unserializable> {
+        match name {
+            "str" => Some(Unserializable::Str),
+            "repr" => Some(Unserializable::Repr),
+            "error" => Some(Unserializable::Error),
+            "skip" => Some(Unserializable::Skip),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unserializable::Str => "str",
+            Unserializable::Repr => "repr",
+            Unserializable::Error => "error",
+            Unserializable::Skip => "skip",
+        }
+    }
+}
+
+/// Converts a Python object to a structured value.
+///
+/// Anything that has no structured equivalent falls back to its `str()`,
+/// the same way `json.dumps(..., default=str)` would.
+pub fn from_py(obj: &PyAny) -> Value {
+    convert(obj, Unserializable::Str)
+        .ok()
+        .flatten()
+        .unwrap_or(Value::Null)
+}
+
+/// `from_py` with a choice of fallback, `None` when the value is skipped.
+pub fn convert(obj: &PyAny, policy: Unserializable) -> PyResult<Option<Value>> {
+    if obj.is_none() {
+        return Ok(Some(Value::Null));
+    }
+
+    // bool is a subclass of int, so it has to be checked first.
+    if let Ok(b) = obj.downcast::<PyBool>() {
+        return Ok(Some(Value::Bool(b.is_true())));
+    }
+
+    if obj.downcast::<PyLong>().is_ok() {
+        if let Ok(i) = obj.extract::<i64>() {
+            return Ok(Some(Value::from(i)));
+        }
+        if let Ok(u) = obj.extract::<u64>() {
+            return Ok(Some(Value::from(u)));
+        }
+    }
+
+    if let Ok(f) = obj.downcast::<PyFloat>() {
+        if let Some(n) = Number::from_f64(f.value()) {
+            return Ok(Some(Value::Number(n)));
+        }
+    }
+
+    if let Ok(s) = obj.downcast::<PyUnicode>() {
+        if let Ok(s) = s.to_str() {
+            return Ok(Some(Value::from(s)));
+        }
+    }
+
+    if let Ok(list) = obj.downcast::<PyList>() {
+        return array(list.iter(), policy).map(Some);
+    }
+
+    if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        return array(tuple.iter(), policy).map(Some);
+    }
+
+    if let Ok(dict) = obj.downcast::<PyDict>() {
+        return convert_dict(dict, policy).map(|map| Some(Value::Object(map)));
+    }
+
+    match policy {
+        Unserializable::Str => Ok(Some(fallback(obj))),
+        Unserializable::Repr => Ok(Some(match obj.repr() {
+            Ok(s) => Value::from(s.to_string_lossy().into_owned()),
+            Err(_) => Value::Null,
+        })),
+        Unserializable::Error => Err(PyTypeError::new_err(format!(
+            "Object of type {} is not JSON serializable",
+            obj.get_type().name().unwrap_or("object")
+        ))),
+        Unserializable::Skip => Ok(None),
+    }
+}
+
+fn array<'p>(items: impl Iterator<Item = &'p PyAny>, policy: Unserializable) -> PyResult<Value> {
+    let mut values = Vec::new();
+    for item in items {
+        if let Some(value) = convert(item, policy)? {
+            values.push(value);
+        }
+    }
+
+    Ok(Value::Array(values))
+}
+
+/// Text of a logged message, `str()` for anything that isn't a string.
+pub fn to_text(obj: &PyAny) -> String {
+    match obj.downcast::<PyUnicode>() {
+        Ok(s) => s.to_string_lossy().into_owned(),
+        Err(_) => match fallback(obj) {
+            Value::String(s) => s,
+            _ => String::new(),
+        },
+    }
+}
+
+pub fn map_from_dict(dict: &PyDict) -> Map<String, Value> {
+    convert_dict(dict, Unserializable::Str).unwrap_or_default()
+}
+
+/// `map_from_dict` with the callable values set apart, for `lazy_field`.
+pub fn fields_from_dict(dict: &PyDict) -> (Map<String, Value>, Vec<(String, PyObject)>) {
+    let mut fields = Map::new();
+    let mut lazy = Vec::new();
+
+    for (key, value) in dict.iter() {
+        if value.is_callable() {
+            lazy.push((to_text(key), value.into()));
+        } else {
+            fields.insert(to_text(key), from_py(value));
+        }
+    }
+
+    (fields, lazy)
+}
+
+/// A field whose value is what `function()` returns, called with the GIL.
+/// What it raises is the error.
+pub fn lazy_field(function: PyObject) -> LazyField {
+    Arc::new(move || {
+        Python::with_gil(|py| {
+            function
+                .call0(py)
+                .map(|value| from_py(value.as_ref(py)))
+                .map_err(|e| e.to_string())
+        })
+    })
+}
+
+/// `map_from_dict` with a choice of fallback for the values.
+pub fn convert_dict(dict: &PyDict, policy: Unserializable) -> PyResult<Map<String, Value>> {
+    let mut map = Map::new();
+
+    for (key, value) in dict.iter() {
+        if let Some(value) = convert(value, policy)? {
+            map.insert(to_text(key), value);
+        }
+    }
+
+    Ok(map)
+}
+
+fn fallback(obj: &PyAny) -> Value {
+    match obj.str() {
+        Ok(s) => Value::from(s.to_string_lossy().into_owned()),
+        Err(_) => Value::Null,
+    }
+}
+
+/// The reverse of `from_py`.
+pub fn to_py(py: Python, value: &Value) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.to_object(py),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.to_object(py),
+            (None, Some(u)) => u.to_object(py),
+            _ => n.as_f64().unwrap_or_default().to_object(py),
+        },
+        Value::String(s) => s.to_object(py),
+        Value::Array(values) => {
+            let values: Vec<PyObject> = values.iter().map(|v| to_py(py, v)).collect();
+            PyList::new(py, values).to_object(py)
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map {
+                let _ = dict.set_item(key, to_py(py, value));
+            }
+            dict.to_object(py)
+        }
+    }
+}
+
+/// How many chained exceptions `exception` describes unless told otherwise.
+pub const EXCEPTION_DEPTH: usize = 8;
+
+/// How many characters of a local's `repr` are kept unless told otherwise.
+pub const LOCALS_LENGTH: usize = 200;
+
+/// What `exception` captures besides the exception itself.
+#[derive(Clone, Copy)]
+pub struct Capture {
+    /// How many exceptions of its chain are described.
+    pub depth: usize,
+    /// Captures the frames' local variables, their `repr`s cut to this many
+    /// characters.
+    pub locals: Option<usize>,
+}
+
+impl Default for Capture {
+    fn default() -> Capture {
+        Capture {
+            depth: EXCEPTION_DEPTH,
+            locals: None,
+        }
+    }
+}
+
+/// `exc_info` the way `logging` takes it: an exception instance, a
+/// `sys.exc_info()` tuple, or any other true value for the exception
+/// currently being handled.
+pub fn exception(exc_info: &PyAny, capture: Capture) -> Option<Exception> {
+    let py = exc_info.py();
+
+    let exception = if exc_info.is_instance::<PyBaseException>().unwrap_or(false) {
+        exc_info
+    } else if let Ok((_, exception, _)) = exc_info.extract::<(&PyAny, &PyAny, &PyAny)>() {
+        exception
+    } else if exc_info.is_true().unwrap_or(false) {
+        let (_, exception, _): (&PyAny, &PyAny, &PyAny) = py
+            .import("sys")
+            .ok()?
+            .call_method0("exc_info")
+            .ok()?
+            .extract()
+            .ok()?;
+        exception
+    } else {
+        return None;
+    };
+
+    if exception.is_none() {
+        return None;
+    }
+
+    let mut described = describe(exception, capture, capture.depth, &mut HashSet::new())?;
+    described.stack_trace = stack_trace(exception, &described).unwrap_or_default();
+
+    Some(described)
+}
+
+/// Chains past `depth` are cut, as are the exceptions already in `seen`, so
+/// a cycle in the chain ends where it starts over.
+fn describe(
+    exception: &PyAny,
+    capture: Capture,
+    depth: usize,
+    seen: &mut HashSet<usize>,
+) -> Option<Exception> {
+    seen.insert(exception.as_ptr() as usize);
+
+    let mut frames = Vec::new();
+    let mut traceback = exception.getattr("__traceback__").ok();
+    while let Some(current) = traceback.filter(|traceback| !traceback.is_none()) {
+        frames.extend(frame(current, capture.locals));
+        traceback = current.getattr("tb_next").ok();
+    }
+
+    let mut chained = |attribute: &str| {
+        let next = exception.getattr(attribute).ok()?;
+        if depth == 0 || next.is_none() || seen.contains(&(next.as_ptr() as usize)) {
+            return None;
+        }
+
+        describe(next, capture, depth - 1, seen).map(Box::new)
+    };
+    let cause = chained("__cause__");
+    let context = chained("__context__");
+
+    let class = exception.get_type();
+
+    Some(Exception {
+        kind: class.name().ok()?.to_string(),
+        module: class
+            .getattr("__module__")
+            .and_then(|m| m.extract())
+            .unwrap_or_default(),
+        message: to_text(exception),
+        stack_trace: String::new(),
+        frames,
+        cause,
+        context,
+    })
+}
+
+fn frame(traceback: &PyAny, locals: Option<usize>) -> Option<Frame> {
+    let frame = traceback.getattr("tb_frame").ok()?;
+    let code = frame.getattr("f_code").ok()?;
+
+    Some(Frame {
+        file: code.getattr("co_filename").ok()?.extract().ok()?,
+        line: traceback.getattr("tb_lineno").ok()?.extract().ok()?,
+        function: code.getattr("co_name").ok()?.extract().ok()?,
+        locals: locals.and_then(|length| frame_locals(frame, length).ok()),
+    })
+}
+
+/// Fails, leaving the frame without locals, when any of them fails to
+/// `repr`.
+fn frame_locals(frame: &PyAny, length: usize) -> PyResult<Vec<(String, String)>> {
+    frame
+        .getattr("f_locals")?
+        .call_method0("items")?
+        .iter()?
+        .map(|item| {
+            let (name, value): (String, &PyAny) = item?.extract()?;
+            let repr = value.repr()?;
+            let repr = repr.to_str()?;
+
+            Ok(match repr.char_indices().nth(length) {
+                Some((end, _)) => (name, format!("{}...", &repr[..end])),
+                None => (name, repr.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// What `traceback.format_exception` renders, the captured locals listed
+/// under their frames.
+fn stack_trace(exception: &PyAny, described: &Exception) -> PyResult<String> {
+    let rendered = exception.py().import("traceback")?.call_method1(
+        "TracebackException",
+        (
+            exception.get_type(),
+            exception,
+            exception.getattr("__traceback__")?,
+        ),
+    )?;
+    with_locals(rendered, described)?;
+
+    let lines = rendered.call_method0("format")?.iter()?;
+    lines.map(|line| line?.extract::<String>()).collect()
+}
+
+/// Hands a `TracebackException` the locals `describe` captured, which it
+/// then lists under each frame.
+fn with_locals(rendered: &PyAny, exception: &Exception) -> PyResult<()> {
+    let py = rendered.py();
+
+    for (summary, frame) in rendered.getattr("stack")?.iter()?.zip(&exception.frames) {
+        if let Some(locals) = &frame.locals {
+            let dict = PyDict::new(py);
+            for (name, value) in locals {
+                dict.set_item(name, value)?;
+            }
+            summary?.setattr("locals", dict)?;
+        }
+    }
+
+    let chain = [
+        ("__cause__", &exception.cause),
+        ("__context__", &exception.context),
+    ];
+    for (attribute, chained) in chain.iter() {
+        let next = rendered.getattr(*attribute)?;
+        if let (Some(chained), false) = (chained, next.is_none()) {
+            with_locals(next, chained)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Local};
+use serde_json::{json, Map, Value};
+
+use crate::clock;
+use crate::format::Format;
+use crate::logger::HandlerId;
+use crate::Level;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Record>> = const { RefCell::new(None) };
+}
+
+/// Whether `set_monotonic` is on, and the latest time a record was emitted
+/// with since.
+static MONOTONIC: AtomicBool = AtomicBool::new(false);
+static LAST_TIME: Mutex<Option<DateTime<Local>>> = Mutex::new(None);
+
+/// Computes a field's value when the record is emitted, `Err` with why it
+/// couldn't. See `Record::lazy`.
+pub type LazyField = Arc<dyn Fn() -> Result<Value, String> + Send + Sync>;
+
+/// A single log event, built once per call and handed to every handler.
+#[derive(Clone)]
+pub struct Record {
+    pub level: Level,
+    pub name: String,
+    pub message: String,
+    pub event: Option<Map<String, Value>>,
+    pub extras: Map<String, Value>,
+    pub time: DateTime<Local>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub exception: Option<Exception>,
+    /// Where the record was logged from, when the caller is known.
+    pub caller: Option<Caller>,
+    pub process: u32,
+    /// Id of the thread it was logged on, Python's `threading.get_ident()`
+    /// for records logged from Python.
+    pub thread: Option<u64>,
+    /// Short labels to categorize the record by, `tags=` on the level
+    /// methods.
+    pub tags: Vec<String>,
+    /// Fields computed only once the record passed the filters, and only
+    /// when a handler writes fields. One whose key `extras` has is left out,
+    /// the handlers never see them unresolved.
+    pub lazy: Vec<(String, LazyField)>,
+    /// The handlers the record goes to alone, `handlers=` on the level
+    /// methods. `None` leaves it to the routes.
+    pub handlers: Option<Vec<HandlerId>>,
+    /// The logger's format when the record passed its level, which every
+    /// handler renders it in, see `Logger::emit_except`.
+    pub format: Option<Arc<Format>>,
+}
+
+/// The exception a record was logged with, `exc_info=` on the level methods.
+#[derive(Clone)]
+pub struct Exception {
+    pub kind: String,
+    /// Module of the exception's class, `builtins` for the built in ones.
+    pub module: String,
+    pub message: String,
+    /// The rendered traceback, chain included. Empty on the chained ones.
+    pub stack_trace: String,
+    /// Innermost last, like a traceback.
+    pub frames: Vec<Frame>,
+    /// `__cause__`, what it was raised from.
+    pub cause: Option<Box<Exception>>,
+    /// `__context__`, what was being handled when it was raised.
+    pub context: Option<Box<Exception>>,
+}
+
+/// A frame of an exception's traceback.
+#[derive(Clone)]
+pub struct Frame {
+    pub file: String,
+    pub line: u32,
+    pub function: String,
+    /// The frame's local variables and their `repr`s, when they were
+    /// captured.
+    pub locals: Option<Vec<(String, String)>>,
+}
+
+impl Exception {
+    /// The exception as the JSON handler writes it, the chained ones nested
+    /// under `cause` and `context` and marked `"chained": true`.
+    pub fn to_value(&self) -> Value {
+        self.fields(false)
+    }
+
+    fn fields(&self, chained: bool) -> Value {
+        let frames: Vec<Value> = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let mut fields = json!({
+                    "file": frame.file,
+                    "line": frame.line,
+                    "function": frame.function,
+                });
+                if let Some(locals) = &frame.locals {
+                    let locals: Map<String, Value> = locals
+                        .iter()
+                        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                        .collect();
+                    fields["locals"] = Value::Object(locals);
+                }
+
+                fields
+            })
+            .collect();
+
+        let mut fields = json!({
+            "type": self.kind,
+            "module": self.module,
+            "message": self.message,
+        });
+        if chained {
+            fields["chained"] = Value::from(true);
+        } else {
+            fields["stack_trace"] = Value::from(self.stack_trace.as_str());
+        }
+        fields["frames"] = Value::from(frames);
+        if let Some(cause) = &self.cause {
+            fields["cause"] = cause.fields(true);
+        }
+        if let Some(context) = &self.context {
+            fields["context"] = context.fields(true);
+        }
+
+        fields
+    }
+}
+
+/// The source location of a logging call.
+#[derive(Clone)]
+pub struct Caller {
+    pub file: String,
+    pub line: u32,
+    pub function: String,
+}
+
+impl Record {
+    pub fn new(level: Level, name: &str, message: &str) -> Record {
+        Record {
+            level,
+            name: name.to_string(),
+            message: message.to_string(),
+            event: None,
+            extras: Map::new(),
+            time: clock::now(),
+            trace_id: None,
+            span_id: None,
+            exception: None,
+            caller: None,
+            process: std::process::id(),
+            thread: None,
+            tags: Vec::new(),
+            lazy: Vec::new(),
+            handlers: None,
+            format: None,
+        }
+    }
+
+    /// Flat representation used by the structured handlers, extras are
+    /// merged at the top level but never shadow the builtin keys.
+    ///
+    /// Event records (a dict passed as the message) contribute their keys in
+    /// place of `message`.
+    pub fn to_map(&self) -> Map<String, Value> {
+        let mut map = self.extras.clone();
+
+        match &self.event {
+            Some(event) => map.extend(event.clone()),
+            None => {
+                map.insert(String::from("message"), Value::from(self.message.as_str()));
+            }
+        }
+        map.insert(String::from("level"), Value::from(self.level.as_str()));
+        map.insert(String::from("name"), Value::from(self.name.as_str()));
+
+        if let Some(trace_id) = &self.trace_id {
+            map.insert(String::from("trace_id"), Value::from(trace_id.as_str()));
+        }
+        if let Some(span_id) = &self.span_id {
+            map.insert(String::from("span_id"), Value::from(span_id.as_str()));
+        }
+        if !self.tags.is_empty() {
+            map.insert(String::from("tags"), Value::from(self.tags.clone()));
+        }
+        if let Some(exception) = &self.exception {
+            map.insert(String::from("exception"), exception.to_value());
+        }
+
+        map
+    }
+}
+
+/// Keeps record times from going backwards when the wall clock does, after
+/// an NTP correction say: every record emitted, by any logger, gets at least
+/// the time of the one before it.
+pub fn set_monotonic(enabled: bool) {
+    MONOTONIC.store(enabled, Ordering::Relaxed);
+    *LAST_TIME.lock().unwrap() = None;
+}
+
+pub fn monotonic() -> bool {
+    MONOTONIC.load(Ordering::Relaxed)
+}
+
+/// Moves `record`'s time up to the last one emitted, with `set_monotonic` on.
+pub fn clamp_time(record: &mut Record) {
+    if !monotonic() {
+        return;
+    }
+
+    let mut last = LAST_TIME.lock().unwrap();
+    match *last {
+        Some(time) if time > record.time => record.time = time,
+        _ => *last = Some(record.time),
+    }
+}
+
+/// Runs `f` with `record` visible to the console formatter through
+/// `with_current`, so the formatter sees the same record (and timestamp)
+/// as every other handler. The record is handed back afterwards.
+pub fn scoped<F: FnOnce(&Record)>(record: Record, f: F) -> Record {
+    let previous = CURRENT.with(|cell| cell.replace(Some(record)));
+
+    CURRENT.with(|cell| f(cell.borrow().as_ref().unwrap()));
+
+    CURRENT
+        .with(|cell| cell.replace(previous))
+        .expect("current record taken while in scope")
+}
+
+/// Calls `f` with the record currently being emitted on this thread, records
+/// that didn't come through `Soda` (e.g. other crates using `log`) get `None`.
+pub fn with_current<R, F: FnOnce(Option<&Record>) -> R>(f: F) -> R {
+    CURRENT.with(|cell| f(cell.borrow().as_ref()))
+}
+
+/// What to do with a bytes message that isn't valid UTF-8.
+#[derive(Clone, Copy)]
+pub enum DecodeErrors {
+    /// Raise, the record isn't logged.
+    Strict,
+    /// Log it with invalid sequences replaced by U+FFFD.
+    Replace,
+}
+
+impl DecodeErrors {
+    pub fn parse(name: &str) -> Option<DecodeErrors> {
+        match name {
+            "strict" => Some(DecodeErrors::Strict),
+            "replace" => Some(DecodeErrors::Replace),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DecodeErrors::Strict => "strict",
+            DecodeErrors::Replace => "replace",
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<String, std::str::Utf8Error> {
+        match self {
+            DecodeErrors::Strict => std::str::from_utf8(bytes).map(str::to_string),
+            DecodeErrors::Replace => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Checks records against a JSON Schema before they're emitted, see
+//! `Schema`.
+
+use std::{error::Error, fmt};
+
+use jsonschema::Validator;
+use serde_json::Value;
+
+use crate::record::Record;
+
+/// What happens to a record that doesn't conform.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OnViolation {
+    /// Dropped, with a warning on stderr.
+    Drop,
+    /// Emitted with a `schema_violations` field listing what's wrong.
+    Flag,
+    /// Not emitted, `emit` returns a `Violation`.
+    Raise,
+}
+
+impl OnViolation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OnViolation::Drop => "drop",
+            OnViolation::Flag => "flag",
+            OnViolation::Raise => "raise",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<OnViolation> {
+        match name {
+            "drop" => Some(OnViolation::Drop),
+            "flag" => Some(OnViolation::Flag),
+            "raise" => Some(OnViolation::Raise),
+            _ => None,
+        }
+    }
+}
+
+/// A JSON Schema records are checked against, as the structured handlers
+/// write them: the fields, `message` (or an event's keys), `level`, `name`
+/// and the rest of `Record::to_map`.
+pub struct Schema {
+    schema: Value,
+    validator: Validator,
+    pub on_violation: OnViolation,
+}
+
+impl Schema {
+    /// Fails with why when `schema` isn't a valid schema.
+    pub fn new(schema: Value, on_violation: OnViolation) -> Result<Schema, String> {
+        let validator = jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+
+        Ok(Schema {
+            schema,
+            validator,
+            on_violation,
+        })
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    /// What's wrong with `record`, nothing when it conforms.
+    pub fn violations(&self, record: &Record) -> Vec<String> {
+        let instance = Value::Object(record.to_map());
+
+        self.validator
+            .iter_errors(&instance)
+            .map(|error| match error.instance_path().to_string() {
+                path if path.is_empty() => error.to_string(),
+                path => format!("{} at {}", error, path),
+            })
+            .collect()
+    }
+}
+
+/// A record that didn't conform to the schema, under `OnViolation::Raise`.
+#[derive(Debug)]
+pub struct Violation {
+    pub violations: Vec<String>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "record doesn't match the schema: {}",
+            self.violations.join("; ")
+        )
+    }
+}
+
+impl Error for Violation {}
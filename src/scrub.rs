@@ -0,0 +1,238 @@
+//! Replaces personal data found in records with typed placeholders, see
+//! `Scrubber`.
+
+use std::{collections::HashSet, sync::Arc};
+
+use regex::Regex;
+use ring::digest::{digest, SHA256};
+use serde_json::Value;
+
+use crate::handlers::to_hex;
+use crate::record::{Exception, Record};
+use crate::stats::Stats;
+
+/// What a scrubber looks for, in the order it does: a span one kind took
+/// isn't looked at by the next.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PiiKind {
+    Email,
+    CreditCard,
+    Ipv4,
+    Phone,
+}
+
+pub const PII_KINDS: [PiiKind; 4] = [
+    PiiKind::Email,
+    PiiKind::CreditCard,
+    PiiKind::Ipv4,
+    PiiKind::Phone,
+];
+
+impl PiiKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PiiKind::Email => "email",
+            PiiKind::CreditCard => "credit_card",
+            PiiKind::Ipv4 => "ipv4",
+            PiiKind::Phone => "phone",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<PiiKind> {
+        PII_KINDS.iter().copied().find(|kind| kind.as_str() == name)
+    }
+
+    fn pattern(self) -> &'static str {
+        match self {
+            PiiKind::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+            PiiKind::CreditCard => r"\b\d(?:[ -]?\d){12,18}\b",
+            PiiKind::Ipv4 => concat!(
+                r"\b(?:(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\.){3}",
+                r"(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\b",
+            ),
+            // Plain runs of digits, ids and timestamps, aren't taken for one.
+            PiiKind::Phone => concat!(
+                r"\+\d{9,15}\b|",
+                r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{1,4}\)[ .-]?)?\d{2,4}(?:[ .-]\d{2,4}){1,4}\b",
+            ),
+        }
+    }
+
+    /// Whether a match is one, beyond what the pattern can tell.
+    fn confirms(self, found: &str) -> bool {
+        let digits = found.bytes().filter(u8::is_ascii_digit).count();
+
+        match self {
+            PiiKind::CreditCard => (13..=19).contains(&digits) && luhn(found),
+            // A date and time has as many digits as a phone number.
+            PiiKind::Phone => (9..=15).contains(&digits) && !starts_with_date(found),
+            PiiKind::Email | PiiKind::Ipv4 => true,
+        }
+    }
+
+    /// What the placeholder's hash is taken of, so the same number written
+    /// differently gets the same one.
+    fn normalize(self, found: &str) -> String {
+        match self {
+            PiiKind::Email => found.to_lowercase(),
+            PiiKind::CreditCard | PiiKind::Phone => {
+                found.chars().filter(char::is_ascii_digit).collect()
+            }
+            PiiKind::Ipv4 => found.to_string(),
+        }
+    }
+}
+
+/// Replaces each email address, phone number, credit card number or IPv4
+/// address it finds in a record's message, fields, event and exception with
+/// `<kind:hash>`, `hash` being 8 hex characters of the value's SHA-256 so the
+/// same value can be followed across records without being shown.
+pub struct Scrubber {
+    kinds: Vec<(PiiKind, Regex)>,
+    /// Fields left as they are, at any depth.
+    no_scrub: HashSet<String>,
+    stats: Arc<Stats>,
+}
+
+impl Scrubber {
+    pub fn new(kinds: &[PiiKind], no_scrub: Vec<String>, stats: Arc<Stats>) -> Scrubber {
+        let kinds = PII_KINDS
+            .iter()
+            .copied()
+            .filter(|kind| kinds.contains(kind))
+            .map(|kind| (kind, Regex::new(kind.pattern()).unwrap()))
+            .collect();
+
+        Scrubber {
+            kinds,
+            no_scrub: no_scrub.into_iter().collect(),
+            stats,
+        }
+    }
+
+    pub fn scrub(&self, record: &mut Record) {
+        record.message = self.text(&record.message);
+
+        for (key, value) in record.extras.iter_mut() {
+            if !self.no_scrub.contains(key) {
+                self.value(value);
+            }
+        }
+        if let Some(event) = record.event.as_mut() {
+            for (key, value) in event.iter_mut() {
+                if !self.no_scrub.contains(key) {
+                    self.value(value);
+                }
+            }
+        }
+        if let Some(exception) = record.exception.as_mut() {
+            self.exception(exception);
+        }
+    }
+
+    fn value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.text(text),
+            // A card number logged as an int is still one.
+            Value::Number(number) => {
+                let text = number.to_string();
+                let scrubbed = self.text(&text);
+                if scrubbed != text {
+                    *value = Value::from(scrubbed);
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.value(value)),
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if !self.no_scrub.contains(key) {
+                        self.value(value);
+                    }
+                }
+            }
+            Value::Null | Value::Bool(_) => {}
+        }
+    }
+
+    fn exception(&self, exception: &mut Exception) {
+        exception.message = self.text(&exception.message);
+        exception.stack_trace = self.text(&exception.stack_trace);
+
+        for frame in exception.frames.iter_mut() {
+            for (name, local) in frame.locals.iter_mut().flatten() {
+                if !self.no_scrub.contains(name) {
+                    *local = self.text(local);
+                }
+            }
+        }
+        for chained in exception
+            .cause
+            .iter_mut()
+            .chain(exception.context.iter_mut())
+        {
+            self.exception(chained);
+        }
+    }
+
+    fn text(&self, text: &str) -> String {
+        let mut found: Vec<(usize, usize, PiiKind)> = Vec::new();
+
+        for (kind, regex) in &self.kinds {
+            for m in regex.find_iter(text) {
+                let taken = found
+                    .iter()
+                    .any(|(start, end, _)| m.start() < *end && *start < m.end());
+                if !taken && kind.confirms(m.as_str()) {
+                    found.push((m.start(), m.end(), *kind));
+                }
+            }
+        }
+        if found.is_empty() {
+            return text.to_string();
+        }
+        found.sort_by_key(|(start, _, _)| *start);
+
+        let mut scrubbed = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, end, kind) in found {
+            let hash = digest(&SHA256, kind.normalize(&text[start..end]).as_bytes());
+            scrubbed.push_str(&text[last..start]);
+            scrubbed.push_str(&format!(
+                "<{}:{}>",
+                kind.as_str(),
+                &to_hex(hash.as_ref())[..8]
+            ));
+            last = end;
+            self.stats.scrubbed(kind);
+        }
+        scrubbed.push_str(&text[last..]);
+
+        scrubbed
+    }
+}
+
+/// The Luhn checksum of the digits in `number`, which card numbers pass.
+fn luhn(number: &str) -> bool {
+    let mut sum = 0;
+
+    for (i, digit) in number.bytes().rev().filter(u8::is_ascii_digit).enumerate() {
+        let mut digit = u32::from(digit - b'0');
+        if i % 2 == 1 {
+            digit *= 2;
+            if digit > 9 {
+                digit -= 9;
+            }
+        }
+        sum += digit;
+    }
+
+    sum % 10 == 0
+}
+
+fn starts_with_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+
+    bytes.len() >= 10
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+}
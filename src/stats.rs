@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::scrub::{PiiKind, PII_KINDS};
+use crate::Level;
+
+const LEVELS: [Level; 7] = [
+    Level::NOTSET,
+    Level::TRACE,
+    Level::DEBUG,
+    Level::INFO,
+    Level::WARNING,
+    Level::ERROR,
+    Level::CRITICAL,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HandlerKind {
+    Console,
+    File,
+    Fluentd,
+    Json,
+    LevelSplit,
+    Memory,
+    Otlp,
+    Sqlite,
+}
+
+const HANDLERS: [HandlerKind; 8] = [
+    HandlerKind::Console,
+    HandlerKind::File,
+    HandlerKind::Fluentd,
+    HandlerKind::Json,
+    HandlerKind::LevelSplit,
+    HandlerKind::Memory,
+    HandlerKind::Otlp,
+    HandlerKind::Sqlite,
+];
+
+impl HandlerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HandlerKind::Console => "console",
+            HandlerKind::File => "file",
+            HandlerKind::Fluentd => "fluentd",
+            HandlerKind::Json => "json",
+            HandlerKind::LevelSplit => "level_split",
+            HandlerKind::Memory => "memory",
+            HandlerKind::Otlp => "otlp",
+            HandlerKind::Sqlite => "sqlite",
+        }
+    }
+
+    pub fn all() -> impl Iterator<Item = HandlerKind> {
+        HANDLERS.iter().copied()
+    }
+
+    pub fn parse(name: &str) -> Option<HandlerKind> {
+        HANDLERS.iter().copied().find(|kind| kind.as_str() == name)
+    }
+}
+
+/// Counters shared by the logger, its handlers and the metrics exporters.
+/// Everything is a relaxed atomic so counting never blocks logging.
+#[derive(Default)]
+pub struct Stats {
+    records: [[AtomicU64; LEVELS.len()]; HANDLERS.len()],
+    dropped: AtomicU64,
+    processor_errors: AtomicU64,
+    filter_errors: AtomicU64,
+    format_errors: AtomicU64,
+    hook_errors: AtomicU64,
+    field_errors: AtomicU64,
+    rotation_errors: AtomicU64,
+    schema_violations: AtomicU64,
+    scrubbed: [AtomicU64; PII_KINDS.len()],
+    /// Records sampling left out, by logger name, the one count kept
+    /// behind a lock.
+    sampled_out: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Stats {
+    pub fn record(&self, handler: HandlerKind, level: Level) {
+        self.records[handler as usize][level as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self, count: u64) {
+        self.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Every non zero `(handler, level, count)`.
+    pub fn records(&self) -> Vec<(HandlerKind, Level, u64)> {
+        let mut records = Vec::new();
+
+        for handler in HANDLERS.iter() {
+            for level in LEVELS.iter() {
+                let count =
+                    self.records[*handler as usize][*level as usize].load(Ordering::Relaxed);
+                if count > 0 {
+                    records.push((*handler, *level, count));
+                }
+            }
+        }
+
+        records
+    }
+
+    pub fn dropped_total(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn processor_error(&self) {
+        self.processor_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn processor_errors_total(&self) -> u64 {
+        self.processor_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn filter_error(&self) {
+        self.filter_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn filter_errors_total(&self) -> u64 {
+        self.filter_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn format_error(&self) {
+        self.format_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn format_errors_total(&self) -> u64 {
+        self.format_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn hook_error(&self) {
+        self.hook_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hook_errors_total(&self) -> u64 {
+        self.hook_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn field_error(&self) {
+        self.field_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn field_errors_total(&self) -> u64 {
+        self.field_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn rotation_error(&self) {
+        self.rotation_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rotation_errors_total(&self) -> u64 {
+        self.rotation_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn schema_violation(&self) {
+        self.schema_violations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn schema_violations_total(&self) -> u64 {
+        self.schema_violations.load(Ordering::Relaxed)
+    }
+
+    pub fn sampled_out(&self, name: &str) {
+        let mut sampled_out = self.sampled_out.lock().unwrap();
+        match sampled_out.get_mut(name) {
+            Some(count) => *count += 1,
+            None => {
+                sampled_out.insert(name.to_string(), 1);
+            }
+        }
+    }
+
+    /// `(name, count)` of the records sampling left out, by logger name.
+    pub fn sampled_out_totals(&self) -> BTreeMap<String, u64> {
+        self.sampled_out.lock().unwrap().clone()
+    }
+
+    pub fn scrubbed(&self, kind: PiiKind) {
+        self.scrubbed[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `(kind, count)` of the values scrubbed, every kind included.
+    pub fn scrubbed_totals(&self) -> Vec<(PiiKind, u64)> {
+        PII_KINDS
+            .iter()
+            .map(|kind| (*kind, self.scrubbed[*kind as usize].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Prometheus text exposition of the counters.
+    pub fn exposition(&self) -> String {
+        let mut out = String::from(
+            "# HELP soda_records_total Records emitted, by level and handler.\n\
+             # TYPE soda_records_total counter\n",
+        );
+
+        for (handler, level, count) in self.records() {
+            out.push_str(&format!(
+                "soda_records_total{{level=\"{}\",handler=\"{}\"}} {}\n",
+                level.as_str().to_lowercase(),
+                handler.as_str(),
+                count
+            ));
+        }
+
+        out.push_str(&format!(
+            "# HELP soda_dropped_records_total Records dropped before reaching a handler.\n\
+             # TYPE soda_dropped_records_total counter\n\
+             soda_dropped_records_total {}\n",
+            self.dropped_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_processor_errors_total Record processors that failed.\n\
+             # TYPE soda_processor_errors_total counter\n\
+             soda_processor_errors_total {}\n",
+            self.processor_errors_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_filter_errors_total Record filters that failed.\n\
+             # TYPE soda_filter_errors_total counter\n\
+             soda_filter_errors_total {}\n",
+            self.filter_errors_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_format_errors_total Messages with a placeholder no field filled.\n\
+             # TYPE soda_format_errors_total counter\n\
+             soda_format_errors_total {}\n",
+            self.format_errors_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_hook_errors_total Emit hooks that failed.\n\
+             # TYPE soda_hook_errors_total counter\n\
+             soda_hook_errors_total {}\n",
+            self.hook_errors_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_field_errors_total Lazy fields that failed to compute.\n\
+             # TYPE soda_field_errors_total counter\n\
+             soda_field_errors_total {}\n",
+            self.field_errors_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_rotation_errors_total Rotation callbacks that failed.\n\
+             # TYPE soda_rotation_errors_total counter\n\
+             soda_rotation_errors_total {}\n",
+            self.rotation_errors_total()
+        ));
+
+        out.push_str(&format!(
+            "# HELP soda_schema_violations_total Records that didn't match the schema.\n\
+             # TYPE soda_schema_violations_total counter\n\
+             soda_schema_violations_total {}\n",
+            self.schema_violations_total()
+        ));
+
+        out.push_str(
+            "# HELP soda_scrubbed_total Personal data replaced in records, by kind.\n\
+             # TYPE soda_scrubbed_total counter\n",
+        );
+        for (kind, count) in self.scrubbed_totals() {
+            out.push_str(&format!(
+                "soda_scrubbed_total{{kind=\"{}\"}} {}\n",
+                kind.as_str(),
+                count
+            ));
+        }
+
+        out.push_str(
+            "# HELP soda_sampled_out_total Records sampling left out, by logger name.\n\
+             # TYPE soda_sampled_out_total counter\n",
+        );
+        for (name, count) in self.sampled_out_totals() {
+            out.push_str(&format!(
+                "soda_sampled_out_total{{name=\"{}\"}} {}\n",
+                name.replace('\\', "\\\\").replace('"', "\\\""),
+                count
+            ));
+        }
+
+        out
+    }
+}
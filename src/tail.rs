@@ -0,0 +1,162 @@
+//! Reads a log file back from its end, see `tail`, and as it grows, see
+//! `Follow`.
+
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// Bytes read at a time going back from the end of a file.
+const BLOCK: u64 = 8192;
+
+/// The last `n` lines of the file at `path`, read a block at a time from its
+/// end, so a large file costs no more than a small one. Bytes that aren't
+/// UTF-8 are replaced.
+pub fn tail(path: &str, n: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let mut start = file.metadata()?.len();
+    let mut tail: Vec<u8> = Vec::new();
+    let mut newlines = 0;
+
+    // One newline more than lines wanted tells where the first one starts,
+    // the last line ending in one or not.
+    while start > 0 && newlines <= n {
+        let from = start.saturating_sub(BLOCK);
+        let mut block = vec![0; (start - from) as usize];
+        file.seek(SeekFrom::Start(from))?;
+        file.read_exact(&mut block)?;
+
+        newlines += block.iter().filter(|b| **b == b'\n').count();
+        block.extend_from_slice(&tail);
+        tail = block;
+        start = from;
+    }
+
+    let text = String::from_utf8_lossy(&tail);
+    let mut lines: Vec<&str> = text.lines().collect();
+    if start > 0 {
+        // The end of a line starting in the block before.
+        lines.remove(0);
+    }
+    let skipped = lines.len().saturating_sub(n);
+
+    Ok(lines[skipped..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// Reads the lines appended to a file since the last `read`, carrying on in
+/// the file at the path when the one being read was rotated away or
+/// truncated.
+pub struct Follow {
+    path: String,
+    /// The file being read, `None` until there's one at `path`.
+    file: Option<File>,
+    /// Where the next read starts.
+    offset: u64,
+    /// A line still being written.
+    partial: Vec<u8>,
+}
+
+impl Follow {
+    /// Follows the file at `path` from its end, what's there already
+    /// skipped. A file that isn't there yet is read from the start once it
+    /// is.
+    pub fn new(path: &str) -> Follow {
+        let file = File::open(path).ok();
+        let offset = file
+            .as_ref()
+            .and_then(|file| file.metadata().ok())
+            .map_or(0, |metadata| metadata.len());
+
+        Follow {
+            path: path.to_string(),
+            file,
+            offset,
+            partial: Vec::new(),
+        }
+    }
+
+    /// The whole lines written since the last call. `path` is where the file
+    /// is written to now, the same unless its handler moved on to another.
+    /// What was left in a file rotated away is read before the new file.
+    pub fn read(&mut self, path: &str) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+
+        self.read_on()?;
+        let current = match fs::metadata(path) {
+            Ok(current) => Some(current),
+            // The file renamed and not made again yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(current) = current {
+            let reading = match &self.file {
+                Some(file) => Some(file.metadata()?),
+                None => None,
+            };
+            let moved = match &reading {
+                Some(reading) => path != self.path || !same_file(reading, &current),
+                None => true,
+            };
+
+            if moved {
+                // The end of it, written since the read above.
+                self.read_on()?;
+                self.lines(&mut lines, true);
+                self.path = path.to_string();
+                self.file = Some(File::open(path)?);
+                self.offset = 0;
+                self.read_on()?;
+            } else if current.len() < self.offset {
+                self.partial.clear();
+                self.offset = 0;
+                self.read_on()?;
+            }
+        }
+        self.lines(&mut lines, false);
+
+        Ok(lines)
+    }
+
+    /// Adds what was written from `offset` on to `partial`.
+    fn read_on(&mut self) -> io::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            file.seek(SeekFrom::Start(self.offset))?;
+            self.offset += file.read_to_end(&mut self.partial)? as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the whole lines of `partial` to `lines`, the last one too when
+    /// it's `ended`, the file written to no more.
+    fn lines(&mut self, lines: &mut Vec<String>, ended: bool) {
+        let end = if ended {
+            self.partial.len()
+        } else {
+            match self.partial.iter().rposition(|b| *b == b'\n') {
+                Some(end) => end + 1,
+                None => return,
+            }
+        };
+
+        let whole: Vec<u8> = self.partial.drain(..end).collect();
+        lines.extend(String::from_utf8_lossy(&whole).lines().map(str::to_string));
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Without inodes to go by, the file is taken for the same until it's
+/// smaller than what was read of it.
+#[cfg(not(unix))]
+fn same_file(_: &Metadata, _: &Metadata) -> bool {
+    true
+}
@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+
+use serde_json::{Map, Value};
+
+use crate::format::Format;
+use crate::record::Record;
+
+/// Renders a `setFormat` template against a record.
+///
+/// Supported placeholders are `{time}`, `{name}`, `{level}`, `{message}`,
+/// `{trace_id}`, `{span_id}`, `{tags}` (as `#cache #perf`), `{extras}` (all
+/// extras as `key=value`) and
+/// `{extra[key]}`. Unknown placeholders are written back untouched, `{{` and
+/// `}}` produce literal braces.
+///
+/// Times are written with the format's `datefmt`, extras with its
+/// `coercion` and the message escaped with `escape_control`.
+pub fn render(template: &str, record: &Record, format: &Format) -> String {
+    substitute(template, record.message.len(), |out, key| {
+        placeholder(out, key, record, format)
+    })
+}
+
+/// Whether rendering `template` writes the `key` field, through `{extras}`
+/// or `{extra[key]}`.
+pub fn shows_field(template: &str, key: &str) -> bool {
+    template.contains("{extras}") || template.contains(&format!("{{extra[{}]}}", key))
+}
+
+/// A message template filled in by `fill`.
+pub struct Filled {
+    pub message: String,
+    /// The fields a placeholder took, in the order they appear.
+    pub used: Vec<String>,
+    /// Placeholders with no field of that name, left as they were.
+    pub missing: usize,
+}
+
+/// Fills `{key}` placeholders in a logged message from `fields`, e.g.
+/// `"user {user} logged in"`. Like `render`, a placeholder without a field is
+/// written back untouched and `{{` and `}}` produce literal braces.
+pub fn fill(message: &str, fields: &Map<String, Value>, coercion: &Coercion) -> Filled {
+    let mut used = Vec::new();
+    let mut missing = 0;
+
+    let message = substitute(message, 0, |out, key| match fields.get(key) {
+        Some(value) => {
+            out.push_str(&coercion.text(value));
+            used.push(key.to_string());
+            true
+        }
+        None => {
+            missing += 1;
+            false
+        }
+    });
+
+    Filled {
+        message,
+        used,
+        missing,
+    }
+}
+
+/// A piece of a template, see `pieces`.
+pub enum Piece<'a> {
+    Literal(&'a str),
+    /// The key of a `{key}` placeholder.
+    Placeholder(&'a str),
+}
+
+/// The literal text and placeholders of `template` in order. `{{` and `}}`
+/// are literal braces, as is a `{` never closed along with what follows.
+pub fn pieces(template: &str) -> impl Iterator<Item = Piece<'_>> {
+    let mut rest = template;
+
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        for escaped in ["{{", "}}"] {
+            if let Some(after) = rest.strip_prefix(escaped) {
+                rest = after;
+                return Some(Piece::Literal(&escaped[..1]));
+            }
+        }
+        if let Some(after) = rest.strip_prefix('{') {
+            let piece = match after.find('}') {
+                Some(end) => {
+                    rest = &after[end + 1..];
+                    Piece::Placeholder(&after[..end])
+                }
+                None => Piece::Literal(std::mem::take(&mut rest)),
+            };
+            return Some(piece);
+        }
+
+        // A lone `}` is a literal one.
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| matches!(c, '{' | '}'))
+            .map_or(rest.len(), |(end, _)| end);
+        let (literal, after) = rest.split_at(end);
+        rest = after;
+
+        Some(Piece::Literal(literal))
+    })
+}
+
+/// Walks `template` writing each `{key}` through `placeholder`, which returns
+/// `false` to have it written back as is.
+fn substitute<F>(template: &str, extra: usize, mut placeholder: F) -> String
+where
+    F: FnMut(&mut String, &str) -> bool,
+{
+    let mut out = String::with_capacity(template.len() + extra);
+
+    for piece in pieces(template) {
+        match piece {
+            Piece::Literal(literal) => out.push_str(literal),
+            Piece::Placeholder(key) => {
+                if !placeholder(&mut out, key) {
+                    out.push('{');
+                    out.push_str(key);
+                    out.push('}');
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn placeholder(out: &mut String, key: &str, record: &Record, format: &Format) -> bool {
+    let coercion = &format.coercion;
+    match key {
+        "time" => out.push_str(&record.time.format(&format.datefmt).to_string()),
+        "name" => out.push_str(&record.name),
+        "level" => out.push_str(record.level.as_str()),
+        "message" => out.push_str(&format.message(&record.message)),
+        "trace_id" => out.push_str(record.trace_id.as_deref().unwrap_or("")),
+        "span_id" => out.push_str(record.span_id.as_deref().unwrap_or("")),
+        "tags" => {
+            let tags: Vec<String> = record.tags.iter().map(|tag| format!("#{}", tag)).collect();
+            out.push_str(&tags.join(" "));
+        }
+        "extras" => {
+            let pairs: Vec<String> = record
+                .extras
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, coercion.text(v)))
+                .collect();
+            out.push_str(&pairs.join(" "));
+        }
+        _ => {
+            let name = match key.strip_prefix("extra[").and_then(|k| k.strip_suffix(']')) {
+                Some(name) => name,
+                None => return false,
+            };
+
+            match record.extras.get(name) {
+                Some(value) => out.push_str(&coercion.text(value)),
+                None => return false,
+            }
+        }
+    }
+
+    true
+}
+
+/// `text` with its control characters written as escapes, so a message
+/// can't start a line of its own or move the cursor: `\n`, `\r` and `\t`
+/// as such, the others as `\xHH` or `\u{HHHH}`. U+2028 and U+2029, which
+/// some viewers break lines at, are escaped too.
+pub fn escape_control(text: &str) -> Cow<'_, str> {
+    let control = |c: char| c.is_control() || c == '\u{2028}' || c == '\u{2029}';
+    if !text.chars().any(control) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x80 && control(c) => {
+                escaped.push_str(&format!("\\x{:02x}", c as u32))
+            }
+            c if control(c) => escaped.push_str(&format!("\\u{{{:04x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
+/// Text form of an extra, strings are written without quotes.
+pub fn text(value: &Value) -> String {
+    Coercion::default().text(value)
+}
+
+/// How a field that isn't a string is written in text, in a message's
+/// placeholders and a template's extras. Values nested in a list or a dict
+/// keep their JSON form.
+#[derive(Clone, PartialEq)]
+pub struct Coercion {
+    /// Digits after the point a float is written with, all it takes to
+    /// read back the same float when `None`.
+    pub float_precision: Option<usize>,
+    pub true_text: String,
+    pub false_text: String,
+    pub none_text: String,
+}
+
+impl Default for Coercion {
+    fn default() -> Coercion {
+        Coercion {
+            float_precision: None,
+            true_text: String::from("true"),
+            false_text: String::from("false"),
+            none_text: String::from("null"),
+        }
+    }
+}
+
+impl Coercion {
+    pub fn text(&self, value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Bool(true) => self.true_text.clone(),
+            Value::Bool(false) => self.false_text.clone(),
+            Value::Null => self.none_text.clone(),
+            Value::Number(n) if n.is_f64() => match (self.float_precision, n.as_f64()) {
+                (Some(precision), Some(n)) => format!("{:.*}", precision, n),
+                _ => n.to_string(),
+            },
+            other => other.to_string(),
+        }
+    }
+}
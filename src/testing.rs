@@ -0,0 +1,13 @@
+//! What the tests share.
+
+use std::{fs, path::PathBuf, process};
+
+/// An empty directory of its own for the test called `name`, left behind
+/// for a look at what failed.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("soda-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
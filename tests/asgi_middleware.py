@@ -0,0 +1,35 @@
+"""A tiny ASGI middleware giving each request a correlation id.
+
+Every record logged while a request is handled carries its id as
+`request_id`, and the response echoes it back in an `x-request-id` header.
+A request that comes with that header keeps the id it was given.
+"""
+
+
+class CorrelationId:
+    header = b"x-request-id"
+
+    def __init__(self, app, logger):
+        self.app = app
+        self.logger = logger
+
+    async def __call__(self, scope, receive, send):
+        if scope["type"] != "http":
+            return await self.app(scope, receive, send)
+
+        given = dict(scope.get("headers", [])).get(self.header)
+        request_id = self.logger.new_correlation_id(
+            value=given.decode("latin-1") if given else None
+        )
+
+        async def send_with_id(message):
+            if message["type"] == "http.response.start":
+                headers = list(message.get("headers", []))
+                headers.append((self.header, request_id.encode("latin-1")))
+                message = dict(message, headers=headers)
+            await send(message)
+
+        try:
+            await self.app(scope, receive, send_with_id)
+        finally:
+            self.logger.clear_correlation_id()